@@ -0,0 +1,10 @@
+(module
+    ;; Entry point, which never returns
+    (func $main
+        (loop $loop
+            (br $loop)
+        )
+    )
+
+    (export "main" (func $main))
+)
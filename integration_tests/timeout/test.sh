@@ -0,0 +1,17 @@
+#! /bin/bash
+
+# Wasmer should interrupt the loop after a second; `timeout` only
+# stops it if `--timeout` doesn't.
+timeout 30s ./target/release/wasmer run --timeout 1 examples/infinite_loop.wat 2> ./timeout.out
+status=$?
+
+if [ $status -ne 0 ] && [ $status -ne 124 ] && grep "Interrupted" ./timeout.out
+then
+    echo "timeout integration test succeeded"
+    rm ./timeout.out
+    exit 0
+else
+    echo "timeout integration test failed"
+    rm ./timeout.out
+    exit -1
+fi
@@ -0,0 +1,168 @@
+//! Support for the threads proposal's atomic instructions.
+//!
+//! Neither Cranelift nor `cranelift-wasm` know about atomics yet, so before
+//! a function body is translated, each atomic instruction in it is rewritten
+//! into a call (see the `lowering` module). `FuncEnv` recognizes those calls
+//! and lowers them to a bounds and alignment check followed by a call to the
+//! matching atomic vmcall.
+use crate::relocation::call_names;
+use cranelift_codegen::ir::{self, types::*};
+use wasmparser::Operator;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AtomicOpKind {
+    Load,
+    Store,
+    RmwAdd,
+    RmwSub,
+    RmwAnd,
+    RmwOr,
+    RmwXor,
+    RmwXchg,
+    Cmpxchg,
+    Wait,
+    Notify,
+}
+
+/// An atomic instruction that a function body was rewritten to call.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AtomicOp {
+    pub kind: AtomicOpKind,
+    /// The type of the accessed value, `I32` or `I64`.
+    pub ty: ir::Type,
+    /// The number of bytes accessed in memory.
+    pub width: u8,
+    /// The static offset added to the address operand.
+    pub offset: u32,
+}
+
+impl AtomicOp {
+    /// The operand types of the instruction, as seen by webassembly.
+    pub fn params(&self) -> Vec<ir::Type> {
+        match self.kind {
+            AtomicOpKind::Load => vec![I32],
+            AtomicOpKind::Cmpxchg => vec![I32, self.ty, self.ty],
+            AtomicOpKind::Wait => vec![I32, self.ty, I64],
+            AtomicOpKind::Notify => vec![I32, I32],
+            _ => vec![I32, self.ty],
+        }
+    }
+
+    /// The result types of the instruction, as seen by webassembly.
+    pub fn returns(&self) -> Vec<ir::Type> {
+        match self.kind {
+            AtomicOpKind::Store => vec![],
+            AtomicOpKind::Wait | AtomicOpKind::Notify => vec![I32],
+            _ => vec![self.ty],
+        }
+    }
+
+    /// The name of the vmcall implementing this instruction.
+    pub fn name(&self) -> ir::ExternalName {
+        let index = match self.kind {
+            AtomicOpKind::Load => call_names::ATOMIC_LOAD,
+            AtomicOpKind::Store => call_names::ATOMIC_STORE,
+            AtomicOpKind::RmwAdd => call_names::ATOMIC_RMW_ADD,
+            AtomicOpKind::RmwSub => call_names::ATOMIC_RMW_SUB,
+            AtomicOpKind::RmwAnd => call_names::ATOMIC_RMW_AND,
+            AtomicOpKind::RmwOr => call_names::ATOMIC_RMW_OR,
+            AtomicOpKind::RmwXor => call_names::ATOMIC_RMW_XOR,
+            AtomicOpKind::RmwXchg => call_names::ATOMIC_RMW_XCHG,
+            AtomicOpKind::Cmpxchg => call_names::ATOMIC_CMPXCHG,
+            AtomicOpKind::Wait => call_names::ATOMIC_WAIT,
+            AtomicOpKind::Notify => call_names::ATOMIC_NOTIFY,
+        };
+        ir::ExternalName::user(call_names::ATOMIC_NAMESPACE, index)
+    }
+
+    pub fn from_operator(op: &Operator) -> Option<Self> {
+        use self::AtomicOpKind::*;
+
+        let (kind, ty, width, memarg) = match op {
+            Operator::Wake { memarg } => (Notify, I32, 4, memarg),
+            Operator::I32Wait { memarg } => (Wait, I32, 4, memarg),
+            Operator::I64Wait { memarg } => (Wait, I64, 8, memarg),
+
+            Operator::I32AtomicLoad { memarg } => (Load, I32, 4, memarg),
+            Operator::I64AtomicLoad { memarg } => (Load, I64, 8, memarg),
+            Operator::I32AtomicLoad8U { memarg } => (Load, I32, 1, memarg),
+            Operator::I32AtomicLoad16U { memarg } => (Load, I32, 2, memarg),
+            Operator::I64AtomicLoad8U { memarg } => (Load, I64, 1, memarg),
+            Operator::I64AtomicLoad16U { memarg } => (Load, I64, 2, memarg),
+            Operator::I64AtomicLoad32U { memarg } => (Load, I64, 4, memarg),
+
+            Operator::I32AtomicStore { memarg } => (Store, I32, 4, memarg),
+            Operator::I64AtomicStore { memarg } => (Store, I64, 8, memarg),
+            Operator::I32AtomicStore8 { memarg } => (Store, I32, 1, memarg),
+            Operator::I32AtomicStore16 { memarg } => (Store, I32, 2, memarg),
+            Operator::I64AtomicStore8 { memarg } => (Store, I64, 1, memarg),
+            Operator::I64AtomicStore16 { memarg } => (Store, I64, 2, memarg),
+            Operator::I64AtomicStore32 { memarg } => (Store, I64, 4, memarg),
+
+            Operator::I32AtomicRmwAdd { memarg } => (RmwAdd, I32, 4, memarg),
+            Operator::I64AtomicRmwAdd { memarg } => (RmwAdd, I64, 8, memarg),
+            Operator::I32AtomicRmw8UAdd { memarg } => (RmwAdd, I32, 1, memarg),
+            Operator::I32AtomicRmw16UAdd { memarg } => (RmwAdd, I32, 2, memarg),
+            Operator::I64AtomicRmw8UAdd { memarg } => (RmwAdd, I64, 1, memarg),
+            Operator::I64AtomicRmw16UAdd { memarg } => (RmwAdd, I64, 2, memarg),
+            Operator::I64AtomicRmw32UAdd { memarg } => (RmwAdd, I64, 4, memarg),
+
+            Operator::I32AtomicRmwSub { memarg } => (RmwSub, I32, 4, memarg),
+            Operator::I64AtomicRmwSub { memarg } => (RmwSub, I64, 8, memarg),
+            Operator::I32AtomicRmw8USub { memarg } => (RmwSub, I32, 1, memarg),
+            Operator::I32AtomicRmw16USub { memarg } => (RmwSub, I32, 2, memarg),
+            Operator::I64AtomicRmw8USub { memarg } => (RmwSub, I64, 1, memarg),
+            Operator::I64AtomicRmw16USub { memarg } => (RmwSub, I64, 2, memarg),
+            Operator::I64AtomicRmw32USub { memarg } => (RmwSub, I64, 4, memarg),
+
+            Operator::I32AtomicRmwAnd { memarg } => (RmwAnd, I32, 4, memarg),
+            Operator::I64AtomicRmwAnd { memarg } => (RmwAnd, I64, 8, memarg),
+            Operator::I32AtomicRmw8UAnd { memarg } => (RmwAnd, I32, 1, memarg),
+            Operator::I32AtomicRmw16UAnd { memarg } => (RmwAnd, I32, 2, memarg),
+            Operator::I64AtomicRmw8UAnd { memarg } => (RmwAnd, I64, 1, memarg),
+            Operator::I64AtomicRmw16UAnd { memarg } => (RmwAnd, I64, 2, memarg),
+            Operator::I64AtomicRmw32UAnd { memarg } => (RmwAnd, I64, 4, memarg),
+
+            Operator::I32AtomicRmwOr { memarg } => (RmwOr, I32, 4, memarg),
+            Operator::I64AtomicRmwOr { memarg } => (RmwOr, I64, 8, memarg),
+            Operator::I32AtomicRmw8UOr { memarg } => (RmwOr, I32, 1, memarg),
+            Operator::I32AtomicRmw16UOr { memarg } => (RmwOr, I32, 2, memarg),
+            Operator::I64AtomicRmw8UOr { memarg } => (RmwOr, I64, 1, memarg),
+            Operator::I64AtomicRmw16UOr { memarg } => (RmwOr, I64, 2, memarg),
+            Operator::I64AtomicRmw32UOr { memarg } => (RmwOr, I64, 4, memarg),
+
+            Operator::I32AtomicRmwXor { memarg } => (RmwXor, I32, 4, memarg),
+            Operator::I64AtomicRmwXor { memarg } => (RmwXor, I64, 8, memarg),
+            Operator::I32AtomicRmw8UXor { memarg } => (RmwXor, I32, 1, memarg),
+            Operator::I32AtomicRmw16UXor { memarg } => (RmwXor, I32, 2, memarg),
+            Operator::I64AtomicRmw8UXor { memarg } => (RmwXor, I64, 1, memarg),
+            Operator::I64AtomicRmw16UXor { memarg } => (RmwXor, I64, 2, memarg),
+            Operator::I64AtomicRmw32UXor { memarg } => (RmwXor, I64, 4, memarg),
+
+            Operator::I32AtomicRmwXchg { memarg } => (RmwXchg, I32, 4, memarg),
+            Operator::I64AtomicRmwXchg { memarg } => (RmwXchg, I64, 8, memarg),
+            Operator::I32AtomicRmw8UXchg { memarg } => (RmwXchg, I32, 1, memarg),
+            Operator::I32AtomicRmw16UXchg { memarg } => (RmwXchg, I32, 2, memarg),
+            Operator::I64AtomicRmw8UXchg { memarg } => (RmwXchg, I64, 1, memarg),
+            Operator::I64AtomicRmw16UXchg { memarg } => (RmwXchg, I64, 2, memarg),
+            Operator::I64AtomicRmw32UXchg { memarg } => (RmwXchg, I64, 4, memarg),
+
+            Operator::I32AtomicRmwCmpxchg { memarg } => (Cmpxchg, I32, 4, memarg),
+            Operator::I64AtomicRmwCmpxchg { memarg } => (Cmpxchg, I64, 8, memarg),
+            Operator::I32AtomicRmw8UCmpxchg { memarg } => (Cmpxchg, I32, 1, memarg),
+            Operator::I32AtomicRmw16UCmpxchg { memarg } => (Cmpxchg, I32, 2, memarg),
+            Operator::I64AtomicRmw8UCmpxchg { memarg } => (Cmpxchg, I64, 1, memarg),
+            Operator::I64AtomicRmw16UCmpxchg { memarg } => (Cmpxchg, I64, 2, memarg),
+            Operator::I64AtomicRmw32UCmpxchg { memarg } => (Cmpxchg, I64, 4, memarg),
+
+            _ => return None,
+        };
+
+        Some(AtomicOp {
+            kind,
+            ty,
+            width,
+            offset: memarg.offset,
+        })
+    }
+}
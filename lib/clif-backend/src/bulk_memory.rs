@@ -0,0 +1,448 @@
+//! Support for the bulk memory proposal.
+//!
+//! The version of `wasmparser` that `cranelift-wasm` reads modules with
+//! doesn't know about passive segments, segments with an explicit memory
+//! or table index or the data count section. So before a module is
+//! translated, its passive segments are taken out of it, the remaining
+//! segments are rewritten to their MVP encoding and the data count
+//! section is removed.
+//!
+//! Like atomic instructions, the bulk memory instructions are rewritten
+//! into calls (see the `lowering` module), which `FuncEnv` lowers to calls
+//! to the matching vmcalls.
+use crate::{lowering::write_var_u32, relocation::call_names};
+use cranelift_codegen::ir::{self, types::*};
+use cranelift_wasm::{WasmError, WasmResult};
+use hashbrown::HashMap;
+use wasmer_runtime_core::{
+    structures::TypedIndex,
+    types::{DataIndex, ElemIndex, FuncIndex},
+};
+use wasmparser::{BinaryReader, BinaryReaderError, Operator};
+
+const BULK_MEMORY_PREFIX: u32 = 0xfc;
+const REF_NULL_OPCODE: u32 = 0xd0;
+const REF_FUNC_OPCODE: u32 = 0xd2;
+const END_OPCODE: u32 = 0x0b;
+
+const ELEMENT_SECTION_ID: u32 = 9;
+const CODE_SECTION_ID: u32 = 10;
+const DATA_SECTION_ID: u32 = 11;
+const DATA_COUNT_SECTION_ID: u32 = 12;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BulkMemoryOpKind {
+    MemoryInit,
+    DataDrop,
+    MemoryCopy,
+    MemoryFill,
+    TableInit,
+    ElemDrop,
+    TableCopy,
+}
+
+/// A bulk memory instruction that a function body was rewritten to call.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BulkMemoryOp {
+    MemoryInit { segment: u32 },
+    DataDrop { segment: u32 },
+    MemoryCopy,
+    MemoryFill,
+    TableInit { segment: u32, table: u32 },
+    ElemDrop { segment: u32 },
+    TableCopy { dst_table: u32, src_table: u32 },
+}
+
+impl BulkMemoryOp {
+    pub fn kind(&self) -> BulkMemoryOpKind {
+        match self {
+            BulkMemoryOp::MemoryInit { .. } => BulkMemoryOpKind::MemoryInit,
+            BulkMemoryOp::DataDrop { .. } => BulkMemoryOpKind::DataDrop,
+            BulkMemoryOp::MemoryCopy => BulkMemoryOpKind::MemoryCopy,
+            BulkMemoryOp::MemoryFill => BulkMemoryOpKind::MemoryFill,
+            BulkMemoryOp::TableInit { .. } => BulkMemoryOpKind::TableInit,
+            BulkMemoryOp::ElemDrop { .. } => BulkMemoryOpKind::ElemDrop,
+            BulkMemoryOp::TableCopy { .. } => BulkMemoryOpKind::TableCopy,
+        }
+    }
+
+    /// The operand types of the instruction, as seen by webassembly.
+    pub fn params(&self) -> Vec<ir::Type> {
+        match self {
+            BulkMemoryOp::DataDrop { .. } | BulkMemoryOp::ElemDrop { .. } => vec![],
+            _ => vec![I32, I32, I32],
+        }
+    }
+
+    /// The name of the vmcall implementing this instruction.
+    pub fn name(&self) -> ir::ExternalName {
+        let index = match self.kind() {
+            BulkMemoryOpKind::MemoryInit => call_names::MEMORY_INIT,
+            BulkMemoryOpKind::DataDrop => call_names::DATA_DROP,
+            BulkMemoryOpKind::MemoryCopy => call_names::MEMORY_COPY,
+            BulkMemoryOpKind::MemoryFill => call_names::MEMORY_FILL,
+            BulkMemoryOpKind::TableInit => call_names::TABLE_INIT,
+            BulkMemoryOpKind::ElemDrop => call_names::ELEM_DROP,
+            BulkMemoryOpKind::TableCopy => call_names::TABLE_COPY,
+        };
+        ir::ExternalName::user(call_names::BULK_MEMORY_NAMESPACE, index)
+    }
+
+    /// Reads the bulk memory instruction at the position of `reader`.
+    ///
+    /// Returns `None`, and leaves `reader` where it was, if there isn't one.
+    pub fn read(reader: &mut BinaryReader) -> Result<Option<Self>, BinaryReaderError> {
+        let mut op_reader = reader.clone();
+        if op_reader.eof() || op_reader.read_u8()? != BULK_MEMORY_PREFIX {
+            return Ok(None);
+        }
+
+        // The memory indices are always zero.
+        let op = match op_reader.read_var_u32()? {
+            0x08 => {
+                let segment = op_reader.read_var_u32()?;
+                op_reader.read_u8()?;
+                BulkMemoryOp::MemoryInit { segment }
+            }
+            0x09 => BulkMemoryOp::DataDrop {
+                segment: op_reader.read_var_u32()?,
+            },
+            0x0a => {
+                op_reader.read_u8()?;
+                op_reader.read_u8()?;
+                BulkMemoryOp::MemoryCopy
+            }
+            0x0b => {
+                op_reader.read_u8()?;
+                BulkMemoryOp::MemoryFill
+            }
+            0x0c => {
+                let segment = op_reader.read_var_u32()?;
+                let table = op_reader.read_var_u32()?;
+                BulkMemoryOp::TableInit { segment, table }
+            }
+            0x0d => BulkMemoryOp::ElemDrop {
+                segment: op_reader.read_var_u32()?,
+            },
+            0x0e => {
+                let dst_table = op_reader.read_var_u32()?;
+                let src_table = op_reader.read_var_u32()?;
+                BulkMemoryOp::TableCopy {
+                    dst_table,
+                    src_table,
+                }
+            }
+            _ => return Ok(None),
+        };
+
+        *reader = op_reader;
+        Ok(Some(op))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct LoweredSegments {
+    pub wasm: Vec<u8>,
+    /// The contents of the passive data segments, by segment index.
+    pub passive_data: HashMap<DataIndex, Vec<u8>>,
+    /// The functions in the passive element segments, by segment index.
+    pub passive_elements: HashMap<ElemIndex, Vec<Option<FuncIndex>>>,
+    /// How many bytes shorter the part of the module
+    /// before the code section got.
+    pub shrunk_by: usize,
+}
+
+/// Takes the passive segments out of `wasm`, rewrites the remaining
+/// ones to their MVP encoding and removes the data count section.
+///
+/// Returns `None` if there is nothing to change.
+pub fn lower_bulk_memory_segments(wasm: &[u8]) -> WasmResult<Option<LoweredSegments>> {
+    let to_wasm_error = |e: BinaryReaderError| WasmError::InvalidWebAssembly {
+        message: e.message,
+        offset: e.offset,
+    };
+
+    let mut reader = BinaryReader::new(wasm);
+    // Skip the magic number and the version.
+    reader.skip_bytes(8).map_err(to_wasm_error)?;
+
+    let mut lowered = wasm[..8].to_vec();
+    let mut passive_data = HashMap::new();
+    let mut passive_elements = HashMap::new();
+    let mut shrunk_by = None;
+
+    while !reader.eof() {
+        let section_start = reader.current_position();
+        let id = reader.read_u8().map_err(to_wasm_error)?;
+        let size = reader.read_var_u32().map_err(to_wasm_error)? as usize;
+        let contents_offset = reader.current_position();
+        let contents = reader.read_bytes(size).map_err(to_wasm_error)?;
+
+        if id == CODE_SECTION_ID {
+            shrunk_by = Some(section_start - lowered.len());
+        }
+
+        let contents = match id {
+            DATA_COUNT_SECTION_ID => continue,
+            ELEMENT_SECTION_ID => {
+                lower_element_section(contents, contents_offset, &mut passive_elements)?
+            }
+            DATA_SECTION_ID => lower_data_section(contents, contents_offset, &mut passive_data)
+                .map_err(to_wasm_error)?,
+            _ => contents.to_vec(),
+        };
+
+        write_var_u32(&mut lowered, id);
+        write_var_u32(&mut lowered, contents.len() as u32);
+        lowered.extend_from_slice(&contents);
+    }
+
+    if lowered == wasm {
+        return Ok(None);
+    }
+
+    Ok(Some(LoweredSegments {
+        shrunk_by: shrunk_by.unwrap_or(wasm.len() - lowered.len()),
+        wasm: lowered,
+        passive_data,
+        passive_elements,
+    }))
+}
+
+/// Reads a constant expression, like a segment's offset, up to and
+/// including its `end`, and returns its bytes.
+pub fn read_init_expr<'a>(reader: &mut BinaryReader<'a>) -> Result<&'a [u8], BinaryReaderError> {
+    let mut expr_reader = reader.clone();
+    let start = reader.current_position();
+    loop {
+        if let Operator::End = expr_reader.read_operator()? {
+            break;
+        }
+    }
+    reader.read_bytes(expr_reader.current_position() - start)
+}
+
+/// Reads a `ref.func` or `ref.null` element expression.
+fn read_element_expr(reader: &mut BinaryReader) -> Result<Option<u32>, BinaryReaderError> {
+    let offset = reader.original_position();
+    let element = match reader.read_u8()? {
+        REF_FUNC_OPCODE => Some(reader.read_var_u32()?),
+        REF_NULL_OPCODE => {
+            // Later versions of the proposal give `ref.null` a type.
+            if reader.read_u8()? != END_OPCODE {
+                reader.read_u8()?;
+            }
+            return Ok(None);
+        }
+        _ => None,
+    };
+
+    match (element, reader.read_u8()?) {
+        (Some(func_index), END_OPCODE) => Ok(Some(func_index)),
+        _ => Err(BinaryReaderError {
+            message: "invalid element expression",
+            offset,
+        }),
+    }
+}
+
+fn lower_element_section(
+    contents: &[u8],
+    offset: usize,
+    passive_elements: &mut HashMap<ElemIndex, Vec<Option<FuncIndex>>>,
+) -> WasmResult<Vec<u8>> {
+    let to_wasm_error = |e: BinaryReaderError| WasmError::InvalidWebAssembly {
+        message: e.message,
+        offset: e.offset,
+    };
+
+    let mut reader = BinaryReader::new_with_offset(contents, offset);
+    let mut active_count = 0;
+    let mut active = Vec::new();
+
+    for index in 0..reader.read_var_u32().map_err(to_wasm_error)? {
+        // Bit 0 is set for passive and declared segments, bit 1 for active
+        // segments with a table index and declared segments, and bit 2 for
+        // segments made of expressions instead of function indices.
+        let flags = reader.read_var_u32().map_err(to_wasm_error)?;
+        let is_active = flags & 0b001 == 0;
+
+        let table_index = if is_active && flags & 0b010 != 0 {
+            reader.read_var_u32().map_err(to_wasm_error)?
+        } else {
+            0
+        };
+        let init_expr = if is_active {
+            Some(read_init_expr(&mut reader).map_err(to_wasm_error)?)
+        } else {
+            None
+        };
+        // The element kind, or type, which is always `anyfunc`.
+        if flags & 0b011 != 0 {
+            reader.read_u8().map_err(to_wasm_error)?;
+        }
+
+        let mut elements = Vec::new();
+        for _ in 0..reader.read_var_u32().map_err(to_wasm_error)? {
+            elements.push(if flags & 0b100 == 0 {
+                Some(reader.read_var_u32().map_err(to_wasm_error)?)
+            } else {
+                read_element_expr(&mut reader).map_err(to_wasm_error)?
+            });
+        }
+
+        match init_expr {
+            Some(init_expr) => {
+                // In the MVP encoding, a segment starts with its table index.
+                active_count += 1;
+                write_var_u32(&mut active, table_index);
+                active.extend_from_slice(init_expr);
+                write_var_u32(&mut active, elements.len() as u32);
+                for element in elements {
+                    match element {
+                        Some(func_index) => write_var_u32(&mut active, func_index),
+                        None => {
+                            return Err(WasmError::Unsupported(
+                                "null references in active element segments",
+                            ));
+                        }
+                    }
+                }
+            }
+            None if flags & 0b010 == 0 => {
+                passive_elements.insert(
+                    ElemIndex::new(index as usize),
+                    elements
+                        .into_iter()
+                        .map(|element| element.map(|index| FuncIndex::new(index as usize)))
+                        .collect(),
+                );
+            }
+            // Declared segments only declare which functions `ref.func` can refer to.
+            None => {}
+        }
+    }
+
+    let mut lowered = Vec::new();
+    write_var_u32(&mut lowered, active_count);
+    lowered.extend_from_slice(&active);
+    Ok(lowered)
+}
+
+fn lower_data_section(
+    contents: &[u8],
+    offset: usize,
+    passive_data: &mut HashMap<DataIndex, Vec<u8>>,
+) -> Result<Vec<u8>, BinaryReaderError> {
+    let mut reader = BinaryReader::new_with_offset(contents, offset);
+    let mut active_count = 0;
+    let mut active = Vec::new();
+
+    for index in 0..reader.read_var_u32()? {
+        // 0 is an active segment for the first memory, 1 a passive
+        // segment and 2 an active segment with a memory index.
+        let flags = reader.read_var_u32()?;
+        if flags == 2 {
+            // The memory index, which is always zero.
+            reader.read_var_u32()?;
+        }
+        let init_expr = if flags != 1 {
+            Some(read_init_expr(&mut reader)?)
+        } else {
+            None
+        };
+
+        let len = reader.read_var_u32()? as usize;
+        let data = reader.read_bytes(len)?;
+
+        match init_expr {
+            Some(init_expr) => {
+                active_count += 1;
+                write_var_u32(&mut active, 0);
+                active.extend_from_slice(init_expr);
+                write_var_u32(&mut active, len as u32);
+                active.extend_from_slice(data);
+            }
+            None => {
+                passive_data.insert(DataIndex::new(index as usize), data.to_vec());
+            }
+        }
+    }
+
+    let mut lowered = Vec::new();
+    write_var_u32(&mut lowered, active_count);
+    lowered.extend_from_slice(&active);
+    Ok(lowered)
+}
+
+#[cfg(test)]
+mod bulk_memory_tests {
+    use super::{lower_bulk_memory_segments, BulkMemoryOp};
+    use wasmer_runtime_core::{
+        structures::TypedIndex,
+        types::{DataIndex, ElemIndex, FuncIndex},
+    };
+    use wasmparser::BinaryReader;
+
+    #[test]
+    fn test_lower_bulk_memory_segments() {
+        #[rustfmt::skip]
+        let wasm = [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+            0x09, 0x0d,                   // element section
+            0x02,                         // two segments
+            0x01, 0x00, 0x01, 0x00,       // passive: [0]
+            0x02, 0x00, 0x41, 0x01, 0x0b, // active, table 0, offset 1:
+            0x00, 0x01, 0x00,             //   [0]
+            0x0c, 0x01, 0x02,             // data count section: two segments
+            0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b, // code section
+            0x0b, 0x0b,                   // data section
+            0x02,                         // two segments
+            0x00, 0x41, 0x00, 0x0b,       // active, offset 0:
+            0x01, 0x61,                   //   "a"
+            0x01, 0x02, 0x62, 0x63,       // passive: "bc"
+        ];
+
+        let lowered = lower_bulk_memory_segments(&wasm).unwrap().unwrap();
+
+        #[rustfmt::skip]
+        assert_eq!(
+            lowered.wasm,
+            [
+                0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+                0x09, 0x07,
+                0x01,
+                0x00, 0x41, 0x01, 0x0b, 0x01, 0x00,
+                0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b,
+                0x0b, 0x07,
+                0x01,
+                0x00, 0x41, 0x00, 0x0b, 0x01, 0x61,
+            ]
+        );
+        assert_eq!(
+            lowered.passive_elements[&ElemIndex::new(0)],
+            [Some(FuncIndex::new(0))]
+        );
+        assert_eq!(lowered.passive_data[&DataIndex::new(1)], b"bc");
+        assert_eq!(lowered.shrunk_by, 9);
+
+        assert_eq!(lower_bulk_memory_segments(&wasm[..8]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_bulk_memory_op() {
+        // table.init 3 0, then i32.const 0.
+        let code = [0xfc, 0x0c, 0x03, 0x00, 0x41, 0x00];
+        let mut reader = BinaryReader::new(&code);
+
+        assert_eq!(
+            BulkMemoryOp::read(&mut reader).unwrap(),
+            Some(BulkMemoryOp::TableInit {
+                segment: 3,
+                table: 0
+            })
+        );
+        assert_eq!(BulkMemoryOp::read(&mut reader).unwrap(), None);
+        assert_eq!(reader.current_position(), 4);
+    }
+}
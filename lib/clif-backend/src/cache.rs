@@ -50,6 +50,7 @@ pub struct TrampolineCache {
     #[serde(with = "serde_bytes")]
     pub code: Vec<u8>,
     pub offsets: HashMap<SigIndex, usize>,
+    pub dynamic_offsets: HashMap<SigIndex, usize>,
 }
 
 #[derive(Serialize, Deserialize)]
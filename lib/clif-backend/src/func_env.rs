@@ -1,10 +1,21 @@
-use crate::{module::Converter, module_env::ModuleEnv, relocation::call_names};
+use crate::{
+    atomics::{AtomicOp, AtomicOpKind},
+    bulk_memory::BulkMemoryOp,
+    lowering::LoweredOp,
+    module::Converter,
+    module_env::ModuleEnv,
+    reference_types::ReferenceOp,
+    relocation::{
+        call_names, INTERRUPTED_TRAP_CODE, OUT_OF_FUEL_TRAP_CODE, UNALIGNED_ATOMIC_TRAP_CODE,
+    },
+    simd::{SimdOp, SimdOpKind},
+};
 use cranelift_codegen::{
-    cursor::FuncCursor,
-    ir::{self, InstBuilder},
+    cursor::{Cursor, FuncCursor},
+    ir::{self, condcodes::IntCC, InstBuilder},
     isa,
 };
-use cranelift_entity::EntityRef;
+use cranelift_entity::{EntityRef, EntitySet};
 use cranelift_wasm::{self, FuncEnvironment, ModuleEnvironment};
 use std::mem;
 use wasmer_runtime_core::{
@@ -16,11 +27,588 @@ use wasmer_runtime_core::{
 
 pub struct FuncEnv<'env, 'module, 'isa> {
     env: &'env ModuleEnv<'module, 'isa>,
+    /// The instructions that the function body was rewritten
+    /// to call. See the `lowering` module.
+    lowered_ops: &'env [LoweredOp],
 }
 
 impl<'env, 'module, 'isa> FuncEnv<'env, 'module, 'isa> {
-    pub fn new(env: &'env ModuleEnv<'module, 'isa>) -> Self {
-        Self { env }
+    pub fn new(env: &'env ModuleEnv<'module, 'isa>, lowered_ops: &'env [LoweredOp]) -> Self {
+        Self { env, lowered_ops }
+    }
+
+    /// Returns the instruction that `func_index` stands for,
+    /// if it is past the end of the module's function index space.
+    fn lowered_op(&self, func_index: cranelift_wasm::FuncIndex) -> Option<LoweredOp> {
+        func_index
+            .index()
+            .checked_sub(self.env.module.info.func_assoc.len())
+            .map(|index| self.lowered_ops[index])
+    }
+
+    /// Loads the base and the bound of the first memory, the only
+    /// one that atomic and bulk memory instructions can access.
+    fn memory_base_and_bound(
+        &self,
+        pos: &mut FuncCursor,
+        vmctx: ir::Value,
+    ) -> (ir::Value, ir::Value) {
+        let ptr_type = self.pointer_type();
+        let mflags = ir::MemFlags::trusted();
+
+        let (memories_offset, memory_index) =
+            match MemoryIndex::new(0).local_or_import(&self.env.module.info) {
+                LocalOrImport::Local(local_mem_index) => {
+                    (vm::Ctx::offset_memories(), local_mem_index.index())
+                }
+                LocalOrImport::Import(import_mem_index) => (
+                    vm::Ctx::offset_imported_memories(),
+                    import_mem_index.index(),
+                ),
+            };
+
+        let memories = pos
+            .ins()
+            .load(ptr_type, mflags, vmctx, memories_offset as i32);
+        let local_memory = pos.ins().load(
+            ptr_type,
+            mflags,
+            memories,
+            (memory_index * mem::size_of::<*mut vm::LocalMemory>()) as i32,
+        );
+        let base = pos.ins().load(
+            ptr_type,
+            mflags,
+            local_memory,
+            vm::LocalMemory::offset_base() as i32,
+        );
+        let bound = pos.ins().load(
+            ptr_type,
+            mflags,
+            local_memory,
+            vm::LocalMemory::offset_bound() as i32,
+        );
+        (base, bound)
+    }
+
+    /// Generates code for an atomic instruction.
+    ///
+    /// The access is bounds and alignment checked here, so the
+    /// vmcall only ever sees valid, aligned host addresses.
+    fn translate_atomic(
+        &self,
+        mut pos: FuncCursor,
+        atomic_op: AtomicOp,
+        args: &[ir::Value],
+    ) -> ir::Inst {
+        let ptr_type = self.pointer_type();
+
+        let vmctx = pos
+            .func
+            .special_param(ir::ArgumentPurpose::VMContext)
+            .expect("missing vmctx parameter");
+
+        let (base, bound) = self.memory_base_and_bound(&mut pos, vmctx);
+
+        let addr = pos.ins().uextend(ptr_type, args[0]);
+        let addr = pos.ins().iadd_imm(addr, i64::from(atomic_op.offset));
+
+        let end = pos.ins().iadd_imm(addr, i64::from(atomic_op.width));
+        let out_of_bounds = pos.ins().icmp(IntCC::UnsignedGreaterThan, end, bound);
+        pos.ins()
+            .trapnz(out_of_bounds, ir::TrapCode::HeapOutOfBounds);
+
+        if atomic_op.width > 1 {
+            let misaligned = pos.ins().band_imm(addr, i64::from(atomic_op.width - 1));
+            pos.ins()
+                .trapnz(misaligned, ir::TrapCode::User(UNALIGNED_ATOMIC_TRAP_CODE));
+        }
+
+        let host_addr = pos.ins().iadd(base, addr);
+
+        let mut call_args = vec![host_addr];
+        if atomic_op.kind != AtomicOpKind::Notify {
+            let width = pos.ins().iconst(ir::types::I32, i64::from(atomic_op.width));
+            call_args.push(width);
+        }
+        for (i, &arg) in args[1..].iter().enumerate() {
+            // Values of the accessed type are passed zero-extended to 64 bits.
+            let is_value = match atomic_op.kind {
+                AtomicOpKind::Wait => i == 0,
+                AtomicOpKind::Notify => false,
+                _ => true,
+            };
+            if is_value && atomic_op.ty == ir::types::I32 {
+                call_args.push(pos.ins().uextend(ir::types::I64, arg));
+            } else {
+                call_args.push(arg);
+            }
+        }
+
+        let returns = match atomic_op.kind {
+            AtomicOpKind::Store => vec![],
+            AtomicOpKind::Wait | AtomicOpKind::Notify => vec![ir::AbiParam::new(ir::types::I32)],
+            _ => vec![ir::AbiParam::new(ir::types::I64)],
+        };
+
+        let signature = pos.func.import_signature(ir::Signature {
+            call_conv: self.target_config().default_call_conv,
+            params: call_args
+                .iter()
+                .map(|&arg| ir::AbiParam::new(pos.func.dfg.value_type(arg)))
+                .collect(),
+            returns,
+        });
+
+        let atomic_func = pos.func.import_function(ir::ExtFuncData {
+            name: atomic_op.name(),
+            signature,
+            colocated: false,
+        });
+
+        let call_inst = pos.ins().call(atomic_func, &call_args);
+
+        // Narrow the result back down for `i32` instructions.
+        match pos.func.dfg.inst_results(call_inst).first() {
+            Some(&result) if pos.func.dfg.value_type(result) != atomic_op.returns()[0] => {
+                let result = pos.ins().ireduce(ir::types::I32, result);
+                pos.func.dfg.value_def(result).unwrap_inst()
+            }
+            _ => call_inst,
+        }
+    }
+
+    /// Generates code for a bulk memory instruction.
+    ///
+    /// The vmcalls that can go out of bounds return
+    /// nonzero when they do, and that is turned into a trap.
+    fn translate_bulk_memory(
+        &self,
+        mut pos: FuncCursor,
+        bulk_memory_op: BulkMemoryOp,
+        args: &[ir::Value],
+    ) -> ir::Inst {
+        let vmctx = pos
+            .func
+            .special_param(ir::ArgumentPurpose::VMContext)
+            .expect("missing vmctx parameter");
+
+        let (mut call_args, trap_code) = match bulk_memory_op {
+            BulkMemoryOp::MemoryCopy | BulkMemoryOp::MemoryFill => {
+                let (base, bound) = self.memory_base_and_bound(&mut pos, vmctx);
+                (vec![base, bound], Some(ir::TrapCode::HeapOutOfBounds))
+            }
+            BulkMemoryOp::MemoryInit { segment } => {
+                let memory_index = pos.ins().iconst(ir::types::I32, 0);
+                let segment = pos.ins().iconst(ir::types::I32, i64::from(segment));
+                (
+                    vec![vmctx, memory_index, segment],
+                    Some(ir::TrapCode::HeapOutOfBounds),
+                )
+            }
+            BulkMemoryOp::TableInit { segment, table } => {
+                let table_index = pos.ins().iconst(ir::types::I32, i64::from(table));
+                let segment = pos.ins().iconst(ir::types::I32, i64::from(segment));
+                (
+                    vec![vmctx, table_index, segment],
+                    Some(ir::TrapCode::TableOutOfBounds),
+                )
+            }
+            BulkMemoryOp::TableCopy {
+                dst_table,
+                src_table,
+            } => {
+                let dst_table_index = pos.ins().iconst(ir::types::I32, i64::from(dst_table));
+                let src_table_index = pos.ins().iconst(ir::types::I32, i64::from(src_table));
+                (
+                    vec![vmctx, dst_table_index, src_table_index],
+                    Some(ir::TrapCode::TableOutOfBounds),
+                )
+            }
+            BulkMemoryOp::DataDrop { segment } | BulkMemoryOp::ElemDrop { segment } => {
+                let segment = pos.ins().iconst(ir::types::I32, i64::from(segment));
+                (vec![vmctx, segment], None)
+            }
+        };
+        call_args.extend_from_slice(args);
+
+        let returns = match trap_code {
+            Some(_) => vec![ir::AbiParam::new(ir::types::I32)],
+            None => vec![],
+        };
+
+        let signature = pos.func.import_signature(ir::Signature {
+            call_conv: self.target_config().default_call_conv,
+            params: call_args
+                .iter()
+                .map(|&arg| ir::AbiParam::new(pos.func.dfg.value_type(arg)))
+                .collect(),
+            returns,
+        });
+
+        let bulk_memory_func = pos.func.import_function(ir::ExtFuncData {
+            name: bulk_memory_op.name(),
+            signature,
+            colocated: false,
+        });
+
+        let call_inst = pos.ins().call(bulk_memory_func, &call_args);
+
+        match trap_code {
+            Some(trap_code) => {
+                let out_of_bounds = pos.func.dfg.first_result(call_inst);
+                pos.ins().trapnz(out_of_bounds, trap_code)
+            }
+            None => call_inst,
+        }
+    }
+
+    /// Loads the number of elements in the table at `table_index`.
+    fn table_count(&self, pos: &mut FuncCursor, vmctx: ir::Value, table_index: u32) -> ir::Value {
+        let ptr_type = self.pointer_type();
+        let mflags = ir::MemFlags::trusted();
+
+        let (tables_offset, table_index) =
+            match TableIndex::new(table_index as usize).local_or_import(&self.env.module.info) {
+                LocalOrImport::Local(local_table_index) => {
+                    (vm::Ctx::offset_tables(), local_table_index.index())
+                }
+                LocalOrImport::Import(import_table_index) => (
+                    vm::Ctx::offset_imported_tables(),
+                    import_table_index.index(),
+                ),
+            };
+
+        let tables = pos
+            .ins()
+            .load(ptr_type, mflags, vmctx, tables_offset as i32);
+        let local_table = pos.ins().load(
+            ptr_type,
+            mflags,
+            tables,
+            (table_index * mem::size_of::<*mut vm::LocalTable>()) as i32,
+        );
+        pos.ins().load(
+            ptr_type,
+            mflags,
+            local_table,
+            vm::LocalTable::offset_count() as i32,
+        )
+    }
+
+    /// Generates code for a reference types instruction.
+    ///
+    /// `table.get` and `table.set` are bounds checked here, and
+    /// `table.fill` traps when its vmcall returns nonzero.
+    fn translate_reference(
+        &self,
+        mut pos: FuncCursor,
+        reference_op: ReferenceOp,
+        args: &[ir::Value],
+    ) -> ir::Inst {
+        let vmctx = pos
+            .func
+            .special_param(ir::ArgumentPurpose::VMContext)
+            .expect("missing vmctx parameter");
+
+        if let ReferenceOp::TableGet { table } | ReferenceOp::TableSet { table } = reference_op {
+            let count = self.table_count(&mut pos, vmctx, table);
+            let index = pos.ins().uextend(self.pointer_type(), args[0]);
+            let out_of_bounds = pos
+                .ins()
+                .icmp(IntCC::UnsignedGreaterThanOrEqual, index, count);
+            pos.ins()
+                .trapnz(out_of_bounds, ir::TrapCode::TableOutOfBounds);
+        }
+
+        let index = pos
+            .ins()
+            .iconst(ir::types::I32, i64::from(reference_op.index()));
+        let mut call_args = vec![vmctx, index];
+        call_args.extend_from_slice(args);
+
+        let returns = match reference_op {
+            ReferenceOp::TableFill { .. } => vec![ir::types::I32],
+            _ => reference_op.returns(),
+        };
+
+        let signature = pos.func.import_signature(ir::Signature {
+            call_conv: self.target_config().default_call_conv,
+            params: call_args
+                .iter()
+                .map(|&arg| ir::AbiParam::new(pos.func.dfg.value_type(arg)))
+                .collect(),
+            returns: returns.into_iter().map(ir::AbiParam::new).collect(),
+        });
+
+        let reference_func = pos.func.import_function(ir::ExtFuncData {
+            name: reference_op.name(),
+            signature,
+            colocated: false,
+        });
+
+        let call_inst = pos.ins().call(reference_func, &call_args);
+
+        match reference_op {
+            ReferenceOp::TableFill { .. } => {
+                let out_of_bounds = pos.func.dfg.first_result(call_inst);
+                pos.ins()
+                    .trapnz(out_of_bounds, ir::TrapCode::TableOutOfBounds)
+            }
+            _ => call_inst,
+        }
+    }
+
+    /// Generates code for a SIMD instruction.
+    ///
+    /// The vmcalls take the opcode (and lane) of the instruction first,
+    /// and scalars as their bits in an `i64`. `v128` results come back
+    /// through a return buffer, which `lower_multi_value_returns` sets up.
+    fn translate_simd(&self, mut pos: FuncCursor, simd_op: SimdOp, args: &[ir::Value]) -> ir::Inst {
+        use cranelift_codegen::ir::types::{F32, F64, I32, I64};
+
+        let opcode = pos.ins().iconst(I32, i64::from(simd_op.opcode));
+        let mut call_args = vec![opcode];
+        if let SimdOpKind::ExtractLane | SimdOpKind::ReplaceLane = simd_op.kind() {
+            call_args.push(pos.ins().iconst(I32, i64::from(simd_op.lane)));
+        }
+        for &arg in args {
+            let arg = match pos.func.dfg.value_type(arg) {
+                I32 => pos.ins().uextend(I64, arg),
+                F32 => {
+                    let bits = pos.ins().bitcast(I32, arg);
+                    pos.ins().uextend(I64, bits)
+                }
+                F64 => pos.ins().bitcast(I64, arg),
+                _ => arg,
+            };
+            call_args.push(arg);
+        }
+
+        let returns = match simd_op.kind() {
+            SimdOpKind::ExtractLane => vec![I64],
+            _ => vec![I64, I64],
+        };
+
+        let signature = pos.func.import_signature(ir::Signature {
+            call_conv: self.target_config().default_call_conv,
+            params: call_args
+                .iter()
+                .map(|&arg| ir::AbiParam::new(pos.func.dfg.value_type(arg)))
+                .collect(),
+            returns: returns.into_iter().map(ir::AbiParam::new).collect(),
+        });
+
+        let simd_func = pos.func.import_function(ir::ExtFuncData {
+            name: simd_op.name(),
+            signature,
+            colocated: false,
+        });
+
+        let call_inst = pos.ins().call(simd_func, &call_args);
+        if simd_op.kind() != SimdOpKind::ExtractLane {
+            return call_inst;
+        }
+
+        let bits = pos.func.dfg.first_result(call_inst);
+        let result = match simd_op.returns()[0] {
+            I32 => pos.ins().ireduce(I32, bits),
+            F32 => {
+                let bits = pos.ins().ireduce(I32, bits);
+                pos.ins().bitcast(F32, bits)
+            }
+            F64 => pos.ins().bitcast(F64, bits),
+            _ => return call_inst,
+        };
+        pos.func.dfg.value_def(result).unwrap_inst()
+    }
+
+    /// Checks for an interrupt on entry to `func` and at the top of
+    /// every loop in it, so that no code can run forever unnoticed.
+    ///
+    /// Seeing an interrupt clears it and traps.
+    pub fn insert_interrupt_checks(&self, func: &mut ir::Function) {
+        let ptr_type = self.pointer_type();
+        let mflags = ir::MemFlags::trusted();
+
+        let vmctx = func
+            .special_param(ir::ArgumentPurpose::VMContext)
+            .expect("missing vmctx parameter");
+
+        // Loops are the targets of branches back to an
+        // earlier (or the same) block in the layout.
+        let mut checked_ebbs = vec![func.layout.entry_block().expect("missing entry block")];
+        let mut seen_ebbs = EntitySet::new();
+        for ebb in func.layout.ebbs() {
+            seen_ebbs.insert(ebb);
+            for inst in func.layout.ebb_insts(ebb) {
+                let dests = match func.dfg.analyze_branch(inst) {
+                    ir::instructions::BranchInfo::NotABranch => vec![],
+                    ir::instructions::BranchInfo::SingleDest(dest, _) => vec![dest],
+                    ir::instructions::BranchInfo::Table(jump_table, default) => func.jump_tables
+                        [jump_table]
+                        .iter()
+                        .cloned()
+                        .chain(default)
+                        .collect(),
+                };
+                for dest in dests {
+                    if seen_ebbs.contains(dest) && !checked_ebbs.contains(&dest) {
+                        checked_ebbs.push(dest);
+                    }
+                }
+            }
+        }
+
+        let trap_ebb = func.dfg.make_ebb();
+        func.layout.append_ebb(trap_ebb);
+
+        // The flag is an `AtomicBool`, so it's accessed as a single byte.
+
+        for ebb in checked_ebbs {
+            let mut pos = FuncCursor::new(func).at_first_insertion_point(ebb);
+            let interrupted_ptr = pos.ins().load(
+                ptr_type,
+                mflags,
+                vmctx,
+                vm::Ctx::offset_interrupted() as i32,
+            );
+            let interrupted = pos.ins().uload8(ir::types::I32, mflags, interrupted_ptr, 0);
+            pos.ins().brnz(interrupted, trap_ebb, &[]);
+        }
+
+        let mut pos = FuncCursor::new(func).at_bottom(trap_ebb);
+        let interrupted_ptr = pos.ins().load(
+            ptr_type,
+            mflags,
+            vmctx,
+            vm::Ctx::offset_interrupted() as i32,
+        );
+        let not_interrupted = pos.ins().iconst(ir::types::I32, 0);
+        pos.ins()
+            .istore8(mflags, not_interrupted, interrupted_ptr, 0);
+        pos.ins().trap(ir::TrapCode::User(INTERRUPTED_TRAP_CODE));
+    }
+
+    /// Charges fuel at the start of every basic block in `func`.
+    ///
+    /// Each block costs the number of instructions in it. When there
+    /// isn't enough fuel left for a block, it traps before running it.
+    pub fn insert_fuel_checks(&self, func: &mut ir::Function) {
+        let vmctx = func
+            .special_param(ir::ArgumentPurpose::VMContext)
+            .expect("missing vmctx parameter");
+
+        let ebbs: Vec<_> = func.layout.ebbs().collect();
+        for ebb in ebbs {
+            let cost = func.layout.ebb_insts(ebb).count().max(1) as i64;
+
+            let mut pos = FuncCursor::new(func).at_first_insertion_point(ebb);
+            let mflags = ir::MemFlags::trusted();
+
+            let fuel = pos
+                .ins()
+                .load(ir::types::I64, mflags, vmctx, vm::Ctx::offset_fuel() as i32);
+            let out_of_fuel = pos.ins().icmp_imm(IntCC::UnsignedLessThan, fuel, cost);
+            pos.ins()
+                .trapnz(out_of_fuel, ir::TrapCode::User(OUT_OF_FUEL_TRAP_CODE));
+            let fuel = pos.ins().iadd_imm(fuel, -cost);
+            pos.ins()
+                .store(mflags, fuel, vmctx, vm::Ctx::offset_fuel() as i32);
+        }
+    }
+
+    /// Lowers the functions that `func` has, calls or is that return
+    /// more than one value.
+    ///
+    /// Instead of returning the values, they take a pointer to a buffer for
+    /// them as their last argument, with a `u64` for each. That's what host
+    /// functions that return tuples expect, and there are only a few
+    /// registers to return values in.
+    pub fn lower_multi_value_returns(&self, func: &mut ir::Function) {
+        let ptr_type = self.pointer_type();
+        let mflags = ir::MemFlags::trusted();
+
+        let lowered_sigs: Vec<_> = func
+            .dfg
+            .signatures
+            .iter_mut()
+            .filter_map(|(sig_ref, sig)| {
+                lower_multi_value_signature(sig, ptr_type).map(|returns| (sig_ref, returns))
+            })
+            .collect();
+
+        let returns_ptr = lower_multi_value_signature(&mut func.signature, ptr_type).map(|_| {
+            let entry_ebb = func.layout.entry_block().expect("missing entry block");
+            func.dfg.append_ebb_param(entry_ebb, ptr_type)
+        });
+
+        // Calls return into a stack slot, and the values
+        // are loaded from it right after each call.
+        let returns_slot = lowered_sigs
+            .iter()
+            .map(|(_, returns)| returns.len())
+            .max()
+            .map(|len| {
+                func.create_stack_slot(ir::StackSlotData::new(
+                    ir::StackSlotKind::ExplicitSlot,
+                    (len * mem::size_of::<u64>()) as u32,
+                ))
+            });
+
+        if lowered_sigs.is_empty() && returns_ptr.is_none() {
+            return;
+        }
+
+        let insts: Vec<_> = func
+            .layout
+            .ebbs()
+            .flat_map(|ebb| func.layout.ebb_insts(ebb))
+            .collect();
+
+        let mut pos = FuncCursor::new(func);
+        for inst in insts {
+            pos.goto_inst(inst);
+
+            if pos.func.dfg[inst].opcode() == ir::Opcode::Return {
+                if let Some(returns_ptr) = returns_ptr {
+                    let values = pos.func.dfg.inst_args(inst).to_vec();
+                    for (index, value) in values.into_iter().enumerate() {
+                        pos.ins().store(
+                            mflags,
+                            value,
+                            returns_ptr,
+                            (index * mem::size_of::<u64>()) as i32,
+                        );
+                    }
+                    pos.func.dfg.replace(inst).return_(&[]);
+                }
+                continue;
+            }
+
+            let returns = match pos.func.dfg.call_signature(inst) {
+                Some(sig_ref) => match lowered_sigs.iter().find(|(lowered, _)| *lowered == sig_ref)
+                {
+                    Some((_, returns)) => returns,
+                    None => continue,
+                },
+                None => continue,
+            };
+            let returns_slot = returns_slot.expect("missing returns slot");
+
+            let returns_addr = pos.ins().stack_addr(ptr_type, returns_slot, 0);
+            pos.func.dfg.append_inst_arg(inst, returns_addr);
+
+            let results = pos.func.dfg.inst_results(inst).to_vec();
+            pos.func.dfg.clear_results(inst);
+            pos.goto_after_inst(inst);
+            for (index, (result, ty)) in results.into_iter().zip(returns).enumerate() {
+                let value =
+                    pos.ins()
+                        .stack_load(*ty, returns_slot, (index * mem::size_of::<u64>()) as i32);
+                pos.func.dfg.change_to_alias(result, value);
+            }
+        }
     }
 
     /// Creates a signature with VMContext as the last param
@@ -266,7 +854,7 @@ impl<'env, 'module, 'isa> FuncEnvironment for FuncEnv<'env, 'module, 'isa> {
                 });
 
                 let table_struct_ptr_offset =
-                    local_table_index.index() * vm::LocalTable::size() as usize;
+                    local_table_index.index() * mem::size_of::<*mut vm::LocalTable>();
 
                 let table_struct_ptr_ptr = func.create_global_value(ir::GlobalValueData::IAddImm {
                     base: tables_base,
@@ -288,7 +876,7 @@ impl<'env, 'module, 'isa> FuncEnvironment for FuncEnv<'env, 'module, 'isa> {
                 });
 
                 let table_struct_ptr_offset =
-                    import_table_index.index() * vm::LocalTable::size() as usize;
+                    import_table_index.index() * mem::size_of::<*mut vm::LocalTable>();
 
                 let table_struct_ptr_ptr = func.create_global_value(ir::GlobalValueData::IAddImm {
                     base: tables_base,
@@ -357,6 +945,30 @@ impl<'env, 'module, 'isa> FuncEnvironment for FuncEnv<'env, 'module, 'isa> {
         func: &mut ir::Function,
         func_index: cranelift_wasm::FuncIndex,
     ) -> ir::FuncRef {
+        // Lowered instructions are only given a signature matching their
+        // operands here. The actual call is made by `translate_call`.
+        if let Some(lowered_op) = self.lowered_op(func_index) {
+            let signature = func.import_signature(ir::Signature {
+                call_conv: self.target_config().default_call_conv,
+                params: lowered_op
+                    .params(&self.env.signatures)
+                    .into_iter()
+                    .map(ir::AbiParam::new)
+                    .collect(),
+                returns: lowered_op
+                    .returns(&self.env.signatures)
+                    .into_iter()
+                    .map(ir::AbiParam::new)
+                    .collect(),
+            });
+
+            return func.import_function(ir::ExtFuncData {
+                name: ir::ExternalName::user(0, func_index.as_u32()),
+                signature,
+                colocated: false,
+            });
+        }
+
         // Get signature of function.
         let signature_index = self.env.get_func_type(func_index);
 
@@ -476,6 +1088,38 @@ impl<'env, 'module, 'isa> FuncEnvironment for FuncEnv<'env, 'module, 'isa> {
         callee: ir::FuncRef,
         call_args: &[ir::Value],
     ) -> cranelift_wasm::WasmResult<ir::Inst> {
+        match self.lowered_op(clif_callee_index) {
+            Some(LoweredOp::Atomic(atomic_op)) => {
+                return Ok(self.translate_atomic(pos, atomic_op, call_args));
+            }
+            Some(LoweredOp::BulkMemory(bulk_memory_op)) => {
+                return Ok(self.translate_bulk_memory(pos, bulk_memory_op, call_args));
+            }
+            Some(LoweredOp::Reference(reference_op)) => {
+                return Ok(self.translate_reference(pos, reference_op, call_args));
+            }
+            Some(LoweredOp::Simd(simd_op)) => {
+                return Ok(self.translate_simd(pos, simd_op, call_args));
+            }
+            Some(LoweredOp::CallIndirect { sig, table }) => {
+                let clif_sig_index = Converter(sig).into();
+                let clif_table_index = cranelift_wasm::TableIndex::new(table as usize);
+                let table = self.make_table(pos.func, clif_table_index);
+                let sig_ref = self.make_indirect_sig(pos.func, clif_sig_index);
+                let (&callee, call_args) = call_args.split_last().expect("missing callee");
+                return self.translate_call_indirect(
+                    pos,
+                    clif_table_index,
+                    table,
+                    clif_sig_index,
+                    sig_ref,
+                    callee,
+                    call_args,
+                );
+            }
+            None => {}
+        }
+
         let callee_index: FuncIndex = Converter(clif_callee_index).into();
 
         match callee_index.local_or_import(&self.env.module.info) {
@@ -673,3 +1317,20 @@ impl<'env, 'module, 'isa> FuncEnvironment for FuncEnv<'env, 'module, 'isa> {
         Ok(*pos.func.dfg.inst_results(call_inst).first().unwrap())
     }
 }
+
+/// Replaces the return values of `sig`, if it has more than one,
+/// with a pointer to a buffer for them as its last parameter.
+///
+/// Returns the types of the values that it returned.
+pub fn lower_multi_value_signature(
+    sig: &mut ir::Signature,
+    ptr_type: ir::Type,
+) -> Option<Vec<ir::Type>> {
+    if sig.returns.len() <= 1 {
+        return None;
+    }
+
+    let returns = sig.returns.drain(..).map(|ret| ret.value_type).collect();
+    sig.params.push(ir::AbiParam::new(ptr_type));
+    Some(returns)
+}
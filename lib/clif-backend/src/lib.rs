@@ -1,11 +1,17 @@
+mod atomics;
+mod bulk_memory;
 mod cache;
 mod func_env;
 mod libcalls;
+mod lowering;
 mod module;
 mod module_env;
+mod multi_value;
+mod reference_types;
 mod relocation;
 mod resolver;
 mod signal;
+mod simd;
 mod trampoline;
 
 use cranelift_codegen::{
@@ -16,19 +22,19 @@ use target_lexicon::Triple;
 
 use wasmer_runtime_core::cache::{Artifact, Error as CacheError};
 use wasmer_runtime_core::{
-    backend::{Compiler, Token},
+    backend::{Compiler, CompilerConfig, Features, Token},
     error::{CompileError, CompileResult},
     module::ModuleInner,
 };
 
+#[macro_use]
+extern crate lazy_static;
 #[macro_use]
 extern crate serde_derive;
 
 extern crate rayon;
 extern crate serde;
 
-use wasmparser::{self, WasmDecoder};
-
 pub struct CraneliftCompiler {}
 
 impl CraneliftCompiler {
@@ -39,13 +45,19 @@ impl CraneliftCompiler {
 
 impl Compiler for CraneliftCompiler {
     /// Compiles wasm binary to a wasmer module.
-    fn compile(&self, wasm: &[u8], _: Token) -> CompileResult<ModuleInner> {
-        validate(wasm)?;
+    fn compile(
+        &self,
+        wasm: &[u8],
+        compiler_config: CompilerConfig,
+        _: Token,
+    ) -> CompileResult<ModuleInner> {
+        validate(wasm, compiler_config.features)?;
 
         let isa = get_isa();
 
         let mut module = module::Module::new(wasm);
-        let module_env = module_env::ModuleEnv::new(&mut module, &*isa);
+        module.info.features = compiler_config.features;
+        let module_env = module_env::ModuleEnv::new(&mut module, &*isa, compiler_config);
 
         let func_bodies = module_env.translate(wasm)?;
 
@@ -94,6 +106,9 @@ fn get_isa() -> Box<isa::TargetIsa> {
     let flags = {
         let mut builder = settings::builder();
         builder.set("opt_level", "best").unwrap();
+        // Check for overflow explicitly, so `i32.div_s` of `INT_MIN` by -1
+        // isn't reported as a division by zero like the native trap would be.
+        builder.set("avoid_div_traps", "true").unwrap();
 
         if cfg!(not(test)) {
             builder.set("enable_verifier", "false").unwrap();
@@ -106,18 +121,9 @@ fn get_isa() -> Box<isa::TargetIsa> {
     isa::lookup(Triple::host()).unwrap().finish(flags)
 }
 
-fn validate(bytes: &[u8]) -> CompileResult<()> {
-    let mut parser = wasmparser::ValidatingParser::new(bytes, None);
-    loop {
-        let state = parser.read();
-        match *state {
-            wasmparser::ParserState::EndWasm => break Ok(()),
-            wasmparser::ParserState::Error(err) => Err(CompileError::ValidationError {
-                msg: err.message.to_string(),
-            })?,
-            _ => {}
-        }
-    }
+fn validate(bytes: &[u8], features: Features) -> CompileResult<()> {
+    wasmer_runtime_core::validate_and_report_errors_with_features(bytes, features)
+        .map_err(|msg| CompileError::ValidationError { msg })
 }
 
 /// The current version of this crate
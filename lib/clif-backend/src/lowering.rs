@@ -0,0 +1,330 @@
+//! Rewriting of instructions that `cranelift-wasm` can't translate.
+//!
+//! Before a function body is translated, each such instruction in it is
+//! rewritten into a `call` to a function index past the end of the module's
+//! function index space. `FuncEnv` recognizes those calls and generates the
+//! code for the instruction itself.
+//!
+//! Instructions that involve reference types but have an equivalent without
+//! them are replaced by that instead (see the `reference_types` module), and
+//! so are those that involve `v128`s (see the `simd` module).
+use crate::{
+    atomics::AtomicOp,
+    bulk_memory::BulkMemoryOp,
+    reference_types::{self, ReferenceOp},
+    simd::{LoweredSimdBody, SimdOp},
+};
+use cranelift_codegen::ir::{self, types::I32};
+use cranelift_wasm::{WasmError, WasmResult};
+use wasmer_runtime_core::{
+    structures::{SliceMap, TypedIndex},
+    types::SigIndex,
+};
+use wasmparser::BinaryReader;
+
+const CALL_OPCODE: u8 = 0x10;
+
+/// An instruction that a function body was rewritten to call.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LoweredOp {
+    Atomic(AtomicOp),
+    BulkMemory(BulkMemoryOp),
+    Reference(ReferenceOp),
+    Simd(SimdOp),
+    /// A `call_indirect` through a table other than the first one.
+    CallIndirect {
+        sig: SigIndex,
+        table: u32,
+    },
+}
+
+impl LoweredOp {
+    /// The operand types of the instruction, as seen by webassembly.
+    ///
+    /// `call_indirect` takes those of its type from `signatures`.
+    pub fn params(&self, signatures: &SliceMap<SigIndex, ir::Signature>) -> Vec<ir::Type> {
+        match self {
+            LoweredOp::Atomic(op) => op.params(),
+            LoweredOp::BulkMemory(op) => op.params(),
+            LoweredOp::Reference(op) => op.params(),
+            LoweredOp::Simd(op) => op.params(),
+            // The callee's operands, then its index in the table.
+            LoweredOp::CallIndirect { sig, .. } => signatures[*sig]
+                .params
+                .iter()
+                .map(|param| param.value_type)
+                .chain(Some(I32))
+                .collect(),
+        }
+    }
+
+    /// The result types of the instruction, as seen by webassembly.
+    ///
+    /// `call_indirect` takes those of its type from `signatures`.
+    pub fn returns(&self, signatures: &SliceMap<SigIndex, ir::Signature>) -> Vec<ir::Type> {
+        match self {
+            LoweredOp::Atomic(op) => op.returns(),
+            LoweredOp::BulkMemory(_) => vec![],
+            LoweredOp::Reference(op) => op.returns(),
+            LoweredOp::Simd(op) => op.returns(),
+            LoweredOp::CallIndirect { sig, .. } => signatures[*sig]
+                .returns
+                .iter()
+                .map(|ret| ret.value_type)
+                .collect(),
+        }
+    }
+}
+
+/// A function body with some of its instructions rewritten into calls.
+#[derive(Debug, PartialEq)]
+pub struct LoweredBody {
+    pub body: Vec<u8>,
+    pub ops: Vec<LoweredOp>,
+    /// `(lowered, original)` offset pairs at the start and end of
+    /// each rewritten instruction.
+    offsets: Vec<(usize, usize)>,
+}
+
+impl LoweredBody {
+    /// Maps the offset of an instruction in the lowered body back
+    /// to its offset in the original body.
+    pub fn original_offset(&self, offset: usize) -> usize {
+        let index = match self
+            .offsets
+            .binary_search_by_key(&offset, |&(lowered, _)| lowered)
+        {
+            Ok(index) => index,
+            Err(0) => return offset,
+            Err(index) => index - 1,
+        };
+        let (lowered, original) = self.offsets[index];
+        original + (offset - lowered)
+    }
+}
+
+/// Copies a function body while replacing parts of it.
+struct Rewriter<'a> {
+    body: &'a [u8],
+    lowered: Vec<u8>,
+    offsets: Vec<(usize, usize)>,
+    copied_up_to: usize,
+}
+
+impl<'a> Rewriter<'a> {
+    /// Replaces the bytes of the body from `start` to `end`, which
+    /// must come after those replaced so far, with `replacement`.
+    fn replace(&mut self, start: usize, end: usize, replacement: &[u8]) {
+        self.lowered
+            .extend_from_slice(&self.body[self.copied_up_to..start]);
+        self.offsets.push((self.lowered.len(), start));
+        self.lowered.extend_from_slice(replacement);
+        self.copied_up_to = end;
+        self.offsets.push((self.lowered.len(), end));
+    }
+}
+
+/// Rewrites every atomic, bulk memory and reference types instruction in
+/// `body`, along with every `call_indirect` through a table other than
+/// the first one, into a call to `first_index + i`, where `i` is the index
+/// of the instruction in the returned list.
+///
+/// The parts of the body in `simd` are replaced first, and the calls
+/// to its instructions are to the start of the list.
+///
+/// Returns `None` if the body doesn't contain any such instructions.
+pub fn lower_body(
+    body: &[u8],
+    first_index: u32,
+    simd: Option<LoweredSimdBody>,
+) -> WasmResult<Option<LoweredBody>> {
+    let to_wasm_error = |e: wasmparser::BinaryReaderError| WasmError::InvalidWebAssembly {
+        message: e.message,
+        offset: e.offset,
+    };
+
+    let mut reader = BinaryReader::new(body);
+    let mut rewriter = Rewriter {
+        body,
+        lowered: Vec::new(),
+        offsets: Vec::new(),
+        copied_up_to: 0,
+    };
+    let (simd_replacements, mut ops) = match simd {
+        Some(simd) => (
+            simd.replacements,
+            simd.ops.into_iter().map(LoweredOp::Simd).collect(),
+        ),
+        None => (Vec::new(), Vec::new()),
+    };
+    let mut simd_replacements = simd_replacements.into_iter().peekable();
+
+    // The SIMD lowering rewrites all the local declarations if it changes any.
+    if let Some(&(0, end, _)) = simd_replacements.peek() {
+        let (_, _, replacement) = simd_replacements.next().unwrap();
+        rewriter.replace(0, end, &replacement);
+        reader.skip_bytes(end).map_err(to_wasm_error)?;
+    } else {
+        let mut locals_total = 0;
+        for _ in 0..reader.read_local_count().map_err(to_wasm_error)? {
+            reader
+                .read_local_decl(&mut locals_total)
+                .map_err(to_wasm_error)?;
+            // The type is the last byte of the declaration.
+            let end = reader.current_position();
+            if reference_types::is_reference_type(body[end - 1]) {
+                rewriter.replace(end - 1, end, &[reference_types::I64_TYPE]);
+            }
+        }
+    }
+
+    while !reader.eof() {
+        let start = reader.current_position();
+        if let Some(&(replaced_start, end, _)) = simd_replacements.peek() {
+            if replaced_start == start {
+                let (_, _, replacement) = simd_replacements.next().unwrap();
+                rewriter.replace(start, end, &replacement);
+                reader.skip_bytes(end - start).map_err(to_wasm_error)?;
+                continue;
+            }
+        }
+
+        if let Some(replacement) =
+            reference_types::read_replaced_instruction(&mut reader).map_err(to_wasm_error)?
+        {
+            rewriter.replace(start, reader.current_position(), &replacement);
+            continue;
+        }
+
+        // These instructions have to be read first,
+        // since `read_operator` doesn't know about them.
+        let lowered_op = if let Some(op) = ReferenceOp::read(&mut reader).map_err(to_wasm_error)? {
+            Some(LoweredOp::Reference(op))
+        } else if let Some((sig, table)) =
+            reference_types::read_call_indirect(&mut reader).map_err(to_wasm_error)?
+        {
+            Some(LoweredOp::CallIndirect {
+                sig: SigIndex::new(sig as usize),
+                table,
+            })
+        } else if let Some(op) = BulkMemoryOp::read(&mut reader).map_err(to_wasm_error)? {
+            Some(LoweredOp::BulkMemory(op))
+        } else {
+            AtomicOp::from_operator(&reader.read_operator().map_err(to_wasm_error)?)
+                .map(LoweredOp::Atomic)
+        };
+
+        if let Some(lowered_op) = lowered_op {
+            let index = match ops.iter().position(|op| *op == lowered_op) {
+                Some(index) => index,
+                None => {
+                    ops.push(lowered_op);
+                    ops.len() - 1
+                }
+            };
+
+            let mut call = vec![CALL_OPCODE];
+            write_var_u32(&mut call, first_index + index as u32);
+            rewriter.replace(start, reader.current_position(), &call);
+        }
+    }
+
+    if rewriter.offsets.is_empty() {
+        return Ok(None);
+    }
+
+    let Rewriter {
+        mut lowered,
+        offsets,
+        copied_up_to,
+        ..
+    } = rewriter;
+    lowered.extend_from_slice(&body[copied_up_to..]);
+
+    Ok(Some(LoweredBody {
+        body: lowered,
+        ops,
+        offsets,
+    }))
+}
+
+pub fn write_var_u32(buf: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(byte);
+            break;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod lowering_tests {
+    use super::{lower_body, LoweredOp};
+    use crate::{
+        atomics::{AtomicOp, AtomicOpKind},
+        bulk_memory::BulkMemoryOp,
+    };
+    use cranelift_codegen::ir::types::I32;
+
+    #[test]
+    fn test_lower_atomics() {
+        #[rustfmt::skip]
+        let body = [
+            0x00,                   // no locals
+            0x41, 0x00,             // i32.const 0
+            0xfe, 0x10, 0x02, 0x08, // i32.atomic.load align=4 offset=8
+            0x1a,                   // drop
+            0x0b,                   // end
+        ];
+
+        let lowered = lower_body(&body, 200, None).unwrap().unwrap();
+
+        assert_eq!(
+            lowered.body,
+            [0x00, 0x41, 0x00, 0x10, 0xc8, 0x01, 0x1a, 0x0b]
+        );
+        assert_eq!(
+            lowered.ops,
+            [LoweredOp::Atomic(AtomicOp {
+                kind: AtomicOpKind::Load,
+                ty: I32,
+                width: 4,
+                offset: 8,
+            })]
+        );
+
+        // `i32.const 0`, the call and `drop` map back to where
+        // they started in the original body.
+        assert_eq!(lowered.original_offset(1), 1);
+        assert_eq!(lowered.original_offset(3), 3);
+        assert_eq!(lowered.original_offset(6), 7);
+
+        assert_eq!(lower_body(&[0x00, 0x0b], 200, None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_lower_bulk_memory() {
+        #[rustfmt::skip]
+        let body = [
+            0x00,             // no locals
+            0xfc, 0x09, 0x01, // data.drop 1
+            0xfc, 0x09, 0x01, // data.drop 1
+            0xfc, 0x02,       // i32.trunc_sat_f32_s
+            0x0b,             // end
+        ];
+
+        let lowered = lower_body(&body, 3, None).unwrap().unwrap();
+
+        assert_eq!(
+            lowered.body,
+            [0x00, 0x10, 0x03, 0x10, 0x03, 0xfc, 0x02, 0x0b]
+        );
+        assert_eq!(
+            lowered.ops,
+            [LoweredOp::BulkMemory(BulkMemoryOp::DataDrop { segment: 1 })]
+        );
+    }
+}
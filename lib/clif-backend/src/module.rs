@@ -7,10 +7,10 @@ use cranelift_wasm;
 use hashbrown::HashMap;
 use std::sync::Arc;
 
-use wasmer_runtime_core::cache::{Artifact, Error as CacheError};
+use wasmer_runtime_core::cache::{Artifact, Error as CacheError, WasmHash};
 
 use wasmer_runtime_core::{
-    backend::Backend,
+    backend::{Backend, Features},
     error::CompileResult,
     module::{ModuleInfo, ModuleInner, StringTable},
     structures::{Map, TypedIndex},
@@ -42,6 +42,9 @@ impl Module {
                 data_initializers: Vec::new(),
                 elem_initializers: Vec::new(),
 
+                passive_data: HashMap::new(),
+                passive_elements: HashMap::new(),
+
                 start_func: None,
 
                 func_assoc: Map::new(),
@@ -50,6 +53,15 @@ impl Module {
 
                 namespace_table: StringTable::new(),
                 name_table: StringTable::new(),
+
+                custom_sections: HashMap::new(),
+
+                func_names: HashMap::new(),
+                local_names: HashMap::new(),
+
+                wasm_hash: WasmHash::generate(wasm),
+
+                features: Features::default(),
             },
         }
     }
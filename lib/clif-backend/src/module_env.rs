@@ -1,24 +1,34 @@
 use crate::{
+    bulk_memory::lower_bulk_memory_segments,
     func_env::FuncEnv,
+    lowering::lower_body,
     module::{Converter, Module},
+    multi_value::lower_multi_value_types,
+    reference_types::lower_reference_types,
+    simd::{lower_simd_body, lower_simd_types},
 };
 use cranelift_codegen::{ir, isa};
 use cranelift_wasm::{self, translate_module, FuncTranslator, ModuleEnvironment};
-use std::sync::Arc;
+use hashbrown::HashMap;
+use std::{borrow::Cow, sync::Arc};
 use wasmer_runtime_core::{
+    backend::CompilerConfig,
     error::{CompileError, CompileResult},
     module::{
-        DataInitializer, ExportIndex, ImportName, NameIndex, NamespaceIndex, StringTableBuilder,
-        TableInitializer,
+        DataInitializer, ExportIndex, ImportName, ModuleInfo, NameIndex, NamespaceIndex,
+        StringTableBuilder, TableInitializer,
     },
     structures::{Map, TypedIndex},
     types::{
-        ElementType, GlobalDescriptor, GlobalIndex, GlobalInit, Initializer, LocalFuncIndex,
-        LocalOrImport, MemoryDescriptor, SigIndex, TableDescriptor, Value,
+        AnyRef, ElementType, FuncIndex, FuncRef, FuncSig, GlobalDescriptor, GlobalIndex,
+        GlobalInit, Initializer, LocalFuncIndex, LocalOrImport, MemoryDescriptor, SigIndex,
+        TableDescriptor, Type, Value,
     },
     units::Pages,
 };
 
+const CUSTOM_SECTION_ID: u32 = 0;
+
 pub struct ModuleEnv<'module, 'isa> {
     pub module: &'module mut Module,
     isa: &'isa isa::TargetIsa,
@@ -27,10 +37,37 @@ pub struct ModuleEnv<'module, 'isa> {
     func_bodies: Map<LocalFuncIndex, ir::Function>,
     namespace_table_builder: StringTableBuilder<NamespaceIndex>,
     name_table_builder: StringTableBuilder<NameIndex>,
+    /// Whether to charge fuel and check for interrupts in the generated
+    /// code, and which proposals are enabled.
+    config: CompilerConfig,
+    /// Where the module's bytes start in memory. Function bodies are
+    /// slices of them, so this gives each body's offset in the module.
+    wasm_start: usize,
+    /// The results of the types that were rewritten to have none,
+    /// by type index. See the `multi_value` module.
+    multi_value_returns: HashMap<u32, Vec<ir::Type>>,
+    /// The types with `v128` parameters or results, as they were before
+    /// being rewritten, by type index. See the `simd` module.
+    simd_signatures: HashMap<u32, FuncSig>,
+    /// The types with reference typed parameters or results, as they
+    /// were before being rewritten, by type index. See the
+    /// `reference_types` module.
+    reference_signatures: HashMap<u32, FuncSig>,
+    /// The types of the reference typed globals, by their indices.
+    reference_globals: HashMap<u32, Type>,
+    /// The element types of the tables, imported ones first. Empty
+    /// if the module doesn't use reference types.
+    table_elements: Vec<ElementType>,
+    /// How far rewriting types and segments moved the function bodies back.
+    shrunk_by: isize,
 }
 
 impl<'module, 'isa> ModuleEnv<'module, 'isa> {
-    pub fn new(module: &'module mut Module, isa: &'isa isa::TargetIsa) -> Self {
+    pub fn new(
+        module: &'module mut Module,
+        isa: &'isa isa::TargetIsa,
+        config: CompilerConfig,
+    ) -> Self {
         Self {
             module,
             isa,
@@ -39,18 +76,210 @@ impl<'module, 'isa> ModuleEnv<'module, 'isa> {
             func_bodies: Map::new(),
             namespace_table_builder: StringTableBuilder::new(),
             name_table_builder: StringTableBuilder::new(),
+            config,
+            wasm_start: 0,
+            multi_value_returns: HashMap::new(),
+            simd_signatures: HashMap::new(),
+            reference_signatures: HashMap::new(),
+            reference_globals: HashMap::new(),
+            table_elements: Vec::new(),
+            shrunk_by: 0,
         }
     }
 
     pub fn translate(mut self, wasm: &[u8]) -> CompileResult<Map<LocalFuncIndex, ir::Function>> {
-        translate_module(wasm, &mut self)
+        let lowered = if self.config.features.simd {
+            lower_simd_types(wasm)
+                .map_err(|e| CompileError::InternalError { msg: e.to_string() })?
+        } else {
+            None
+        };
+        let translated = match lowered {
+            Some(lowered) => {
+                self.simd_signatures = lowered.signatures;
+                self.shrunk_by = lowered.shrunk_by;
+                Cow::Owned(lowered.wasm)
+            }
+            None => Cow::Borrowed(wasm),
+        };
+
+        let lowered = lower_reference_types(&translated)
+            .map_err(|e| CompileError::InternalError { msg: e.to_string() })?;
+        let translated = match lowered {
+            Some(lowered) => {
+                self.reference_signatures = lowered.signatures;
+                self.reference_globals = lowered.globals;
+                self.table_elements = lowered.table_elements;
+                self.shrunk_by += lowered.shrunk_by;
+                Cow::Owned(lowered.wasm)
+            }
+            None => translated,
+        };
+
+        let lowered = lower_multi_value_types(&translated)
             .map_err(|e| CompileError::InternalError { msg: e.to_string() })?;
+        let translated = match lowered {
+            Some(lowered) => {
+                self.multi_value_returns = lowered.returns;
+                self.shrunk_by += lowered.shrunk_by as isize;
+                Cow::Owned(lowered.wasm)
+            }
+            None => translated,
+        };
+
+        let lowered = lower_bulk_memory_segments(&translated)
+            .map_err(|e| CompileError::InternalError { msg: e.to_string() })?;
+        let translated = match lowered {
+            Some(lowered) => {
+                self.module.info.passive_data = lowered.passive_data;
+                self.module.info.passive_elements = lowered.passive_elements;
+                self.shrunk_by += lowered.shrunk_by as isize;
+                Cow::Owned(lowered.wasm)
+            }
+            None => translated,
+        };
+
+        self.wasm_start = translated.as_ptr() as usize;
+        translate_module(&translated, &mut self)
+            .map_err(|e| CompileError::InternalError { msg: e.to_string() })?;
+
+        // `translate_module` skips over custom sections, so collect them separately,
+        // as the module was written rather than as it was lowered.
+        read_custom_sections(wasm, &mut self.module.info).map_err(|e| {
+            CompileError::InternalError {
+                msg: e.message.to_string(),
+            }
+        })?;
 
         self.module.info.namespace_table = self.namespace_table_builder.finish();
         self.module.info.name_table = self.name_table_builder.finish();
 
         Ok(self.func_bodies)
     }
+
+    /// The type of the next global to be declared, which
+    /// is an `i64` to cranelift if it is reference typed.
+    fn global_type(&self, global: &cranelift_wasm::Global) -> Type {
+        match self.reference_globals.get(&(self.globals.len() as u32)) {
+            Some(&ty) => ty,
+            None => Converter(global.ty).into(),
+        }
+    }
+
+    /// The element type of the next table to be declared.
+    fn table_element(&self, table: &cranelift_wasm::Table) -> ElementType {
+        use cranelift_wasm::TableElementType;
+
+        let table_index = self.module.info.imported_tables.len() + self.module.info.tables.len();
+        match (table.ty, self.table_elements.get(table_index)) {
+            (TableElementType::Func, Some(&element)) => element,
+            (TableElementType::Func, None) => ElementType::Anyfunc,
+            // `cranelift-wasm` reads a numeric element type as `Val`, which
+            // validation rejects before the module is translated, and `anyref`
+            // tables were rewritten to `funcref` ones.
+            (TableElementType::Val(ty), _) => {
+                unreachable!("validation rejects tables of {} elements", ty)
+            }
+        }
+    }
+}
+
+/// Reads the contents of every custom section in `wasm` into `info`,
+/// along with the function and local names from the name section.
+///
+/// The sections are walked by hand rather than with a `ModuleReader`,
+/// which fails on the sections of proposals it doesn't know about.
+fn read_custom_sections(
+    wasm: &[u8],
+    info: &mut ModuleInfo,
+) -> Result<(), wasmparser::BinaryReaderError> {
+    let mut reader = wasmparser::BinaryReader::new(wasm);
+    // Skip the magic number and the version.
+    reader.skip_bytes(8)?;
+
+    while !reader.eof() {
+        let id = reader.read_u8()?;
+        let size = reader.read_var_u32()? as usize;
+        let contents_offset = reader.current_position();
+        let contents = reader.read_bytes(size)?;
+        if id != CUSTOM_SECTION_ID {
+            continue;
+        }
+
+        let mut section = wasmparser::BinaryReader::new_with_offset(contents, contents_offset);
+        let name = section.read_string()?;
+        let data_offset = contents_offset + section.current_position();
+        let data = section.read_bytes(section.bytes_remaining())?;
+
+        // A malformed name section doesn't make the module invalid,
+        // so its names are just left out.
+        if name == b"name" {
+            if let Ok((func_names, local_names)) = read_names(data, data_offset) {
+                info.func_names = func_names;
+                info.local_names = local_names;
+            }
+        }
+
+        info.custom_sections
+            .entry(String::from_utf8_lossy(name).into_owned())
+            .or_default()
+            .push(data.to_vec());
+    }
+
+    Ok(())
+}
+
+/// Reads the function and local names from a name section.
+fn read_names(
+    data: &[u8],
+    offset: usize,
+) -> Result<
+    (
+        HashMap<FuncIndex, String>,
+        HashMap<FuncIndex, HashMap<u32, String>>,
+    ),
+    wasmparser::BinaryReaderError,
+> {
+    let mut func_names = HashMap::new();
+    let mut local_names = HashMap::new();
+
+    let mut names = wasmparser::NameSectionReader::new(data, offset)?;
+    while !names.eof() {
+        match names.read()? {
+            wasmparser::Name::Function(function_name) => {
+                let mut naming_reader = function_name.get_map()?;
+                for _ in 0..naming_reader.get_count() {
+                    let naming = naming_reader.read()?;
+                    func_names.insert(
+                        FuncIndex::new(naming.index as usize),
+                        String::from_utf8_lossy(naming.name).into_owned(),
+                    );
+                }
+            }
+            wasmparser::Name::Local(local_name) => {
+                let mut function_local_reader = local_name.get_function_local_reader()?;
+                for _ in 0..function_local_reader.get_count() {
+                    let function_local_name = function_local_reader.read()?;
+                    let mut naming_reader = function_local_name.get_map()?;
+                    let mut locals = HashMap::new();
+                    for _ in 0..naming_reader.get_count() {
+                        let naming = naming_reader.read()?;
+                        locals.insert(
+                            naming.index,
+                            String::from_utf8_lossy(naming.name).into_owned(),
+                        );
+                    }
+                    local_names.insert(
+                        FuncIndex::new(function_local_name.func_index as usize),
+                        locals,
+                    );
+                }
+            }
+            wasmparser::Name::Module(_) => {}
+        }
+    }
+
+    Ok((func_names, local_names))
 }
 
 impl<'module, 'isa, 'data> ModuleEnvironment<'data> for ModuleEnv<'module, 'isa> {
@@ -61,11 +290,23 @@ impl<'module, 'isa, 'data> ModuleEnvironment<'data> for ModuleEnv<'module, 'isa>
 
     /// Declares a function signature to the environment.
     fn declare_signature(&mut self, sig: &ir::Signature) {
-        self.signatures.push(sig.clone());
-        self.module
-            .info
-            .signatures
-            .push(Arc::new(Converter(sig).into()));
+        let mut sig = sig.clone();
+        let type_index = self.signatures.len() as u32;
+        if let Some(returns) = self.multi_value_returns.get(&type_index) {
+            sig.returns = returns.iter().cloned().map(ir::AbiParam::new).collect();
+        }
+
+        let func_sig = match self
+            .simd_signatures
+            .get(&type_index)
+            .or_else(|| self.reference_signatures.get(&type_index))
+        {
+            Some(func_sig) => func_sig.clone(),
+            None => Converter(&sig).into(),
+        };
+
+        self.module.info.signatures.push(Arc::new(func_sig));
+        self.signatures.push(sig);
     }
 
     /// Return the signature with the given index.
@@ -123,10 +364,17 @@ impl<'module, 'isa, 'data> ModuleEnvironment<'data> for ModuleEnv<'module, 'isa>
     fn declare_global(&mut self, global: cranelift_wasm::Global) {
         let desc = GlobalDescriptor {
             mutable: global.mutability,
-            ty: Converter(global.ty).into(),
+            ty: self.global_type(&global),
         };
 
         let init = match global.initializer {
+            // The rewritten `ref.null`.
+            cranelift_wasm::GlobalInit::I64Const(_) if desc.ty == Type::AnyRef => {
+                Initializer::Const(Value::AnyRef(AnyRef::null()))
+            }
+            cranelift_wasm::GlobalInit::I64Const(_) if desc.ty == Type::FuncRef => {
+                Initializer::Const(Value::FuncRef(FuncRef::null()))
+            }
             cranelift_wasm::GlobalInit::I32Const(x) => Initializer::Const(Value::I32(x)),
             cranelift_wasm::GlobalInit::I64Const(x) => Initializer::Const(Value::I64(x)),
             cranelift_wasm::GlobalInit::F32Const(x) => {
@@ -175,7 +423,7 @@ impl<'module, 'isa, 'data> ModuleEnvironment<'data> for ModuleEnv<'module, 'isa>
 
         let desc = GlobalDescriptor {
             mutable: global.mutability,
-            ty: Converter(global.ty).into(),
+            ty: self.global_type(&global),
         };
 
         // Add global ir to the list of globals
@@ -191,13 +439,9 @@ impl<'module, 'isa, 'data> ModuleEnvironment<'data> for ModuleEnv<'module, 'isa>
 
     /// Declares a table to the environment.
     fn declare_table(&mut self, table: cranelift_wasm::Table) {
-        use cranelift_wasm::TableElementType;
         // Add table ir to the list of tables
         self.module.info.tables.push(TableDescriptor {
-            element: match table.ty {
-                TableElementType::Func => ElementType::Anyfunc,
-                _ => unimplemented!(),
-            },
+            element: self.table_element(&table),
             minimum: table.minimum,
             maximum: table.maximum,
         });
@@ -210,8 +454,6 @@ impl<'module, 'isa, 'data> ModuleEnvironment<'data> for ModuleEnv<'module, 'isa>
         namespace: &'data str,
         name: &'data str,
     ) {
-        use cranelift_wasm::TableElementType;
-
         let namespace_index = self.namespace_table_builder.register(namespace);
         let name_index = self.name_table_builder.register(name);
 
@@ -221,10 +463,7 @@ impl<'module, 'isa, 'data> ModuleEnvironment<'data> for ModuleEnv<'module, 'isa>
         };
 
         let imported_table = TableDescriptor {
-            element: match table.ty {
-                TableElementType::Func => ElementType::Anyfunc,
-                _ => unimplemented!(),
-            },
+            element: self.table_element(&table),
             minimum: table.minimum,
             maximum: table.maximum,
         };
@@ -383,8 +622,30 @@ impl<'module, 'isa, 'data> ModuleEnvironment<'data> for ModuleEnv<'module, 'isa>
     fn define_function_body(&mut self, body_bytes: &'data [u8]) -> cranelift_wasm::WasmResult<()> {
         let mut func_translator = FuncTranslator::new();
 
+        // The code section comes after the type section and all but the data
+        // segments, so bodies moved back by however much those shrunk.
+        let body_offset =
+            ((body_bytes.as_ptr() as usize - self.wasm_start) as isize + self.shrunk_by) as usize;
+
+        let lowered = {
+            let first_index = self.module.info.func_assoc.len() as u32;
+            let simd = if self.config.features.simd {
+                let func_index = self.func_bodies.next_index().convert_up(&self.module.info);
+                let sig_index = self.module.info.func_assoc[func_index];
+                let func_sig = &self.module.info.signatures[sig_index];
+                lower_simd_body(body_bytes, func_sig, &self.module.info, first_index)?
+            } else {
+                None
+            };
+            lower_body(body_bytes, first_index, simd)?
+        };
+        let (body_bytes, lowered_ops) = match &lowered {
+            Some(lowered) => (&lowered.body[..], &lowered.ops[..]),
+            None => (body_bytes, &[][..]),
+        };
+
         let func_body = {
-            let mut func_env = FuncEnv::new(self);
+            let mut func_env = FuncEnv::new(self, lowered_ops);
             let func_index = self.func_bodies.next_index();
             let name = ir::ExternalName::user(0, func_index.index() as u32);
 
@@ -396,6 +657,31 @@ impl<'module, 'isa, 'data> ModuleEnvironment<'data> for ModuleEnv<'module, 'isa>
 
             func_translator.translate(body_bytes, &mut func, &mut func_env)?;
 
+            // Source locations start out relative to the (possibly lowered)
+            // function body, but backtraces report offsets in the module.
+            for ebb in func.layout.ebbs() {
+                for inst in func.layout.ebb_insts(ebb) {
+                    let srcloc = func.srclocs[inst];
+                    if !srcloc.is_default() {
+                        let offset = match &lowered {
+                            Some(lowered) => lowered.original_offset(srcloc.bits() as usize),
+                            None => srcloc.bits() as usize,
+                        };
+                        func.srclocs[inst] = ir::SourceLoc::new((body_offset + offset) as u32);
+                    }
+                }
+            }
+
+            func_env.lower_multi_value_returns(&mut func);
+
+            if self.config.interruptible {
+                func_env.insert_interrupt_checks(&mut func);
+            }
+
+            if self.config.metering {
+                func_env.insert_fuel_checks(&mut func);
+            }
+
             #[cfg(feature = "debug")]
             {
                 use cranelift_codegen::cursor::{Cursor, FuncCursor};
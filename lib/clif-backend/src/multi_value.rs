@@ -0,0 +1,147 @@
+//! Support for the multi-value proposal's functions that return more than one value.
+//!
+//! `cranelift-wasm` translates calls to and returns from such functions just
+//! fine, but the version of `wasmparser` it reads modules with refuses types
+//! with more than one result. So before a module is translated, each of those
+//! types is rewritten to have no results, and `ModuleEnv` gives the types their
+//! results back as they're declared. `FuncEnv::lower_multi_value_returns` then
+//! lowers the functions to ones that Cranelift can compile.
+//!
+//! Blocks that take parameters or have more than one result refer to their
+//! type by index, which that version of `wasmparser` can't read at all, so
+//! modules with such blocks are rejected when they're validated.
+use crate::lowering::write_var_u32;
+use cranelift_codegen::ir::{self, types::*};
+use cranelift_wasm::{WasmError, WasmResult};
+use hashbrown::HashMap;
+use wasmparser::{BinaryReader, Type};
+
+const TYPE_SECTION_ID: u32 = 1;
+
+#[derive(Debug, PartialEq)]
+pub struct LoweredModule {
+    pub wasm: Vec<u8>,
+    /// The results of the types that were rewritten, by type index.
+    pub returns: HashMap<u32, Vec<ir::Type>>,
+    /// How many bytes shorter the module got. Everything
+    /// after the type section moved back by that many.
+    pub shrunk_by: usize,
+}
+
+/// Rewrites every type in `wasm` with more than one result to have none.
+///
+/// Returns `None` if no type has more than one result.
+pub fn lower_multi_value_types(wasm: &[u8]) -> WasmResult<Option<LoweredModule>> {
+    let to_wasm_error = |e: wasmparser::BinaryReaderError| WasmError::InvalidWebAssembly {
+        message: e.message,
+        offset: e.offset,
+    };
+
+    let mut reader = BinaryReader::new(wasm);
+    // Skip the magic number and the version.
+    reader.skip_bytes(8).map_err(to_wasm_error)?;
+
+    while !reader.eof() {
+        let section_start = reader.current_position();
+        let id = reader.read_u8().map_err(to_wasm_error)?;
+        let size = reader.read_var_u32().map_err(to_wasm_error)? as usize;
+        if id != TYPE_SECTION_ID {
+            reader.skip_bytes(size).map_err(to_wasm_error)?;
+            continue;
+        }
+
+        let mut section = Vec::new();
+        let mut returns = HashMap::new();
+
+        let count = reader.read_var_u32().map_err(to_wasm_error)?;
+        write_var_u32(&mut section, count);
+        for index in 0..count {
+            let entry_start = reader.current_position();
+            // The form, which is always `func`.
+            reader.read_type().map_err(to_wasm_error)?;
+            for _ in 0..reader.read_var_u32().map_err(to_wasm_error)? {
+                reader.read_type().map_err(to_wasm_error)?;
+            }
+
+            let returns_start = reader.current_position();
+            let mut types = Vec::new();
+            for _ in 0..reader.read_var_u32().map_err(to_wasm_error)? {
+                let offset = reader.current_position();
+                types.push(match reader.read_type().map_err(to_wasm_error)? {
+                    Type::I32 => I32,
+                    Type::I64 => I64,
+                    Type::F32 => F32,
+                    Type::F64 => F64,
+                    _ => Err(WasmError::InvalidWebAssembly {
+                        message: "invalid result type",
+                        offset,
+                    })?,
+                });
+            }
+
+            if types.len() > 1 {
+                section.extend_from_slice(&wasm[entry_start..returns_start]);
+                write_var_u32(&mut section, 0);
+                returns.insert(index, types);
+            } else {
+                section.extend_from_slice(&wasm[entry_start..reader.current_position()]);
+            }
+        }
+
+        if returns.is_empty() {
+            return Ok(None);
+        }
+
+        let mut lowered = wasm[..section_start].to_vec();
+        write_var_u32(&mut lowered, TYPE_SECTION_ID);
+        write_var_u32(&mut lowered, section.len() as u32);
+        lowered.extend_from_slice(&section);
+        lowered.extend_from_slice(&wasm[reader.current_position()..]);
+
+        return Ok(Some(LoweredModule {
+            shrunk_by: wasm.len() - lowered.len(),
+            wasm: lowered,
+            returns,
+        }));
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod multi_value_tests {
+    use super::lower_multi_value_types;
+    use cranelift_codegen::ir::types::{F64, I32};
+
+    #[test]
+    fn test_lower_multi_value_types() {
+        #[rustfmt::skip]
+        let wasm = [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+            0x01, 0x0b,                   // type section
+            0x02,                         // two types
+            0x60, 0x01, 0x7f, 0x01, 0x7f, // [i32] -> [i32]
+            0x60, 0x00, 0x02, 0x7f, 0x7c, // [] -> [i32, f64]
+            0x03, 0x02, 0x01, 0x01,       // function section
+        ];
+
+        let lowered = lower_multi_value_types(&wasm).unwrap().unwrap();
+
+        #[rustfmt::skip]
+        assert_eq!(
+            lowered.wasm,
+            [
+                0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+                0x01, 0x09,
+                0x02,
+                0x60, 0x01, 0x7f, 0x01, 0x7f,
+                0x60, 0x00, 0x00,
+                0x03, 0x02, 0x01, 0x01,
+            ]
+        );
+        assert_eq!(lowered.returns[&1], [I32, F64]);
+        assert_eq!(lowered.shrunk_by, 2);
+
+        assert_eq!(lower_multi_value_types(&wasm[..8]).unwrap(), None);
+    }
+}
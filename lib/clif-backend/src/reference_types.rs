@@ -0,0 +1,489 @@
+//! Support for the reference types proposal.
+//!
+//! `cranelift-wasm` doesn't know about the `anyref` and `funcref` types,
+//! so in compiled code, reference values are 64-bit integers (see the
+//! reference types vmcalls), which only works on 64-bit targets. Before
+//! a module is translated, the reference types in its type, import and
+//! global sections are rewritten to `i64`, and what they were is recorded
+//! so that `ModuleInfo` can be given the right types.
+//!
+//! In function bodies, `ref.null` and `ref.is_null` are rewritten to
+//! `i64.const 0` and `i64.eqz`, and reference typed locals, blocks and
+//! `select`s to `i64` ones. The table instructions and `ref.func` are
+//! rewritten into calls (see the `lowering` module), which `FuncEnv`
+//! lowers to calls to the matching vmcalls.
+use crate::{bulk_memory::read_init_expr, lowering::write_var_u32, relocation::call_names};
+use cranelift_codegen::ir::{self, types::*};
+use cranelift_wasm::{WasmError, WasmResult};
+use hashbrown::HashMap;
+use wasmer_runtime_core::types::{ElementType, FuncSig, Type};
+use wasmparser::{BinaryReader, BinaryReaderError};
+
+const ANYREF_TYPE: u8 = 0x6f;
+const FUNCREF_TYPE: u8 = 0x70;
+/// The type that reference types are rewritten to.
+pub const I64_TYPE: u8 = 0x7e;
+
+const BLOCK_OPCODE: u32 = 0x02;
+const IF_OPCODE: u32 = 0x04;
+const CALL_INDIRECT_OPCODE: u32 = 0x11;
+const SELECT_OPCODE: u8 = 0x1b;
+const TYPED_SELECT_OPCODE: u32 = 0x1c;
+const TABLE_GET_OPCODE: u32 = 0x25;
+const TABLE_SET_OPCODE: u32 = 0x26;
+const I64_CONST_OPCODE: u8 = 0x42;
+const I64_EQZ_OPCODE: u8 = 0x50;
+const REF_NULL_OPCODE: u32 = 0xd0;
+const REF_IS_NULL_OPCODE: u32 = 0xd1;
+const REF_FUNC_OPCODE: u32 = 0xd2;
+const TABLE_PREFIX: u32 = 0xfc;
+const END_OPCODE: u8 = 0x0b;
+
+const TYPE_SECTION_ID: u32 = 1;
+const IMPORT_SECTION_ID: u32 = 2;
+const TABLE_SECTION_ID: u32 = 4;
+const GLOBAL_SECTION_ID: u32 = 6;
+const CODE_SECTION_ID: u32 = 10;
+
+const TABLE_IMPORT_KIND: u32 = 1;
+const MEMORY_IMPORT_KIND: u32 = 2;
+const GLOBAL_IMPORT_KIND: u32 = 3;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReferenceOpKind {
+    RefFunc,
+    TableGet,
+    TableSet,
+    TableSize,
+    TableGrow,
+    TableFill,
+}
+
+/// A reference types instruction that a function body was rewritten to call.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReferenceOp {
+    RefFunc { func: u32 },
+    TableGet { table: u32 },
+    TableSet { table: u32 },
+    TableSize { table: u32 },
+    TableGrow { table: u32 },
+    TableFill { table: u32 },
+}
+
+impl ReferenceOp {
+    pub fn kind(&self) -> ReferenceOpKind {
+        match self {
+            ReferenceOp::RefFunc { .. } => ReferenceOpKind::RefFunc,
+            ReferenceOp::TableGet { .. } => ReferenceOpKind::TableGet,
+            ReferenceOp::TableSet { .. } => ReferenceOpKind::TableSet,
+            ReferenceOp::TableSize { .. } => ReferenceOpKind::TableSize,
+            ReferenceOp::TableGrow { .. } => ReferenceOpKind::TableGrow,
+            ReferenceOp::TableFill { .. } => ReferenceOpKind::TableFill,
+        }
+    }
+
+    /// The function or table index immediate of the instruction.
+    pub fn index(&self) -> u32 {
+        match *self {
+            ReferenceOp::RefFunc { func } => func,
+            ReferenceOp::TableGet { table }
+            | ReferenceOp::TableSet { table }
+            | ReferenceOp::TableSize { table }
+            | ReferenceOp::TableGrow { table }
+            | ReferenceOp::TableFill { table } => table,
+        }
+    }
+
+    /// The operand types of the instruction, as seen by webassembly.
+    pub fn params(&self) -> Vec<ir::Type> {
+        match self.kind() {
+            ReferenceOpKind::RefFunc | ReferenceOpKind::TableSize => vec![],
+            ReferenceOpKind::TableGet => vec![I32],
+            ReferenceOpKind::TableSet => vec![I32, I64],
+            ReferenceOpKind::TableGrow => vec![I64, I32],
+            ReferenceOpKind::TableFill => vec![I32, I64, I32],
+        }
+    }
+
+    /// The result types of the instruction, as seen by webassembly.
+    pub fn returns(&self) -> Vec<ir::Type> {
+        match self.kind() {
+            ReferenceOpKind::RefFunc | ReferenceOpKind::TableGet => vec![I64],
+            ReferenceOpKind::TableSize | ReferenceOpKind::TableGrow => vec![I32],
+            ReferenceOpKind::TableSet | ReferenceOpKind::TableFill => vec![],
+        }
+    }
+
+    /// The name of the vmcall implementing this instruction.
+    pub fn name(&self) -> ir::ExternalName {
+        let index = match self.kind() {
+            ReferenceOpKind::RefFunc => call_names::REF_FUNC,
+            ReferenceOpKind::TableGet => call_names::TABLE_GET,
+            ReferenceOpKind::TableSet => call_names::TABLE_SET,
+            ReferenceOpKind::TableSize => call_names::TABLE_SIZE,
+            ReferenceOpKind::TableGrow => call_names::TABLE_GROW,
+            ReferenceOpKind::TableFill => call_names::TABLE_FILL,
+        };
+        ir::ExternalName::user(call_names::REFERENCE_NAMESPACE, index)
+    }
+
+    /// Reads the reference types instruction at the position of `reader`.
+    ///
+    /// Returns `None`, and leaves `reader` where it was, if there isn't one.
+    pub fn read(reader: &mut BinaryReader) -> Result<Option<Self>, BinaryReaderError> {
+        let mut op_reader = reader.clone();
+        let op = match op_reader.read_u8()? {
+            REF_FUNC_OPCODE => ReferenceOp::RefFunc {
+                func: op_reader.read_var_u32()?,
+            },
+            TABLE_GET_OPCODE => ReferenceOp::TableGet {
+                table: op_reader.read_var_u32()?,
+            },
+            TABLE_SET_OPCODE => ReferenceOp::TableSet {
+                table: op_reader.read_var_u32()?,
+            },
+            TABLE_PREFIX => {
+                let op = op_reader.read_var_u32()?;
+                let table = op_reader.read_var_u32()?;
+                match op {
+                    0x0f => ReferenceOp::TableGrow { table },
+                    0x10 => ReferenceOp::TableSize { table },
+                    0x11 => ReferenceOp::TableFill { table },
+                    _ => return Ok(None),
+                }
+            }
+            _ => return Ok(None),
+        };
+
+        *reader = op_reader;
+        Ok(Some(op))
+    }
+}
+
+/// Reads a `call_indirect` through a table other than the first one,
+/// whose table index `cranelift-wasm` can't read, and returns its
+/// type and table indices.
+///
+/// Returns `None`, and leaves `reader` where it was, if there isn't one.
+pub fn read_call_indirect(
+    reader: &mut BinaryReader,
+) -> Result<Option<(u32, u32)>, BinaryReaderError> {
+    let mut op_reader = reader.clone();
+    if op_reader.read_u8()? != CALL_INDIRECT_OPCODE {
+        return Ok(None);
+    }
+
+    let sig = op_reader.read_var_u32()?;
+    let table = op_reader.read_var_u32()?;
+    if table == 0 {
+        return Ok(None);
+    }
+
+    *reader = op_reader;
+    Ok(Some((sig, table)))
+}
+
+/// Reads an instruction that involves reference types but can be replaced
+/// by one that doesn't, and returns the bytes of the replacement.
+///
+/// Returns `None`, and leaves `reader` where it was, if there isn't one.
+pub fn read_replaced_instruction(
+    reader: &mut BinaryReader,
+) -> Result<Option<Vec<u8>>, BinaryReaderError> {
+    let mut op_reader = reader.clone();
+    let replacement = match op_reader.read_u8()? {
+        REF_NULL_OPCODE => vec![I64_CONST_OPCODE, 0],
+        REF_IS_NULL_OPCODE => vec![I64_EQZ_OPCODE],
+        TYPED_SELECT_OPCODE => {
+            for _ in 0..op_reader.read_var_u32()? {
+                op_reader.read_u8()?;
+            }
+            vec![SELECT_OPCODE]
+        }
+        // `block`, `loop` and `if`.
+        opcode @ BLOCK_OPCODE..=IF_OPCODE => {
+            if !is_reference_type(op_reader.read_u8()? as u8) {
+                return Ok(None);
+            }
+            vec![opcode as u8, I64_TYPE]
+        }
+        _ => return Ok(None),
+    };
+
+    *reader = op_reader;
+    Ok(Some(replacement))
+}
+
+/// Whether `byte` encodes `anyref` or `funcref`.
+pub fn is_reference_type(byte: u8) -> bool {
+    byte == ANYREF_TYPE || byte == FUNCREF_TYPE
+}
+
+#[derive(Debug, PartialEq)]
+pub struct LoweredTypes {
+    pub wasm: Vec<u8>,
+    /// The types that have reference typed parameters
+    /// or results, as they were, by type index.
+    pub signatures: HashMap<u32, FuncSig>,
+    /// The types of the reference typed globals, by
+    /// their indices, imported ones included.
+    pub globals: HashMap<u32, Type>,
+    /// The element types of the tables, imported ones first.
+    pub table_elements: Vec<ElementType>,
+    /// How many bytes shorter the part of the module before the
+    /// code section got. `ref.null` global initializers make it
+    /// longer, so this can be negative.
+    pub shrunk_by: isize,
+}
+
+/// Rewrites the reference types in the type, import
+/// and global sections of `wasm` to `i64`.
+///
+/// Returns `None` if there is nothing to change.
+pub fn lower_reference_types(wasm: &[u8]) -> WasmResult<Option<LoweredTypes>> {
+    let to_wasm_error = |e: BinaryReaderError| WasmError::InvalidWebAssembly {
+        message: e.message,
+        offset: e.offset,
+    };
+
+    let mut reader = BinaryReader::new(wasm);
+    // Skip the magic number and the version.
+    reader.skip_bytes(8).map_err(to_wasm_error)?;
+
+    let mut lowered = wasm[..8].to_vec();
+    let mut signatures = HashMap::new();
+    let mut globals = HashMap::new();
+    let mut global_count = 0;
+    let mut table_elements = Vec::new();
+    let mut shrunk_by = None;
+
+    while !reader.eof() {
+        let section_start = reader.current_position();
+        let id = reader.read_u8().map_err(to_wasm_error)?;
+        let size = reader.read_var_u32().map_err(to_wasm_error)? as usize;
+        let contents_offset = reader.current_position();
+        let contents = reader.read_bytes(size).map_err(to_wasm_error)?;
+
+        if id == CODE_SECTION_ID {
+            shrunk_by = Some(section_start as isize - lowered.len() as isize);
+        }
+
+        let contents = match id {
+            TYPE_SECTION_ID => lower_type_section(contents, contents_offset, &mut signatures)
+                .map_err(to_wasm_error)?,
+            IMPORT_SECTION_ID => lower_import_section(
+                contents,
+                contents_offset,
+                &mut globals,
+                &mut global_count,
+                &mut table_elements,
+            )
+            .map_err(to_wasm_error)?,
+            TABLE_SECTION_ID => lower_table_section(contents, contents_offset, &mut table_elements)
+                .map_err(to_wasm_error)?,
+            GLOBAL_SECTION_ID => {
+                lower_global_section(contents, contents_offset, &mut globals, &mut global_count)?
+            }
+            _ => contents.to_vec(),
+        };
+
+        write_var_u32(&mut lowered, id);
+        write_var_u32(&mut lowered, contents.len() as u32);
+        lowered.extend_from_slice(&contents);
+    }
+
+    if lowered == wasm {
+        return Ok(None);
+    }
+
+    Ok(Some(LoweredTypes {
+        shrunk_by: shrunk_by.unwrap_or(wasm.len() as isize - lowered.len() as isize),
+        wasm: lowered,
+        signatures,
+        globals,
+        table_elements,
+    }))
+}
+
+fn read_value_type(reader: &mut BinaryReader) -> Result<Type, BinaryReaderError> {
+    let offset = reader.original_position();
+    Ok(match reader.read_type()? {
+        wasmparser::Type::I32 => Type::I32,
+        wasmparser::Type::I64 => Type::I64,
+        wasmparser::Type::F32 => Type::F32,
+        wasmparser::Type::F64 => Type::F64,
+        wasmparser::Type::AnyFunc => Type::FuncRef,
+        wasmparser::Type::AnyRef => Type::AnyRef,
+        _ => Err(BinaryReaderError {
+            message: "invalid value type",
+            offset,
+        })?,
+    })
+}
+
+/// Reads a value type, rewriting it in `lowered` if it is a reference type.
+fn lower_value_type(
+    reader: &mut BinaryReader,
+    lowered: &mut [u8],
+) -> Result<Type, BinaryReaderError> {
+    let position = reader.current_position();
+    let ty = read_value_type(reader)?;
+    if ty.is_reference() {
+        lowered[position] = I64_TYPE;
+    }
+    Ok(ty)
+}
+
+/// Reads a table type, rewriting `anyref` to `anyfunc` in `lowered`
+/// so that `cranelift-wasm` can read it.
+fn lower_table_type(
+    reader: &mut BinaryReader,
+    lowered: &mut [u8],
+) -> Result<ElementType, BinaryReaderError> {
+    let position = reader.current_position();
+    let element = match reader.read_u8()? as u8 {
+        ANYREF_TYPE => {
+            lowered[position] = FUNCREF_TYPE;
+            ElementType::AnyRef
+        }
+        _ => ElementType::Anyfunc,
+    };
+    read_limits(reader)?;
+    Ok(element)
+}
+
+fn read_limits(reader: &mut BinaryReader) -> Result<(), BinaryReaderError> {
+    let flags = reader.read_var_u32()?;
+    reader.read_var_u32()?;
+    if flags & 1 != 0 {
+        reader.read_var_u32()?;
+    }
+    Ok(())
+}
+
+fn lower_type_section(
+    contents: &[u8],
+    offset: usize,
+    signatures: &mut HashMap<u32, FuncSig>,
+) -> Result<Vec<u8>, BinaryReaderError> {
+    let mut reader = BinaryReader::new_with_offset(contents, offset);
+    let mut lowered = contents.to_vec();
+
+    for index in 0..reader.read_var_u32()? {
+        // The form, which is always `func`.
+        reader.read_u8()?;
+
+        let mut types = [Vec::new(), Vec::new()];
+        for types in &mut types {
+            for _ in 0..reader.read_var_u32()? {
+                types.push(lower_value_type(&mut reader, &mut lowered)?);
+            }
+        }
+
+        let [params, returns] = types;
+        if params.iter().chain(&returns).any(|ty| ty.is_reference()) {
+            signatures.insert(index, FuncSig::new(params, returns));
+        }
+    }
+
+    Ok(lowered)
+}
+
+fn lower_import_section(
+    contents: &[u8],
+    offset: usize,
+    globals: &mut HashMap<u32, Type>,
+    global_count: &mut u32,
+    table_elements: &mut Vec<ElementType>,
+) -> Result<Vec<u8>, BinaryReaderError> {
+    let mut reader = BinaryReader::new_with_offset(contents, offset);
+    let mut lowered = contents.to_vec();
+
+    for _ in 0..reader.read_var_u32()? {
+        // The module and field names.
+        reader.read_string()?;
+        reader.read_string()?;
+
+        match reader.read_u8()? {
+            TABLE_IMPORT_KIND => table_elements.push(lower_table_type(&mut reader, &mut lowered)?),
+            MEMORY_IMPORT_KIND => read_limits(&mut reader)?,
+            GLOBAL_IMPORT_KIND => {
+                let ty = lower_value_type(&mut reader, &mut lowered)?;
+                if ty.is_reference() {
+                    globals.insert(*global_count, ty);
+                }
+                *global_count += 1;
+                // The mutability.
+                reader.read_u8()?;
+            }
+            // A function's type index.
+            _ => {
+                reader.read_var_u32()?;
+            }
+        }
+    }
+
+    Ok(lowered)
+}
+
+fn lower_table_section(
+    contents: &[u8],
+    offset: usize,
+    table_elements: &mut Vec<ElementType>,
+) -> Result<Vec<u8>, BinaryReaderError> {
+    let mut reader = BinaryReader::new_with_offset(contents, offset);
+    let mut lowered = contents.to_vec();
+
+    for _ in 0..reader.read_var_u32()? {
+        table_elements.push(lower_table_type(&mut reader, &mut lowered)?);
+    }
+
+    Ok(lowered)
+}
+
+/// Rewrites the types of the reference typed globals to `i64`,
+/// and their `ref.null` initializers to `i64.const 0`.
+fn lower_global_section(
+    contents: &[u8],
+    offset: usize,
+    globals: &mut HashMap<u32, Type>,
+    global_count: &mut u32,
+) -> WasmResult<Vec<u8>> {
+    let to_wasm_error = |e: BinaryReaderError| WasmError::InvalidWebAssembly {
+        message: e.message,
+        offset: e.offset,
+    };
+
+    let mut reader = BinaryReader::new_with_offset(contents, offset);
+    let mut lowered = Vec::new();
+
+    let count = reader.read_var_u32().map_err(to_wasm_error)?;
+    write_var_u32(&mut lowered, count);
+    for _ in 0..count {
+        let global_type = contents[reader.current_position()];
+        let ty = read_value_type(&mut reader).map_err(to_wasm_error)?;
+        let mutability = reader.read_u8().map_err(to_wasm_error)? as u8;
+        let init_expr = match contents[reader.current_position()] as u32 {
+            REF_FUNC_OPCODE => {
+                return Err(WasmError::Unsupported("`ref.func` global initializers"));
+            }
+            _ => read_init_expr(&mut reader).map_err(to_wasm_error)?,
+        };
+
+        if ty.is_reference() {
+            globals.insert(*global_count, ty);
+            lowered.extend_from_slice(&[I64_TYPE, mutability]);
+            match init_expr[0] as u32 {
+                REF_NULL_OPCODE => lowered.extend_from_slice(&[I64_CONST_OPCODE, 0, END_OPCODE]),
+                // `global.get` of an imported reference typed global.
+                _ => lowered.extend_from_slice(init_expr),
+            }
+        } else {
+            lowered.extend_from_slice(&[global_type, mutability]);
+            lowered.extend_from_slice(init_expr);
+        }
+        *global_count += 1;
+    }
+
+    Ok(lowered)
+}
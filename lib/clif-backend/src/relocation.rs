@@ -2,8 +2,13 @@
 //! This structures are used by Cranelift when compiling functions to mark
 //! any other calls that this function is doing, so we can "patch" the
 //! function addrs in runtime with the functions we need.
+use crate::{
+    atomics::AtomicOpKind, bulk_memory::BulkMemoryOpKind, reference_types::ReferenceOpKind,
+    simd::SimdOpKind,
+};
 use cranelift_codegen::binemit;
 use cranelift_codegen::ir::{self, ExternalName, SourceLoc};
+use cranelift_codegen::isa;
 use wasmer_runtime_core::{
     structures::TypedIndex,
     types::{FuncIndex, SigIndex},
@@ -13,6 +18,10 @@ pub mod call_names {
     pub const LOCAL_NAMESPACE: u32 = 1;
     pub const IMPORT_NAMESPACE: u32 = 2;
     pub const SIG_NAMESPACE: u32 = 3;
+    pub const ATOMIC_NAMESPACE: u32 = 4;
+    pub const BULK_MEMORY_NAMESPACE: u32 = 5;
+    pub const REFERENCE_NAMESPACE: u32 = 6;
+    pub const SIMD_NAMESPACE: u32 = 7;
 
     pub const STATIC_MEM_GROW: u32 = 0;
     pub const STATIC_MEM_SIZE: u32 = 1;
@@ -20,8 +29,54 @@ pub mod call_names {
     pub const SHARED_STATIC_MEM_SIZE: u32 = 3;
     pub const DYNAMIC_MEM_GROW: u32 = 4;
     pub const DYNAMIC_MEM_SIZE: u32 = 5;
+
+    pub const ATOMIC_LOAD: u32 = 0;
+    pub const ATOMIC_STORE: u32 = 1;
+    pub const ATOMIC_RMW_ADD: u32 = 2;
+    pub const ATOMIC_RMW_SUB: u32 = 3;
+    pub const ATOMIC_RMW_AND: u32 = 4;
+    pub const ATOMIC_RMW_OR: u32 = 5;
+    pub const ATOMIC_RMW_XOR: u32 = 6;
+    pub const ATOMIC_RMW_XCHG: u32 = 7;
+    pub const ATOMIC_CMPXCHG: u32 = 8;
+    pub const ATOMIC_WAIT: u32 = 9;
+    pub const ATOMIC_NOTIFY: u32 = 10;
+
+    pub const MEMORY_INIT: u32 = 0;
+    pub const DATA_DROP: u32 = 1;
+    pub const MEMORY_COPY: u32 = 2;
+    pub const MEMORY_FILL: u32 = 3;
+    pub const TABLE_INIT: u32 = 4;
+    pub const ELEM_DROP: u32 = 5;
+    pub const TABLE_COPY: u32 = 6;
+
+    pub const REF_FUNC: u32 = 0;
+    pub const TABLE_GET: u32 = 1;
+    pub const TABLE_SET: u32 = 2;
+    pub const TABLE_SIZE: u32 = 3;
+    pub const TABLE_GROW: u32 = 4;
+    pub const TABLE_FILL: u32 = 5;
+
+    pub const SIMD_SPLAT: u32 = 0;
+    pub const SIMD_EXTRACT_LANE: u32 = 1;
+    pub const SIMD_REPLACE_LANE: u32 = 2;
+    pub const SIMD_UNARY: u32 = 3;
+    pub const SIMD_BINARY: u32 = 4;
+    pub const SIMD_TERNARY: u32 = 5;
 }
 
+/// The `TrapCode::User` code cranelift-wasm uses for `unreachable`.
+pub const UNREACHABLE_TRAP_CODE: u16 = 0;
+
+/// The `TrapCode::User` code used when an atomic access is misaligned.
+pub const UNALIGNED_ATOMIC_TRAP_CODE: u16 = 1;
+
+/// The `TrapCode::User` code used when metered code runs out of fuel.
+pub const OUT_OF_FUEL_TRAP_CODE: u16 = 2;
+
+/// The `TrapCode::User` code used when running code is interrupted.
+pub const INTERRUPTED_TRAP_CODE: u16 = 3;
+
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Reloc {
     Abs8,
@@ -87,6 +142,10 @@ pub enum RelocationType {
     Intrinsic(String),
     LibCall(LibCall),
     VmCall(VmCall),
+    Atomic(AtomicOpKind),
+    BulkMemory(BulkMemoryOpKind),
+    Reference(ReferenceOpKind),
+    Simd(SimdOpKind),
     Signature(SigIndex),
 }
 
@@ -160,6 +219,48 @@ impl binemit::RelocSink for RelocSink {
                         _ => unimplemented!(),
                     })),
                     SIG_NAMESPACE => RelocationType::Signature(SigIndex::new(index as usize)),
+                    ATOMIC_NAMESPACE => RelocationType::Atomic(match index {
+                        ATOMIC_LOAD => AtomicOpKind::Load,
+                        ATOMIC_STORE => AtomicOpKind::Store,
+                        ATOMIC_RMW_ADD => AtomicOpKind::RmwAdd,
+                        ATOMIC_RMW_SUB => AtomicOpKind::RmwSub,
+                        ATOMIC_RMW_AND => AtomicOpKind::RmwAnd,
+                        ATOMIC_RMW_OR => AtomicOpKind::RmwOr,
+                        ATOMIC_RMW_XOR => AtomicOpKind::RmwXor,
+                        ATOMIC_RMW_XCHG => AtomicOpKind::RmwXchg,
+                        ATOMIC_CMPXCHG => AtomicOpKind::Cmpxchg,
+                        ATOMIC_WAIT => AtomicOpKind::Wait,
+                        ATOMIC_NOTIFY => AtomicOpKind::Notify,
+                        _ => unimplemented!(),
+                    }),
+                    BULK_MEMORY_NAMESPACE => RelocationType::BulkMemory(match index {
+                        MEMORY_INIT => BulkMemoryOpKind::MemoryInit,
+                        DATA_DROP => BulkMemoryOpKind::DataDrop,
+                        MEMORY_COPY => BulkMemoryOpKind::MemoryCopy,
+                        MEMORY_FILL => BulkMemoryOpKind::MemoryFill,
+                        TABLE_INIT => BulkMemoryOpKind::TableInit,
+                        ELEM_DROP => BulkMemoryOpKind::ElemDrop,
+                        TABLE_COPY => BulkMemoryOpKind::TableCopy,
+                        _ => unimplemented!(),
+                    }),
+                    REFERENCE_NAMESPACE => RelocationType::Reference(match index {
+                        REF_FUNC => ReferenceOpKind::RefFunc,
+                        TABLE_GET => ReferenceOpKind::TableGet,
+                        TABLE_SET => ReferenceOpKind::TableSet,
+                        TABLE_SIZE => ReferenceOpKind::TableSize,
+                        TABLE_GROW => ReferenceOpKind::TableGrow,
+                        TABLE_FILL => ReferenceOpKind::TableFill,
+                        _ => unimplemented!(),
+                    }),
+                    SIMD_NAMESPACE => RelocationType::Simd(match index {
+                        SIMD_SPLAT => SimdOpKind::Splat,
+                        SIMD_EXTRACT_LANE => SimdOpKind::ExtractLane,
+                        SIMD_REPLACE_LANE => SimdOpKind::ReplaceLane,
+                        SIMD_UNARY => SimdOpKind::Unary,
+                        SIMD_BINARY => SimdOpKind::Binary,
+                        SIMD_TERNARY => SimdOpKind::Ternary,
+                        _ => unimplemented!(),
+                    }),
                     _ => unimplemented!(),
                 };
                 self.external_relocs.push(ExternalRelocation {
@@ -248,12 +349,16 @@ pub struct TrapData {
 #[derive(Serialize, Deserialize)]
 pub struct TrapSink {
     trap_datas: Vec<(usize, TrapData)>,
+    /// The offset of each instruction in the code, sorted,
+    /// with the offset of the wasm operator it came from.
+    source_locs: Vec<(usize, u32)>,
 }
 
 impl TrapSink {
     pub fn new() -> TrapSink {
         TrapSink {
             trap_datas: Vec::new(),
+            source_locs: Vec::new(),
         }
     }
 
@@ -264,6 +369,20 @@ impl TrapSink {
             .map(|(_, trap_data)| *trap_data)
     }
 
+    /// Finds the last instruction that starts at or before `offset`,
+    /// returning where it starts and its offset in the wasm module.
+    pub fn lookup_source_loc(&self, offset: usize) -> Option<(usize, u32)> {
+        let index = match self
+            .source_locs
+            .binary_search_by_key(&offset, |&(inst_offset, _)| inst_offset)
+        {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        Some(self.source_locs[index])
+    }
+
     pub fn drain_local(&mut self, current_func_offset: usize, local: &mut LocalTrapSink) {
         self.trap_datas.extend(
             local
@@ -271,16 +390,42 @@ impl TrapSink {
                 .drain(..)
                 .map(|(offset, trap_data)| (current_func_offset + offset, trap_data)),
         );
+        self.source_locs.extend(
+            local
+                .source_locs
+                .drain(..)
+                .map(|(offset, srcloc)| (current_func_offset + offset, srcloc)),
+        );
     }
 }
 
 pub struct LocalTrapSink {
     trap_datas: Vec<(usize, TrapData)>,
+    source_locs: Vec<(usize, u32)>,
 }
 
 impl LocalTrapSink {
     pub fn new() -> Self {
-        LocalTrapSink { trap_datas: vec![] }
+        LocalTrapSink {
+            trap_datas: vec![],
+            source_locs: vec![],
+        }
+    }
+
+    /// Records where the instructions of a compiled function came from.
+    ///
+    /// This has to be called after the function has been emitted,
+    /// while `func` still has its final layout and encodings.
+    pub fn record_source_locs(&mut self, isa: &isa::TargetIsa, func: &ir::Function) {
+        let encinfo = isa.encoding_info();
+        for ebb in func.layout.ebbs() {
+            for (offset, inst, _size) in func.inst_offsets(ebb, &encinfo) {
+                let srcloc = func.srclocs[inst];
+                if !srcloc.is_default() {
+                    self.source_locs.push((offset as usize, srcloc.bits()));
+                }
+            }
+        }
     }
 }
 
@@ -1,11 +1,15 @@
 use crate::{cache::BackendCache, trampoline::Trampolines};
 use crate::{
+    atomics::AtomicOpKind,
+    bulk_memory::BulkMemoryOpKind,
     libcalls,
+    reference_types::ReferenceOpKind,
     relocation::{
         ExternalRelocation, LibCall, LocalRelocation, LocalTrapSink, Reloc, RelocSink,
         RelocationType, TrapSink, VmCall, VmCallKind,
     },
     signal::HandlerData,
+    simd::SimdOpKind,
 };
 use rayon::prelude::*;
 
@@ -70,8 +74,12 @@ impl FuncResolverBuilder {
                 .map_err(|e| CacheError::Unknown(e.to_string()))?;
         }
 
-        let handler_data =
-            HandlerData::new(backend_cache.trap_sink, code.as_ptr() as _, code.size());
+        let handler_data = HandlerData::new(
+            backend_cache.trap_sink,
+            &backend_cache.offsets,
+            code.as_ptr() as _,
+            code.size(),
+        );
 
         Ok((
             Self {
@@ -119,6 +127,7 @@ impl FuncResolverBuilder {
                             &mut local_trap_sink,
                         )
                         .map_err(|e| CompileError::InternalError { msg: e.to_string() })?;
+                        local_trap_sink.record_source_locs(isa, &ctx.func);
                         ctx.clear();
                         Ok((code_buf, (reloc_sink, local_trap_sink)))
                     },
@@ -178,8 +187,12 @@ impl FuncResolverBuilder {
             previous_end = new_end;
         }
 
-        let handler_data =
-            HandlerData::new(Arc::new(trap_sink), memory.as_ptr() as _, memory.size());
+        let handler_data = HandlerData::new(
+            Arc::new(trap_sink),
+            &map,
+            memory.as_ptr() as _,
+            memory.size(),
+        );
 
         let mut func_resolver_builder = Self {
             map,
@@ -259,8 +272,12 @@ impl FuncResolverBuilder {
                             VmCallKind::StaticMemoryGrow => vmcalls::local_static_memory_grow as _,
                             VmCallKind::StaticMemorySize => vmcalls::local_static_memory_size as _,
 
-                            VmCallKind::SharedStaticMemoryGrow => unimplemented!(),
-                            VmCallKind::SharedStaticMemorySize => unimplemented!(),
+                            VmCallKind::SharedStaticMemoryGrow => {
+                                vmcalls::local_shared_static_memory_grow as _
+                            }
+                            VmCallKind::SharedStaticMemorySize => {
+                                vmcalls::local_shared_static_memory_size as _
+                            }
 
                             VmCallKind::DynamicMemoryGrow => {
                                 vmcalls::local_dynamic_memory_grow as _
@@ -277,8 +294,12 @@ impl FuncResolverBuilder {
                                 vmcalls::imported_static_memory_size as _
                             }
 
-                            VmCallKind::SharedStaticMemoryGrow => unimplemented!(),
-                            VmCallKind::SharedStaticMemorySize => unimplemented!(),
+                            VmCallKind::SharedStaticMemoryGrow => {
+                                vmcalls::imported_shared_static_memory_grow as _
+                            }
+                            VmCallKind::SharedStaticMemorySize => {
+                                vmcalls::imported_shared_static_memory_size as _
+                            }
 
                             VmCallKind::DynamicMemoryGrow => {
                                 vmcalls::imported_dynamic_memory_grow as _
@@ -288,6 +309,44 @@ impl FuncResolverBuilder {
                             }
                         },
                     },
+                    RelocationType::Atomic(kind) => match kind {
+                        AtomicOpKind::Load => vmcalls::atomic_load as _,
+                        AtomicOpKind::Store => vmcalls::atomic_store as _,
+                        AtomicOpKind::RmwAdd => vmcalls::atomic_rmw_add as _,
+                        AtomicOpKind::RmwSub => vmcalls::atomic_rmw_sub as _,
+                        AtomicOpKind::RmwAnd => vmcalls::atomic_rmw_and as _,
+                        AtomicOpKind::RmwOr => vmcalls::atomic_rmw_or as _,
+                        AtomicOpKind::RmwXor => vmcalls::atomic_rmw_xor as _,
+                        AtomicOpKind::RmwXchg => vmcalls::atomic_rmw_xchg as _,
+                        AtomicOpKind::Cmpxchg => vmcalls::atomic_cmpxchg as _,
+                        AtomicOpKind::Wait => vmcalls::atomic_wait as _,
+                        AtomicOpKind::Notify => vmcalls::atomic_notify as _,
+                    },
+                    RelocationType::BulkMemory(kind) => match kind {
+                        BulkMemoryOpKind::MemoryInit => vmcalls::memory_init as _,
+                        BulkMemoryOpKind::DataDrop => vmcalls::data_drop as _,
+                        BulkMemoryOpKind::MemoryCopy => vmcalls::memory_copy as _,
+                        BulkMemoryOpKind::MemoryFill => vmcalls::memory_fill as _,
+                        BulkMemoryOpKind::TableInit => vmcalls::table_init as _,
+                        BulkMemoryOpKind::ElemDrop => vmcalls::elem_drop as _,
+                        BulkMemoryOpKind::TableCopy => vmcalls::table_copy as _,
+                    },
+                    RelocationType::Reference(kind) => match kind {
+                        ReferenceOpKind::RefFunc => vmcalls::ref_func as _,
+                        ReferenceOpKind::TableGet => vmcalls::table_get as _,
+                        ReferenceOpKind::TableSet => vmcalls::table_set as _,
+                        ReferenceOpKind::TableSize => vmcalls::table_size as _,
+                        ReferenceOpKind::TableGrow => vmcalls::table_grow as _,
+                        ReferenceOpKind::TableFill => vmcalls::table_fill as _,
+                    },
+                    RelocationType::Simd(kind) => match kind {
+                        SimdOpKind::Splat => vmcalls::simd_splat as _,
+                        SimdOpKind::ExtractLane => vmcalls::simd_extract_lane as _,
+                        SimdOpKind::ReplaceLane => vmcalls::simd_replace_lane as _,
+                        SimdOpKind::Unary => vmcalls::simd_unary as _,
+                        SimdOpKind::Binary => vmcalls::simd_binary as _,
+                        SimdOpKind::Ternary => vmcalls::simd_ternary as _,
+                    },
                     RelocationType::Signature(sig_index) => {
                         let sig_index =
                             SigRegistry.lookup_sig_index(Arc::clone(&signatures[sig_index]));
@@ -386,8 +445,13 @@ extern "C" fn f32_print(_ctx: &mut vm::Ctx, n: f32) {
 extern "C" fn f64_print(_ctx: &mut vm::Ctx, n: f64) {
     print!(" f64: {},", n);
 }
-extern "C" fn start_debug(_ctx: &mut vm::Ctx, func_index: u32) {
-    print!("func ({}), args: [", func_index);
+extern "C" fn start_debug(ctx: &mut vm::Ctx, func_index: u32) {
+    let info = ctx.module_info();
+    let func_index = LocalFuncIndex::new(func_index as usize).convert_up(info);
+    match info.func_name(func_index) {
+        Some(name) => print!("func {} ({}), args: [", name, func_index.index()),
+        None => print!("func ({}), args: [", func_index.index()),
+    }
 }
 extern "C" fn end_debug(_ctx: &mut vm::Ctx) {
     println!(" ]");
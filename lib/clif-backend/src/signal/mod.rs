@@ -1,14 +1,25 @@
-use crate::relocation::{TrapData, TrapSink};
+use crate::relocation::{
+    TrapCode, TrapData, TrapSink, INTERRUPTED_TRAP_CODE, OUT_OF_FUEL_TRAP_CODE,
+    UNALIGNED_ATOMIC_TRAP_CODE, UNREACHABLE_TRAP_CODE,
+};
 use crate::trampoline::Trampolines;
 use hashbrown::HashSet;
 use libc::c_void;
-use std::{cell::Cell, sync::Arc};
+use std::{
+    cell::Cell,
+    ptr::NonNull,
+    sync::{Arc, RwLock},
+};
 use wasmer_runtime_core::{
     backend::{ProtectedCaller, Token, UserTrapper},
-    error::RuntimeResult,
+    error::{FrameInfo, RuntimeError, RuntimeResult, TrapKind},
     export::Context,
     module::{ExportIndex, ModuleInfo, ModuleInner},
-    types::{FuncIndex, FuncSig, LocalOrImport, SigIndex, Type, Value},
+    structures::{Map, TypedIndex},
+    types::{
+        AnyRef, FuncIndex, FuncRef, FuncSig, LocalFuncIndex, LocalOrImport, MemoryIndex, SigIndex,
+        TableIndex, Type, Value,
+    },
     vm::{self, ImportBacking},
 };
 
@@ -25,14 +36,66 @@ pub use self::unix::*;
 pub use self::windows::*;
 
 thread_local! {
-    pub static TRAP_EARLY_DATA: Cell<Option<String>> = Cell::new(None);
+    pub static TRAP_EARLY_DATA: Cell<Option<RuntimeError>> = Cell::new(None);
+}
+
+lazy_static! {
+    /// The handler data of every module that is alive. Webassembly code calls
+    /// the code of other modules through its imports, so a trap can happen
+    /// outside of the module that `call_protected` was called for.
+    static ref MODULE_HANDLER_DATA: RwLock<Vec<HandlerData>> = RwLock::new(Vec::new());
+}
+
+/// Looks up the trap at `ip`, in the code of the module that `handler_data`
+/// belongs to or else in the code of any other module.
+pub fn lookup_trap(handler_data: &HandlerData, ip: *const c_void) -> Option<TrapData> {
+    handler_data.lookup(ip).or_else(|| {
+        MODULE_HANDLER_DATA
+            .read()
+            .unwrap()
+            .iter()
+            .find(|handler_data| handler_data.contains(ip))
+            .and_then(|handler_data| handler_data.lookup(ip))
+    })
+}
+
+/// Works out why an instruction with trap code `trapcode` trapped.
+///
+/// `in_func` says where it happened, for traps that only have a message.
+pub fn trap_kind(trapcode: TrapCode, in_func: &str) -> TrapKind {
+    match trapcode {
+        TrapCode::StackOverflow => TrapKind::StackOverflow,
+        TrapCode::HeapOutOfBounds => TrapKind::OutOfBoundsAccess {
+            memory: MemoryIndex::new(0),
+            addr: None,
+        },
+        TrapCode::TableOutOfBounds => TrapKind::TableOutOfBounds {
+            table: TableIndex::new(0),
+        },
+        TrapCode::IndirectCallToNull => TrapKind::IndirectCallToNull {
+            table: TableIndex::new(0),
+        },
+        TrapCode::BadSignature => TrapKind::IndirectCallSignature {
+            table: TableIndex::new(0),
+        },
+        TrapCode::IntegerOverflow => TrapKind::IntegerOverflow,
+        TrapCode::IntegerDivisionByZero => TrapKind::IntegerDivisionByZero,
+        TrapCode::BadConversionToInteger => TrapKind::BadConversionToInteger,
+        TrapCode::User(UNREACHABLE_TRAP_CODE) => TrapKind::Unreachable,
+        TrapCode::User(UNALIGNED_ATOMIC_TRAP_CODE) => TrapKind::UnalignedAtomic,
+        TrapCode::User(OUT_OF_FUEL_TRAP_CODE) => TrapKind::OutOfFuel,
+        TrapCode::User(INTERRUPTED_TRAP_CODE) => TrapKind::Interrupted,
+        _ => TrapKind::Unknown {
+            msg: format!("unknown trap{}", in_func),
+        },
+    }
 }
 
 pub struct Trapper;
 
 impl UserTrapper for Trapper {
-    unsafe fn do_early_trap(&self, msg: String) -> ! {
-        TRAP_EARLY_DATA.with(|cell| cell.set(Some(msg)));
+    unsafe fn do_early_trap(&self, error: RuntimeError) -> ! {
+        TRAP_EARLY_DATA.with(|cell| cell.set(Some(error)));
         trigger_trap()
     }
 }
@@ -59,6 +122,11 @@ impl Caller {
             func_export_set.insert(start_func_index);
         }
 
+        MODULE_HANDLER_DATA
+            .write()
+            .unwrap()
+            .push(handler_data.clone());
+
         Self {
             func_export_set,
             handler_data,
@@ -67,6 +135,16 @@ impl Caller {
     }
 }
 
+impl Drop for Caller {
+    fn drop(&mut self) {
+        let exec_buffer_ptr = self.handler_data.exec_buffer_ptr;
+        MODULE_HANDLER_DATA
+            .write()
+            .unwrap()
+            .retain(|handler_data| handler_data.exec_buffer_ptr != exec_buffer_ptr);
+    }
+}
+
 impl ProtectedCaller for Caller {
     fn call(
         &self,
@@ -83,31 +161,40 @@ impl ProtectedCaller for Caller {
         let vmctx_ptr = match ctx {
             Context::External(external_vmctx) => external_vmctx,
             Context::Internal => vmctx,
+            Context::Env(_) | Context::Dynamic(_) => {
+                unreachable!("imported closures are called through their import")
+            }
         };
 
         assert!(self.func_export_set.contains(&func_index));
 
-        assert!(
-            signature.returns().len() <= 1,
-            "multi-value returns not yet supported"
-        );
-
         assert!(
             signature.check_param_value_types(params),
             "incorrect signature"
         );
 
+        // A `v128` takes up two slots, the low half first.
         let param_vec: Vec<u64> = params
             .iter()
-            .map(|val| match val {
-                Value::I32(x) => *x as u64,
-                Value::I64(x) => *x as u64,
-                Value::F32(x) => x.to_bits() as u64,
-                Value::F64(x) => x.to_bits(),
+            .flat_map(|val| match val {
+                Value::I32(x) => vec![*x as u64],
+                Value::I64(x) => vec![*x as u64],
+                Value::F32(x) => vec![x.to_bits() as u64],
+                Value::F64(x) => vec![x.to_bits()],
+                Value::AnyRef(x) => vec![x.to_bits()],
+                Value::FuncRef(x) => vec![x.to_bits()],
+                Value::V128(x) => vec![*x as u64, (*x >> 64) as u64],
             })
             .collect();
 
-        let mut return_vec = vec![0; signature.returns().len()];
+        let mut return_vec = vec![
+            0;
+            signature
+                .returns()
+                .iter()
+                .map(|ty| ty.buffer_len())
+                .sum()
+        ];
 
         let trampoline = self
             .trampolines
@@ -115,7 +202,7 @@ impl ProtectedCaller for Caller {
             .expect("that trampoline doesn't exist");
 
         #[cfg(not(target_os = "windows"))]
-        call_protected(&self.handler_data, || unsafe {
+        call_protected(&self.handler_data, &module.info, || unsafe {
             // Leap of faith.
             trampoline(
                 vmctx_ptr,
@@ -129,6 +216,7 @@ impl ProtectedCaller for Caller {
         #[cfg(target_os = "windows")]
         call_protected(
             &self.handler_data,
+            &module.info,
             trampoline,
             vmctx_ptr,
             func_ptr,
@@ -136,14 +224,24 @@ impl ProtectedCaller for Caller {
             return_vec.as_mut_ptr(),
         )?;
 
-        Ok(return_vec
+        let mut return_slots = return_vec.into_iter();
+        Ok(signature
+            .returns()
             .iter()
-            .zip(signature.returns().iter())
-            .map(|(&x, ty)| match ty {
-                Type::I32 => Value::I32(x as i32),
-                Type::I64 => Value::I64(x as i64),
-                Type::F32 => Value::F32(f32::from_bits(x as u32)),
-                Type::F64 => Value::F64(f64::from_bits(x as u64)),
+            .map(|ty| {
+                let x = return_slots.next().unwrap();
+                match ty {
+                    Type::I32 => Value::I32(x as i32),
+                    Type::I64 => Value::I64(x as i64),
+                    Type::F32 => Value::F32(f32::from_bits(x as u32)),
+                    Type::F64 => Value::F64(f64::from_bits(x as u64)),
+                    Type::AnyRef => Value::AnyRef(unsafe { AnyRef::from_bits(x) }),
+                    Type::FuncRef => Value::FuncRef(unsafe { FuncRef::from_bits(x) }),
+                    Type::V128 => {
+                        let high = return_slots.next().unwrap();
+                        Value::V128(u128::from(high) << 64 | u128::from(x))
+                    }
+                }
             })
             .collect())
     }
@@ -151,6 +249,10 @@ impl ProtectedCaller for Caller {
     fn get_early_trapper(&self) -> Box<dyn UserTrapper> {
         Box::new(Trapper)
     }
+
+    fn get_dynamic_trampoline(&self, sig_index: SigIndex) -> Option<NonNull<vm::Func>> {
+        self.trampolines.lookup_dynamic(sig_index)
+    }
 }
 
 fn get_func_from_index(
@@ -194,6 +296,8 @@ unsafe impl Sync for HandlerData {}
 #[derive(Clone)]
 pub struct HandlerData {
     pub trap_data: Arc<TrapSink>,
+    /// Where each local function starts in the executable buffer.
+    func_offsets: Arc<[usize]>,
     exec_buffer_ptr: *const c_void,
     exec_buffer_size: usize,
 }
@@ -201,16 +305,86 @@ pub struct HandlerData {
 impl HandlerData {
     pub fn new(
         trap_data: Arc<TrapSink>,
+        func_offsets: &Map<LocalFuncIndex, usize>,
         exec_buffer_ptr: *const c_void,
         exec_buffer_size: usize,
     ) -> Self {
         Self {
             trap_data,
+            func_offsets: func_offsets.iter().map(|(_, &offset)| offset).collect(),
             exec_buffer_ptr,
             exec_buffer_size,
         }
     }
 
+    /// Whether `ip` is in the code of this module.
+    pub fn contains(&self, ip: *const c_void) -> bool {
+        let ip = ip as usize;
+        let buffer_ptr = self.exec_buffer_ptr as usize;
+        buffer_ptr <= ip && ip < buffer_ptr + self.exec_buffer_size
+    }
+
+    /// Returns the local function that `ip` is in.
+    pub fn lookup_func(&self, ip: *const c_void) -> Option<LocalFuncIndex> {
+        if self.contains(ip) {
+            let offset = ip as usize - self.exec_buffer_ptr as usize;
+            // Functions are laid out in order, so `ip` is in the
+            // last one that starts at or before it.
+            let index = match self.func_offsets.binary_search(&offset) {
+                Ok(index) => index,
+                Err(0) => return None,
+                Err(index) => index - 1,
+            };
+            Some(LocalFuncIndex::new(index))
+        } else {
+            None
+        }
+    }
+
+    /// Describes the functions that `frames` are in, skipping
+    /// any that aren't in the code of this module.
+    ///
+    /// The first frame is where the trap happened and the rest are
+    /// return addresses, which are looked up as the call before them.
+    pub fn backtrace(&self, info: &ModuleInfo, frames: &[*const c_void]) -> Vec<FrameInfo> {
+        frames
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &ip)| {
+                let ip = if i == 0 { ip } else { (ip as usize - 1) as _ };
+                self.frame_info(info, ip)
+            })
+            .collect()
+    }
+
+    fn frame_info(&self, info: &ModuleInfo, ip: *const c_void) -> Option<FrameInfo> {
+        let local_func_index = self.lookup_func(ip)?;
+        let func_start = self.func_offsets[local_func_index.index()];
+        let func_index = local_func_index.convert_up(info);
+
+        let module_offset = self
+            .trap_data
+            .lookup_source_loc(ip as usize - self.exec_buffer_ptr as usize)
+            .filter(|&(inst_offset, _)| inst_offset >= func_start)
+            .map(|(_, srcloc)| srcloc as usize);
+
+        Some(FrameInfo {
+            func_index,
+            func_name: info.func_name(func_index).map(str::to_string),
+            module_offset,
+        })
+    }
+
+    /// Describes the function that `ip` is in for trap messages,
+    /// using its name from the name section if it has one.
+    pub fn describe_func(&self, info: &ModuleInfo, ip: *const c_void) -> Option<String> {
+        let func_index = self.lookup_func(ip)?.convert_up(info);
+        Some(match info.func_name(func_index) {
+            Some(name) => format!("function `{}`", name),
+            None => format!("function {}", func_index.index()),
+        })
+    }
+
     pub fn lookup(&self, ip: *const c_void) -> Option<TrapData> {
         let ip = ip as usize;
         let buffer_ptr = self.exec_buffer_ptr as usize;
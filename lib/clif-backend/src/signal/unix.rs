@@ -9,19 +9,20 @@
 //! are very special, the async signal unsafety of Rust's TLS implementation generally does not affect the correctness here
 //! unless you have memory unsafety elsewhere in your code.
 //!
-use crate::relocation::{TrapCode, TrapData};
+use crate::relocation::TrapData;
 use crate::signal::HandlerData;
 use libc::{c_int, c_void, siginfo_t};
 use nix::sys::signal::{
     sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal, SIGBUS, SIGFPE, SIGILL, SIGSEGV,
 };
 use std::cell::{Cell, UnsafeCell};
-use std::ptr;
 use std::sync::Once;
+use std::{mem, ptr};
 use wasmer_runtime_core::{
-    error::{RuntimeError, RuntimeResult},
+    error::{RuntimeError, RuntimeResult, TrapKind},
+    module::ModuleInfo,
     structures::TypedIndex,
-    types::{MemoryIndex, TableIndex},
+    types::MemoryIndex,
 };
 
 extern "C" fn signal_trap_handler(
@@ -52,11 +53,26 @@ pub unsafe fn install_sighandler() {
 }
 
 const SETJMP_BUFFER_LEN: usize = 27;
+/// How close to the stack pointer a fault has to be to count as a stack overflow.
+const STACK_FAULT_DISTANCE: isize = 4096;
+/// The most frames a backtrace keeps, so a stack overflow doesn't
+/// produce one with hundreds of thousands of them.
+const MAX_BACKTRACE_FRAMES: usize = 1000;
 pub static SIGHANDLER_INIT: Once = Once::new();
 
 thread_local! {
     pub static SETJMP_BUFFER: UnsafeCell<[c_int; SETJMP_BUFFER_LEN]> = UnsafeCell::new([0; SETJMP_BUFFER_LEN]);
-    pub static CAUGHT_ADDRESSES: Cell<(*const c_void, *const c_void)> = Cell::new((ptr::null(), ptr::null()));
+    pub static CAUGHT_CONTEXT: Cell<FaultContext> = Cell::new(FaultContext {
+        faulting_addr: ptr::null(),
+        ip: ptr::null(),
+        sp: ptr::null(),
+        fp: ptr::null(),
+    });
+    /// The return addresses the signal handler collected, and how many
+    /// there are. The handler must not allocate, so the buffer has a fixed size.
+    pub static CAUGHT_FRAMES: UnsafeCell<([*const c_void; MAX_BACKTRACE_FRAMES], usize)> =
+        UnsafeCell::new(([ptr::null(); MAX_BACKTRACE_FRAMES], 0));
+    pub static CURRENT_HANDLER_DATA: Cell<*const HandlerData> = Cell::new(ptr::null());
     pub static CURRENT_EXECUTABLE_BUFFER: Cell<*const c_void> = Cell::new(ptr::null());
 }
 
@@ -66,7 +82,11 @@ pub unsafe fn trigger_trap() -> ! {
     longjmp(jmp_buf as *mut c_void, 0)
 }
 
-pub fn call_protected<T>(handler_data: &HandlerData, f: impl FnOnce() -> T) -> RuntimeResult<T> {
+pub fn call_protected<T>(
+    handler_data: &HandlerData,
+    info: &ModuleInfo,
+    f: impl FnOnce() -> T,
+) -> RuntimeResult<T> {
     unsafe {
         let jmp_buf = SETJMP_BUFFER.with(|buf| buf.get());
         let prev_jmp_buf = *jmp_buf;
@@ -75,49 +95,61 @@ pub fn call_protected<T>(handler_data: &HandlerData, f: impl FnOnce() -> T) -> R
             install_sighandler();
         });
 
+        let prev_handler_data = CURRENT_HANDLER_DATA.with(|cell| cell.replace(handler_data));
+        // Set up the buffer for backtraces here. Doing it lazily in the
+        // signal handler would overflow the handler's small stack.
+        CAUGHT_FRAMES.with(|cell| (*cell.get()).1 = 0);
+
         let signum = setjmp(jmp_buf as *mut _);
         if signum != 0 {
             *jmp_buf = prev_jmp_buf;
+            CURRENT_HANDLER_DATA.with(|cell| cell.set(prev_handler_data));
 
-            if let Some(msg) = super::TRAP_EARLY_DATA.with(|cell| cell.replace(None)) {
-                Err(RuntimeError::User { msg })
+            if let Some(error) = super::TRAP_EARLY_DATA.with(|cell| cell.replace(None)) {
+                Err(error)
             } else {
-                let (faulting_addr, inst_ptr) = CAUGHT_ADDRESSES.with(|cell| cell.get());
+                let context = CAUGHT_CONTEXT.with(|cell| cell.get());
+                let in_func = handler_data
+                    .describe_func(info, context.ip)
+                    .map(|func| format!(" in {}", func))
+                    .unwrap_or_default();
+                let backtrace = CAUGHT_FRAMES.with(|cell| {
+                    let (frames, len) = &mut *cell.get();
+                    handler_data.backtrace(info, &frames[..mem::replace(len, 0)])
+                });
 
+                let signal = Signal::from_c_int(signum);
                 if let Some(TrapData {
                     trapcode,
                     srcloc: _,
-                }) = handler_data.lookup(inst_ptr)
+                }) = super::lookup_trap(handler_data, context.ip)
                 {
-                    Err(match Signal::from_c_int(signum) {
-                        Ok(SIGILL) => match trapcode {
-                            TrapCode::BadSignature => RuntimeError::IndirectCallSignature {
-                                table: TableIndex::new(0),
-                            },
-                            TrapCode::IndirectCallToNull => RuntimeError::IndirectCallToNull {
-                                table: TableIndex::new(0),
-                            },
-                            TrapCode::HeapOutOfBounds => RuntimeError::OutOfBoundsAccess {
-                                memory: MemoryIndex::new(0),
-                                addr: None,
-                            },
-                            TrapCode::TableOutOfBounds => RuntimeError::TableOutOfBounds {
-                                table: TableIndex::new(0),
-                            },
-                            _ => RuntimeError::Unknown {
-                                msg: "unknown trap".to_string(),
-                            },
-                        },
-                        Ok(SIGSEGV) | Ok(SIGBUS) => RuntimeError::OutOfBoundsAccess {
+                    Err(RuntimeError::Trap {
+                        kind: super::trap_kind(trapcode, &in_func),
+                        backtrace,
+                    })
+                } else if (signal == Ok(SIGSEGV) || signal == Ok(SIGBUS))
+                    && (context.faulting_addr as isize - context.sp as isize).abs()
+                        < STACK_FAULT_DISTANCE
+                {
+                    // A fault right next to the stack pointer is a stack overflow. It
+                    // can happen outside of webassembly code, in the stack probe that
+                    // functions with large frames call, so there may be no backtrace.
+                    Err(RuntimeError::Trap {
+                        kind: TrapKind::StackOverflow,
+                        backtrace,
+                    })
+                } else if !backtrace.is_empty() && (signal == Ok(SIGSEGV) || signal == Ok(SIGBUS)) {
+                    // Webassembly code only touches its stack and its memories.
+                    Err(RuntimeError::Trap {
+                        kind: TrapKind::OutOfBoundsAccess {
                             memory: MemoryIndex::new(0),
                             addr: None,
                         },
-                        Ok(SIGFPE) => RuntimeError::IllegalArithmeticOperation,
-                        _ => unimplemented!(),
-                    }
-                    .into())
+                        backtrace,
+                    })
                 } else {
-                    let signal = match Signal::from_c_int(signum) {
+                    let signal = match signal {
                         Ok(SIGFPE) => "floating-point exception",
                         Ok(SIGILL) => "illegal instruction",
                         Ok(SIGSEGV) => "segmentation violation",
@@ -126,15 +158,24 @@ pub fn call_protected<T>(handler_data: &HandlerData, f: impl FnOnce() -> T) -> R
                         _ => "unkown trapped signal",
                     };
                     // When the trap-handler is fully implemented, this will return more information.
-                    Err(RuntimeError::Unknown {
-                        msg: format!("trap at {:p} - {}", faulting_addr, signal),
+                    let msg = format!(
+                        "trap at {:p}{} - {}",
+                        context.faulting_addr, in_func, signal
+                    );
+                    if backtrace.is_empty() {
+                        Err(RuntimeError::Unknown { msg })
+                    } else {
+                        Err(RuntimeError::Trap {
+                            kind: TrapKind::Unknown { msg },
+                            backtrace,
+                        })
                     }
-                    .into())
                 }
             }
         } else {
             let ret = f(); // TODO: Switch stack?
             *jmp_buf = prev_jmp_buf;
+            CURRENT_HANDLER_DATA.with(|cell| cell.set(prev_handler_data));
             Ok(ret)
         }
     }
@@ -151,17 +192,69 @@ pub unsafe fn do_unwind(signum: i32, siginfo: *const c_void, ucontext: *const c_
         ::std::process::abort();
     }
 
-    CAUGHT_ADDRESSES.with(|cell| cell.set(get_faulting_addr_and_ip(siginfo, ucontext)));
+    let context = get_fault_context(siginfo, ucontext);
+    CAUGHT_CONTEXT.with(|cell| cell.set(context));
+    CAUGHT_FRAMES.with(|cell| {
+        let (frames, len) = &mut *cell.get();
+        *len = walk_frames(&context, frames);
+    });
 
     longjmp(jmp_buf as *mut ::nix::libc::c_void, signum)
 }
 
+/// The state of the thread when a signal was raised.
+#[derive(Clone, Copy)]
+pub struct FaultContext {
+    faulting_addr: *const c_void,
+    ip: *const c_void,
+    sp: *const c_void,
+    fp: *const c_void,
+}
+
+/// Collects the instruction pointer and the return addresses of the
+/// webassembly frames below it, by following the frame pointers
+/// that Cranelift's prologues set up.
+///
+/// The walk stops at the first frame that isn't in the code of the
+/// module being called, or once `frames` is full. Nothing is collected
+/// unless the signal was raised in webassembly code.
+///
+/// This runs in the signal handler, so it fills in `frames` instead of
+/// allocating, and returns how many of them it filled in.
+unsafe fn walk_frames(context: &FaultContext, frames: &mut [*const c_void]) -> usize {
+    let handler_data = CURRENT_HANDLER_DATA.with(|cell| cell.get());
+    if handler_data.is_null() || !(*handler_data).contains(context.ip) {
+        return 0;
+    }
+
+    frames[0] = context.ip;
+    let mut len = 1;
+    let mut fp = context.fp;
+
+    // Each frame starts with the caller's frame pointer,
+    // followed by the return address into the caller.
+    while fp >= context.sp && (fp as *const usize).align_offset(mem::align_of::<usize>()) == 0 {
+        let return_addr = *(fp as *const *const c_void).offset(1);
+        if len == frames.len() || !(*handler_data).contains(return_addr) {
+            break;
+        }
+        frames[len] = return_addr;
+        len += 1;
+
+        let caller_fp = *(fp as *const *const c_void);
+        // Callers are always further up the stack.
+        if caller_fp <= fp {
+            break;
+        }
+        fp = caller_fp;
+    }
+
+    len
+}
+
 #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
-unsafe fn get_faulting_addr_and_ip(
-    siginfo: *const c_void,
-    ucontext: *const c_void,
-) -> (*const c_void, *const c_void) {
-    use libc::{ucontext_t, RIP};
+unsafe fn get_fault_context(siginfo: *const c_void, ucontext: *const c_void) -> FaultContext {
+    use libc::{ucontext_t, REG_RBP, REG_RIP, REG_RSP};
 
     #[allow(dead_code)]
     #[repr(C)]
@@ -177,16 +270,18 @@ unsafe fn get_faulting_addr_and_ip(
     let si_addr = (*siginfo).si_addr;
 
     let ucontext = ucontext as *const ucontext_t;
-    let rip = (*ucontext).uc_mcontext.gregs[RIP as usize];
+    let gregs = &(*ucontext).uc_mcontext.gregs;
 
-    (si_addr as _, rip as _)
+    FaultContext {
+        faulting_addr: si_addr as _,
+        ip: gregs[REG_RIP as usize] as _,
+        sp: gregs[REG_RSP as usize] as _,
+        fp: gregs[REG_RBP as usize] as _,
+    }
 }
 
 #[cfg(all(target_os = "macos", target_arch = "x86_64"))]
-unsafe fn get_faulting_addr_and_ip(
-    siginfo: *const c_void,
-    ucontext: *const c_void,
-) -> (*const c_void, *const c_void) {
+unsafe fn get_fault_context(siginfo: *const c_void, ucontext: *const c_void) -> FaultContext {
     #[allow(dead_code)]
     #[repr(C)]
     struct ucontext_t {
@@ -240,9 +335,14 @@ unsafe fn get_faulting_addr_and_ip(
     let si_addr = (*siginfo).si_addr;
 
     let ucontext = ucontext as *const ucontext_t;
-    let rip = (*(*ucontext).uc_mcontext).ss.rip;
+    let ss = &(*(*ucontext).uc_mcontext).ss;
 
-    (si_addr, rip as _)
+    FaultContext {
+        faulting_addr: si_addr,
+        ip: ss.rip as _,
+        sp: ss.rsp as _,
+        fp: ss.rbp as _,
+    }
 }
 
 #[cfg(not(any(
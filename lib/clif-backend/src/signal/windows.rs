@@ -1,4 +1,4 @@
-use crate::relocation::{TrapCode, TrapData};
+use crate::relocation::TrapData;
 use crate::signal::HandlerData;
 use crate::trampoline::Trampoline;
 use std::cell::Cell;
@@ -7,9 +7,10 @@ use std::ptr;
 use wasmer_runtime_core::vm::Ctx;
 use wasmer_runtime_core::vm::Func;
 use wasmer_runtime_core::{
-    error::{RuntimeError, RuntimeResult},
+    error::{RuntimeError, RuntimeResult, TrapKind},
+    module::ModuleInfo,
     structures::TypedIndex,
-    types::{MemoryIndex, TableIndex},
+    types::MemoryIndex,
 };
 use wasmer_win_exception_handler::CallProtectedData;
 pub use wasmer_win_exception_handler::_call_protected;
@@ -18,7 +19,7 @@ use winapi::um::minwinbase::{
     EXCEPTION_ACCESS_VIOLATION, EXCEPTION_FLT_DENORMAL_OPERAND, EXCEPTION_FLT_DIVIDE_BY_ZERO,
     EXCEPTION_FLT_INEXACT_RESULT, EXCEPTION_FLT_INVALID_OPERATION, EXCEPTION_FLT_OVERFLOW,
     EXCEPTION_FLT_STACK_CHECK, EXCEPTION_FLT_UNDERFLOW, EXCEPTION_ILLEGAL_INSTRUCTION,
-    EXCEPTION_INT_DIVIDE_BY_ZERO, EXCEPTION_INT_OVERFLOW, EXCEPTION_STACK_OVERFLOW,
+    EXCEPTION_STACK_OVERFLOW,
 };
 
 thread_local! {
@@ -27,6 +28,7 @@ thread_local! {
 
 pub fn call_protected(
     handler_data: &HandlerData,
+    info: &ModuleInfo,
     trampoline: Trampoline,
     ctx: *mut Ctx,
     func: *const Func,
@@ -35,8 +37,8 @@ pub fn call_protected(
 ) -> RuntimeResult<()> {
     // TODO: trap early
     // user code error
-    //    if let Some(msg) = super::TRAP_EARLY_DATA.with(|cell| cell.replace(None)) {
-    //        return Err(RuntimeError::User { msg });
+    //    if let Some(error) = super::TRAP_EARLY_DATA.with(|cell| cell.replace(None)) {
+    //        return Err(error);
     //    }
 
     let result = _call_protected(trampoline, ctx, func, param_vec, return_vec);
@@ -51,44 +53,37 @@ pub fn call_protected(
         instructionPointer: instruction_pointer,
     } = result.unwrap_err();
 
-    if let Some(TrapData {
+    let in_func = handler_data
+        .describe_func(info, instruction_pointer as _)
+        .map(|func| format!(" in {}", func))
+        .unwrap_or_default();
+    // The exception handler doesn't give us the frame pointer,
+    // so the backtrace only has the function that trapped.
+    let backtrace = handler_data.backtrace(info, &[instruction_pointer as _]);
+
+    if signum as DWORD == EXCEPTION_STACK_OVERFLOW && !backtrace.is_empty() {
+        Err(RuntimeError::Trap {
+            kind: TrapKind::StackOverflow,
+            backtrace,
+        })
+    } else if let Some(TrapData {
         trapcode,
         srcloc: _,
-    }) = handler_data.lookup(instruction_pointer as _)
+    }) = super::lookup_trap(handler_data, instruction_pointer as _)
     {
-        Err(match signum as DWORD {
-            EXCEPTION_ACCESS_VIOLATION => RuntimeError::OutOfBoundsAccess {
+        Err(RuntimeError::Trap {
+            kind: super::trap_kind(trapcode, &in_func),
+            backtrace,
+        })
+    } else if signum as DWORD == EXCEPTION_ACCESS_VIOLATION && !backtrace.is_empty() {
+        // Webassembly code only touches its own memories.
+        Err(RuntimeError::Trap {
+            kind: TrapKind::OutOfBoundsAccess {
                 memory: MemoryIndex::new(0),
                 addr: None,
             },
-            EXCEPTION_ILLEGAL_INSTRUCTION => match trapcode {
-                TrapCode::BadSignature => RuntimeError::IndirectCallSignature {
-                    table: TableIndex::new(0),
-                },
-                TrapCode::IndirectCallToNull => RuntimeError::IndirectCallToNull {
-                    table: TableIndex::new(0),
-                },
-                TrapCode::HeapOutOfBounds => RuntimeError::OutOfBoundsAccess {
-                    memory: MemoryIndex::new(0),
-                    addr: None,
-                },
-                TrapCode::TableOutOfBounds => RuntimeError::TableOutOfBounds {
-                    table: TableIndex::new(0),
-                },
-                _ => RuntimeError::Unknown {
-                    msg: "unknown trap".to_string(),
-                },
-            },
-            EXCEPTION_STACK_OVERFLOW => RuntimeError::Unknown {
-                msg: "unknown trap".to_string(),
-            },
-            EXCEPTION_INT_DIVIDE_BY_ZERO => RuntimeError::IllegalArithmeticOperation,
-            EXCEPTION_INT_OVERFLOW => RuntimeError::IllegalArithmeticOperation,
-            _ => RuntimeError::Unknown {
-                msg: "unknown trap".to_string(),
-            },
-        }
-        .into())
+            backtrace,
+        })
     } else {
         let signal = match signum as DWORD {
             EXCEPTION_FLT_DENORMAL_OPERAND
@@ -103,10 +98,15 @@ pub fn call_protected(
             _ => "unkown trapped signal",
         };
 
-        Err(RuntimeError::Unknown {
-            msg: format!("trap at {} - {}", exception_address, signal),
+        let msg = format!("trap at {}{} - {}", exception_address, in_func, signal);
+        if backtrace.is_empty() {
+            Err(RuntimeError::Unknown { msg })
+        } else {
+            Err(RuntimeError::Trap {
+                kind: TrapKind::Unknown { msg },
+                backtrace,
+            })
         }
-        .into())
     }
 }
 
@@ -0,0 +1,1191 @@
+//! Support for the 128-bit SIMD proposal.
+//!
+//! Neither `cranelift-wasm` nor the version of Cranelift it uses knows
+//! about `v128`, so in compiled code, a `v128` is a pair of `i64`s, the
+//! low half first. Before a module is translated, the `v128`s in its type
+//! section are rewritten to pairs of `i64`s, and what the types were is
+//! recorded so that `ModuleInfo` can be given the right types. A single
+//! `v128` result becomes two results, which the `multi_value` module then
+//! takes care of. `v128` globals aren't supported.
+//!
+//! In function bodies, `v128` locals are split into two `i64` ones, and
+//! blocks with a `v128` result pass it in scratch locals, declared after
+//! all the others. Loads, stores and constants become pairs of `i64`
+//! ones, and the other SIMD instructions are rewritten into calls (see
+//! the `lowering` module), which `FuncEnv` lowers to calls to the SIMD
+//! vmcalls. Doing that needs to know which operands are `v128`s, so the
+//! operand stack is followed through the body.
+//!
+//! `simd_wasmparser` is used to read the SIMD instructions and types,
+//! since the version of `wasmparser` that `cranelift-wasm` uses can't.
+//! It knows the final encoding of the proposal, which is what modules
+//! are validated against once SIMD is enabled.
+use crate::{lowering::write_var_u32, relocation::call_names};
+use cranelift_codegen::ir::{self, types::*};
+use cranelift_wasm::{WasmError, WasmResult};
+use hashbrown::HashMap;
+use simd_wasmparser::{
+    BinaryReader, BinaryReaderError, BlockType, FunctionBody, GlobalSectionReader,
+    ImportSectionReader, MemArg, Operator, OperatorsReader, TypeRef, ValType,
+};
+use wasmer_runtime_core::{
+    module::ModuleInfo,
+    structures::TypedIndex,
+    types::{FuncIndex, FuncSig, SigIndex, Type},
+};
+
+const I64_TYPE: u8 = 0x7e;
+const I32_TYPE: u8 = 0x7f;
+const F32_TYPE: u8 = 0x7d;
+const F64_TYPE: u8 = 0x7c;
+const EMPTY_BLOCK_TYPE: u8 = 0x40;
+
+const LOOP_OPCODE: u8 = 0x03;
+const ELSE_OPCODE: u8 = 0x05;
+const CALL_OPCODE: u8 = 0x10;
+const DROP_OPCODE: u8 = 0x1a;
+const SELECT_OPCODE: u8 = 0x1b;
+const LOCAL_GET_OPCODE: u8 = 0x20;
+const LOCAL_SET_OPCODE: u8 = 0x21;
+const LOCAL_TEE_OPCODE: u8 = 0x22;
+const I32_LOAD_OPCODE: u8 = 0x28;
+const I64_LOAD_OPCODE: u8 = 0x29;
+const I32_LOAD8_U_OPCODE: u8 = 0x2d;
+const I32_LOAD16_U_OPCODE: u8 = 0x2f;
+const I32_STORE_OPCODE: u8 = 0x36;
+const I64_STORE_OPCODE: u8 = 0x37;
+const I32_STORE8_OPCODE: u8 = 0x3a;
+const I32_STORE16_OPCODE: u8 = 0x3b;
+const I64_CONST_OPCODE: u8 = 0x42;
+const I64_EXTEND_I32_U_OPCODE: u8 = 0xad;
+const BULK_PREFIX: u8 = 0xfc;
+const SIMD_PREFIX: u8 = 0xfd;
+const ATOMIC_PREFIX: u8 = 0xfe;
+
+const TYPE_SECTION_ID: u8 = 1;
+const IMPORT_SECTION_ID: u8 = 2;
+const GLOBAL_SECTION_ID: u8 = 6;
+const CODE_SECTION_ID: u8 = 10;
+
+/// The `i64.load` alignment a `v128` access is split into
+/// can be at most 8 bytes.
+const MAX_I64_ALIGN: u32 = 3;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SimdOpKind {
+    Splat,
+    ExtractLane,
+    ReplaceLane,
+    Unary,
+    Binary,
+    Ternary,
+}
+
+/// A SIMD instruction that a function body was rewritten to call.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SimdOp {
+    /// The opcode of the instruction after the `0xfd` prefix.
+    pub opcode: u8,
+    /// The lane immediate of `extract_lane` and `replace_lane`.
+    pub lane: u8,
+}
+
+impl SimdOp {
+    pub fn kind(&self) -> SimdOpKind {
+        match self.opcode {
+            0x0f..=0x14 => SimdOpKind::Splat,
+            0x15 | 0x16 | 0x18 | 0x19 | 0x1b | 0x1d | 0x1f | 0x21 => SimdOpKind::ExtractLane,
+            // `any_true`, `all_true` and `bitmask`.
+            0x53 | 0x63 | 0x64 | 0x83 | 0x84 | 0xa3 | 0xa4 | 0xc3 | 0xc4 => {
+                SimdOpKind::ExtractLane
+            }
+            0x17 | 0x1a | 0x1c | 0x1e | 0x20 | 0x22 => SimdOpKind::ReplaceLane,
+            // The shifts.
+            0x6b..=0x6d | 0x8b..=0x8d | 0xab..=0xad | 0xcb..=0xcd => SimdOpKind::ReplaceLane,
+            // `shuffle` and `bitselect`.
+            0x0d | 0x52 => SimdOpKind::Ternary,
+            // The extending loads, whose `i64.load` is done before the call.
+            0x01..=0x06 => SimdOpKind::Unary,
+            0x4d | 0x5e..=0x62 | 0x67..=0x6a | 0x74 | 0x75 | 0x7a | 0x7c..=0x81 | 0x87..=0x8a => {
+                SimdOpKind::Unary
+            }
+            0x94 | 0xa0 | 0xa1 | 0xa7..=0xaa | 0xc0 | 0xc1 | 0xc7..=0xca => SimdOpKind::Unary,
+            0xe0 | 0xe1 | 0xe3 | 0xec | 0xed | 0xef | 0xf8..=0xff => SimdOpKind::Unary,
+            _ => SimdOpKind::Binary,
+        }
+    }
+
+    /// The type of the scalar operand or result of the instruction, if any.
+    fn scalar_type(&self) -> Option<ir::Type> {
+        match self.kind() {
+            SimdOpKind::Splat | SimdOpKind::ExtractLane | SimdOpKind::ReplaceLane => {
+                Some(match self.opcode {
+                    0x12 | 0x1d | 0x1e => I64,
+                    0x13 | 0x1f | 0x20 => F32,
+                    0x14 | 0x21 | 0x22 => F64,
+                    _ => I32,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// The operand types of the instruction, as seen by webassembly
+    /// once `v128`s are pairs of `i64`s.
+    pub fn params(&self) -> Vec<ir::Type> {
+        match self.kind() {
+            SimdOpKind::Splat => vec![self.scalar_type().unwrap()],
+            SimdOpKind::ExtractLane => vec![I64, I64],
+            SimdOpKind::ReplaceLane => vec![I64, I64, self.scalar_type().unwrap()],
+            SimdOpKind::Unary => vec![I64; 2],
+            SimdOpKind::Binary => vec![I64; 4],
+            SimdOpKind::Ternary => vec![I64; 6],
+        }
+    }
+
+    /// The result types of the instruction, as seen by webassembly
+    /// once `v128`s are pairs of `i64`s.
+    pub fn returns(&self) -> Vec<ir::Type> {
+        match self.kind() {
+            SimdOpKind::ExtractLane => vec![self.scalar_type().unwrap()],
+            _ => vec![I64, I64],
+        }
+    }
+
+    /// The name of the vmcall implementing this instruction.
+    pub fn name(&self) -> ir::ExternalName {
+        let index = match self.kind() {
+            SimdOpKind::Splat => call_names::SIMD_SPLAT,
+            SimdOpKind::ExtractLane => call_names::SIMD_EXTRACT_LANE,
+            SimdOpKind::ReplaceLane => call_names::SIMD_REPLACE_LANE,
+            SimdOpKind::Unary => call_names::SIMD_UNARY,
+            SimdOpKind::Binary => call_names::SIMD_BINARY,
+            SimdOpKind::Ternary => call_names::SIMD_TERNARY,
+        };
+        ir::ExternalName::user(call_names::SIMD_NAMESPACE, index)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct LoweredSimdTypes {
+    pub wasm: Vec<u8>,
+    /// The types that have `v128` parameters or results, as they were, by type index.
+    pub signatures: HashMap<u32, FuncSig>,
+    /// How many bytes shorter the part of the module before the code
+    /// section got. Splitting `v128`s makes it longer, so this is
+    /// usually negative.
+    pub shrunk_by: isize,
+}
+
+/// Rewrites the `v128`s in the type section of `wasm` to pairs of `i64`s.
+///
+/// Returns `None` if there is nothing to change.
+pub fn lower_simd_types(wasm: &[u8]) -> WasmResult<Option<LoweredSimdTypes>> {
+    let mut reader = BinaryReader::new(wasm, 0);
+    // Skip the magic number and the version.
+    reader.read_bytes(8).map_err(to_wasm_error)?;
+
+    let mut lowered = wasm[..8].to_vec();
+    let mut signatures = HashMap::new();
+    let mut shrunk_by = None;
+
+    while !reader.eof() {
+        let section_start = reader.current_position();
+        let id = reader.read_u8().map_err(to_wasm_error)?;
+        let size = reader.read_var_u32().map_err(to_wasm_error)? as usize;
+        let contents_offset = reader.current_position();
+        let contents = reader.read_bytes(size).map_err(to_wasm_error)?;
+
+        if id == CODE_SECTION_ID {
+            shrunk_by = Some(section_start as isize - lowered.len() as isize);
+        }
+
+        let contents = match id {
+            TYPE_SECTION_ID => lower_type_section(contents, contents_offset, &mut signatures)
+                .map_err(to_wasm_error)?,
+            IMPORT_SECTION_ID => {
+                let imports =
+                    ImportSectionReader::new(BinaryReader::new(contents, contents_offset))
+                        .map_err(to_wasm_error)?;
+                for import in imports.into_imports() {
+                    if let TypeRef::Global(global) = import.map_err(to_wasm_error)?.ty {
+                        check_global_type(global.content_type)?;
+                    }
+                }
+                contents.to_vec()
+            }
+            GLOBAL_SECTION_ID => {
+                let globals =
+                    GlobalSectionReader::new(BinaryReader::new(contents, contents_offset))
+                        .map_err(to_wasm_error)?;
+                for global in globals {
+                    check_global_type(global.map_err(to_wasm_error)?.ty.content_type)?;
+                }
+                contents.to_vec()
+            }
+            _ => contents.to_vec(),
+        };
+
+        lowered.push(id);
+        write_var_u32(&mut lowered, contents.len() as u32);
+        lowered.extend_from_slice(&contents);
+    }
+
+    if signatures.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(LoweredSimdTypes {
+        shrunk_by: shrunk_by.unwrap_or(wasm.len() as isize - lowered.len() as isize),
+        wasm: lowered,
+        signatures,
+    }))
+}
+
+fn to_wasm_error(e: BinaryReaderError) -> WasmError {
+    WasmError::InvalidWebAssembly {
+        // Modules are validated before they're compiled, so this doesn't happen.
+        message: "malformed module",
+        offset: e.offset(),
+    }
+}
+
+fn check_global_type(ty: ValType) -> WasmResult<()> {
+    match ty {
+        ValType::V128 => Err(WasmError::Unsupported("`v128` globals")),
+        _ => Ok(()),
+    }
+}
+
+fn read_value_type(reader: &mut BinaryReader) -> Result<Type, BinaryReaderError> {
+    Ok(match reader.read::<ValType>()? {
+        ValType::I32 => Type::I32,
+        ValType::I64 => Type::I64,
+        ValType::F32 => Type::F32,
+        ValType::F64 => Type::F64,
+        ValType::V128 => Type::V128,
+        // Modules that use SIMD can't use reference types,
+        // so these are only ever left as they are.
+        ValType::Ref(ty) if ty.is_func_ref() => Type::FuncRef,
+        ValType::Ref(_) => Type::AnyRef,
+    })
+}
+
+fn lower_type_section(
+    contents: &[u8],
+    offset: usize,
+    signatures: &mut HashMap<u32, FuncSig>,
+) -> Result<Vec<u8>, BinaryReaderError> {
+    let mut reader = BinaryReader::new(contents, offset);
+    let mut lowered = Vec::new();
+
+    let count = reader.read_var_u32()?;
+    write_var_u32(&mut lowered, count);
+    for index in 0..count {
+        // The form, which is always `func`.
+        lowered.push(reader.read_u8()?);
+
+        let mut types = [Vec::new(), Vec::new()];
+        for types in &mut types {
+            let count = reader.read_var_u32()?;
+            let mut lowered_types = Vec::new();
+            for _ in 0..count {
+                let position = reader.current_position();
+                let ty = read_value_type(&mut reader)?;
+                if ty == Type::V128 {
+                    lowered_types.extend_from_slice(&[I64_TYPE, I64_TYPE]);
+                } else {
+                    lowered_types.push(contents[position]);
+                }
+                types.push(ty);
+            }
+            write_var_u32(&mut lowered, lowered_types.len() as u32);
+            lowered.extend_from_slice(&lowered_types);
+        }
+
+        let [params, returns] = types;
+        if params.iter().chain(&returns).any(|&ty| ty == Type::V128) {
+            signatures.insert(index, FuncSig::new(params, returns));
+        }
+    }
+
+    Ok(lowered)
+}
+
+/// The parts of a function body to replace so that it doesn't use `v128`s.
+#[derive(Debug, PartialEq)]
+pub struct LoweredSimdBody {
+    /// `(start, end, replacement)` for each part of the body to replace, in order.
+    pub replacements: Vec<(usize, usize, Vec<u8>)>,
+    /// The SIMD instructions the body calls, from `first_index` on.
+    pub ops: Vec<SimdOp>,
+}
+
+/// Works out how to rewrite `body`, the body of a function of type
+/// `func_sig`, so that it doesn't use `v128`s, with each SIMD instruction
+/// that can't be rewritten to plain ones becoming a call to `first_index + i`,
+/// where `i` is the index of the instruction in the returned list.
+///
+/// Returns `None` if the body doesn't use `v128`s.
+pub fn lower_simd_body(
+    body: &[u8],
+    func_sig: &FuncSig,
+    info: &ModuleInfo,
+    first_index: u32,
+) -> WasmResult<Option<LoweredSimdBody>> {
+    let mut lowering = BodyLowering {
+        body,
+        info,
+        first_index,
+        locals: Vec::new(),
+        scratch: 0,
+        uses_scratch: false,
+        stack: Vec::new(),
+        frames: Vec::new(),
+        replacements: Vec::new(),
+        ops: Vec::new(),
+    };
+    lowering.lower(func_sig)?;
+
+    if lowering.replacements.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(LoweredSimdBody {
+        replacements: lowering.replacements,
+        ops: lowering.ops,
+    }))
+}
+
+/// A block, loop or if that is being lowered, or the function itself.
+struct Frame {
+    /// The height of the operand stack when the frame was entered.
+    height: usize,
+    /// Which of the frame's results are `v128`s.
+    results: Vec<bool>,
+    /// Whether the frame's result is a single `v128`, which is passed
+    /// in the scratch locals. Only ever true for blocks and ifs.
+    v128_result: bool,
+    /// Whether the code being lowered can't be reached.
+    unreachable: bool,
+}
+
+/// The scratch locals of a function body.
+struct Scratch {
+    t: u32,
+    lo: u32,
+    hi: u32,
+    u: u32,
+}
+
+struct BodyLowering<'a> {
+    body: &'a [u8],
+    info: &'a ModuleInfo,
+    first_index: u32,
+    /// The index each local is moved to, and whether it is a `v128`.
+    locals: Vec<(u32, bool)>,
+    /// The index of the first scratch local.
+    scratch: u32,
+    uses_scratch: bool,
+    /// Whether each operand on the stack is a `v128`.
+    stack: Vec<bool>,
+    frames: Vec<Frame>,
+    replacements: Vec<(usize, usize, Vec<u8>)>,
+    ops: Vec<SimdOp>,
+}
+
+impl<'a> BodyLowering<'a> {
+    fn lower(&mut self, func_sig: &FuncSig) -> WasmResult<()> {
+        let body = FunctionBody::new(BinaryReader::new(self.body, 0));
+
+        let mut next_local = 0;
+        for &param in func_sig.params() {
+            self.locals.push((next_local, param == Type::V128));
+            next_local += if param == Type::V128 { 2 } else { 1 };
+        }
+
+        let mut decls = Vec::new();
+        let mut locals = body.get_locals_reader().map_err(to_wasm_error)?;
+        for _ in 0..locals.get_count() {
+            let (count, ty) = locals.read().map_err(to_wasm_error)?;
+            let is_v128 = ty == ValType::V128;
+            for _ in 0..count {
+                self.locals.push((next_local, is_v128));
+                next_local += if is_v128 { 2 } else { 1 };
+            }
+            decls.push((count, ty));
+        }
+        let locals_end = locals.original_position();
+        let mut reader = OperatorsReader::new(locals.get_binary_reader());
+        self.scratch = next_local;
+
+        self.frames.push(Frame {
+            height: 0,
+            results: func_sig
+                .returns()
+                .iter()
+                .map(|&ty| ty == Type::V128)
+                .collect(),
+            v128_result: false,
+            unreachable: false,
+        });
+
+        while !reader.eof() {
+            let start = reader.original_position();
+            let opcode = self.body[start];
+            let sub_opcode = match opcode {
+                BULK_PREFIX | SIMD_PREFIX | ATOMIC_PREFIX => {
+                    let mut sub_opcode_reader = reader.get_binary_reader();
+                    sub_opcode_reader.read_u8().map_err(to_wasm_error)?;
+                    sub_opcode_reader.read_var_u32().map_err(to_wasm_error)?
+                }
+                _ => 0,
+            };
+            let op = reader.read().map_err(to_wasm_error)?;
+            let end = reader.original_position();
+
+            if let Some(replacement) = self.lower_operator(opcode, sub_opcode, op, start, end)? {
+                self.replacements.push((start, end, replacement));
+            }
+        }
+
+        let has_v128_locals = decls.iter().any(|&(_, ty)| ty == ValType::V128);
+        if self.uses_scratch || has_v128_locals {
+            let mut lowered_decls = Vec::new();
+            for (count, ty) in decls {
+                let count = if ty == ValType::V128 {
+                    count * 2
+                } else {
+                    count
+                };
+                lowered_decls.push((count, lowered_local_type(ty)));
+            }
+            if self.uses_scratch {
+                lowered_decls.push((1, I32_TYPE));
+                lowered_decls.push((3, I64_TYPE));
+            }
+
+            let mut replacement = Vec::new();
+            write_var_u32(&mut replacement, lowered_decls.len() as u32);
+            for (count, ty) in lowered_decls {
+                write_var_u32(&mut replacement, count);
+                replacement.push(ty);
+            }
+            self.replacements.insert(0, (0, locals_end, replacement));
+        }
+
+        Ok(())
+    }
+
+    /// Follows `op` on the operand stack, returning what to replace it with, if anything.
+    fn lower_operator(
+        &mut self,
+        opcode: u8,
+        sub_opcode: u32,
+        op: Operator,
+        start: usize,
+        end: usize,
+    ) -> WasmResult<Option<Vec<u8>>> {
+        let body = self.body;
+        let original = &body[start..end];
+        let mut replacement = Vec::new();
+
+        match op {
+            Operator::Block { blockty }
+            | Operator::Loop { blockty }
+            | Operator::If { blockty } => {
+                if let Operator::If { .. } = op {
+                    self.pop();
+                }
+                let (params, results) = self.block_type(blockty)?;
+                for _ in &params {
+                    self.pop();
+                }
+                let height = self.stack.len();
+                self.stack.extend(params);
+
+                let v128_result = opcode != LOOP_OPCODE && results == [true];
+                if results == [true] {
+                    replacement.extend_from_slice(&[opcode, EMPTY_BLOCK_TYPE]);
+                }
+                self.frames.push(Frame {
+                    height,
+                    results,
+                    v128_result,
+                    unreachable: false,
+                });
+            }
+            Operator::Else => {
+                if self.frames.last().unwrap().v128_result {
+                    let scratch = self.scratch();
+                    local(&mut replacement, LOCAL_SET_OPCODE, scratch.hi);
+                    local(&mut replacement, LOCAL_SET_OPCODE, scratch.lo);
+                    replacement.push(ELSE_OPCODE);
+                }
+                let frame = self.frames.last_mut().unwrap();
+                frame.unreachable = false;
+                self.stack.truncate(frame.height);
+            }
+            Operator::End => {
+                let frame = self.frames.pop().unwrap();
+                if frame.v128_result {
+                    let scratch = self.scratch();
+                    local(&mut replacement, LOCAL_SET_OPCODE, scratch.hi);
+                    local(&mut replacement, LOCAL_SET_OPCODE, scratch.lo);
+                    replacement.extend_from_slice(original);
+                    local(&mut replacement, LOCAL_GET_OPCODE, scratch.lo);
+                    local(&mut replacement, LOCAL_GET_OPCODE, scratch.hi);
+                }
+                self.stack.truncate(frame.height);
+                self.stack.extend(frame.results);
+            }
+            Operator::Br { relative_depth } => {
+                if self.is_v128_label(relative_depth) {
+                    let scratch = self.scratch();
+                    local(&mut replacement, LOCAL_SET_OPCODE, scratch.hi);
+                    local(&mut replacement, LOCAL_SET_OPCODE, scratch.lo);
+                    replacement.extend_from_slice(original);
+                }
+                self.set_unreachable();
+            }
+            Operator::BrIf { relative_depth } => {
+                self.pop();
+                if self.is_v128_label(relative_depth) {
+                    let scratch = self.scratch();
+                    local(&mut replacement, LOCAL_SET_OPCODE, scratch.t);
+                    local(&mut replacement, LOCAL_SET_OPCODE, scratch.hi);
+                    local(&mut replacement, LOCAL_SET_OPCODE, scratch.lo);
+                    local(&mut replacement, LOCAL_GET_OPCODE, scratch.lo);
+                    local(&mut replacement, LOCAL_GET_OPCODE, scratch.hi);
+                    local(&mut replacement, LOCAL_GET_OPCODE, scratch.t);
+                    replacement.extend_from_slice(original);
+                }
+            }
+            Operator::BrTable { targets } => {
+                self.pop();
+                let depths = targets
+                    .targets()
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(to_wasm_error)?;
+                let default = targets.default();
+                let v128_labels = depths
+                    .iter()
+                    .chain(Some(&default))
+                    .filter(|&&depth| self.is_v128_label(depth))
+                    .count();
+                if v128_labels == depths.len() + 1 {
+                    let scratch = self.scratch();
+                    local(&mut replacement, LOCAL_SET_OPCODE, scratch.t);
+                    local(&mut replacement, LOCAL_SET_OPCODE, scratch.hi);
+                    local(&mut replacement, LOCAL_SET_OPCODE, scratch.lo);
+                    local(&mut replacement, LOCAL_GET_OPCODE, scratch.t);
+                    replacement.extend_from_slice(original);
+                } else if v128_labels != 0 {
+                    // The function's `v128` result is passed on the
+                    // stack, and that of blocks in the scratch locals.
+                    return Err(WasmError::Unsupported(
+                        "`br_table` to both the function and a `v128` block",
+                    ));
+                }
+                self.set_unreachable();
+            }
+            Operator::Unreachable | Operator::Return => self.set_unreachable(),
+            Operator::Call { function_index } => {
+                let sig_index = self.info.func_assoc[FuncIndex::new(function_index as usize)];
+                self.call(sig_index);
+            }
+            Operator::CallIndirect { type_index, .. } => {
+                self.pop();
+                self.call(SigIndex::new(type_index as usize));
+            }
+            Operator::Drop => {
+                if self.pop() {
+                    replacement.extend_from_slice(&[DROP_OPCODE, DROP_OPCODE]);
+                }
+            }
+            Operator::Select | Operator::TypedSelect { .. } => {
+                self.pop();
+                let is_v128 = self.pop() | self.pop();
+                self.stack.push(is_v128);
+                if is_v128 {
+                    // Select each half with the same condition.
+                    let scratch = self.scratch();
+                    local(&mut replacement, LOCAL_SET_OPCODE, scratch.t);
+                    local(&mut replacement, LOCAL_SET_OPCODE, scratch.hi);
+                    local(&mut replacement, LOCAL_SET_OPCODE, scratch.lo);
+                    local(&mut replacement, LOCAL_SET_OPCODE, scratch.u);
+                    local(&mut replacement, LOCAL_GET_OPCODE, scratch.lo);
+                    local(&mut replacement, LOCAL_GET_OPCODE, scratch.t);
+                    replacement.push(SELECT_OPCODE);
+                    local(&mut replacement, LOCAL_GET_OPCODE, scratch.u);
+                    local(&mut replacement, LOCAL_GET_OPCODE, scratch.hi);
+                    local(&mut replacement, LOCAL_GET_OPCODE, scratch.t);
+                    replacement.push(SELECT_OPCODE);
+                }
+            }
+            Operator::LocalGet { local_index }
+            | Operator::LocalSet { local_index }
+            | Operator::LocalTee { local_index } => {
+                let (index, is_v128) = self.locals[local_index as usize];
+                match (opcode, is_v128) {
+                    (LOCAL_GET_OPCODE, true) => {
+                        local(&mut replacement, LOCAL_GET_OPCODE, index);
+                        local(&mut replacement, LOCAL_GET_OPCODE, index + 1);
+                    }
+                    (LOCAL_SET_OPCODE, true) => {
+                        local(&mut replacement, LOCAL_SET_OPCODE, index + 1);
+                        local(&mut replacement, LOCAL_SET_OPCODE, index);
+                    }
+                    (_, true) => {
+                        local(&mut replacement, LOCAL_SET_OPCODE, index + 1);
+                        local(&mut replacement, LOCAL_TEE_OPCODE, index);
+                        local(&mut replacement, LOCAL_GET_OPCODE, index + 1);
+                    }
+                    (_, false) if index != local_index => local(&mut replacement, opcode, index),
+                    _ => {}
+                }
+                match opcode {
+                    LOCAL_GET_OPCODE => self.stack.push(is_v128),
+                    LOCAL_SET_OPCODE => {
+                        self.pop();
+                    }
+                    _ => {}
+                }
+            }
+            _ if opcode == SIMD_PREFIX => {
+                replacement = self.lower_simd_operator(sub_opcode as u8, op, end);
+            }
+            _ => {
+                let (params, results) = plain_arity(opcode, sub_opcode);
+                for _ in 0..params {
+                    self.pop();
+                }
+                for _ in 0..results {
+                    self.stack.push(false);
+                }
+            }
+        }
+
+        if replacement.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(replacement))
+        }
+    }
+
+    /// Follows a SIMD instruction on the operand stack, returning what to replace it with.
+    fn lower_simd_operator(&mut self, opcode: u8, op: Operator, end: usize) -> Vec<u8> {
+        let mut replacement = Vec::new();
+
+        match op {
+            Operator::V128Load { memarg } => {
+                self.pop();
+                self.stack.push(true);
+                let scratch = self.scratch();
+                let (align, offset) = (u32::from(memarg.align).min(MAX_I64_ALIGN), offset(memarg));
+                local(&mut replacement, LOCAL_TEE_OPCODE, scratch.t);
+                memory(&mut replacement, I64_LOAD_OPCODE, align, offset);
+                local(&mut replacement, LOCAL_GET_OPCODE, scratch.t);
+                memory(
+                    &mut replacement,
+                    I64_LOAD_OPCODE,
+                    align,
+                    offset.saturating_add(8),
+                );
+            }
+            Operator::V128Store { memarg } => {
+                self.pop();
+                self.pop();
+                let scratch = self.scratch();
+                let (align, offset) = (u32::from(memarg.align).min(MAX_I64_ALIGN), offset(memarg));
+                local(&mut replacement, LOCAL_SET_OPCODE, scratch.hi);
+                local(&mut replacement, LOCAL_SET_OPCODE, scratch.lo);
+                local(&mut replacement, LOCAL_TEE_OPCODE, scratch.t);
+                local(&mut replacement, LOCAL_GET_OPCODE, scratch.hi);
+                memory(
+                    &mut replacement,
+                    I64_STORE_OPCODE,
+                    align,
+                    offset.saturating_add(8),
+                );
+                local(&mut replacement, LOCAL_GET_OPCODE, scratch.t);
+                local(&mut replacement, LOCAL_GET_OPCODE, scratch.lo);
+                memory(&mut replacement, I64_STORE_OPCODE, align, offset);
+            }
+            Operator::V128Const { value } => {
+                self.stack.push(true);
+                i64_const_v128(&mut replacement, value.bytes());
+            }
+            Operator::I8x16Shuffle { lanes } => {
+                self.pop();
+                self.pop();
+                self.stack.push(true);
+                // The lanes are passed as a third `v128` operand.
+                i64_const_v128(&mut replacement, &lanes);
+                self.call_simd(&mut replacement, SimdOp { opcode, lane: 0 });
+            }
+            Operator::V128Load8Splat { memarg }
+            | Operator::V128Load16Splat { memarg }
+            | Operator::V128Load32Splat { memarg }
+            | Operator::V128Load64Splat { memarg } => {
+                self.pop();
+                self.stack.push(true);
+                let (load_opcode, splat_opcode) = match op {
+                    Operator::V128Load8Splat { .. } => (I32_LOAD8_U_OPCODE, 0x0f),
+                    Operator::V128Load16Splat { .. } => (I32_LOAD16_U_OPCODE, 0x10),
+                    Operator::V128Load32Splat { .. } => (I32_LOAD_OPCODE, 0x11),
+                    _ => (I64_LOAD_OPCODE, 0x12),
+                };
+                memory(
+                    &mut replacement,
+                    load_opcode,
+                    memarg.align.into(),
+                    offset(memarg),
+                );
+                self.call_simd(
+                    &mut replacement,
+                    SimdOp {
+                        opcode: splat_opcode,
+                        lane: 0,
+                    },
+                );
+            }
+            Operator::V128Load8x8S { memarg }
+            | Operator::V128Load8x8U { memarg }
+            | Operator::V128Load16x4S { memarg }
+            | Operator::V128Load16x4U { memarg }
+            | Operator::V128Load32x2S { memarg }
+            | Operator::V128Load32x2U { memarg } => {
+                self.pop();
+                self.stack.push(true);
+                // Load the low half, then extend its lanes across the whole `v128`.
+                memory(
+                    &mut replacement,
+                    I64_LOAD_OPCODE,
+                    memarg.align.into(),
+                    offset(memarg),
+                );
+                replacement.extend_from_slice(&[I64_CONST_OPCODE, 0]);
+                self.call_simd(&mut replacement, SimdOp { opcode, lane: 0 });
+            }
+            Operator::V128Load32Zero { memarg } | Operator::V128Load64Zero { memarg } => {
+                self.pop();
+                self.stack.push(true);
+                if let Operator::V128Load32Zero { .. } = op {
+                    memory(
+                        &mut replacement,
+                        I32_LOAD_OPCODE,
+                        memarg.align.into(),
+                        offset(memarg),
+                    );
+                    replacement.push(I64_EXTEND_I32_U_OPCODE);
+                } else {
+                    memory(
+                        &mut replacement,
+                        I64_LOAD_OPCODE,
+                        memarg.align.into(),
+                        offset(memarg),
+                    );
+                }
+                replacement.extend_from_slice(&[I64_CONST_OPCODE, 0]);
+            }
+            Operator::V128Load8Lane { memarg, lane }
+            | Operator::V128Load16Lane { memarg, lane }
+            | Operator::V128Load32Lane { memarg, lane }
+            | Operator::V128Load64Lane { memarg, lane } => {
+                self.pop();
+                self.pop();
+                self.stack.push(true);
+                // Load the lane, then replace it in the `v128`.
+                let (load_opcode, replace_opcode) = match op {
+                    Operator::V128Load8Lane { .. } => (I32_LOAD8_U_OPCODE, 0x17),
+                    Operator::V128Load16Lane { .. } => (I32_LOAD16_U_OPCODE, 0x1a),
+                    Operator::V128Load32Lane { .. } => (I32_LOAD_OPCODE, 0x1c),
+                    _ => (I64_LOAD_OPCODE, 0x1e),
+                };
+                let scratch = self.scratch();
+                let value = if load_opcode == I64_LOAD_OPCODE {
+                    scratch.u
+                } else {
+                    scratch.t
+                };
+                local(&mut replacement, LOCAL_SET_OPCODE, scratch.hi);
+                local(&mut replacement, LOCAL_SET_OPCODE, scratch.lo);
+                memory(
+                    &mut replacement,
+                    load_opcode,
+                    memarg.align.into(),
+                    offset(memarg),
+                );
+                local(&mut replacement, LOCAL_SET_OPCODE, value);
+                local(&mut replacement, LOCAL_GET_OPCODE, scratch.lo);
+                local(&mut replacement, LOCAL_GET_OPCODE, scratch.hi);
+                local(&mut replacement, LOCAL_GET_OPCODE, value);
+                self.call_simd(
+                    &mut replacement,
+                    SimdOp {
+                        opcode: replace_opcode,
+                        lane,
+                    },
+                );
+            }
+            Operator::V128Store8Lane { memarg, lane }
+            | Operator::V128Store16Lane { memarg, lane }
+            | Operator::V128Store32Lane { memarg, lane }
+            | Operator::V128Store64Lane { memarg, lane } => {
+                self.pop();
+                self.pop();
+                // Extract the lane, then store it.
+                let (extract_opcode, store_opcode) = match op {
+                    Operator::V128Store8Lane { .. } => (0x16, I32_STORE8_OPCODE),
+                    Operator::V128Store16Lane { .. } => (0x19, I32_STORE16_OPCODE),
+                    Operator::V128Store32Lane { .. } => (0x1b, I32_STORE_OPCODE),
+                    _ => (0x1d, I64_STORE_OPCODE),
+                };
+                self.call_simd(
+                    &mut replacement,
+                    SimdOp {
+                        opcode: extract_opcode,
+                        lane,
+                    },
+                );
+                memory(
+                    &mut replacement,
+                    store_opcode,
+                    memarg.align.into(),
+                    offset(memarg),
+                );
+            }
+            _ => {
+                let simd_op = SimdOp {
+                    opcode,
+                    // The lane immediate of `extract_lane` and
+                    // `replace_lane` is the last byte of the instruction.
+                    lane: match opcode {
+                        0x15..=0x22 => self.body[end - 1],
+                        _ => 0,
+                    },
+                };
+
+                let (params, result) = match simd_op.kind() {
+                    SimdOpKind::Splat => (1, true),
+                    SimdOpKind::ExtractLane => (1, false),
+                    SimdOpKind::ReplaceLane | SimdOpKind::Binary => (2, true),
+                    SimdOpKind::Unary => (1, true),
+                    SimdOpKind::Ternary => (3, true),
+                };
+                for _ in 0..params {
+                    self.pop();
+                }
+                self.stack.push(result);
+                self.call_simd(&mut replacement, simd_op);
+            }
+        }
+
+        replacement
+    }
+
+    /// Pops an operand, returning whether it is a `v128`.
+    ///
+    /// In unreachable code, the stack can be popped past
+    /// the current frame, which yields anything.
+    fn pop(&mut self) -> bool {
+        let frame = self.frames.last().unwrap();
+        if self.stack.len() <= frame.height {
+            debug_assert!(frame.unreachable);
+            return false;
+        }
+        self.stack.pop().unwrap()
+    }
+
+    fn set_unreachable(&mut self) {
+        let frame = self.frames.last_mut().unwrap();
+        frame.unreachable = true;
+        self.stack.truncate(frame.height);
+    }
+
+    /// Whether a branch to `relative_depth` passes a `v128` in the scratch locals.
+    fn is_v128_label(&self, relative_depth: u32) -> bool {
+        self.frames[self.frames.len() - 1 - relative_depth as usize].v128_result
+    }
+
+    /// Which of the parameters and results of a block type are `v128`s.
+    fn block_type(&self, ty: BlockType) -> WasmResult<(Vec<bool>, Vec<bool>)> {
+        Ok(match ty {
+            BlockType::Empty => (vec![], vec![]),
+            BlockType::Type(ty) => (vec![], vec![ty == ValType::V128]),
+            BlockType::FuncType(index) => {
+                let sig = &self.info.signatures[SigIndex::new(index as usize)];
+                if sig
+                    .params()
+                    .iter()
+                    .chain(sig.returns())
+                    .any(|&ty| ty == Type::V128)
+                {
+                    return Err(WasmError::Unsupported(
+                        "`v128`s in block types with a type index",
+                    ));
+                }
+                (
+                    vec![false; sig.params().len()],
+                    vec![false; sig.returns().len()],
+                )
+            }
+        })
+    }
+
+    /// Follows a call to a function of type `sig_index` on the operand stack.
+    fn call(&mut self, sig_index: SigIndex) {
+        let sig = &self.info.signatures[sig_index];
+        for _ in sig.params() {
+            self.pop();
+        }
+        for &ty in sig.returns() {
+            self.stack.push(ty == Type::V128);
+        }
+    }
+
+    /// The scratch locals, which are declared once they're used.
+    fn scratch(&mut self) -> Scratch {
+        self.uses_scratch = true;
+        Scratch {
+            t: self.scratch,
+            lo: self.scratch + 1,
+            hi: self.scratch + 2,
+            u: self.scratch + 3,
+        }
+    }
+
+    /// Writes a call to the vmcall for `simd_op`.
+    fn call_simd(&mut self, buf: &mut Vec<u8>, simd_op: SimdOp) {
+        let index = match self.ops.iter().position(|&op| op == simd_op) {
+            Some(index) => index,
+            None => {
+                self.ops.push(simd_op);
+                self.ops.len() - 1
+            }
+        };
+        buf.push(CALL_OPCODE);
+        write_var_u32(buf, self.first_index + index as u32);
+    }
+}
+
+/// The type locals of type `ty` are declared with once `v128`s are pairs
+/// of `i64`s, and reference types are `i64`s as in the `reference_types`
+/// module.
+fn lowered_local_type(ty: ValType) -> u8 {
+    match ty {
+        ValType::I32 => I32_TYPE,
+        ValType::F32 => F32_TYPE,
+        ValType::F64 => F64_TYPE,
+        _ => I64_TYPE,
+    }
+}
+
+/// How many operands an instruction that doesn't involve `v128`s pops,
+/// and how many results it pushes.
+///
+/// Control and variable instructions are followed separately.
+fn plain_arity(opcode: u8, sub_opcode: u32) -> (usize, usize) {
+    match opcode {
+        // `global.get`, `memory.size`, the constants, `ref.null` and `ref.func`.
+        0x23 | 0x3f | 0x41..=0x44 | 0xd0 | 0xd2 => (0, 1),
+        // `global.set`.
+        0x24 => (1, 0),
+        // `table.set` and the stores.
+        0x26 | 0x36..=0x3e => (2, 0),
+        // `table.get`, the loads, `memory.grow` and the unary operators.
+        0x25 | 0x28..=0x35 | 0x40 | 0x45 | 0x50 | 0x67..=0x69 | 0x79..=0x7b => (1, 1),
+        0x8b..=0x91 | 0x99..=0x9f | 0xa7..=0xc4 | 0xd1 => (1, 1),
+        // The binary operators.
+        0x46..=0x4f | 0x51..=0x66 | 0x6a..=0x78 | 0x7c..=0x8a | 0x92..=0x98 | 0xa0..=0xa6 => (2, 1),
+        BULK_PREFIX => match sub_opcode {
+            // The saturating conversions.
+            0x00..=0x07 => (1, 1),
+            // `data.drop` and `elem.drop`.
+            0x09 | 0x0d => (0, 0),
+            // `table.grow` and `table.size`.
+            0x0f => (2, 1),
+            0x10 => (0, 1),
+            // `memory.init`, `memory.copy`, `memory.fill`,
+            // `table.init`, `table.copy` and `table.fill`.
+            _ => (3, 0),
+        },
+        ATOMIC_PREFIX => match sub_opcode {
+            // `atomic.notify`, `i32.atomic.wait` and the read-modify-writes.
+            0x00 | 0x1e..=0x47 => (2, 1),
+            // `i64.atomic.wait` and the compare-exchanges.
+            0x01 | 0x02 | 0x48..=0x4e => (3, 1),
+            // `atomic.fence`.
+            0x03 => (0, 0),
+            // The loads.
+            0x10..=0x16 => (1, 1),
+            // The stores.
+            _ => (2, 0),
+        },
+        // `nop`.
+        _ => (0, 0),
+    }
+}
+
+fn local(buf: &mut Vec<u8>, opcode: u8, index: u32) {
+    buf.push(opcode);
+    write_var_u32(buf, index);
+}
+
+/// The offset of a memory access, which fits in 32 bits
+/// without the memory64 proposal.
+fn offset(memarg: MemArg) -> u32 {
+    memarg.offset as u32
+}
+
+fn memory(buf: &mut Vec<u8>, opcode: u8, align: u32, offset: u32) {
+    buf.push(opcode);
+    write_var_u32(buf, align);
+    write_var_u32(buf, offset);
+}
+
+/// Writes two `i64.const`s with the low and high halves of `bytes`.
+fn i64_const_v128(buf: &mut Vec<u8>, bytes: &[u8; 16]) {
+    for half in bytes.chunks(8) {
+        let mut value = 0;
+        for (index, &byte) in half.iter().enumerate() {
+            value |= i64::from(byte) << (index * 8);
+        }
+        buf.push(I64_CONST_OPCODE);
+        write_var_i64(buf, value);
+    }
+}
+
+fn write_var_i64(buf: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            buf.push(byte);
+            break;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod simd_tests {
+    use super::{lower_simd_body, lower_simd_types, SimdOp};
+    use crate::{
+        lowering::{lower_body, LoweredOp},
+        module::Module,
+    };
+    use wasmer_runtime_core::types::{FuncSig, Type};
+
+    #[test]
+    fn test_lower_simd_types() {
+        #[rustfmt::skip]
+        let wasm = [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+            0x01, 0x0a, 0x02,             // type section
+            0x60, 0x01, 0x7b, 0x01, 0x7b, // (func (param v128) (result v128))
+            0x60, 0x01, 0x7f, 0x00,       // (func (param i32))
+            0x0a, 0x01, 0x00,             // empty code section
+        ];
+
+        let lowered = lower_simd_types(&wasm).unwrap().unwrap();
+
+        assert_eq!(
+            lowered.wasm[8..],
+            [
+                0x01, 0x0c, 0x02, 0x60, 0x02, 0x7e, 0x7e, 0x02, 0x7e, 0x7e, 0x60, 0x01, 0x7f, 0x00,
+                0x0a, 0x01, 0x00,
+            ][..]
+        );
+        assert_eq!(lowered.shrunk_by, -2);
+        assert_eq!(lowered.signatures.len(), 1);
+        assert_eq!(
+            lowered.signatures[&0],
+            FuncSig::new(vec![Type::V128], vec![Type::V128])
+        );
+
+        assert_eq!(lower_simd_types(&lowered.wasm).unwrap(), None);
+    }
+
+    #[test]
+    fn test_lower_simd_locals() {
+        #[rustfmt::skip]
+        let body = [
+            0x01, 0x01, 0x7b, // one v128 local
+            0x20, 0x00,       // local.get 0
+            0x21, 0x01,       // local.set 1
+            0x20, 0x01,       // local.get 1
+            0x20, 0x00,       // local.get 0
+            0xfd, 0xae, 0x01, // i32x4.add
+            0x0b,             // end
+        ];
+        let info = Module::new(&[]).info;
+        let func_sig = FuncSig::new(vec![Type::V128], vec![Type::V128]);
+
+        let simd = lower_simd_body(&body, &func_sig, &info, 200).unwrap();
+        let lowered = lower_body(&body, 200, simd).unwrap().unwrap();
+
+        #[rustfmt::skip]
+        assert_eq!(
+            lowered.body,
+            [
+                0x01, 0x02, 0x7e,
+                0x20, 0x00, 0x20, 0x01,
+                0x21, 0x03, 0x21, 0x02,
+                0x20, 0x02, 0x20, 0x03,
+                0x20, 0x00, 0x20, 0x01,
+                0x10, 0xc8, 0x01,
+                0x0b,
+            ]
+        );
+        assert_eq!(
+            lowered.ops,
+            [LoweredOp::Simd(SimdOp {
+                opcode: 0xae,
+                lane: 0
+            })]
+        );
+    }
+
+    #[test]
+    fn test_lower_simd_blocks() {
+        #[rustfmt::skip]
+        let body = [
+            0x00,             // no locals
+            0x02, 0x7b,       // block (result v128)
+            0xfd, 0x0c,       // v128.const
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0x0b,             // end
+            0xfd, 0x1b, 0x02, // i32x4.extract_lane 2
+            0x0b,             // end
+        ];
+        let info = Module::new(&[]).info;
+        let func_sig = FuncSig::new(vec![], vec![Type::I32]);
+
+        let simd = lower_simd_body(&body, &func_sig, &info, 3).unwrap();
+        let lowered = lower_body(&body, 3, simd).unwrap().unwrap();
+
+        // The block's result goes through the scratch locals.
+        #[rustfmt::skip]
+        assert_eq!(
+            lowered.body,
+            [
+                0x02, 0x01, 0x7f, 0x03, 0x7e,
+                0x02, 0x40,
+                0x42, 0x01, 0x42, 0x7f,
+                0x21, 0x02, 0x21, 0x01, 0x0b, 0x20, 0x01, 0x20, 0x02,
+                0x10, 0x03,
+                0x0b,
+            ]
+        );
+        assert_eq!(
+            lowered.ops,
+            [LoweredOp::Simd(SimdOp {
+                opcode: 0x1b,
+                lane: 2
+            })]
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use crate::cache::TrampolineCache;
+use crate::func_env::lower_multi_value_signature;
 use cranelift_codegen::{
     binemit::{NullTrapSink, Reloc, RelocSink},
     cursor::{Cursor, FuncCursor},
@@ -7,7 +8,7 @@ use cranelift_codegen::{
 };
 use hashbrown::HashMap;
 use std::ffi::c_void;
-use std::{iter, mem};
+use std::{iter, mem, ptr::NonNull};
 use wasmer_runtime_core::{
     backend::sys::{Memory, Protect},
     module::{ExportIndex, ModuleInfo},
@@ -29,6 +30,7 @@ pub type Trampoline =
 pub struct Trampolines {
     memory: Memory,
     offsets: HashMap<SigIndex, usize>,
+    dynamic_offsets: HashMap<SigIndex, usize>,
 }
 
 impl Trampolines {
@@ -37,6 +39,7 @@ impl Trampolines {
         //     #[serde(with = "serde_bytes")]
         //     code: Vec<u8>,
         //     offsets: HashMap<SigIndex, usize>,
+        //     dynamic_offsets: HashMap<SigIndex, usize>,
         // }
 
         let mut memory = Memory::with_size(cache.code.len()).unwrap();
@@ -52,6 +55,7 @@ impl Trampolines {
         Self {
             memory,
             offsets: cache.offsets,
+            dynamic_offsets: cache.dynamic_offsets,
         }
     }
 
@@ -65,6 +69,7 @@ impl Trampolines {
         TrampolineCache {
             code,
             offsets: self.offsets.clone(),
+            dynamic_offsets: self.dynamic_offsets.clone(),
         }
     }
 
@@ -78,15 +83,32 @@ impl Trampolines {
             })
             .chain(module.start_func.iter());
 
+        // Dynamic host functions can be imported with any
+        // of the signatures of the imported functions.
+        let mut imported_sigs = Vec::new();
+        for (imported_func_index, _) in module.imported_functions.iter() {
+            let sig_index = module.func_assoc[imported_func_index.convert_up(module)];
+            if !imported_sigs.contains(&sig_index) {
+                imported_sigs.push(sig_index);
+            }
+        }
+
         let mut compiled_functions = Vec::new();
         let mut ctx = Context::new();
         let mut total_size = 0;
 
-        for exported_func_index in func_index_iter {
-            let sig_index = module.func_assoc[*exported_func_index];
+        let trampoline_funcs = func_index_iter
+            .map(|func_index| (false, module.func_assoc[*func_index]))
+            .chain(imported_sigs.into_iter().map(|sig_index| (true, sig_index)));
+
+        for (dynamic, sig_index) in trampoline_funcs {
             let func_sig = &module.signatures[sig_index];
 
-            let trampoline_func = generate_func(&func_sig);
+            let trampoline_func = if dynamic {
+                generate_dynamic_func(func_sig)
+            } else {
+                generate_func(func_sig)
+            };
 
             ctx.func = trampoline_func;
 
@@ -102,7 +124,7 @@ impl Trampolines {
             ctx.clear();
 
             total_size += round_up(code_buf.len(), mem::size_of::<usize>());
-            compiled_functions.push((sig_index, code_buf));
+            compiled_functions.push((dynamic, sig_index, code_buf));
         }
 
         let mut memory = Memory::with_size(total_size).unwrap();
@@ -118,14 +140,19 @@ impl Trampolines {
 
         let mut previous_end = 0;
         let mut trampolines = HashMap::with_capacity(compiled_functions.len());
+        let mut dynamic_trampolines = HashMap::new();
 
-        for (sig_index, compiled) in compiled_functions.iter() {
+        for (dynamic, sig_index, compiled) in compiled_functions.iter() {
             let new_end = previous_end + round_up(compiled.len(), mem::size_of::<usize>());
             unsafe {
                 memory.as_slice_mut()[previous_end..previous_end + compiled.len()]
                     .copy_from_slice(&compiled[..]);
             }
-            trampolines.insert(*sig_index, previous_end);
+            if *dynamic {
+                dynamic_trampolines.insert(*sig_index, previous_end);
+            } else {
+                trampolines.insert(*sig_index, previous_end);
+            }
             previous_end = new_end;
         }
 
@@ -136,6 +163,7 @@ impl Trampolines {
         Self {
             memory,
             offsets: trampolines,
+            dynamic_offsets: dynamic_trampolines,
         }
     }
 
@@ -145,6 +173,15 @@ impl Trampolines {
 
         unsafe { Some(mem::transmute(ptr)) }
     }
+
+    /// Looks up the function that forwards calls
+    /// with this signature to a `DynamicFunc`.
+    pub fn lookup_dynamic(&self, sig_index: SigIndex) -> Option<NonNull<vm::Func>> {
+        let offset = *self.dynamic_offsets.get(&sig_index)?;
+        let ptr = unsafe { self.memory.as_ptr().add(offset) };
+
+        NonNull::new(ptr as *mut vm::Func)
+    }
 }
 
 /// This function generates a trampoline for the specific signature
@@ -166,13 +203,14 @@ fn generate_func(func_sig: &FuncSig) -> ir::Function {
 
     let mut pos = FuncCursor::new(&mut func).at_first_insertion_point(entry_ebb);
 
-    let mut args_vec = Vec::with_capacity(func_sig.params().len() + 1);
+    let params = wasm_tys_to_clif(func_sig.params());
+    let mut args_vec = Vec::with_capacity(params.len() + 2);
     args_vec.push(vmctx_ptr);
-    for (index, wasm_ty) in func_sig.params().iter().enumerate() {
+    for (index, clif_ty) in params.into_iter().enumerate() {
         let mem_flags = ir::MemFlags::trusted();
 
         let val = pos.ins().load(
-            wasm_ty_to_clif(*wasm_ty),
+            clif_ty,
             mem_flags,
             args_ptr,
             (index * mem::size_of::<u64>()) as i32,
@@ -180,6 +218,11 @@ fn generate_func(func_sig: &FuncSig) -> ir::Function {
         args_vec.push(val);
     }
 
+    // Functions that return more than one value store them right into the buffer.
+    if wasm_tys_to_clif(func_sig.returns()).len() > 1 {
+        args_vec.push(returns_ptr);
+    }
+
     let call_inst = pos.ins().call_indirect(export_sig_ref, func_ptr, &args_vec);
 
     let return_values = pos.func.dfg.inst_results(call_inst).to_vec();
@@ -200,13 +243,111 @@ fn generate_func(func_sig: &FuncSig) -> ir::Function {
     func
 }
 
-fn wasm_ty_to_clif(ty: Type) -> ir::types::Type {
-    match ty {
-        Type::I32 => ir::types::I32,
-        Type::I64 => ir::types::I64,
-        Type::F32 => ir::types::F32,
-        Type::F64 => ir::types::F64,
+/// This function generates the function a `DynamicFunc` with the
+/// signature passed into it gets called through.
+///
+/// It's called with a `vm::FuncCtx` in place of the vmctx. The arguments
+/// get stored in a buffer, and along with one for the return values,
+/// passed to the function that the environment of the `vm::FuncCtx` starts with.
+fn generate_dynamic_func(func_sig: &FuncSig) -> ir::Function {
+    let mut func = ir::Function::with_name_signature(
+        ir::ExternalName::testcase("dynamic"),
+        generate_export_signature(func_sig),
+    );
+
+    let call_sig_ref = func.import_signature(generate_dynamic_call_signature());
+
+    let param_tys = wasm_tys_to_clif(func_sig.params());
+    let return_tys = wasm_tys_to_clif(func_sig.returns());
+
+    let args_slot = func.create_stack_slot(ir::StackSlotData::new(
+        ir::StackSlotKind::ExplicitSlot,
+        (param_tys.len() * mem::size_of::<u64>()) as u32,
+    ));
+    let returns_slot = func.create_stack_slot(ir::StackSlotData::new(
+        ir::StackSlotKind::ExplicitSlot,
+        (return_tys.len() * mem::size_of::<u64>()) as u32,
+    ));
+
+    let entry_ebb = func.dfg.make_ebb();
+    let func_ctx_ptr = func.dfg.append_ebb_param(entry_ebb, ir::types::I64);
+    let params: Vec<_> = param_tys
+        .into_iter()
+        .map(|clif_ty| func.dfg.append_ebb_param(entry_ebb, clif_ty))
+        .collect();
+    // Functions that return more than one value are given a buffer for them.
+    let returns_buffer = if return_tys.len() > 1 {
+        Some(func.dfg.append_ebb_param(entry_ebb, ir::types::I64))
+    } else {
+        None
+    };
+    func.layout.append_ebb(entry_ebb);
+
+    let mut pos = FuncCursor::new(&mut func).at_first_insertion_point(entry_ebb);
+
+    for (index, param) in params.into_iter().enumerate() {
+        pos.ins()
+            .stack_store(param, args_slot, (index * mem::size_of::<u64>()) as i32);
     }
+
+    let mem_flags = ir::MemFlags::trusted();
+    let func_env_ptr = pos.ins().load(
+        ir::types::I64,
+        mem_flags,
+        func_ctx_ptr,
+        vm::FuncCtx::offset_func_env() as i32,
+    );
+    let call_ptr = pos.ins().load(ir::types::I64, mem_flags, func_env_ptr, 0);
+
+    let args_ptr = pos.ins().stack_addr(ir::types::I64, args_slot, 0);
+    let returns_ptr = match returns_buffer {
+        Some(returns_buffer) => returns_buffer,
+        None => pos.ins().stack_addr(ir::types::I64, returns_slot, 0),
+    };
+    pos.ins().call_indirect(
+        call_sig_ref,
+        call_ptr,
+        &[func_ctx_ptr, args_ptr, returns_ptr],
+    );
+
+    if returns_buffer.is_some() {
+        pos.ins().return_(&[]);
+        return func;
+    }
+
+    let return_values: Vec<_> = return_tys
+        .into_iter()
+        .enumerate()
+        .map(|(index, clif_ty)| {
+            pos.ins().stack_load(
+                clif_ty,
+                returns_slot,
+                (index * mem::size_of::<u64>()) as i32,
+            )
+        })
+        .collect();
+
+    pos.ins().return_(&return_values);
+
+    func
+}
+
+/// The Cranelift types of values of `wasm_tys`, which take up
+/// one slot each in argument and return value buffers.
+///
+/// A `v128` is passed around as two `i64`s, the low half first.
+fn wasm_tys_to_clif(wasm_tys: &[Type]) -> Vec<ir::types::Type> {
+    wasm_tys
+        .iter()
+        .flat_map(|wasm_ty| match wasm_ty {
+            Type::I32 => vec![ir::types::I32],
+            Type::I64 => vec![ir::types::I64],
+            Type::F32 => vec![ir::types::F32],
+            Type::F64 => vec![ir::types::F64],
+            Type::AnyRef | Type::FuncRef => vec![ir::types::I64],
+            Type::V128 => vec![ir::types::I64, ir::types::I64],
+        })
+        .collect()
 }
 
 fn generate_trampoline_signature() -> ir::Signature {
@@ -226,17 +367,38 @@ fn generate_trampoline_signature() -> ir::Signature {
     sig
 }
 
-fn generate_export_signature(func_sig: &FuncSig) -> ir::Signature {
+/// The signature of the function that `DynamicFunc`s
+/// get called with: `(func_ctx, args, returns)`.
+fn generate_dynamic_call_signature() -> ir::Signature {
     let isa = super::get_isa();
     let call_convention = isa.default_call_conv();
-    let mut export_clif_sig = ir::Signature::new(call_convention);
+    let mut sig = ir::Signature::new(call_convention);
 
-    let func_sig_iter = func_sig.params().iter().map(|wasm_ty| ir::AbiParam {
-        value_type: wasm_ty_to_clif(*wasm_ty),
+    let ptr_param = ir::AbiParam {
+        value_type: ir::types::I64,
         purpose: ir::ArgumentPurpose::Normal,
         extension: ir::ArgumentExtension::None,
         location: ir::ArgumentLoc::Unassigned,
-    });
+    };
+
+    sig.params = vec![ptr_param, ptr_param, ptr_param];
+
+    sig
+}
+
+fn generate_export_signature(func_sig: &FuncSig) -> ir::Signature {
+    let isa = super::get_isa();
+    let call_convention = isa.default_call_conv();
+    let mut export_clif_sig = ir::Signature::new(call_convention);
+
+    let func_sig_iter = wasm_tys_to_clif(func_sig.params())
+        .into_iter()
+        .map(|clif_ty| ir::AbiParam {
+            value_type: clif_ty,
+            purpose: ir::ArgumentPurpose::Normal,
+            extension: ir::ArgumentExtension::None,
+            location: ir::ArgumentLoc::Unassigned,
+        });
 
     export_clif_sig.params = iter::once(ir::AbiParam {
         value_type: ir::types::I64,
@@ -247,17 +409,18 @@ fn generate_export_signature(func_sig: &FuncSig) -> ir::Signature {
     .chain(func_sig_iter)
     .collect();
 
-    export_clif_sig.returns = func_sig
-        .returns()
-        .iter()
-        .map(|wasm_ty| ir::AbiParam {
-            value_type: wasm_ty_to_clif(*wasm_ty),
+    export_clif_sig.returns = wasm_tys_to_clif(func_sig.returns())
+        .into_iter()
+        .map(|clif_ty| ir::AbiParam {
+            value_type: clif_ty,
             purpose: ir::ArgumentPurpose::Normal,
             extension: ir::ArgumentExtension::None,
             location: ir::ArgumentLoc::Unassigned,
         })
         .collect();
 
+    lower_multi_value_signature(&mut export_clif_sig, ir::types::I64);
+
     export_clif_sig
 }
 
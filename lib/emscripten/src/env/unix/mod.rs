@@ -9,18 +9,24 @@ use std::os::raw::c_char;
 
 use crate::env::call_malloc;
 use crate::utils::{copy_cstr_into_wasm, copy_terminated_array_of_cstrs};
-use wasmer_runtime_core::vm::Ctx;
+use wasmer_runtime_core::{
+    memory::{Array, WasmPtr},
+    vm::Ctx,
+};
 
 // #[no_mangle]
 /// emscripten: _getenv // (name: *const char) -> *const c_char;
-pub fn _getenv(ctx: &mut Ctx, name: i32) -> u32 {
+pub fn _getenv(ctx: &mut Ctx, name: WasmPtr<u8, Array>) -> u32 {
     debug!("emscripten::_getenv");
 
-    let name_addr = emscripten_memory_pointer!(ctx.memory(0), name) as *const c_char;
+    let name = match name.get_c_string(ctx.memory(0)) {
+        Some(name) => name,
+        None => return 0,
+    };
 
-    debug!("=> name({:?})", unsafe { CStr::from_ptr(name_addr) });
+    debug!("=> name({:?})", name);
 
-    let c_str = unsafe { getenv(name_addr) };
+    let c_str = unsafe { getenv(name.as_ptr()) };
     if c_str.is_null() {
         return 0;
     }
@@ -29,16 +35,26 @@ pub fn _getenv(ctx: &mut Ctx, name: i32) -> u32 {
 }
 
 /// emscripten: _setenv // (name: *const char, name: *const value, overwrite: int);
-pub fn _setenv(ctx: &mut Ctx, name: c_int, value: c_int, overwrite: c_int) -> c_int {
+pub fn _setenv(
+    ctx: &mut Ctx,
+    name: WasmPtr<u8, Array>,
+    value: WasmPtr<u8, Array>,
+    overwrite: c_int,
+) -> c_int {
     debug!("emscripten::_setenv");
 
-    let name_addr = emscripten_memory_pointer!(ctx.memory(0), name) as *const c_char;
-    let value_addr = emscripten_memory_pointer!(ctx.memory(0), value) as *const c_char;
+    let (name, value) = match (
+        name.get_c_string(ctx.memory(0)),
+        value.get_c_string(ctx.memory(0)),
+    ) {
+        (Some(name), Some(value)) => (name, value),
+        _ => return -1,
+    };
 
-    debug!("=> name({:?})", unsafe { CStr::from_ptr(name_addr) });
-    debug!("=> value({:?})", unsafe { CStr::from_ptr(value_addr) });
+    debug!("=> name({:?})", name);
+    debug!("=> value({:?})", value);
 
-    unsafe { setenv(name_addr, value_addr, overwrite) }
+    unsafe { setenv(name.as_ptr(), value.as_ptr(), overwrite) }
 }
 
 /// emscripten: _putenv // (name: *const char);
@@ -53,14 +69,17 @@ pub fn _putenv(ctx: &mut Ctx, name: c_int) -> c_int {
 }
 
 /// emscripten: _unsetenv // (name: *const char);
-pub fn _unsetenv(ctx: &mut Ctx, name: c_int) -> c_int {
+pub fn _unsetenv(ctx: &mut Ctx, name: WasmPtr<u8, Array>) -> c_int {
     debug!("emscripten::_unsetenv");
 
-    let name_addr = emscripten_memory_pointer!(ctx.memory(0), name) as *const c_char;
+    let name = match name.get_c_string(ctx.memory(0)) {
+        Some(name) => name,
+        None => return -1,
+    };
 
-    debug!("=> name({:?})", unsafe { CStr::from_ptr(name_addr) });
+    debug!("=> name({:?})", name);
 
-    unsafe { unsetenv(name_addr) }
+    unsafe { unsetenv(name.as_ptr()) }
 }
 
 #[allow(clippy::cast_ptr_alignment)]
@@ -37,7 +37,11 @@ use libc::{
     write,
     // sockaddr_in,
 };
-use wasmer_runtime_core::vm::Ctx;
+use wasmer_runtime_core::{
+    memory::{Array, WasmPtr},
+    types::ValueType,
+    vm::Ctx,
+};
 
 use super::env;
 use std::slice;
@@ -56,6 +60,14 @@ use std::ffi::CString;
 #[cfg(not(target_os = "darwin"))]
 const SO_NOSIGPIPE: c_int = 0;
 
+/// The `struct iovec` that readv and writev take an array of.
+#[derive(Copy, Clone, ValueType)]
+#[repr(C)]
+struct GuestIovec {
+    iov_base: WasmPtr<u8, Array>,
+    iov_len: u32,
+}
+
 /// exit
 pub fn ___syscall1(ctx: &mut Ctx, which: c_int, mut varargs: VarArgs) {
     debug!("emscripten::___syscall1 (exit) {}", which);
@@ -248,7 +260,6 @@ pub fn ___syscall140(ctx: &mut Ctx, which: i32, mut varargs: VarArgs) -> i32 {
 }
 
 /// readv
-#[allow(clippy::cast_ptr_alignment)]
 pub fn ___syscall145(ctx: &mut Ctx, which: c_int, mut varargs: VarArgs) -> i32 {
     // -> ssize_t
     debug!("emscripten::___syscall145 (readv) {}", which);
@@ -260,70 +271,61 @@ pub fn ___syscall145(ctx: &mut Ctx, which: c_int, mut varargs: VarArgs) -> i32 {
     // unsafe { readv(fd, iov_addr, iovcnt) }
 
     let fd: i32 = varargs.get(ctx);
-    let iov: i32 = varargs.get(ctx);
-    let iovcnt: i32 = varargs.get(ctx);
-
-    #[repr(C)]
-    struct GuestIovec {
-        iov_base: i32,
-        iov_len: i32,
-    }
-
-    debug!("=> fd: {}, iov: {}, iovcnt = {}", fd, iov, iovcnt);
+    let iov: WasmPtr<GuestIovec, Array> = varargs.get(ctx);
+    let iovcnt: u32 = varargs.get(ctx);
+
+    debug!("=> fd: {}, iov: {:?}, iovcnt = {}", fd, iov, iovcnt);
+    let iovecs = match iov.deref(ctx.memory(0), 0, iovcnt) {
+        Some(iovecs) => iovecs,
+        None => return -1,
+    };
     let mut ret = 0;
-    unsafe {
-        for i in 0..iovcnt {
-            let guest_iov_addr =
-                emscripten_memory_pointer!(ctx.memory(0), (iov + i * 8)) as *mut GuestIovec;
-            let iov_base = emscripten_memory_pointer!(ctx.memory(0), (*guest_iov_addr).iov_base)
-                as *mut c_void;
-            let iov_len = (*guest_iov_addr).iov_len as _;
-            // debug!("=> iov_addr: {:?}, {:?}", iov_base, iov_len);
-            let curr = read(fd, iov_base, iov_len);
-            if curr < 0 {
-                return -1;
-            }
-            ret += curr;
+    for iovec in iovecs {
+        let GuestIovec { iov_base, iov_len } = iovec.get();
+        let buf = match iov_base.deref(ctx.memory(0), 0, iov_len) {
+            Some(buf) => buf,
+            None => return -1,
+        };
+        // debug!("=> iov_addr: {:?}, {:?}", iov_base, iov_len);
+        let curr = unsafe { read(fd, buf.as_ptr() as *mut c_void, iov_len as _) };
+        if curr < 0 {
+            return -1;
         }
-        // debug!(" => ret: {}", ret);
-        ret as _
+        ret += curr;
     }
+    // debug!(" => ret: {}", ret);
+    ret as _
 }
 
 // writev
-#[allow(clippy::cast_ptr_alignment)]
 pub fn ___syscall146(ctx: &mut Ctx, which: i32, mut varargs: VarArgs) -> i32 {
     // -> ssize_t
     debug!("emscripten::___syscall146 (writev) {}", which);
     let fd: i32 = varargs.get(ctx);
-    let iov: i32 = varargs.get(ctx);
-    let iovcnt: i32 = varargs.get(ctx);
-
-    #[repr(C)]
-    struct GuestIovec {
-        iov_base: i32,
-        iov_len: i32,
-    }
-
-    debug!("=> fd: {}, iov: {}, iovcnt = {}", fd, iov, iovcnt);
+    let iov: WasmPtr<GuestIovec, Array> = varargs.get(ctx);
+    let iovcnt: u32 = varargs.get(ctx);
+
+    debug!("=> fd: {}, iov: {:?}, iovcnt = {}", fd, iov, iovcnt);
+    let iovecs = match iov.deref(ctx.memory(0), 0, iovcnt) {
+        Some(iovecs) => iovecs,
+        None => return -1,
+    };
     let mut ret = 0;
-    unsafe {
-        for i in 0..iovcnt {
-            let guest_iov_addr =
-                emscripten_memory_pointer!(ctx.memory(0), (iov + i * 8)) as *mut GuestIovec;
-            let iov_base = emscripten_memory_pointer!(ctx.memory(0), (*guest_iov_addr).iov_base)
-                as *const c_void;
-            let iov_len = (*guest_iov_addr).iov_len as _;
-            // debug!("=> iov_addr: {:?}, {:?}", iov_base, iov_len);
-            let curr = write(fd, iov_base, iov_len);
-            if curr < 0 {
-                return -1;
-            }
-            ret += curr;
+    for iovec in iovecs {
+        let GuestIovec { iov_base, iov_len } = iovec.get();
+        let buf = match iov_base.deref(ctx.memory(0), 0, iov_len) {
+            Some(buf) => buf,
+            None => return -1,
+        };
+        // debug!("=> iov_addr: {:?}, {:?}", iov_base, iov_len);
+        let curr = unsafe { write(fd, buf.as_ptr() as *const c_void, iov_len as _) };
+        if curr < 0 {
+            return -1;
         }
-        // debug!(" => ret: {}", ret);
-        ret as _
+        ret += curr;
     }
+    // debug!(" => ret: {}", ret);
+    ret as _
 }
 
 pub fn ___syscall168(_ctx: &mut Ctx, _one: i32, _two: i32) -> i32 {
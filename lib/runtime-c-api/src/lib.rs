@@ -13,7 +13,9 @@ use std::{ffi::c_void, ptr};
 use wasmer_runtime::{Ctx, Global, ImportObject, Instance, Memory, Module, Table, Value};
 use wasmer_runtime_core::export::{Context, Export, FuncPointer};
 use wasmer_runtime_core::import::Namespace;
-use wasmer_runtime_core::types::{ElementType, FuncSig, MemoryDescriptor, TableDescriptor, Type};
+use wasmer_runtime_core::types::{
+    AnyRef, ElementType, FuncRef, FuncSig, MemoryDescriptor, TableDescriptor, Type,
+};
 use wasmer_runtime_core::units::{Bytes, Pages};
 
 #[allow(non_camel_case_types)]
@@ -43,6 +45,9 @@ pub enum wasmer_value_tag {
     WASM_I64,
     WASM_F32,
     WASM_F64,
+    WASM_V128,
+    WASM_ANYREF,
+    WASM_FUNCREF,
 }
 
 #[repr(C)]
@@ -52,6 +57,11 @@ pub union wasmer_value {
     I64: int64_t,
     F32: f32,
     F64: f64,
+    /// The bytes of a `v128`, its first lane first.
+    V128: [uint8_t; 16],
+    /// An `anyref` or a `funcref`, null being a null pointer. Only
+    /// a `funcref` that WebAssembly code returned can be passed back.
+    REF: *const c_void,
 }
 
 #[repr(C)]
@@ -609,25 +619,7 @@ pub unsafe extern "C" fn wasmer_instance_call(
     match result {
         Ok(results_vec) => {
             if results_vec.len() > 0 {
-                let ret = match results_vec[0] {
-                    Value::I32(x) => wasmer_value_t {
-                        tag: wasmer_value_tag::WASM_I32,
-                        value: wasmer_value { I32: x },
-                    },
-                    Value::I64(x) => wasmer_value_t {
-                        tag: wasmer_value_tag::WASM_I64,
-                        value: wasmer_value { I64: x },
-                    },
-                    Value::F32(x) => wasmer_value_t {
-                        tag: wasmer_value_tag::WASM_F32,
-                        value: wasmer_value { F32: x },
-                    },
-                    Value::F64(x) => wasmer_value_t {
-                        tag: wasmer_value_tag::WASM_F64,
-                        value: wasmer_value { F64: x },
-                    },
-                };
-                results[0] = ret;
+                results[0] = results_vec[0].clone().into();
             }
             wasmer_result_t::WASMER_OK
         }
@@ -986,24 +978,16 @@ pub extern "C" fn wasmer_instance_destroy(instance: *mut wasmer_instance_t) {
 impl From<wasmer_value_t> for Value {
     fn from(v: wasmer_value_t) -> Self {
         unsafe {
-            match v {
-                wasmer_value_t {
-                    tag: WASM_I32,
-                    value: wasmer_value { I32 },
-                } => Value::I32(I32),
-                wasmer_value_t {
-                    tag: WASM_I64,
-                    value: wasmer_value { I64 },
-                } => Value::I64(I64),
-                wasmer_value_t {
-                    tag: WASM_F32,
-                    value: wasmer_value { F32 },
-                } => Value::F32(F32),
-                wasmer_value_t {
-                    tag: WASM_F64,
-                    value: wasmer_value { F64 },
-                } => Value::F64(F64),
-                _ => panic!("not implemented"),
+            match v.tag {
+                wasmer_value_tag::WASM_I32 => Value::I32(v.value.I32),
+                wasmer_value_tag::WASM_I64 => Value::I64(v.value.I64),
+                wasmer_value_tag::WASM_F32 => Value::F32(v.value.F32),
+                wasmer_value_tag::WASM_F64 => Value::F64(v.value.F64),
+                wasmer_value_tag::WASM_V128 => Value::V128(u128::from_le_bytes(v.value.V128)),
+                wasmer_value_tag::WASM_ANYREF => Value::AnyRef(AnyRef::new(v.value.REF)),
+                wasmer_value_tag::WASM_FUNCREF => {
+                    Value::FuncRef(FuncRef::from_bits(v.value.REF as u64))
+                }
             }
         }
     }
@@ -1028,6 +1012,22 @@ impl From<Value> for wasmer_value_t {
                 tag: wasmer_value_tag::WASM_F64,
                 value: wasmer_value { F64: x },
             },
+            Value::V128(x) => wasmer_value_t {
+                tag: wasmer_value_tag::WASM_V128,
+                value: wasmer_value {
+                    V128: x.to_le_bytes(),
+                },
+            },
+            Value::AnyRef(x) => wasmer_value_t {
+                tag: wasmer_value_tag::WASM_ANYREF,
+                value: wasmer_value { REF: x.as_ptr() },
+            },
+            Value::FuncRef(x) => wasmer_value_t {
+                tag: wasmer_value_tag::WASM_FUNCREF,
+                value: wasmer_value {
+                    REF: x.to_bits() as *const c_void,
+                },
+            },
         }
     }
 }
@@ -1039,7 +1039,9 @@ impl From<Type> for wasmer_value_tag {
             Type::I64 => wasmer_value_tag::WASM_I64,
             Type::F32 => wasmer_value_tag::WASM_F32,
             Type::F64 => wasmer_value_tag::WASM_F64,
-            _ => panic!("not implemented"),
+            Type::V128 => wasmer_value_tag::WASM_V128,
+            Type::AnyRef => wasmer_value_tag::WASM_ANYREF,
+            Type::FuncRef => wasmer_value_tag::WASM_FUNCREF,
         }
     }
 }
@@ -1052,7 +1054,9 @@ impl From<wasmer_value_tag> for Type {
                 wasmer_value_tag::WASM_I64 => Type::I64,
                 wasmer_value_tag::WASM_F32 => Type::F32,
                 wasmer_value_tag::WASM_F64 => Type::F64,
-                _ => panic!("not implemented"),
+                wasmer_value_tag::WASM_V128 => Type::V128,
+                wasmer_value_tag::WASM_ANYREF => Type::AnyRef,
+                wasmer_value_tag::WASM_FUNCREF => Type::FuncRef,
             }
         }
     }
@@ -1071,6 +1075,9 @@ impl From<&wasmer_runtime::wasm::Type> for wasmer_value_tag {
             Type::I64 => wasmer_value_tag::WASM_I64,
             Type::F32 => wasmer_value_tag::WASM_F32,
             Type::F64 => wasmer_value_tag::WASM_F64,
+            Type::V128 => wasmer_value_tag::WASM_V128,
+            Type::AnyRef => wasmer_value_tag::WASM_ANYREF,
+            Type::FuncRef => wasmer_value_tag::WASM_FUNCREF,
         }
     }
 }
@@ -24,6 +24,9 @@ enum wasmer_value_tag {
   WASM_I64,
   WASM_F32,
   WASM_F64,
+  WASM_V128,
+  WASM_ANYREF,
+  WASM_FUNCREF,
 };
 typedef uint32_t wasmer_value_tag;
 
@@ -55,6 +58,15 @@ typedef union {
   int64_t I64;
   float F32;
   double F64;
+  /**
+   * The bytes of a `v128`, its first lane first.
+   */
+  uint8_t V128[16];
+  /**
+   * An `anyref` or a `funcref`, null being a null pointer. Only
+   * a `funcref` that WebAssembly code returned can be passed back.
+   */
+  const void *REF;
 } wasmer_value;
 
 typedef struct {
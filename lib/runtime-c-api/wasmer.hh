@@ -22,6 +22,9 @@ enum class wasmer_value_tag : uint32_t {
   WASM_I64,
   WASM_F32,
   WASM_F64,
+  WASM_V128,
+  WASM_ANYREF,
+  WASM_FUNCREF,
 };
 
 struct wasmer_instance_context_t;
@@ -52,6 +55,11 @@ union wasmer_value {
   int64_t I64;
   float F32;
   double F64;
+  /// The bytes of a `v128`, its first lane first.
+  uint8_t V128[16];
+  /// An `anyref` or a `funcref`, null being a null pointer. Only
+  /// a `funcref` that WebAssembly code returned can be passed back.
+  const void *REF;
 };
 
 struct wasmer_value_t {
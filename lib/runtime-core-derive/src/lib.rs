@@ -0,0 +1,128 @@
+//! Derive macros for `wasmer-runtime-core`.
+//!
+//! These are re-exported by `wasmer-runtime-core`, so use them
+//! from there instead of depending on this crate directly.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, Ident, Meta, NestedMeta};
+
+/// Implement `ValueType` for a struct, so that it can be read
+/// from and written to a wasm linear memory as a whole.
+///
+/// The struct must be `#[repr(C)]`, have no padding, not be
+/// generic, and every one of its fields must be a `ValueType`.
+#[proc_macro_derive(ValueType)]
+pub fn derive_value_type(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_value_type(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand_value_type(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(Error::new_spanned(
+                input,
+                "ValueType can only be derived for structs",
+            ))
+        }
+    };
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "ValueType can't be derived for generic structs",
+        ));
+    }
+    if !is_repr_c(input) {
+        return Err(Error::new_spanned(
+            input,
+            "ValueType can only be derived for #[repr(C)] structs",
+        ));
+    }
+
+    let name = &input.ident;
+    let types: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+    let members: Vec<TokenStream2> = match fields {
+        Fields::Named(_) => fields
+            .iter()
+            .map(|field| {
+                let ident = &field.ident;
+                quote!(#ident)
+            })
+            .collect(),
+        _ => (0..fields.iter().count())
+            .map(|index| {
+                let index = syn::Index::from(index);
+                quote!(#index)
+            })
+            .collect(),
+    };
+    // Without padding, each field starts right after the one
+    // before it, and the fields add up to the whole struct.
+    let offsets: Vec<TokenStream2> = (0..types.len())
+        .map(|index| {
+            let before = &types[..index];
+            quote!(0 #( + ::std::mem::size_of::<#before>() )*)
+        })
+        .collect();
+    let members = &members;
+    let padding_check = Ident::new(
+        &format!("__{}_VALUE_TYPE_HAS_NO_PADDING", name),
+        Span::call_site(),
+    );
+    let types = &types;
+    let offsets = &offsets;
+
+    Ok(quote! {
+        impl ::wasmer_runtime_core::types::ValueType for #name {
+            fn into_le(self, buffer: &mut [u8]) {
+                #(
+                    ::wasmer_runtime_core::types::ValueType::into_le(
+                        self.#members,
+                        &mut buffer[#offsets..],
+                    );
+                )*
+            }
+            fn from_le(
+                buffer: &[u8],
+            ) -> ::std::result::Result<Self, ::wasmer_runtime_core::types::ValueError> {
+                if buffer.len() < ::std::mem::size_of::<Self>() {
+                    return Err(::wasmer_runtime_core::types::ValueError::BufferTooSmall);
+                }
+                Ok(Self {
+                    #(
+                        #members: <#types as ::wasmer_runtime_core::types::ValueType>::from_le(
+                            &buffer[#offsets..],
+                        )?,
+                    )*
+                })
+            }
+        }
+
+        // Fails to compile, with mismatched array lengths,
+        // if the fields don't add up to the whole struct.
+        #[allow(dead_code, non_upper_case_globals)]
+        const #padding_check: [(); 0] =
+            [(); ::std::mem::size_of::<#name>() - (0 #( + ::std::mem::size_of::<#types>() )*)];
+    })
+}
+
+fn is_repr_c(input: &DeriveInput) -> bool {
+    input.attrs.iter().any(|attr| match attr.parse_meta() {
+        Ok(Meta::List(list)) => {
+            list.ident == "repr"
+                && list.nested.iter().any(|nested| match nested {
+                    NestedMeta::Meta(Meta::Word(ident)) => ident == "C",
+                    _ => false,
+                })
+        }
+        _ => false,
+    })
+}
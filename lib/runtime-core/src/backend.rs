@@ -1,9 +1,9 @@
 use crate::{
     backing::ImportBacking,
     error::CompileResult,
-    error::RuntimeResult,
+    error::{RuntimeError, RuntimeResult},
     module::ModuleInner,
-    types::{FuncIndex, LocalFuncIndex, Value},
+    types::{FuncIndex, LocalFuncIndex, SigIndex, Value},
     vm,
 };
 
@@ -36,11 +36,90 @@ impl Token {
     }
 }
 
+/// Configuration options for the compiler.
+#[derive(Debug, Clone, Default)]
+pub struct CompilerConfig {
+    /// Charge fuel for the code that runs, trapping with
+    /// [`TrapKind::OutOfFuel`] once it is exhausted.
+    ///
+    /// Instances start out with `u64::MAX` fuel, so set a budget with
+    /// [`Instance::set_fuel`] before calling into untrusted code.
+    ///
+    /// [`TrapKind::OutOfFuel`]: ../error/enum.TrapKind.html#variant.OutOfFuel
+    /// [`Instance::set_fuel`]: ../struct.Instance.html#method.set_fuel
+    pub metering: bool,
+
+    /// Check for an interrupt on function entry and at the top of every
+    /// loop, so that an [`InterruptHandle`] can stop the running code.
+    ///
+    /// Without this, interrupting an instance has no effect.
+    ///
+    /// [`InterruptHandle`]: ../struct.InterruptHandle.html
+    pub interruptible: bool,
+
+    /// The WebAssembly proposals to accept besides the MVP.
+    pub features: Features,
+}
+
+/// WebAssembly proposals that are off unless asked for.
+///
+/// A module that uses a proposal which isn't enabled
+/// fails to validate.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct Features {
+    /// The [bulk memory proposal]: passive data and element segments,
+    /// and the `memory.init`, `memory.copy`, `memory.fill`, `data.drop`,
+    /// `table.init`, `table.copy` and `elem.drop` instructions.
+    ///
+    /// [bulk memory proposal]: https://github.com/WebAssembly/bulk-memory-operations
+    pub bulk_memory: bool,
+
+    /// The [multi-value proposal]: functions that return more than one value.
+    ///
+    /// Blocks, loops and `if`s that take parameters or have more than one
+    /// result aren't supported, so modules with them fail to validate.
+    ///
+    /// [multi-value proposal]: https://github.com/WebAssembly/multi-value
+    pub multi_value: bool,
+
+    /// The [reference types proposal]: `anyref` and `funcref` values, more
+    /// than one table, and the `ref.null`, `ref.is_null`, `ref.func`,
+    /// `table.get`, `table.set`, `table.size` and `table.grow` instructions.
+    ///
+    /// `table.fill` also needs `bulk_memory`.
+    ///
+    /// [reference types proposal]: https://github.com/WebAssembly/reference-types
+    pub reference_types: bool,
+
+    /// The [non-trapping float-to-int conversions proposal]: the
+    /// `i32.trunc_sat_f32_s` family of instructions, which saturate
+    /// instead of trapping on NaN or out of range values.
+    ///
+    /// [non-trapping float-to-int conversions proposal]: https://github.com/WebAssembly/nontrapping-float-to-int-conversions
+    pub sat_float_to_int: bool,
+
+    /// The [sign-extension operators proposal]: `i32.extend8_s`,
+    /// `i32.extend16_s`, `i64.extend8_s`, `i64.extend16_s` and `i64.extend32_s`.
+    ///
+    /// [sign-extension operators proposal]: https://github.com/WebAssembly/sign-extension-ops
+    pub sign_extension: bool,
+
+    /// The [128-bit SIMD proposal]: the `v128` type and the instructions
+    /// that operate on it, except that globals can't be `v128`s. Those
+    /// of the relaxed SIMD proposal aren't included.
+    ///
+    /// It can't be enabled together with `reference_types`.
+    ///
+    /// [128-bit SIMD proposal]: https://github.com/WebAssembly/simd
+    pub simd: bool,
+}
+
 pub trait Compiler {
     /// Compiles a `Module` from WebAssembly binary format.
     /// The `CompileToken` parameter ensures that this can only
     /// be called from inside the runtime.
-    fn compile(&self, wasm: &[u8], _: Token) -> CompileResult<ModuleInner>;
+    fn compile(&self, wasm: &[u8], config: CompilerConfig, _: Token)
+        -> CompileResult<ModuleInner>;
 
     unsafe fn from_cache(&self, cache: Artifact, _: Token) -> Result<ModuleInner, CacheError>;
 }
@@ -75,10 +154,20 @@ pub trait ProtectedCaller: Send + Sync {
     ) -> RuntimeResult<Vec<Value>>;
 
     fn get_early_trapper(&self) -> Box<dyn UserTrapper>;
+
+    /// Returns a function with the signature designated by `sig_index`
+    /// that forwards its arguments to a [`DynamicFunc`].
+    ///
+    /// It's called with a `vm::FuncCtx` whose environment starts with a pointer
+    /// to an `extern "C" fn(*mut vm::FuncCtx, *const u64, *mut u64)`, which it
+    /// calls with the arguments and a buffer for the return values.
+    ///
+    /// [`DynamicFunc`]: ../struct.DynamicFunc.html
+    fn get_dynamic_trampoline(&self, sig_index: SigIndex) -> Option<NonNull<vm::Func>>;
 }
 
 pub trait UserTrapper {
-    unsafe fn do_early_trap(&self, msg: String) -> !;
+    unsafe fn do_early_trap(&self, error: RuntimeError) -> !;
 }
 
 pub trait FuncResolver: Send + Sync {
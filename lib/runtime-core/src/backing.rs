@@ -1,20 +1,28 @@
 use crate::{
-    error::{LinkError, LinkResult},
-    export::{Context, Export},
+    error::{LinkError, LinkResult, TrapKind},
+    export::{Context, Export, FuncEnv},
     global::Global,
     import::ImportObject,
+    limits::ResourceLimiter,
     memory::Memory,
-    module::{ImportName, ModuleInner},
+    module::{DataInitializer, ExternDescriptor, ImportName, ModuleInner, TableInitializer},
     sig_registry::SigRegistry,
+    snapshot::Snapshot,
     structures::{BoxedMap, Map, SliceMap, TypedIndex},
     table::Table,
     types::{
-        ImportedFuncIndex, ImportedGlobalIndex, ImportedMemoryIndex, ImportedTableIndex,
-        Initializer, LocalGlobalIndex, LocalMemoryIndex, LocalOrImport, LocalTableIndex, Value,
+        AnyRef, DataIndex, ElemIndex, ElementType, FuncIndex, ImportedFuncIndex,
+        ImportedGlobalIndex, ImportedMemoryIndex, ImportedTableIndex, Initializer,
+        LocalGlobalIndex, LocalMemoryIndex, LocalOrImport, LocalTableIndex, Value,
     },
+    units::Pages,
     vm,
 };
-use std::{slice, sync::Arc};
+use hashbrown::{HashMap, HashSet};
+use std::{
+    slice,
+    sync::{atomic::AtomicBool, Arc},
+};
 
 #[derive(Debug)]
 pub struct LocalBacking {
@@ -25,6 +33,18 @@ pub struct LocalBacking {
     pub(crate) vm_memories: BoxedMap<LocalMemoryIndex, *mut vm::LocalMemory>,
     pub(crate) vm_tables: BoxedMap<LocalTableIndex, *mut vm::LocalTable>,
     pub(crate) vm_globals: BoxedMap<LocalGlobalIndex, *mut vm::LocalGlobal>,
+
+    /// Set to make the running code trap. See `InterruptHandle`.
+    pub(crate) interrupted: Arc<AtomicBool>,
+
+    /// The passive segments that `data.drop` and `elem.drop` dropped.
+    pub(crate) dropped_data: HashSet<DataIndex>,
+    pub(crate) dropped_elements: HashSet<ElemIndex>,
+
+    pub(crate) func_refs: FuncRefs,
+
+    /// Asked before `memory.grow` and `table.grow` grow anything.
+    pub(crate) limiter: Option<Box<dyn ResourceLimiter>>,
 }
 
 // impl LocalBacking {
@@ -38,16 +58,75 @@ pub struct LocalBacking {
 // }
 
 impl LocalBacking {
-    pub(crate) fn new(module: &ModuleInner, imports: &ImportBacking, vmctx: *mut vm::Ctx) -> Self {
+    /// Creates the backing of `module`, along with the trap that its
+    /// segments caused if one of them didn't fit with bulk memory.
+    pub(crate) fn new(
+        module: &ModuleInner,
+        imports: &ImportBacking,
+        mut limiter: Option<Box<dyn ResourceLimiter>>,
+        snapshot: Option<&Snapshot>,
+        vmctx: *mut vm::Ctx,
+    ) -> LinkResult<(Self, Option<TrapKind>)> {
+        if let Some(limiter) = &mut limiter {
+            Self::check_limits(module, &mut **limiter, snapshot)?;
+        }
+
         let mut memories = Self::generate_memories(module);
         let mut tables = Self::generate_tables(module);
         let mut globals = Self::generate_globals(module, imports);
 
-        let vm_memories = Self::finalize_memories(module, imports, &mut memories);
-        let vm_tables = Self::finalize_tables(module, imports, &mut tables, vmctx);
+        let mut func_refs = FuncRefs::default();
+
+        let elem_initializers = &module.info.elem_initializers[..];
+        let data_initializers = &module.info.data_initializers[..];
+
+        // A snapshot replaces the segment initializers.
+        let (vm_memories, vm_tables, segment_trap) = if snapshot.is_some() {
+            (
+                Self::vm_memories(&mut memories),
+                Self::vm_tables(&mut tables),
+                None,
+            )
+        } else if module.info.features.bulk_memory {
+            // The element segments and then the data segments are written in
+            // order, and the first one that doesn't fit traps. The ones before
+            // it stay written, which shows in the memories and tables imported.
+            let (elements, data, trap) =
+                Self::segments_in_bounds(module, imports, &memories, &tables);
+
+            (
+                Self::finalize_memories(module, imports, &mut memories, &data_initializers[..data]),
+                Self::finalize_tables(
+                    module,
+                    imports,
+                    &mut tables,
+                    &mut func_refs,
+                    vmctx,
+                    &elem_initializers[..elements],
+                ),
+                trap,
+            )
+        } else {
+            // No segment is written unless all of them fit, so a module that fails
+            // to link leaves the memories and tables it imports untouched.
+            Self::check_segments(module, imports, &memories, &tables)?;
+
+            (
+                Self::finalize_memories(module, imports, &mut memories, data_initializers),
+                Self::finalize_tables(
+                    module,
+                    imports,
+                    &mut tables,
+                    &mut func_refs,
+                    vmctx,
+                    elem_initializers,
+                ),
+                None,
+            )
+        };
         let vm_globals = Self::finalize_globals(&mut globals);
 
-        Self {
+        let mut backing = Self {
             memories,
             tables,
             globals,
@@ -55,7 +134,179 @@ impl LocalBacking {
             vm_memories,
             vm_tables,
             vm_globals,
+
+            interrupted: Arc::new(AtomicBool::new(false)),
+
+            dropped_data: HashSet::new(),
+            dropped_elements: HashSet::new(),
+
+            func_refs,
+
+            limiter,
+        };
+
+        if let Some(snapshot) = snapshot {
+            snapshot.restore(module, imports, &mut backing, vmctx);
+        }
+
+        Ok((backing, segment_trap))
+    }
+
+    /// Ask `limiter` whether the memories and tables of `module` can be
+    /// created, at their size in `snapshot` if there is one, before
+    /// they're allocated.
+    fn check_limits(
+        module: &ModuleInner,
+        limiter: &mut dyn ResourceLimiter,
+        snapshot: Option<&Snapshot>,
+    ) -> LinkResult<()> {
+        let mut link_errors = vec![];
+
+        for (local_memory_index, desc) in &module.info.memories {
+            let memory = local_memory_index.convert_up(&module.info);
+            let pages = snapshot.map_or(desc.minimum, |snapshot| {
+                snapshot.memory_pages(local_memory_index)
+            });
+            if !limiter.memory_growing(memory, Pages(0), pages) {
+                link_errors.push(LinkError::MemoryLimitExceeded { memory, pages });
+            }
+        }
+
+        for (local_table_index, desc) in &module.info.tables {
+            let table = local_table_index.convert_up(&module.info);
+            let elements = snapshot.map_or(desc.minimum, |snapshot| {
+                snapshot.table_elements(local_table_index)
+            });
+            if !limiter.table_growing(table, 0, elements) {
+                link_errors.push(LinkError::TableLimitExceeded { table, elements });
+            }
+        }
+
+        if link_errors.is_empty() {
+            Ok(())
+        } else {
+            Err(link_errors)
+        }
+    }
+
+    fn check_segments(
+        module: &ModuleInner,
+        imports: &ImportBacking,
+        memories: &SliceMap<LocalMemoryIndex, Memory>,
+        tables: &SliceMap<LocalTableIndex, Table>,
+    ) -> LinkResult<()> {
+        let mut link_errors = vec![];
+
+        for init in &module.info.data_initializers {
+            if !Self::data_segment_fits(module, imports, memories, init) {
+                link_errors.push(LinkError::DataSegmentDoesNotFit {
+                    memory: init.memory_index,
+                    offset: Self::segment_offset(&init.base, imports),
+                    len: init.data.len(),
+                });
+            }
+        }
+
+        for init in &module.info.elem_initializers {
+            if !Self::elem_segment_fits(module, imports, tables, init) {
+                link_errors.push(LinkError::ElementSegmentDoesNotFit {
+                    table: init.table_index,
+                    offset: Self::segment_offset(&init.base, imports),
+                    len: init.elements.len(),
+                });
+            }
+        }
+
+        if link_errors.len() > 0 {
+            Err(link_errors)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns how many of the element segments and then of the data
+    /// segments fit, up to the first one that doesn't, and the trap
+    /// that one causes.
+    fn segments_in_bounds(
+        module: &ModuleInner,
+        imports: &ImportBacking,
+        memories: &SliceMap<LocalMemoryIndex, Memory>,
+        tables: &SliceMap<LocalTableIndex, Table>,
+    ) -> (usize, usize, Option<TrapKind>) {
+        let elem_initializers = &module.info.elem_initializers;
+        let elements = elem_initializers
+            .iter()
+            .take_while(|init| Self::elem_segment_fits(module, imports, tables, init))
+            .count();
+        if let Some(init) = elem_initializers.get(elements) {
+            let trap = TrapKind::TableOutOfBounds {
+                table: init.table_index,
+            };
+            return (elements, 0, Some(trap));
         }
+
+        let data_initializers = &module.info.data_initializers;
+        let data = data_initializers
+            .iter()
+            .take_while(|init| Self::data_segment_fits(module, imports, memories, init))
+            .count();
+        let trap = data_initializers
+            .get(data)
+            .map(|init| TrapKind::OutOfBoundsAccess {
+                memory: init.memory_index,
+                addr: None,
+            });
+        (elements, data, trap)
+    }
+
+    fn data_segment_fits(
+        module: &ModuleInner,
+        imports: &ImportBacking,
+        memories: &SliceMap<LocalMemoryIndex, Memory>,
+        init: &DataInitializer,
+    ) -> bool {
+        let offset = Self::segment_offset(&init.base, imports);
+        let memory_size = match init.memory_index.local_or_import(&module.info) {
+            LocalOrImport::Local(local_memory_index) => memories[local_memory_index].size(),
+            LocalOrImport::Import(imported_memory_index) => {
+                imports.memories[imported_memory_index].size()
+            }
+        };
+
+        offset + init.data.len() <= memory_size.bytes().0
+    }
+
+    fn elem_segment_fits(
+        module: &ModuleInner,
+        imports: &ImportBacking,
+        tables: &SliceMap<LocalTableIndex, Table>,
+        init: &TableInitializer,
+    ) -> bool {
+        let offset = Self::segment_offset(&init.base, imports);
+        let table_size = match init.table_index.local_or_import(&module.info) {
+            LocalOrImport::Local(local_table_index) => tables[local_table_index].size(),
+            LocalOrImport::Import(imported_table_index) => {
+                imports.tables[imported_table_index].size()
+            }
+        };
+
+        offset + init.elements.len() <= table_size as usize
+    }
+
+    fn segment_offset(base: &Initializer, imports: &ImportBacking) -> usize {
+        let offset = match *base {
+            Initializer::Const(Value::I32(offset)) => offset as u32,
+            Initializer::Const(_) => panic!("a const initializer must be the i32 type"),
+            Initializer::GetGlobal(import_global_index) => {
+                if let Value::I32(x) = imports.globals[import_global_index].get() {
+                    x as u32
+                } else {
+                    panic!("unsupported global type for initialzer")
+                }
+            }
+        };
+
+        offset as usize
     }
 
     fn generate_memories(module: &ModuleInner) -> BoxedMap<LocalMemoryIndex, Memory> {
@@ -67,36 +318,19 @@ impl LocalBacking {
         memories.into_boxed_map()
     }
 
+    /// Writes the data segments in `data_initializers` to memory.
     fn finalize_memories(
         module: &ModuleInner,
         imports: &ImportBacking,
         memories: &mut SliceMap<LocalMemoryIndex, Memory>,
+        data_initializers: &[DataInitializer],
     ) -> BoxedMap<LocalMemoryIndex, *mut vm::LocalMemory> {
         // For each init that has some data...
-        for init in module
-            .info
-            .data_initializers
-            .iter()
-            .filter(|init| init.data.len() > 0)
-        {
-            let init_base = match init.base {
-                Initializer::Const(Value::I32(offset)) => offset as u32,
-                Initializer::Const(_) => panic!("a const initializer must be the i32 type"),
-                Initializer::GetGlobal(import_global_index) => {
-                    if let Value::I32(x) = imports.globals[import_global_index].get() {
-                        x as u32
-                    } else {
-                        panic!("unsupported global type for initialzer")
-                    }
-                }
-            } as usize;
+        for init in data_initializers.iter().filter(|init| init.data.len() > 0) {
+            let init_base = Self::segment_offset(&init.base, imports);
 
             match init.memory_index.local_or_import(&module.info) {
                 LocalOrImport::Local(local_memory_index) => {
-                    let memory_desc = module.info.memories[local_memory_index];
-                    let data_top = init_base + init.data.len();
-                    assert!(memory_desc.minimum.bytes().0 >= data_top);
-
                     let mem = &memories[local_memory_index];
                     for (mem_byte, data_byte) in mem.view()[init_base..init_base + init.data.len()]
                         .iter()
@@ -121,6 +355,12 @@ impl LocalBacking {
             }
         }
 
+        Self::vm_memories(memories)
+    }
+
+    fn vm_memories(
+        memories: &mut SliceMap<LocalMemoryIndex, Memory>,
+    ) -> BoxedMap<LocalMemoryIndex, *mut vm::LocalMemory> {
         memories
             .iter_mut()
             .map(|(_, mem)| mem.vm_local_memory())
@@ -139,106 +379,46 @@ impl LocalBacking {
         tables.into_boxed_map()
     }
 
+    /// Writes the element segments in `elem_initializers` to the tables.
     #[allow(clippy::cast_ptr_alignment)]
     fn finalize_tables(
         module: &ModuleInner,
         imports: &ImportBacking,
         tables: &mut SliceMap<LocalTableIndex, Table>,
+        func_refs: &mut FuncRefs,
         vmctx: *mut vm::Ctx,
+        elem_initializers: &[TableInitializer],
     ) -> BoxedMap<LocalTableIndex, *mut vm::LocalTable> {
-        for init in &module.info.elem_initializers {
-            let init_base = match init.base {
-                Initializer::Const(Value::I32(offset)) => offset as u32,
-                Initializer::Const(_) => panic!("a const initializer must be the i32 type"),
-                Initializer::GetGlobal(import_global_index) => {
-                    if let Value::I32(x) = imports.globals[import_global_index].get() {
-                        x as u32
-                    } else {
-                        panic!("unsupported global type for initialzer")
-                    }
-                }
-            } as usize;
+        for init in elem_initializers {
+            let init_base = Self::segment_offset(&init.base, imports);
 
-            match init.table_index.local_or_import(&module.info) {
-                LocalOrImport::Local(local_table_index) => {
-                    let table = &tables[local_table_index];
+            let table = match init.table_index.local_or_import(&module.info) {
+                LocalOrImport::Local(local_table_index) => &tables[local_table_index],
+                LocalOrImport::Import(import_table_index) => &imports.tables[import_table_index],
+            };
 
-                    if (table.size() as usize) < init_base + init.elements.len() {
-                        let delta = (init_base + init.elements.len()) - table.size() as usize;
-                        // Grow the table if it's too small.
-                        table.grow(delta as u32).expect("couldn't grow table");
+            match table.descriptor().element {
+                ElementType::Anyfunc => table.anyfunc_direct_access_mut(|elements| {
+                    for (i, &func_index) in init.elements.iter().enumerate() {
+                        elements[init_base + i] = anyfunc(module, imports, vmctx, func_index);
                     }
-
-                    table.anyfunc_direct_access_mut(|elements| {
-                        for (i, &func_index) in init.elements.iter().enumerate() {
-                            let sig_index = module.info.func_assoc[func_index];
-                            let signature = &module.info.signatures[sig_index];
-                            let sig_id = vm::SigId(
-                                SigRegistry.lookup_sig_index(Arc::clone(&signature)).index() as u32,
-                            );
-
-                            let (func, ctx) = match func_index.local_or_import(&module.info) {
-                                LocalOrImport::Local(local_func_index) => (
-                                    module
-                                        .func_resolver
-                                        .get(module, local_func_index)
-                                        .unwrap()
-                                        .as_ptr()
-                                        as *const vm::Func,
-                                    vmctx,
-                                ),
-                                LocalOrImport::Import(imported_func_index) => {
-                                    let vm::ImportedFunc { func, vmctx } =
-                                        imports.vm_functions[imported_func_index];
-                                    (func, vmctx)
-                                }
-                            };
-
-                            elements[init_base + i] = vm::Anyfunc { func, ctx, sig_id };
-                        }
-                    });
-                }
-                LocalOrImport::Import(import_table_index) => {
-                    let table = &imports.tables[import_table_index];
-
-                    if (table.size() as usize) < init_base + init.elements.len() {
-                        let delta = (init_base + init.elements.len()) - table.size() as usize;
-                        // Grow the table if it's too small.
-                        table.grow(delta as u32).expect("couldn't grow table");
+                }),
+                // Functions stored in an `anyref` table are funcref values.
+                ElementType::AnyRef => table.anyref_direct_access_mut(|elements| {
+                    for (i, &func_index) in init.elements.iter().enumerate() {
+                        let func_ref = func_refs.get(anyfunc(module, imports, vmctx, func_index));
+                        elements[init_base + i] = unsafe { AnyRef::from_bits(func_ref) };
                     }
-
-                    table.anyfunc_direct_access_mut(|elements| {
-                        for (i, &func_index) in init.elements.iter().enumerate() {
-                            let sig_index = module.info.func_assoc[func_index];
-                            let signature = &module.info.signatures[sig_index];
-                            let sig_id = vm::SigId(
-                                SigRegistry.lookup_sig_index(Arc::clone(&signature)).index() as u32,
-                            );
-
-                            let (func, ctx) = match func_index.local_or_import(&module.info) {
-                                LocalOrImport::Local(local_func_index) => (
-                                    module
-                                        .func_resolver
-                                        .get(module, local_func_index)
-                                        .unwrap()
-                                        .as_ptr()
-                                        as *const vm::Func,
-                                    vmctx,
-                                ),
-                                LocalOrImport::Import(imported_func_index) => {
-                                    let vm::ImportedFunc { func, vmctx } =
-                                        imports.vm_functions[imported_func_index];
-                                    (func, vmctx)
-                                }
-                            };
-
-                            elements[init_base + i] = vm::Anyfunc { func, ctx, sig_id };
-                        }
-                    });
-                }
+                }),
             }
         }
 
+        Self::vm_tables(tables)
+    }
+
+    fn vm_tables(
+        tables: &mut SliceMap<LocalTableIndex, Table>,
+    ) -> BoxedMap<LocalTableIndex, *mut vm::LocalTable> {
         tables
             .iter_mut()
             .map(|(_, table)| table.vm_local_table())
@@ -283,12 +463,82 @@ impl LocalBacking {
     }
 }
 
+/// Creates the table element that refers to the function at `func_index`.
+pub(crate) fn anyfunc(
+    module: &ModuleInner,
+    imports: &ImportBacking,
+    vmctx: *mut vm::Ctx,
+    func_index: FuncIndex,
+) -> vm::Anyfunc {
+    let sig_index = module.info.func_assoc[func_index];
+    let signature = &module.info.signatures[sig_index];
+    let sig_id = vm::SigId(SigRegistry.lookup_sig_index(Arc::clone(&signature)).index() as u32);
+
+    let (func, ctx) = match func_index.local_or_import(&module.info) {
+        LocalOrImport::Local(local_func_index) => (
+            module
+                .func_resolver
+                .get(module, local_func_index)
+                .unwrap()
+                .as_ptr() as *const vm::Func,
+            vmctx,
+        ),
+        LocalOrImport::Import(imported_func_index) => {
+            let vm::ImportedFunc { func, vmctx } = imports.vm_functions[imported_func_index];
+            (func, vmctx)
+        }
+    };
+
+    vm::Anyfunc { func, ctx, sig_id }
+}
+
+/// The funcref values that compiled code has seen. A funcref is a pointer to
+/// one of these table elements, which live as long as the instance does.
+#[derive(Debug, Default)]
+pub(crate) struct FuncRefs(HashMap<(usize, usize, u32), Box<vm::Anyfunc>>);
+
+impl FuncRefs {
+    /// Returns the funcref value of `anyfunc`, which is 0 for a null element.
+    pub(crate) fn get(&mut self, anyfunc: vm::Anyfunc) -> u64 {
+        if anyfunc.func.is_null() {
+            return 0;
+        }
+
+        let element = self
+            .0
+            .entry(Self::key(&anyfunc))
+            .or_insert_with(|| Box::new(anyfunc));
+        &**element as *const vm::Anyfunc as u64
+    }
+
+    /// Iterates over the funcref values handed out so far, and the
+    /// table elements they point to.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (u64, &vm::Anyfunc)> {
+        self.0
+            .values()
+            .map(|element| (&**element as *const vm::Anyfunc as u64, &**element))
+    }
+
+    /// Identifies the function that `anyfunc` refers to.
+    pub(crate) fn key(anyfunc: &vm::Anyfunc) -> (usize, usize, u32) {
+        (
+            anyfunc.func as usize,
+            anyfunc.ctx as usize,
+            anyfunc.sig_id.0,
+        )
+    }
+}
+
 #[derive(Debug)]
 pub struct ImportBacking {
     pub(crate) memories: BoxedMap<ImportedMemoryIndex, Memory>,
     pub(crate) tables: BoxedMap<ImportedTableIndex, Table>,
     pub(crate) globals: BoxedMap<ImportedGlobalIndex, Global>,
 
+    /// The state of imported closures, along with the contexts they're called with.
+    #[allow(dead_code)]
+    pub(crate) func_envs: Vec<(FuncEnv, Box<vm::FuncCtx>)>,
+
     pub(crate) vm_functions: BoxedMap<ImportedFuncIndex, vm::ImportedFunc>,
     pub(crate) vm_memories: BoxedMap<ImportedMemoryIndex, *mut vm::LocalMemory>,
     pub(crate) vm_tables: BoxedMap<ImportedTableIndex, *mut vm::LocalTable>,
@@ -304,11 +554,12 @@ impl ImportBacking {
         let mut failed = false;
         let mut link_errors = vec![];
 
-        let vm_functions = import_functions(module, imports, vmctx).unwrap_or_else(|le| {
-            failed = true;
-            link_errors.extend(le);
-            Map::new().into_boxed_map()
-        });
+        let (func_envs, vm_functions) =
+            import_functions(module, imports, vmctx).unwrap_or_else(|le| {
+                failed = true;
+                link_errors.extend(le);
+                (Vec::new(), Map::new().into_boxed_map())
+            });
 
         let (memories, vm_memories) = import_memories(module, imports).unwrap_or_else(|le| {
             failed = true;
@@ -336,6 +587,7 @@ impl ImportBacking {
                 tables,
                 globals,
 
+                func_envs,
                 vm_functions,
                 vm_memories,
                 vm_tables,
@@ -353,8 +605,12 @@ fn import_functions(
     module: &ModuleInner,
     imports: &ImportObject,
     vmctx: *mut vm::Ctx,
-) -> LinkResult<BoxedMap<ImportedFuncIndex, vm::ImportedFunc>> {
+) -> LinkResult<(
+    Vec<(FuncEnv, Box<vm::FuncCtx>)>,
+    BoxedMap<ImportedFuncIndex, vm::ImportedFunc>,
+)> {
     let mut link_errors = vec![];
+    let mut func_envs = vec![];
     let mut functions = Map::with_capacity(module.info.imported_functions.len());
     for (
         index,
@@ -370,9 +626,8 @@ fn import_functions(
         let namespace = module.info.namespace_table.get(*namespace_index);
         let name = module.info.name_table.get(*name_index);
 
-        let import = imports
-            .get_namespace(namespace)
-            .and_then(|namespace| namespace.get_export(name));
+        let expected = ExternDescriptor::Function(Arc::clone(expected_sig));
+        let import = imports.resolve(namespace, name, &expected);
         match import {
             Some(Export::Function {
                 func,
@@ -380,10 +635,27 @@ fn import_functions(
                 signature,
             }) => {
                 if *expected_sig == signature {
+                    let func: *const vm::Func = match ctx {
+                        Context::Dynamic(_) => module
+                            .protected_caller
+                            .get_dynamic_trampoline(sig_index)
+                            .expect("broken invariant, no trampoline for an imported signature")
+                            .as_ptr(),
+                        _ => func.inner(),
+                    };
                     functions.push(vm::ImportedFunc {
-                        func: func.inner(),
+                        func,
                         vmctx: match ctx {
                             Context::External(ctx) => ctx,
+                            Context::Env(env) | Context::Dynamic(env) => {
+                                let mut func_ctx = Box::new(vm::FuncCtx {
+                                    vmctx,
+                                    func_env: env.as_ptr(),
+                                });
+                                let func_ctx_ptr = &mut *func_ctx as *mut vm::FuncCtx;
+                                func_envs.push((env, func_ctx));
+                                func_ctx_ptr as *mut vm::Ctx
+                            }
                             Context::Internal => vmctx,
                         },
                     });
@@ -415,6 +687,7 @@ fn import_functions(
                 link_errors.push(LinkError::ImportNotFound {
                     namespace: namespace.to_string(),
                     name: name.to_string(),
+                    expected,
                 });
             }
         }
@@ -423,7 +696,7 @@ fn import_functions(
     if link_errors.len() > 0 {
         Err(link_errors)
     } else {
-        Ok(functions.into_boxed_map())
+        Ok((func_envs, functions.into_boxed_map()))
     }
 }
 
@@ -451,9 +724,8 @@ fn import_memories(
         let namespace = module.info.namespace_table.get(*namespace_index);
         let name = module.info.name_table.get(*name_index);
 
-        let memory_import = imports
-            .get_namespace(&namespace)
-            .and_then(|namespace| namespace.get_export(&name));
+        let expected = ExternDescriptor::Memory(*expected_memory_desc);
+        let memory_import = imports.resolve(namespace, name, &expected);
         match memory_import {
             Some(Export::Memory(memory)) => {
                 if expected_memory_desc.fits_in_imported(memory.descriptor()) {
@@ -487,6 +759,7 @@ fn import_memories(
                 link_errors.push(LinkError::ImportNotFound {
                     namespace: namespace.to_string(),
                     name: name.to_string(),
+                    expected,
                 });
             }
         }
@@ -523,9 +796,8 @@ fn import_tables(
         let namespace = module.info.namespace_table.get(*namespace_index);
         let name = module.info.name_table.get(*name_index);
 
-        let table_import = imports
-            .get_namespace(&namespace)
-            .and_then(|namespace| namespace.get_export(&name));
+        let expected = ExternDescriptor::Table(*expected_table_desc);
+        let table_import = imports.resolve(namespace, name, &expected);
         match table_import {
             Some(Export::Table(mut table)) => {
                 if expected_table_desc.fits_in_imported(table.descriptor()) {
@@ -559,6 +831,7 @@ fn import_tables(
                 link_errors.push(LinkError::ImportNotFound {
                     namespace: namespace.to_string(),
                     name: name.to_string(),
+                    expected,
                 });
             }
         }
@@ -594,9 +867,8 @@ fn import_globals(
     {
         let namespace = module.info.namespace_table.get(*namespace_index);
         let name = module.info.name_table.get(*name_index);
-        let import = imports
-            .get_namespace(namespace)
-            .and_then(|namespace| namespace.get_export(name));
+        let expected = ExternDescriptor::Global(*imported_global_desc);
+        let import = imports.resolve(namespace, name, &expected);
         match import {
             Some(Export::Global(mut global)) => {
                 if global.descriptor() == *imported_global_desc {
@@ -630,6 +902,7 @@ fn import_globals(
                 link_errors.push(LinkError::ImportNotFound {
                     namespace: namespace.to_string(),
                     name: name.to_string(),
+                    expected,
                 });
             }
         }
@@ -1,7 +1,11 @@
+use crate::module::ExternDescriptor;
+use crate::structures::TypedIndex;
 use crate::sys::Memory;
 use crate::types::{
-    FuncSig, GlobalDescriptor, MemoryDescriptor, MemoryIndex, TableDescriptor, TableIndex, Type,
+    FuncIndex, FuncSig, GlobalDescriptor, GlobalIndex, MemoryDescriptor, MemoryIndex,
+    TableDescriptor, TableIndex, Type,
 };
+use crate::units::Pages;
 use core::borrow::Borrow;
 use std::sync::Arc;
 
@@ -63,6 +67,7 @@ pub enum LinkError {
     ImportNotFound {
         namespace: String,
         name: String,
+        expected: ExternDescriptor,
     },
     IncorrectMemoryDescriptor {
         namespace: String,
@@ -82,6 +87,22 @@ pub enum LinkError {
         expected: GlobalDescriptor,
         found: GlobalDescriptor,
     },
+    /// A data segment reaches past the end of its memory.
+    DataSegmentDoesNotFit {
+        memory: MemoryIndex,
+        offset: usize,
+        len: usize,
+    },
+    /// An element segment reaches past the end of its table.
+    ElementSegmentDoesNotFit {
+        table: TableIndex,
+        offset: usize,
+        len: usize,
+    },
+    /// The instance's limiter doesn't allow a memory this big.
+    MemoryLimitExceeded { memory: MemoryIndex, pages: Pages },
+    /// The instance's limiter doesn't allow a table this big.
+    TableLimitExceeded { table: TableIndex, elements: u32 },
 }
 
 impl PartialEq for LinkError {
@@ -93,7 +114,7 @@ impl PartialEq for LinkError {
 impl std::fmt::Display for LinkError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            LinkError::ImportNotFound {namespace, name} => write!(f, "Import not found, namespace: {}, name: {}", namespace, name),
+            LinkError::ImportNotFound {namespace, name, expected} => write!(f, "Import not found, namespace: {}, name: {}, expected: {}", namespace, name, expected),
             LinkError::IncorrectGlobalDescriptor {namespace, name,expected,found} => {
                 write!(f, "Incorrect global descriptor, namespace: {}, name: {}, expected global descriptor: {:?}, found global descriptor: {:?}", namespace, name, expected, found)
             },
@@ -109,6 +130,18 @@ impl std::fmt::Display for LinkError {
             LinkError::IncorrectTableDescriptor{namespace, name,expected,found} => {
                 write!(f, "Incorrect table descriptor, namespace: {}, name: {}, expected table descriptor: {:?}, found table descriptor: {:?}", namespace, name, expected, found)
             },
+            LinkError::DataSegmentDoesNotFit{memory, offset, len} => {
+                write!(f, "Data segment does not fit, memory: {}, offset: {}, length: {}", memory.index(), offset, len)
+            },
+            LinkError::ElementSegmentDoesNotFit{table, offset, len} => {
+                write!(f, "Elements segment does not fit, table: {}, offset: {}, length: {}", table.index(), offset, len)
+            },
+            LinkError::MemoryLimitExceeded{memory, pages} => {
+                write!(f, "Memory exceeds the resource limits, memory: {}, pages: {}", memory.index(), pages.0)
+            },
+            LinkError::TableLimitExceeded{table, elements} => {
+                write!(f, "Table exceeds the resource limits, table: {}, elements: {}", table.index(), elements)
+            },
         }
     }
 }
@@ -123,6 +156,72 @@ impl std::error::Error for LinkError {}
 /// Comparing two `RuntimeError`s always evaluates to false.
 #[derive(Debug, Clone)]
 pub enum RuntimeError {
+    /// Webassembly code trapped.
+    Trap {
+        kind: TrapKind,
+        /// The webassembly functions that were on the stack
+        /// when the trap happened, innermost first.
+        backtrace: Vec<FrameInfo>,
+    },
+    User {
+        msg: String,
+    },
+    /// A host function ended the call by returning an [`ExitCode`],
+    /// which is how WASI's `proc_exit` exits.
+    ///
+    /// [`ExitCode`]: struct.ExitCode.html
+    Exit {
+        code: i32,
+    },
+    Unknown {
+        msg: String,
+    },
+}
+
+impl RuntimeError {
+    /// The webassembly functions that were on the stack when
+    /// this error happened, innermost first.
+    ///
+    /// This is empty unless the error is a trap.
+    pub fn backtrace(&self) -> &[FrameInfo] {
+        match self {
+            RuntimeError::Trap { backtrace, .. } => backtrace,
+            _ => &[],
+        }
+    }
+}
+
+/// Returned by a host function to end the call into the
+/// instance with [`RuntimeError::Exit`], instead of trapping.
+///
+/// It doesn't implement `Debug`, since host functions that
+/// return other errors have them reported with it.
+///
+/// [`RuntimeError::Exit`]: enum.RuntimeError.html#variant.Exit
+pub struct ExitCode(pub i32);
+
+impl PartialEq for RuntimeError {
+    fn eq(&self, _other: &RuntimeError) -> bool {
+        false
+    }
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RuntimeError::Trap { kind, .. } => write!(f, "{}", kind),
+            RuntimeError::Unknown { msg } => {
+                write!(f, "Unknown runtime error with message: \"{}\"", msg)
+            }
+            RuntimeError::User { msg } => write!(f, "User runtime error with message: \"{}\"", msg),
+            RuntimeError::Exit { code } => write!(f, "Exited with code {}", code),
+        }
+    }
+}
+
+/// The reason webassembly code trapped.
+#[derive(Debug, Clone)]
+pub enum TrapKind {
     OutOfBoundsAccess {
         memory: MemoryIndex,
         addr: Option<u32>,
@@ -136,34 +235,43 @@ pub enum RuntimeError {
     IndirectCallToNull {
         table: TableIndex,
     },
-    IllegalArithmeticOperation,
-    User {
-        msg: String,
-    },
+    /// The call stack was exhausted.
+    StackOverflow,
+    /// An `unreachable` instruction was executed.
+    Unreachable,
+    IntegerOverflow,
+    IntegerDivisionByZero,
+    /// A float was converted to an integer that can't represent it.
+    BadConversionToInteger,
+    /// An atomic instruction accessed memory that isn't aligned to its size.
+    UnalignedAtomic,
+    OutOfFuel,
+    Interrupted,
     Unknown {
         msg: String,
     },
 }
 
-impl PartialEq for RuntimeError {
-    fn eq(&self, _other: &RuntimeError) -> bool {
-        false
-    }
-}
-
-impl std::fmt::Display for RuntimeError {
+impl std::fmt::Display for TrapKind {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            RuntimeError::IndirectCallSignature { table } => write!(
+            TrapKind::IndirectCallSignature { table } => write!(
                 f,
                 "Indirect call signature error with Table Index \"{:?}\"",
                 table
             ),
-            RuntimeError::IndirectCallToNull { table } => {
+            TrapKind::IndirectCallToNull { table } => {
                 write!(f, "Indirect call to null with table index \"{:?}\"", table)
             }
-            RuntimeError::IllegalArithmeticOperation => write!(f, "Illegal arithmetic operation"),
-            RuntimeError::OutOfBoundsAccess { memory, addr } => match addr {
+            TrapKind::StackOverflow => write!(f, "Call stack exhausted"),
+            TrapKind::Unreachable => write!(f, "Unreachable code reached"),
+            TrapKind::IntegerOverflow => write!(f, "Integer overflow"),
+            TrapKind::IntegerDivisionByZero => write!(f, "Integer division by zero"),
+            TrapKind::BadConversionToInteger => write!(f, "Invalid conversion to integer"),
+            TrapKind::UnalignedAtomic => write!(f, "Unaligned atomic access"),
+            TrapKind::OutOfFuel => write!(f, "Out of fuel"),
+            TrapKind::Interrupted => write!(f, "Interrupted"),
+            TrapKind::OutOfBoundsAccess { memory, addr } => match addr {
                 Some(addr) => write!(
                     f,
                     "Out-of-bounds access with memory index {:?} and address {}",
@@ -171,17 +279,38 @@ impl std::fmt::Display for RuntimeError {
                 ),
                 None => write!(f, "Out-of-bounds access with memory index {:?}", memory),
             },
-            RuntimeError::TableOutOfBounds { table } => {
+            TrapKind::TableOutOfBounds { table } => {
                 write!(f, "Table out of bounds with table index \"{:?}\"", table)
             }
-            RuntimeError::Unknown { msg } => {
-                write!(f, "Unknown runtime error with message: \"{}\"", msg)
-            }
-            RuntimeError::User { msg } => write!(f, "User runtime error with message: \"{}\"", msg),
+            TrapKind::Unknown { msg } => write!(f, "Unknown trap with message: \"{}\"", msg),
         }
     }
 }
 
+/// A webassembly function that was on the stack when a trap happened.
+#[derive(Debug, Clone)]
+pub struct FrameInfo {
+    pub func_index: FuncIndex,
+    /// The name of the function, from the name section.
+    pub func_name: Option<String>,
+    /// The offset in the module of the instruction that was
+    /// running in this function, if it's known.
+    pub module_offset: Option<usize>,
+}
+
+impl std::fmt::Display for FrameInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.func_name {
+            Some(name) => write!(f, "function `{}` (#{})", name, self.func_index.index())?,
+            None => write!(f, "function #{}", self.func_index.index())?,
+        }
+        if let Some(offset) = self.module_offset {
+            write!(f, " at offset {:#x}", offset)?;
+        }
+        Ok(())
+    }
+}
+
 impl std::error::Error for RuntimeError {}
 
 /// This error type is produced by resolving a wasm function
@@ -294,6 +423,71 @@ impl std::fmt::Display for CreationError {
 
 impl std::error::Error for CreationError {}
 
+/// This error type is produced when taking a snapshot of
+/// an instance, or when instantiating a module from one.
+///
+/// Comparing two `SnapshotError`s always evaluates to false.
+#[derive(Debug, Clone)]
+pub enum SnapshotError {
+    /// A table element is a host reference, or a function
+    /// that doesn't belong to the instance or its imports.
+    UnsupportedTableElement {
+        table: TableIndex,
+        index: u32,
+    },
+    /// A global is a host reference, or a function that
+    /// doesn't belong to the instance or its imports.
+    UnsupportedGlobal {
+        global: GlobalIndex,
+    },
+    /// The bytes aren't a snapshot.
+    InvalidFile,
+    /// The snapshot was written in another version of the format.
+    UnsupportedVersion(u64),
+    SerializeError(String),
+    DeserializeError(String),
+    /// The snapshot was taken of an instance of another module.
+    WrongModule,
+    /// The snapshot doesn't fit the module it claims to be from.
+    Incompatible(String),
+}
+
+impl PartialEq for SnapshotError {
+    fn eq(&self, _other: &SnapshotError) -> bool {
+        false
+    }
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SnapshotError::UnsupportedTableElement { table, index } => write!(
+                f,
+                "Table element can't be snapshotted, table: {}, index: {}",
+                table.index(),
+                index
+            ),
+            SnapshotError::UnsupportedGlobal { global } => {
+                write!(f, "Global can't be snapshotted, global: {}", global.index())
+            }
+            SnapshotError::InvalidFile => write!(f, "Not a snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported snapshot version: {}", version)
+            }
+            SnapshotError::SerializeError(msg) => write!(f, "Unable to serialize: {}", msg),
+            SnapshotError::DeserializeError(msg) => write!(f, "Unable to deserialize: {}", msg),
+            SnapshotError::WrongModule => {
+                write!(f, "The snapshot was taken of an instance of another module")
+            }
+            SnapshotError::Incompatible(msg) => {
+                write!(f, "The snapshot doesn't fit the module: {}", msg)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
 /// The amalgamation of all errors that can occur
 /// during the compilation, instantiation, or execution
 /// of a webassembly module.
@@ -307,6 +501,7 @@ pub enum Error {
     ResolveError(ResolveError),
     CallError(CallError),
     CreationError(CreationError),
+    SnapshotError(SnapshotError),
 }
 
 impl PartialEq for Error {
@@ -345,6 +540,12 @@ impl From<CreationError> for Error {
     }
 }
 
+impl From<SnapshotError> for Error {
+    fn from(snapshot_err: SnapshotError) -> Self {
+        Error::SnapshotError(snapshot_err)
+    }
+}
+
 impl From<Vec<LinkError>> for Error {
     fn from(link_errs: Vec<LinkError>) -> Self {
         Error::LinkError(link_errs)
@@ -382,6 +583,7 @@ impl std::fmt::Display for Error {
             Error::ResolveError(err) => write!(f, "resolve error: {}", err),
             Error::CallError(err) => write!(f, "call error: {}", err),
             Error::CreationError(err) => write!(f, "creation error: {}", err),
+            Error::SnapshotError(err) => write!(f, "snapshot error: {}", err),
         }
     }
 }
@@ -3,14 +3,44 @@ use crate::{
     module::ModuleInner, table::Table, types::FuncSig, vm,
 };
 use hashbrown::hash_map;
-use std::sync::Arc;
+use std::{any::Any, ffi::c_void, fmt, sync::Arc};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub enum Context {
     External(*mut vm::Ctx),
+    /// The function is a host function that captures state. It gets
+    /// called with a `vm::FuncCtx` instead of the importing instance's `vm::Ctx`.
+    Env(FuncEnv),
+    /// The function is a [`DynamicFunc`]. Its signature is only known at runtime,
+    /// so the backend provides the function to call in place of the (null)
+    /// function pointer. It's called like `Env`.
+    ///
+    /// [`DynamicFunc`]: ../struct.DynamicFunc.html
+    Dynamic(FuncEnv),
     Internal,
 }
 
+/// The state captured by a host function, shared by every
+/// instance that imports it.
+#[derive(Clone)]
+pub struct FuncEnv(Arc<dyn Any + Send>);
+
+impl FuncEnv {
+    pub(crate) fn new<T: Send + 'static>(env: T) -> Self {
+        FuncEnv(Arc::new(env))
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const c_void {
+        &*self.0 as *const dyn Any as *const c_void
+    }
+}
+
+impl fmt::Debug for FuncEnv {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("FuncEnv").field(&self.as_ptr()).finish()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Export {
     Function {
@@ -1,7 +1,7 @@
 use crate::{
     export::Export,
     import::IsExport,
-    types::{GlobalDescriptor, Type, Value},
+    types::{AnyRef, FuncRef, GlobalDescriptor, Type, Value},
     vm,
 };
 use std::{cell::RefCell, fmt, rc::Rc};
@@ -14,6 +14,8 @@ pub struct Global {
 impl Global {
     /// Create a new `Global` value.
     ///
+    /// Globals can't hold `v128` values, so this panics for one.
+    ///
     /// Usage:
     ///
     /// ```
@@ -27,6 +29,8 @@ impl Global {
 
     /// Create a new, mutable `Global` value.
     ///
+    /// Globals can't hold `v128` values, so this panics for one.
+    ///
     /// Usage:
     ///
     /// ```
@@ -50,6 +54,9 @@ impl Global {
                 Value::I64(x) => x as u64,
                 Value::F32(x) => x.to_bits() as u64,
                 Value::F64(x) => x.to_bits(),
+                Value::AnyRef(x) => x.to_bits(),
+                Value::FuncRef(x) => x.to_bits(),
+                Value::V128(_) => panic!("v128 globals aren't supported"),
             },
         };
 
@@ -79,6 +86,9 @@ impl Global {
                         Value::I64(x) => x as u64,
                         Value::F32(x) => x.to_bits() as u64,
                         Value::F64(x) => x.to_bits(),
+                        Value::AnyRef(x) => x.to_bits(),
+                        Value::FuncRef(x) => x.to_bits(),
+                        Value::V128(_) => unreachable!("there are no v128 globals"),
                     },
                 };
                 *self.storage.borrow_mut() = local_global;
@@ -99,6 +109,9 @@ impl Global {
             Type::I64 => Value::I64(data as i64),
             Type::F32 => Value::F32(f32::from_bits(data as u32)),
             Type::F64 => Value::F64(f64::from_bits(data)),
+            Type::AnyRef => Value::AnyRef(unsafe { AnyRef::from_bits(data) }),
+            Type::FuncRef => Value::FuncRef(unsafe { FuncRef::from_bits(data) }),
+            Type::V128 => unreachable!("there are no v128 globals"),
         }
     }
 
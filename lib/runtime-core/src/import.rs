@@ -1,5 +1,6 @@
-use crate::export::Export;
+use crate::{export::Export, limits::ResourceLimiter, module::ExternDescriptor};
 use hashbrown::{hash_map::Entry, HashMap};
+use std::ffi::c_void;
 
 pub trait LikeNamespace {
     fn get_export(&self, name: &str) -> Option<Export>;
@@ -15,6 +16,9 @@ impl IsExport for Export {
     }
 }
 
+type Fallback = dyn Fn(&str, &str, &ExternDescriptor) -> Option<Export>;
+type LimiterCreator = dyn Fn() -> Box<dyn ResourceLimiter>;
+
 /// All of the import data used when instantiating.
 ///
 /// It's suggested that you use the [`imports!`] macro
@@ -38,6 +42,9 @@ impl IsExport for Export {
 /// ```
 pub struct ImportObject {
     map: HashMap<String, Box<dyn LikeNamespace>>,
+    state_creator: Option<Box<dyn Fn() -> (*mut c_void, extern "C" fn(*mut c_void))>>,
+    fallback: Option<Box<Fallback>>,
+    limiter_creator: Option<Box<LimiterCreator>>,
 }
 
 impl ImportObject {
@@ -45,9 +52,38 @@ impl ImportObject {
     pub fn new() -> Self {
         Self {
             map: HashMap::new(),
+            state_creator: None,
+            fallback: None,
+            limiter_creator: None,
+        }
+    }
+
+    /// Create a new `ImportObject` that creates fresh host state
+    /// for every instance it is used to instantiate.
+    ///
+    /// The `state_creator` returns a pointer to the state, which is stored
+    /// in [`Ctx::data`], and a finalizer that is called with that pointer
+    /// when the instance is dropped.
+    ///
+    /// [`Ctx::data`]: vm/struct.Ctx.html#structfield.data
+    pub fn new_with_data<F>(state_creator: F) -> Self
+    where
+        F: Fn() -> (*mut c_void, extern "C" fn(*mut c_void)) + 'static,
+    {
+        Self {
+            map: HashMap::new(),
+            state_creator: Some(Box::new(state_creator)),
+            fallback: None,
+            limiter_creator: None,
         }
     }
 
+    pub(crate) fn call_state_creator(&self) -> Option<(*mut c_void, extern "C" fn(*mut c_void))> {
+        self.state_creator
+            .as_ref()
+            .map(|state_creator| state_creator())
+    }
+
     /// Register anything that implements `LikeNamespace` as a namespace.
     ///
     /// # Usage:
@@ -79,8 +115,167 @@ impl ImportObject {
     pub fn get_namespace(&self, namespace: &str) -> Option<&(dyn LikeNamespace + 'static)> {
         self.map.get(namespace).map(|namespace| &**namespace)
     }
+
+    /// Set a resolver that is asked for every import that
+    /// the registered namespaces don't contain.
+    ///
+    /// It's given the namespace and name of the import, along with
+    /// what the module expects it to be.
+    ///
+    /// # Usage:
+    /// ```
+    /// # use wasmer_runtime_core::{DynamicFunc, IsExport};
+    /// # use wasmer_runtime_core::{import::ImportObject, module::ExternDescriptor};
+    /// let mut import_object = ImportObject::new();
+    ///
+    /// // Functions that aren't provided trap when they're called.
+    /// import_object.set_fallback(|namespace, name, ty| match ty {
+    ///     ExternDescriptor::Function(signature) => {
+    ///         let msg = format!("`{}::{}` isn't available", namespace, name);
+    ///         let stub = DynamicFunc::new(signature.clone(), move |_ctx, _args| Err(msg.clone()));
+    ///         Some(stub.to_export())
+    ///     }
+    ///     _ => None,
+    /// });
+    /// ```
+    pub fn set_fallback<F>(&mut self, fallback: F)
+    where
+        F: Fn(&str, &str, &ExternDescriptor) -> Option<Export> + 'static,
+    {
+        self.fallback = Some(Box::new(fallback));
+    }
+
+    /// Limit the memory and table elements of every instance that
+    /// this import object is used to instantiate.
+    ///
+    /// `limiter_creator` is called once per instance, so the limiter
+    /// can keep track of what that one instance uses.
+    ///
+    /// # Usage:
+    /// ```
+    /// # use wasmer_runtime_core::import::ImportObject;
+    /// # use wasmer_runtime_core::limits::InstanceLimits;
+    /// # use wasmer_runtime_core::units::Pages;
+    /// let mut import_object = ImportObject::new();
+    ///
+    /// // Up to 16 pages of memory and 1000 table elements per instance.
+    /// import_object.set_limiter(|| InstanceLimits::new(Pages(16), 1000));
+    /// ```
+    pub fn set_limiter<F, L>(&mut self, limiter_creator: F)
+    where
+        F: Fn() -> L + 'static,
+        L: ResourceLimiter + 'static,
+    {
+        self.limiter_creator = Some(Box::new(move || Box::new(limiter_creator())));
+    }
+
+    pub(crate) fn create_limiter(&self) -> Option<Box<dyn ResourceLimiter>> {
+        self.limiter_creator
+            .as_ref()
+            .map(|limiter_creator| limiter_creator())
+    }
+
+    /// Look up an import in the registered namespaces,
+    /// and ask the fallback for it if it isn't there.
+    pub fn resolve(&self, namespace: &str, name: &str, ty: &ExternDescriptor) -> Option<Export> {
+        self.get_namespace(namespace)
+            .and_then(|ns| ns.get_export(name))
+            .or_else(|| {
+                self.fallback
+                    .as_ref()
+                    .and_then(|fallback| fallback(namespace, name, ty))
+            })
+    }
+
+    /// Layer `front` over this import object.
+    ///
+    /// Imports are looked up in `front` first, namespace by namespace,
+    /// and then in this import object, which is useful to add or override
+    /// some imports of a base environment.
+    ///
+    /// The fallbacks are chained in the same order. The state creator
+    /// and limiter of `front` are used if it has them.
+    ///
+    /// # Usage:
+    /// ```
+    /// # use wasmer_runtime_core::{imports, global};
+    /// let base = imports! {
+    ///     "env" => {
+    ///         "limit" => global!(i32 = 10),
+    ///         "offset" => global!(i32 = 0),
+    ///     },
+    /// };
+    ///
+    /// let tenant = imports! {
+    ///     "env" => {
+    ///         "limit" => global!(i32 = 100),
+    ///     },
+    /// };
+    ///
+    /// // `env::limit` comes from `tenant`, and `env::offset` from `base`.
+    /// let import_object = base.chain_front(tenant);
+    /// ```
+    pub fn chain_front(self, front: ImportObject) -> ImportObject {
+        front.chain_back(self)
+    }
+
+    /// Layer this import object over `back`.
+    ///
+    /// This is the same as `back.chain_front(self)`.
+    pub fn chain_back(self, back: ImportObject) -> ImportObject {
+        let ImportObject {
+            mut map,
+            state_creator,
+            fallback,
+            limiter_creator,
+        } = self;
+
+        for (name, back_namespace) in back.map {
+            let namespace = match map.remove(&name) {
+                Some(front_namespace) => Box::new(ChainedNamespace {
+                    front: front_namespace,
+                    back: back_namespace,
+                }),
+                None => back_namespace,
+            };
+            map.insert(name, namespace);
+        }
+
+        let fallback: Option<Box<Fallback>> = match (fallback, back.fallback) {
+            (Some(front), Some(back)) => Some(Box::new(move |namespace, name, ty| {
+                front(namespace, name, ty).or_else(|| back(namespace, name, ty))
+            })),
+            (front, back) => front.or(back),
+        };
+
+        ImportObject {
+            map,
+            state_creator: state_creator.or(back.state_creator),
+            fallback,
+            limiter_creator: limiter_creator.or(back.limiter_creator),
+        }
+    }
+}
+
+/// Two namespaces with the same name, where
+/// exports are looked up in `front` first.
+struct ChainedNamespace {
+    front: Box<dyn LikeNamespace>,
+    back: Box<dyn LikeNamespace>,
 }
 
+impl LikeNamespace for ChainedNamespace {
+    fn get_export(&self, name: &str) -> Option<Export> {
+        self.front
+            .get_export(name)
+            .or_else(|| self.back.get_export(name))
+    }
+}
+
+/// A namespace of imports.
+///
+/// Anything that implements `IsExport` can be inserted: host functions,
+/// memories, tables and globals, as well as the exports of an instance.
 pub struct Namespace {
     map: HashMap<String, Box<dyn IsExport>>,
 }
@@ -106,3 +301,81 @@ impl LikeNamespace for Namespace {
         self.map.get(name).map(|is_export| is_export.to_export())
     }
 }
+
+#[cfg(test)]
+mod import_tests {
+    use super::ImportObject;
+    use crate::{export::Export, global, imports, module::ExternDescriptor, types::Value};
+
+    fn get_global(import_object: &ImportObject, namespace: &str, name: &str) -> Option<Value> {
+        let ty = ExternDescriptor::Global(global!(i32 = 0).descriptor());
+        match import_object.resolve(namespace, name, &ty) {
+            Some(Export::Global(global)) => Some(global.get()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_chain() {
+        let base = imports! {
+            "env" => {
+                "a" => global!(i32 = 1),
+                "b" => global!(i32 = 2),
+            },
+            "base" => {
+                "c" => global!(i32 = 3),
+            },
+        };
+        let front = imports! {
+            "env" => {
+                "a" => global!(i32 = 10),
+            },
+            "front" => {
+                "d" => global!(i32 = 4),
+            },
+        };
+
+        let import_object = base.chain_front(front);
+
+        assert_eq!(get_global(&import_object, "env", "a"), Some(Value::I32(10)));
+        assert_eq!(get_global(&import_object, "env", "b"), Some(Value::I32(2)));
+        assert_eq!(get_global(&import_object, "base", "c"), Some(Value::I32(3)));
+        assert_eq!(
+            get_global(&import_object, "front", "d"),
+            Some(Value::I32(4))
+        );
+        assert_eq!(get_global(&import_object, "env", "e"), None);
+    }
+
+    #[test]
+    fn test_fallback() {
+        let mut base = ImportObject::new();
+        base.set_fallback(|_, name, _| match name {
+            "base" => Some(Export::Global(global!(i32 = 1))),
+            "both" => Some(Export::Global(global!(i32 = 2))),
+            _ => None,
+        });
+        let mut front = imports! {
+            "env" => {
+                "a" => global!(i32 = 10),
+            },
+        };
+        front.set_fallback(|_, name, _| match name {
+            "both" | "a" => Some(Export::Global(global!(i32 = 20))),
+            _ => None,
+        });
+
+        let import_object = base.chain_front(front);
+
+        assert_eq!(get_global(&import_object, "env", "a"), Some(Value::I32(10)));
+        assert_eq!(
+            get_global(&import_object, "env", "both"),
+            Some(Value::I32(20))
+        );
+        assert_eq!(
+            get_global(&import_object, "other", "base"),
+            Some(Value::I32(1))
+        );
+        assert_eq!(get_global(&import_object, "env", "missing"), None);
+    }
+}
@@ -1,18 +1,28 @@
 use crate::{
     backend::Token,
     backing::{ImportBacking, LocalBacking},
-    error::{CallError, CallResult, ResolveError, ResolveResult, Result},
+    error::{
+        CallError, CallResult, ResolveError, ResolveResult, Result, RuntimeError, SnapshotError,
+    },
     export::{Context, Export, ExportIter, FuncPointer},
     global::Global,
     import::{ImportObject, LikeNamespace},
     memory::Memory,
     module::{ExportIndex, Module, ModuleInner},
+    snapshot::Snapshot,
     table::Table,
     typed_func::{Func, Safe, WasmTypeList},
     types::{FuncIndex, FuncSig, GlobalIndex, LocalOrImport, MemoryIndex, TableIndex, Value},
     vm,
 };
-use std::{mem, sync::Arc};
+use std::{
+    mem,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 pub(crate) struct InstanceInner {
     #[allow(dead_code)]
@@ -24,7 +34,12 @@ pub(crate) struct InstanceInner {
 impl Drop for InstanceInner {
     fn drop(&mut self) {
         // Drop the vmctx.
-        unsafe { Box::from_raw(self.vmctx) };
+        let vmctx = unsafe { Box::from_raw(self.vmctx) };
+
+        // Let the host state, if any, clean up after itself.
+        if let Some(data_finalizer) = vmctx.data_finalizer {
+            data_finalizer(vmctx.data);
+        }
     }
 }
 
@@ -38,17 +53,37 @@ impl Drop for InstanceInner {
 pub struct Instance {
     module: Arc<ModuleInner>,
     inner: Box<InstanceInner>,
+    /// The instances this one imports functions from. Those calls use
+    /// their `Ctx`, so they're kept alive for as long as this one is.
+    dependencies: Vec<Rc<Instance>>,
 }
 
 impl Instance {
-    pub(crate) fn new(module: Arc<ModuleInner>, imports: &ImportObject) -> Result<Instance> {
+    /// Instantiates `module`, restoring its state from `snapshot`
+    /// instead of running the initializers and the start function
+    /// if there is one.
+    pub(crate) fn new(
+        module: Arc<ModuleInner>,
+        imports: &ImportObject,
+        snapshot: Option<&Snapshot>,
+    ) -> Result<Instance> {
+        if let Some(snapshot) = snapshot {
+            snapshot.check(&module)?;
+        }
+
         // We need the backing and import_backing to create a vm::Ctx, but we need
         // a vm::Ctx to create a backing and an import_backing. The solution is to create an
         // uninitialized vm::Ctx and then initialize it in-place.
         let mut vmctx = unsafe { Box::new(mem::uninitialized()) };
 
         let import_backing = ImportBacking::new(&module, &imports, &mut *vmctx)?;
-        let backing = LocalBacking::new(&module, &import_backing, &mut *vmctx);
+        let (backing, segment_trap) = LocalBacking::new(
+            &module,
+            &import_backing,
+            imports.create_limiter(),
+            snapshot,
+            &mut *vmctx,
+        )?;
 
         // When Pin is stablized, this will use `Box::pinned` instead of `Box::new`.
         let mut inner = Box::new(InstanceInner {
@@ -60,13 +95,48 @@ impl Instance {
         // Initialize the vm::Ctx in-place after the backing
         // has been boxed.
         unsafe {
-            *inner.vmctx = vm::Ctx::new(&mut inner.backing, &mut inner.import_backing, &module)
+            *inner.vmctx = match imports.call_state_creator() {
+                Some((data, data_finalizer)) => vm::Ctx::new_with_data(
+                    &mut inner.backing,
+                    &mut inner.import_backing,
+                    &module,
+                    data,
+                    data_finalizer,
+                ),
+                None => vm::Ctx::new(&mut inner.backing, &mut inner.import_backing, &module),
+            }
         };
 
-        let instance = Instance { module, inner };
+        let instance = Instance {
+            module,
+            inner,
+            dependencies: Vec::new(),
+        };
 
-        if let Some(start_index) = instance.module.info.start_func {
-            instance.call_with_index(start_index, &[])?;
+        if let Some(kind) = segment_trap {
+            // The functions written to imported tables before the trap call
+            // into this instance, so it's kept alive for them.
+            let info = &instance.module.info;
+            let writes_imported_tables = info.elem_initializers.iter().any(|init| {
+                match init.table_index.local_or_import(info) {
+                    LocalOrImport::Import(_) => true,
+                    LocalOrImport::Local(_) => false,
+                }
+            });
+            if writes_imported_tables {
+                mem::forget(instance);
+            }
+            return Err(RuntimeError::Trap {
+                kind,
+                backtrace: vec![],
+            }
+            .into());
+        }
+
+        if snapshot.is_none() {
+            if let Some(start_index) = instance.module.info.start_func {
+                instance.call_with_index(start_index, &[])?;
+            }
         }
 
         Ok(instance)
@@ -206,8 +276,8 @@ impl Instance {
     /// [`Value`]: enum.Value.html
     ///
     /// # Note:
-    /// This returns `CallResult<Vec<Value>>` in order to support
-    /// the future multi-value returns webassembly feature.
+    /// This returns `CallResult<Vec<Value>>` because webassembly
+    /// functions can return more than one value.
     ///
     /// # Usage:
     /// ```
@@ -259,6 +329,45 @@ impl Instance {
         unsafe { &mut *self.inner.vmctx }
     }
 
+    /// The fuel this instance has left to run on.
+    ///
+    /// Fuel is only consumed by modules compiled with metering
+    /// enabled. See [`CompilerConfig`].
+    ///
+    /// [`CompilerConfig`]: backend/struct.CompilerConfig.html
+    pub fn fuel(&self) -> u64 {
+        unsafe { (*self.inner.vmctx).fuel }
+    }
+
+    /// Set the fuel this instance has left to run on.
+    ///
+    /// A call that runs out of fuel traps with
+    /// [`TrapKind::OutOfFuel`]. The instance can still
+    /// be used after refilling it.
+    ///
+    /// [`TrapKind::OutOfFuel`]: error/enum.TrapKind.html#variant.OutOfFuel
+    pub fn set_fuel(&mut self, fuel: u64) {
+        unsafe { (*self.inner.vmctx).fuel = fuel }
+    }
+
+    /// Keep `instance` alive for as long as this instance is,
+    /// because this instance imports from it.
+    pub(crate) fn depend_on(&mut self, instance: Rc<Instance>) {
+        self.dependencies.push(instance);
+    }
+
+    /// Returns a handle that can stop this instance's
+    /// running code from another thread.
+    ///
+    /// See [`InterruptHandle`].
+    ///
+    /// [`InterruptHandle`]: struct.InterruptHandle.html
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle {
+            interrupted: Arc::clone(&self.inner.backing.interrupted),
+        }
+    }
+
     /// Returns an iterator over all of the items
     /// exported from this instance.
     pub fn exports(&mut self) -> ExportIter {
@@ -269,6 +378,81 @@ impl Instance {
     pub fn module(&self) -> Module {
         Module::new(Arc::clone(&self.module))
     }
+
+    /// Takes a [`Snapshot`] of the memories, mutable globals and
+    /// tables that this instance defines, which the module can be
+    /// instantiated from later with [`Module::instantiate_from_snapshot`].
+    ///
+    /// This fails if a table or global holds a host reference, or a
+    /// function that isn't one of this instance's or its imports'.
+    ///
+    /// # Usage:
+    ///
+    /// ```
+    /// # use wasmer_runtime_core::{error::Result, Instance, Module};
+    /// # use wasmer_runtime_core::import::ImportObject;
+    /// # fn snapshot(instance: &Instance, module: &Module, imports: &ImportObject) -> Result<()> {
+    /// let bytes = instance.snapshot()?.serialize()?;
+    ///
+    /// // Later, possibly in another process.
+    /// use wasmer_runtime_core::snapshot::Snapshot;
+    ///
+    /// let snapshot = Snapshot::deserialize(&bytes)?;
+    /// let instance = module.instantiate_from_snapshot(imports, &snapshot)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Snapshot`]: snapshot/struct.Snapshot.html
+    /// [`Module::instantiate_from_snapshot`]: struct.Module.html#method.instantiate_from_snapshot
+    pub fn snapshot(&self) -> std::result::Result<Snapshot, SnapshotError> {
+        Snapshot::take(
+            &self.module,
+            &self.inner.import_backing,
+            &self.inner.backing,
+            self.inner.vmctx,
+        )
+    }
+}
+
+/// A handle to stop an [`Instance`] from running, usually
+/// from another thread.
+///
+/// Code compiled with [`CompilerConfig::interruptible`] checks for an
+/// interrupt on function entry and at the top of every loop, trapping
+/// with [`TrapKind::Interrupted`] when it sees one. Trapping clears the
+/// interrupt, so later calls run normally again.
+///
+/// An interrupt requested while no code is running stays pending
+/// and stops the next call into the instance instead.
+///
+/// # Usage:
+///
+/// ```
+/// # use wasmer_runtime_core::Instance;
+/// # fn interrupt(instance: Instance) {
+/// let handle = instance.interrupt_handle();
+///
+/// std::thread::spawn(move || {
+///     std::thread::sleep(std::time::Duration::from_secs(1));
+///     handle.interrupt();
+/// });
+/// # }
+/// ```
+///
+/// [`Instance`]: struct.Instance.html
+/// [`CompilerConfig::interruptible`]: backend/struct.CompilerConfig.html#structfield.interruptible
+/// [`TrapKind::Interrupted`]: error/enum.TrapKind.html#variant.Interrupted
+#[derive(Debug, Clone)]
+pub struct InterruptHandle {
+    interrupted: Arc<AtomicBool>,
+}
+
+impl InterruptHandle {
+    /// Make the code running in the instance trap.
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::SeqCst);
+    }
 }
 
 impl Instance {
@@ -324,7 +508,7 @@ impl InstanceInner {
                     func,
                     ctx: match ctx {
                         Context::Internal => Context::External(self.vmctx),
-                        ctx @ Context::External(_) => ctx,
+                        ctx => ctx,
                     },
                     signature,
                 }
@@ -440,8 +624,8 @@ impl<'a> DynFunc<'a> {
     /// [`Value`]: enum.Value.html
     ///
     /// # Note:
-    /// This returns `CallResult<Vec<Value>>` in order to support
-    /// the future multi-value returns webassembly feature.
+    /// This returns `CallResult<Vec<Value>>` because webassembly
+    /// functions can return more than one value.
     ///
     /// # Usage:
     /// ```
@@ -17,9 +17,13 @@ pub mod export;
 pub mod global;
 pub mod import;
 pub mod instance;
+pub mod limits;
+pub mod linker;
 pub mod memory;
 pub mod module;
 mod sig_registry;
+mod simd;
+pub mod snapshot;
 pub mod structures;
 mod sys;
 pub mod table;
@@ -36,11 +40,11 @@ pub use self::error::Result;
 #[doc(inline)]
 pub use self::import::IsExport;
 #[doc(inline)]
-pub use self::instance::Instance;
+pub use self::instance::{Instance, InterruptHandle};
 #[doc(inline)]
 pub use self::module::Module;
 #[doc(inline)]
-pub use self::typed_func::Func;
+pub use self::typed_func::{DynamicFunc, Func};
 use std::sync::Arc;
 
 use self::cache::{Artifact, Error as CacheError};
@@ -65,10 +69,22 @@ pub mod prelude {
 pub fn compile_with(
     wasm: &[u8],
     compiler: &dyn backend::Compiler,
+) -> CompileResult<module::Module> {
+    compile_with_config(wasm, compiler, backend::CompilerConfig::default())
+}
+
+/// The same as [`compile_with`], but with the option of
+/// enabling compiler features, like metering.
+///
+/// [`compile_with`]: fn.compile_with.html
+pub fn compile_with_config(
+    wasm: &[u8],
+    compiler: &dyn backend::Compiler,
+    compiler_config: backend::CompilerConfig,
 ) -> CompileResult<module::Module> {
     let token = backend::Token::generate();
     compiler
-        .compile(wasm, token)
+        .compile(wasm, compiler_config, token)
         .map(|inner| module::Module::new(Arc::new(inner)))
 }
 
@@ -76,16 +92,176 @@ pub fn compile_with(
 /// WebAssembly specification. Returns `true` if validation
 /// succeeded, `false` if validation failed.
 pub fn validate(wasm: &[u8]) -> bool {
-    use wasmparser::WasmDecoder;
-    let mut parser = wasmparser::ValidatingParser::new(wasm, None);
-    loop {
-        let state = parser.read();
-        match *state {
-            wasmparser::ParserState::EndWasm => break true,
-            wasmparser::ParserState::Error(_) => break false,
-            _ => {}
+    validate_and_report_errors(wasm).is_ok()
+}
+
+/// The same as [`validate`], but returns the reason
+/// validation failed as an error.
+///
+/// Besides the MVP, this accepts the threads proposal.
+///
+/// [`validate`]: fn.validate.html
+pub fn validate_and_report_errors(wasm: &[u8]) -> std::result::Result<(), String> {
+    validate_and_report_errors_with_features(wasm, backend::Features::default())
+}
+
+/// The same as [`validate_and_report_errors`], but also
+/// accepts the proposals enabled in `features`.
+///
+/// [`validate_and_report_errors`]: fn.validate_and_report_errors.html
+pub fn validate_and_report_errors_with_features(
+    wasm: &[u8],
+    features: backend::Features,
+) -> std::result::Result<(), String> {
+    if features.simd {
+        return validate_simd(wasm, features);
+    }
+
+    let config = wasmparser::ValidatingParserConfig {
+        operator_config: wasmparser::OperatorValidatorConfig {
+            enable_threads: true,
+            enable_reference_types: features.reference_types,
+            enable_simd: features.simd,
+            enable_bulk_memory: features.bulk_memory,
+            enable_multi_value: features.multi_value,
+        },
+    };
+    wasmparser::validate(wasm, Some(config)).map_err(|err| err.message().to_string())?;
+    validate_operator_features(wasm, features)
+}
+
+/// `wasmparser` always accepts the non-trapping float-to-int
+/// conversions and the sign-extension operators, so they are
+/// rejected here unless they're enabled in `features`.
+///
+/// Blocks with a function type, which only the multi-value proposal
+/// allows, are rejected too, since the backend can't compile them.
+fn validate_operator_features(
+    wasm: &[u8],
+    features: backend::Features,
+) -> std::result::Result<(), String> {
+    use wasmparser::{ModuleReader, Operator, SectionCode, TypeOrFuncType};
+
+    if features.sat_float_to_int && features.sign_extension && !features.multi_value {
+        return Ok(());
+    }
+
+    let to_string = |err: wasmparser::BinaryReaderError| err.message().to_string();
+    let mut reader = ModuleReader::new(wasm).map_err(to_string)?;
+    while !reader.eof() {
+        let section = reader.read().map_err(to_string)?;
+        if section.code != SectionCode::Code {
+            continue;
+        }
+        for body in section.get_code_section_reader().map_err(to_string)? {
+            let mut operators = body
+                .and_then(|body| body.get_operators_reader())
+                .map_err(to_string)?;
+            while !operators.eof() {
+                match operators.read().map_err(to_string)? {
+                    Operator::I32TruncSatF32S
+                    | Operator::I32TruncSatF32U
+                    | Operator::I32TruncSatF64S
+                    | Operator::I32TruncSatF64U
+                    | Operator::I64TruncSatF32S
+                    | Operator::I64TruncSatF32U
+                    | Operator::I64TruncSatF64S
+                    | Operator::I64TruncSatF64U
+                        if !features.sat_float_to_int =>
+                    {
+                        return Err(
+                            "non-trapping float-to-int conversions support is not enabled"
+                                .to_string(),
+                        );
+                    }
+                    Operator::I32Extend8S
+                    | Operator::I32Extend16S
+                    | Operator::I64Extend8S
+                    | Operator::I64Extend16S
+                    | Operator::I64Extend32S
+                        if !features.sign_extension =>
+                    {
+                        return Err("sign extension operations support is not enabled".to_string());
+                    }
+                    Operator::Block {
+                        ty: TypeOrFuncType::FuncType(_),
+                    }
+                    | Operator::Loop {
+                        ty: TypeOrFuncType::FuncType(_),
+                    }
+                    | Operator::If {
+                        ty: TypeOrFuncType::FuncType(_),
+                    } => {
+                        return Err(
+                            "blocks with parameters or more than one result are not supported"
+                                .to_string(),
+                        );
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Validates a module with the SIMD proposal enabled.
+///
+/// `wasmparser` only knows the opcodes the proposal had before it was
+/// standardized, so a newer version is used instead. That one only knows
+/// the final encoding of the reference types proposal, which the backend
+/// doesn't, so the two can't be enabled together.
+fn validate_simd(wasm: &[u8], features: backend::Features) -> std::result::Result<(), String> {
+    use simd_wasmparser::{BlockType, Operator, Parser, Payload, Validator, WasmFeatures};
+
+    if features.reference_types {
+        return Err("the SIMD and reference types proposals can't be enabled together".to_string());
+    }
+
+    let mut wasm_features = WasmFeatures::WASM1 | WasmFeatures::SIMD | WasmFeatures::THREADS;
+    wasm_features.set(WasmFeatures::BULK_MEMORY, features.bulk_memory);
+    wasm_features.set(WasmFeatures::MULTI_VALUE, features.multi_value);
+    wasm_features.set(
+        WasmFeatures::SATURATING_FLOAT_TO_INT,
+        features.sat_float_to_int,
+    );
+    wasm_features.set(WasmFeatures::SIGN_EXTENSION, features.sign_extension);
+
+    let to_string = |err: simd_wasmparser::BinaryReaderError| err.message().to_string();
+    Validator::new_with_features(wasm_features)
+        .validate_all(wasm)
+        .map_err(to_string)?;
+
+    if !features.multi_value {
+        return Ok(());
+    }
+
+    // As in `validate_operator_features`.
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::CodeSectionEntry(body) = payload.map_err(to_string)? {
+            let mut operators = body.get_operators_reader().map_err(to_string)?;
+            while !operators.eof() {
+                match operators.read().map_err(to_string)? {
+                    Operator::Block {
+                        blockty: BlockType::FuncType(_),
+                    }
+                    | Operator::Loop {
+                        blockty: BlockType::FuncType(_),
+                    }
+                    | Operator::If {
+                        blockty: BlockType::FuncType(_),
+                    } => {
+                        return Err(
+                            "blocks with parameters or more than one result are not supported"
+                                .to_string(),
+                        );
+                    }
+                    _ => {}
+                }
+            }
         }
     }
+    Ok(())
 }
 
 pub unsafe fn load_cache_with(
@@ -0,0 +1,126 @@
+//! Limits on the memory and table elements an instance can use,
+//! which are set with [`ImportObject::set_limiter`].
+//!
+//! [`ImportObject::set_limiter`]: ../import/struct.ImportObject.html#method.set_limiter
+
+use crate::{
+    types::{MemoryIndex, TableIndex},
+    units::Pages,
+};
+use hashbrown::HashMap;
+use std::fmt;
+
+/// Decides whether the memories and tables of an
+/// instance are allowed to grow.
+///
+/// Every instance gets its own limiter, which is asked before the
+/// memories and tables that the instance defines are created, as if
+/// they grew from nothing to their minimum size, and whenever the
+/// instance runs `memory.grow` or `table.grow`, on the memories and
+/// tables it defines or imports.
+///
+/// Growing from the host, with [`Memory::grow`] or
+/// [`Table::grow`], doesn't ask the limiter.
+///
+/// [`Memory::grow`]: ../memory/struct.Memory.html#method.grow
+/// [`Table::grow`]: ../table/struct.Table.html#method.grow
+pub trait ResourceLimiter {
+    /// Whether `memory` may grow from `current` to `desired` pages.
+    ///
+    /// The memory still can't grow past its own maximum when this allows it.
+    fn memory_growing(&mut self, memory: MemoryIndex, current: Pages, desired: Pages) -> bool;
+
+    /// Whether `table` may grow from `current` to `desired` elements.
+    ///
+    /// The table still can't grow past its own maximum when this allows it.
+    fn table_growing(&mut self, table: TableIndex, current: u32, desired: u32) -> bool;
+}
+
+impl fmt::Debug for dyn ResourceLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ResourceLimiter")
+    }
+}
+
+/// A [`ResourceLimiter`] that caps the pages of all of the
+/// memories of an instance, and the elements of all of its tables.
+///
+/// Each memory and table is counted at the size it was last allowed
+/// to grow to. That's off when it then fails to grow anyway, or when
+/// the host grows it, until the instance grows it again.
+///
+/// [`ResourceLimiter`]: trait.ResourceLimiter.html
+#[derive(Debug, Clone)]
+pub struct InstanceLimits {
+    max_memory_pages: Pages,
+    max_table_elements: u32,
+    memory_pages: HashMap<MemoryIndex, Pages>,
+    table_elements: HashMap<TableIndex, u32>,
+}
+
+impl InstanceLimits {
+    /// Create limits that allow `max_memory_pages` pages
+    /// and `max_table_elements` table elements in total.
+    pub fn new(max_memory_pages: Pages, max_table_elements: u32) -> Self {
+        Self {
+            max_memory_pages,
+            max_table_elements,
+            memory_pages: HashMap::new(),
+            table_elements: HashMap::new(),
+        }
+    }
+}
+
+impl ResourceLimiter for InstanceLimits {
+    fn memory_growing(&mut self, memory: MemoryIndex, _current: Pages, desired: Pages) -> bool {
+        let others: u64 = self
+            .memory_pages
+            .iter()
+            .filter(|&(&index, _)| index != memory)
+            .map(|(_, pages)| u64::from(pages.0))
+            .sum();
+        if others + u64::from(desired.0) > u64::from(self.max_memory_pages.0) {
+            return false;
+        }
+        self.memory_pages.insert(memory, desired);
+        true
+    }
+
+    fn table_growing(&mut self, table: TableIndex, _current: u32, desired: u32) -> bool {
+        let others: u64 = self
+            .table_elements
+            .iter()
+            .filter(|&(&index, _)| index != table)
+            .map(|(_, &elements)| u64::from(elements))
+            .sum();
+        if others + u64::from(desired) > u64::from(self.max_table_elements) {
+            return false;
+        }
+        self.table_elements.insert(table, desired);
+        true
+    }
+}
+
+#[cfg(test)]
+mod limits_tests {
+    use super::{InstanceLimits, ResourceLimiter};
+    use crate::{
+        structures::TypedIndex,
+        types::{MemoryIndex, TableIndex},
+        units::Pages,
+    };
+
+    #[test]
+    fn test_instance_limits() {
+        let mut limits = InstanceLimits::new(Pages(10), 100);
+
+        assert!(limits.memory_growing(MemoryIndex::new(0), Pages(0), Pages(4)));
+        assert!(limits.memory_growing(MemoryIndex::new(0), Pages(4), Pages(10)));
+        assert!(!limits.memory_growing(MemoryIndex::new(0), Pages(10), Pages(11)));
+        assert!(!limits.memory_growing(MemoryIndex::new(1), Pages(0), Pages(1)));
+
+        assert!(limits.table_growing(TableIndex::new(0), 0, 60));
+        assert!(!limits.table_growing(TableIndex::new(1), 0, 41));
+        assert!(limits.table_growing(TableIndex::new(1), 0, 40));
+    }
+}
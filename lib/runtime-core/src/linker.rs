@@ -0,0 +1,111 @@
+//! The [`Linker`] links modules to each other by registering
+//! instances under a module name.
+//!
+//! [`Linker`]: struct.Linker.html
+
+use crate::{
+    error::Result,
+    export::Export,
+    import::{ImportObject, LikeNamespace},
+    instance::Instance,
+    module::Module,
+};
+use hashbrown::HashMap;
+use std::rc::Rc;
+
+/// Links modules to each other.
+///
+/// An instance registered under a name provides the imports from that
+/// namespace of the modules instantiated afterwards. Its memories, tables
+/// and globals are shared with them, not copied.
+///
+/// An instance created by a `Linker` keeps the registered instances it
+/// imports from alive, so it can outlive the `Linker` and keeps working
+/// when another instance is registered under the same name.
+///
+/// # Usage:
+/// ```
+/// # use wasmer_runtime_core::error::Result;
+/// # use wasmer_runtime_core::{linker::Linker, Module};
+/// # fn link(lib: &Module, app: &Module) -> Result<()> {
+/// let mut linker = Linker::new();
+///
+/// let lib_instance = linker.instantiate(lib)?;
+/// linker.register("lib", lib_instance);
+///
+/// // The imports of `app` from "lib" resolve to the exports of `lib_instance`.
+/// let app_instance = linker.instantiate(app)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Linker {
+    imports: ImportObject,
+    instances: HashMap<String, Rc<Instance>>,
+}
+
+impl Linker {
+    /// Create a new, empty `Linker`.
+    pub fn new() -> Self {
+        Self::with_imports(ImportObject::new())
+    }
+
+    /// Create a `Linker` that resolves the imports that
+    /// no registered instance provides against `imports`.
+    pub fn with_imports(imports: ImportObject) -> Self {
+        Self {
+            imports,
+            instances: HashMap::new(),
+        }
+    }
+
+    /// Register an instance under a module name, replacing the
+    /// instance or namespace that was registered under it before.
+    pub fn register<S>(&mut self, name: S, instance: Instance)
+    where
+        S: Into<String>,
+    {
+        let name = name.into();
+        let instance = Rc::new(instance);
+
+        self.imports
+            .register(name.clone(), RegisteredInstance(Rc::clone(&instance)));
+        self.instances.insert(name, instance);
+    }
+
+    /// Get the instance registered under a module name.
+    pub fn instance(&self, name: &str) -> Option<&Instance> {
+        self.instances.get(name).map(|instance| &**instance)
+    }
+
+    /// Instantiate a module, resolving its imports against
+    /// the registered instances.
+    ///
+    /// Every import that can't be resolved is reported at once,
+    /// along with the type the module expects it to have.
+    pub fn instantiate(&self, module: &Module) -> Result<Instance> {
+        let mut instance = module.instantiate(&self.imports)?;
+
+        let mut namespaces: Vec<_> = module
+            .imports()
+            .into_iter()
+            .map(|import| import.namespace)
+            .collect();
+        namespaces.sort();
+        namespaces.dedup();
+        for namespace in namespaces {
+            if let Some(dependency) = self.instances.get(&namespace) {
+                instance.depend_on(Rc::clone(dependency));
+            }
+        }
+
+        Ok(instance)
+    }
+}
+
+struct RegisteredInstance(Rc<Instance>);
+
+impl LikeNamespace for RegisteredInstance {
+    fn get_export(&self, name: &str) -> Option<Export> {
+        self.0.get_export(name)
+    }
+}
@@ -14,7 +14,7 @@ macro_rules! debug {
 
 #[macro_export]
 macro_rules! func {
-    ($func:path) => {{
+    ($func:expr) => {{
         $crate::Func::new($func)
     }};
 }
@@ -23,16 +23,34 @@ macro_rules! func {
 ///
 /// [`ImportObject`]: struct.ImportObject.html
 ///
-/// # Note:
-/// The `import` macro currently only supports
-/// importing functions.
+/// # Usage:
+/// ```
+/// # use wasmer_runtime_core::{imports, func};
+/// # use wasmer_runtime_core::vm::Ctx;
+/// let import_object = imports! {
+///     "env" => {
+///         "foo" => func!(foo),
+///     },
+/// };
+///
+/// fn foo(_: &mut Ctx, n: i32) -> i32 {
+///     n
+/// }
+/// ```
 ///
+/// A state creator can be passed first to give every instance
+/// its own host data (see [`ImportObject::new_with_data`]):
 ///
-/// # Usage:
 /// ```
 /// # use wasmer_runtime_core::{imports, func};
 /// # use wasmer_runtime_core::vm::Ctx;
+/// # use std::ffi::c_void;
+/// extern "C" fn finalize(data: *mut c_void) {
+///     drop(unsafe { Box::from_raw(data as *mut u32) });
+/// }
+///
 /// let import_object = imports! {
+///     || (Box::into_raw(Box::new(0u32)) as *mut c_void, finalize as extern "C" fn(*mut c_void)),
 ///     "env" => {
 ///         "foo" => func!(foo),
 ///     },
@@ -42,6 +60,29 @@ macro_rules! func {
 ///     n
 /// }
 /// ```
+///
+/// Memories, tables and globals can be imported as well, and
+/// the [`memory!`], [`table!`] and [`global!`] macros create them inline:
+///
+/// ```
+/// # use wasmer_runtime_core::{imports, memory, table, global};
+/// let shared_memory = memory!(shared 1, 16);
+///
+/// let import_object = imports! {
+///     "env" => {
+///         "memory" => shared_memory.clone(),
+///         "scratch" => memory!(1),
+///         "table" => table!(anyfunc, 10, 20),
+///         "answer" => global!(i32 = 42),
+///         "counter" => global!(mut i64 = 0),
+///     },
+/// };
+/// ```
+///
+/// [`ImportObject::new_with_data`]: import/struct.ImportObject.html#method.new_with_data
+/// [`memory!`]: macro.memory.html
+/// [`table!`]: macro.table.html
+/// [`global!`]: macro.global.html
 #[macro_export]
 macro_rules! imports {
     ( $( $ns_name:expr => $ns:tt, )* ) => {{
@@ -57,6 +98,21 @@ macro_rules! imports {
             import_object.register($ns_name, ns);
         })*
 
+        import_object
+    }};
+    ($state_gen:expr, $( $ns_name:expr => $ns:tt, )* ) => {{
+        use $crate::{
+            import::{ImportObject, Namespace},
+        };
+
+        let mut import_object = ImportObject::new_with_data($state_gen);
+
+        $({
+            let ns = $crate::__imports_internal!($ns);
+
+            import_object.register($ns_name, ns);
+        })*
+
         import_object
     }};
 }
@@ -75,3 +131,115 @@ macro_rules! __imports_internal {
         $ns
     };
 }
+
+/// Create a [`Memory`] with the given minimum and maximum number of pages.
+///
+/// A shared memory has to have a maximum, so leaving it out is a compile error.
+///
+/// # Panics:
+/// Panics if the memory can't be created, e.g. if the minimum is larger than the maximum.
+///
+/// # Usage:
+/// ```
+/// # use wasmer_runtime_core::memory;
+/// let unbounded = memory!(1);
+/// let bounded = memory!(1, 16);
+/// let shared = memory!(shared 1, 16);
+/// ```
+///
+/// ```compile_fail
+/// # use wasmer_runtime_core::memory;
+/// let shared = memory!(shared 1);
+/// ```
+///
+/// [`Memory`]: memory/struct.Memory.html
+#[macro_export]
+macro_rules! memory {
+    (shared $minimum:expr, $maximum:expr) => {
+        $crate::__memory_internal!($minimum, Some($crate::units::Pages($maximum)), true)
+    };
+    ($minimum:expr, $maximum:expr) => {
+        $crate::__memory_internal!($minimum, Some($crate::units::Pages($maximum)), false)
+    };
+    ($minimum:expr) => {
+        $crate::__memory_internal!($minimum, None, false)
+    };
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __memory_internal {
+    ($minimum:expr, $maximum:expr, $shared:expr) => {
+        $crate::memory::Memory::new($crate::types::MemoryDescriptor {
+            minimum: $crate::units::Pages($minimum),
+            maximum: $maximum,
+            shared: $shared,
+        })
+        .expect("unable to create memory")
+    };
+}
+
+/// Create a [`Table`] with the given element type, and minimum
+/// and maximum number of elements.
+///
+/// # Panics:
+/// Panics if the table can't be created, e.g. if the minimum is larger than the maximum.
+///
+/// # Usage:
+/// ```
+/// # use wasmer_runtime_core::table;
+/// let unbounded = table!(anyfunc, 10);
+/// let bounded = table!(anyfunc, 10, 20);
+/// ```
+///
+/// [`Table`]: table/struct.Table.html
+#[macro_export]
+macro_rules! table {
+    (anyfunc, $minimum:expr, $maximum:expr) => {
+        $crate::__table_internal!($crate::types::ElementType::Anyfunc, $minimum, Some($maximum))
+    };
+    (anyfunc, $minimum:expr) => {
+        $crate::__table_internal!($crate::types::ElementType::Anyfunc, $minimum, None)
+    };
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __table_internal {
+    ($element:expr, $minimum:expr, $maximum:expr) => {
+        $crate::table::Table::new($crate::types::TableDescriptor {
+            element: $element,
+            minimum: $minimum,
+            maximum: $maximum,
+        })
+        .expect("unable to create table")
+    };
+}
+
+/// Create a [`Global`] of the given type. The value is checked
+/// against the type at compile time.
+///
+/// # Usage:
+/// ```
+/// # use wasmer_runtime_core::global;
+/// let constant = global!(i32 = 42);
+/// let mutable = global!(mut f64 = 0.5);
+/// ```
+///
+/// ```compile_fail
+/// # use wasmer_runtime_core::global;
+/// let constant = global!(i32 = 0.5);
+/// ```
+///
+/// [`Global`]: global/struct.Global.html
+#[macro_export]
+macro_rules! global {
+    (mut $ty:ty = $value:expr) => {{
+        let value: $ty = $value;
+        $crate::global::Global::new_mutable($crate::types::Value::from(value))
+    }};
+    ($ty:ty = $value:expr) => {{
+        let value: $ty = $value;
+        $crate::global::Global::new($crate::types::Value::from(value))
+    }};
+}
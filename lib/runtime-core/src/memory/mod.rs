@@ -9,20 +9,25 @@ use crate::{
     vm,
 };
 use std::{
-    cell::{Cell, RefCell},
-    fmt, mem, ptr,
+    cell::{Cell, RefCell, UnsafeCell},
+    fmt, mem,
     rc::Rc,
+    sync::Arc,
 };
 
 pub use self::atomic::Atomic;
 pub use self::dynamic::DynamicMemory;
+pub use self::ptr::{Array, Item, PointerType, WasmPtr};
 pub use self::static_::{SharedStaticMemory, StaticMemory};
 pub use self::view::{Atomically, MemoryView};
+pub(crate) use self::wait::{notify, wait};
 
 mod atomic;
 mod dynamic;
+mod ptr;
 mod static_;
 mod view;
+mod wait;
 
 #[derive(Clone)]
 enum MemoryVariant {
@@ -148,18 +153,21 @@ impl Memory {
 
     /// Convert this memory to a shared memory if the shared flag
     /// is present in the description used to create it.
+    ///
+    /// Unlike `Memory`, a [`SharedMemory`] can be sent to other threads.
+    ///
+    /// [`SharedMemory`]: memory/struct.SharedMemory.html
     pub fn shared(self) -> Option<SharedMemory> {
-        if self.desc.shared {
-            Some(SharedMemory { desc: self.desc })
-        } else {
-            None
+        match self.variant {
+            MemoryVariant::Shared(shared_mem) => Some(shared_mem),
+            MemoryVariant::Unshared(_) => None,
         }
     }
 
     pub(crate) fn vm_local_memory(&self) -> *mut vm::LocalMemory {
         match &self.variant {
             MemoryVariant::Unshared(unshared_mem) => unshared_mem.vm_local_memory(),
-            MemoryVariant::Shared(_) => unimplemented!(),
+            MemoryVariant::Shared(shared_mem) => shared_mem.vm_local_memory(),
         }
     }
 }
@@ -221,9 +229,9 @@ struct UnsharedMemoryInternal {
 impl UnsharedMemory {
     pub fn new(desc: MemoryDescriptor) -> Result<Self, CreationError> {
         let mut local = vm::LocalMemory {
-            base: ptr::null_mut(),
+            base: std::ptr::null_mut(),
             bound: 0,
-            memory: ptr::null_mut(),
+            memory: std::ptr::null_mut(),
         };
 
         let storage = match desc.memory_type() {
@@ -283,27 +291,99 @@ impl Clone for UnsharedMemory {
     }
 }
 
+/// A linear memory that can be used by several instances,
+/// on several threads, at the same time.
+///
+/// Cloning a `SharedMemory` returns a new handle to the same memory.
 pub struct SharedMemory {
     desc: MemoryDescriptor,
+    internal: Arc<SharedMemoryInternal>,
 }
 
+struct SharedMemoryInternal {
+    memory: Box<SharedStaticMemory>,
+    local: UnsafeCell<vm::LocalMemory>,
+}
+
+// `local` is only written by `SharedStaticMemory::grow`, which holds
+// the memory's lock while doing so. Everything else is already thread-safe.
+unsafe impl Send for SharedMemoryInternal {}
+unsafe impl Sync for SharedMemoryInternal {}
+
 impl SharedMemory {
     fn new(desc: MemoryDescriptor) -> Result<Self, CreationError> {
-        Ok(Self { desc })
+        let mut local = vm::LocalMemory {
+            base: std::ptr::null_mut(),
+            bound: 0,
+            memory: std::ptr::null_mut(),
+        };
+
+        let memory = SharedStaticMemory::new(desc, &mut local)?;
+
+        Ok(SharedMemory {
+            desc,
+            internal: Arc::new(SharedMemoryInternal {
+                memory,
+                local: UnsafeCell::new(local),
+            }),
+        })
     }
 
-    pub fn grow(&self, _delta: Pages) -> Result<Pages, GrowError> {
-        unimplemented!()
+    /// Return the [`MemoryDescriptor`] that this memory
+    /// was created with.
+    ///
+    /// [`MemoryDescriptor`]: struct.MemoryDescriptor.html
+    pub fn descriptor(&self) -> MemoryDescriptor {
+        self.desc
+    }
+
+    /// Grow this memory by the specified number of pages.
+    ///
+    /// This can safely race with other threads growing the same memory.
+    pub fn grow(&self, delta: Pages) -> Result<Pages, GrowError> {
+        unsafe { self.internal.memory.grow(delta, self.internal.local.get()) }
     }
 
+    /// The size, in wasm pages, of this memory.
     pub fn size(&self) -> Pages {
-        unimplemented!()
+        self.internal.memory.size()
+    }
+
+    pub(crate) fn vm_local_memory(&self) -> *mut vm::LocalMemory {
+        self.internal.local.get()
     }
 }
 
 impl Clone for SharedMemory {
     fn clone(&self) -> Self {
-        unimplemented!()
+        SharedMemory {
+            desc: self.desc,
+            internal: Arc::clone(&self.internal),
+        }
+    }
+}
+
+impl From<SharedMemory> for Memory {
+    fn from(shared_mem: SharedMemory) -> Self {
+        Memory {
+            desc: shared_mem.desc,
+            variant: MemoryVariant::Shared(shared_mem),
+        }
+    }
+}
+
+impl IsExport for SharedMemory {
+    fn to_export(&self) -> Export {
+        Export::Memory(self.clone().into())
+    }
+}
+
+impl fmt::Debug for SharedMemory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedMemory")
+            .field("desc", &self.desc)
+            .field("size", &self.size())
+            .finish()
     }
 }
 
@@ -323,4 +403,49 @@ mod memory_tests {
         assert_eq!(unshared_memory.size(), Pages(10));
     }
 
+    #[test]
+    fn test_shared_memory_grow() {
+        use std::thread;
+
+        let shared_memory = Memory::new(MemoryDescriptor {
+            minimum: Pages(1),
+            maximum: Some(Pages(17)),
+            shared: true,
+        })
+        .unwrap()
+        .shared()
+        .unwrap();
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let shared_memory = shared_memory.clone();
+                thread::spawn(move || {
+                    for _ in 0..4 {
+                        shared_memory.grow(Pages(1)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(shared_memory.size(), Pages(17));
+        assert!(shared_memory.grow(Pages(1)).is_err());
+
+        let memory: Memory = shared_memory.into();
+        let view = memory.view::<u8>();
+        view[Pages(17).bytes().0 - 1].set(42);
+        assert_eq!(view[Pages(17).bytes().0 - 1].get(), 42);
+    }
+
+    #[test]
+    fn test_shared_memory_requires_maximum() {
+        assert!(Memory::new(MemoryDescriptor {
+            minimum: Pages(1),
+            maximum: None,
+            shared: true,
+        })
+        .is_err());
+    }
 }
@@ -0,0 +1,220 @@
+//! Pointers into a wasm linear memory that can be used as
+//! host function arguments and dereferenced without `unsafe`.
+
+use crate::{
+    memory::Memory,
+    types::{Type, ValueError, ValueType, WasmExternType},
+};
+use std::{cell::Cell, ffi::CString, fmt, marker::PhantomData, mem, slice};
+
+/// Marks a [`WasmPtr`] that points to a single value.
+///
+/// [`WasmPtr`]: struct.WasmPtr.html
+pub struct Item;
+/// Marks a [`WasmPtr`] that points to the start of several values.
+///
+/// [`WasmPtr`]: struct.WasmPtr.html
+pub struct Array;
+
+pub trait PointerType {}
+impl PointerType for Item {}
+impl PointerType for Array {}
+
+/// A pointer to a `T` inside a wasm linear memory, which
+/// is just an offset from the start of the memory.
+///
+/// A `WasmPtr` has the same representation as an `i32`, so
+/// it can be used in place of one as the argument or
+/// return type of a host function.
+///
+/// # Usage:
+///
+/// ```
+/// # use wasmer_runtime_core::memory::{Array, WasmPtr};
+/// # use wasmer_runtime_core::vm::Ctx;
+/// fn print_str(ctx: &mut Ctx, ptr: WasmPtr<u8, Array>, len: u32) {
+///     match ptr.get_utf8_string(ctx.memory(0), len) {
+///         Some(string) => println!("{}", string),
+///         None => println!("invalid string"),
+///     }
+/// }
+/// ```
+#[repr(transparent)]
+pub struct WasmPtr<T: ValueType, Ty: PointerType = Item> {
+    offset: u32,
+    _phantom: PhantomData<(T, Ty)>,
+}
+
+impl<T: ValueType, Ty: PointerType> WasmPtr<T, Ty> {
+    /// Create a pointer to `offset` bytes from the start of a memory.
+    pub fn new(offset: u32) -> Self {
+        Self {
+            offset,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// The offset, in bytes, from the start of the memory.
+    pub fn offset(self) -> u32 {
+        self.offset
+    }
+}
+
+/// Return the `len` values starting `start` bytes into `memory`, or
+/// `None` if they aren't all in bounds or `start` isn't aligned for `T`.
+fn cells<T: ValueType>(memory: &Memory, start: u64, len: u64) -> Option<&[Cell<T>]> {
+    let end = start.checked_add(len.checked_mul(mem::size_of::<T>() as u64)?)?;
+    if end > memory.size().bytes().0 as u64 || start & (mem::align_of::<T>() as u64 - 1) != 0 {
+        return None;
+    }
+    let base = memory.view::<u8>().as_ptr() as usize;
+    unsafe {
+        Some(slice::from_raw_parts(
+            (base + start as usize) as *const Cell<T>,
+            len as usize,
+        ))
+    }
+}
+
+impl<T: ValueType> WasmPtr<T, Item> {
+    /// Dereference this pointer, returning `None` if the value
+    /// is out of bounds or isn't aligned for `T`.
+    ///
+    /// The returned reference must not be used after
+    /// the memory grows.
+    pub fn deref(self, memory: &Memory) -> Option<&Cell<T>> {
+        cells(memory, u64::from(self.offset), 1).map(|cells| &cells[0])
+    }
+}
+
+impl<T: ValueType> WasmPtr<T, Array> {
+    /// Dereference the `length` values that start `index` values
+    /// past this pointer, returning `None` if any of them are out
+    /// of bounds or the pointer isn't aligned for `T`.
+    ///
+    /// The returned slice must not be used after
+    /// the memory grows.
+    pub fn deref(self, memory: &Memory, index: u32, length: u32) -> Option<&[Cell<T>]> {
+        let start = u64::from(index) * mem::size_of::<T>() as u64 + u64::from(self.offset);
+        cells(memory, start, u64::from(length))
+    }
+}
+
+impl WasmPtr<u8, Array> {
+    /// Copy the `len` bytes at this pointer into a `String`,
+    /// returning `None` if they're out of bounds or aren't valid UTF-8.
+    pub fn get_utf8_string(self, memory: &Memory, len: u32) -> Option<String> {
+        let bytes = self.deref(memory, 0, len)?;
+        String::from_utf8(bytes.iter().map(Cell::get).collect()).ok()
+    }
+
+    /// Copy the nul-terminated string at this pointer into a `CString`,
+    /// returning `None` if the memory ends before the terminator.
+    pub fn get_c_string(self, memory: &Memory) -> Option<CString> {
+        let len = memory.size().bytes().0.checked_sub(self.offset as usize)?;
+        let bytes: Vec<u8> = cells::<u8>(memory, u64::from(self.offset), len as u64)?
+            .iter()
+            .map(Cell::get)
+            .take_while(|&byte| byte != 0)
+            .collect();
+        if bytes.len() == len {
+            return None;
+        }
+        Some(CString::new(bytes).unwrap())
+    }
+}
+
+unsafe impl<T: ValueType, Ty: PointerType> WasmExternType for WasmPtr<T, Ty> {
+    const TYPE: Type = Type::I32;
+}
+
+impl<T: ValueType, Ty: PointerType> ValueType for WasmPtr<T, Ty> {
+    fn into_le(self, buffer: &mut [u8]) {
+        self.offset.into_le(buffer);
+    }
+    fn from_le(buffer: &[u8]) -> Result<Self, ValueError> {
+        Ok(Self::new(<u32 as ValueType>::from_le(buffer)?))
+    }
+}
+
+impl<T: ValueType, Ty: PointerType> Clone for WasmPtr<T, Ty> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: ValueType, Ty: PointerType> Copy for WasmPtr<T, Ty> {}
+
+impl<T: ValueType, Ty: PointerType> PartialEq for WasmPtr<T, Ty> {
+    fn eq(&self, other: &Self) -> bool {
+        self.offset == other.offset
+    }
+}
+
+impl<T: ValueType, Ty: PointerType> Eq for WasmPtr<T, Ty> {}
+
+impl<T: ValueType, Ty: PointerType> fmt::Debug for WasmPtr<T, Ty> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "WasmPtr({:#x})", self.offset)
+    }
+}
+
+#[cfg(test)]
+mod ptr_tests {
+    use super::{Array, WasmPtr};
+    use crate::{memory::Memory, types::MemoryDescriptor, units::Pages};
+
+    #[test]
+    fn test_wasm_ptr_bounds() {
+        let memory = Memory::new(MemoryDescriptor {
+            minimum: Pages(1),
+            maximum: None,
+            shared: false,
+        })
+        .unwrap();
+        let end = Pages(1).bytes().0 as u32;
+
+        let ptr: WasmPtr<u32> = WasmPtr::new(end - 4);
+        ptr.deref(&memory).unwrap().set(42);
+        assert_eq!(memory.view::<u32>()[(end / 4 - 1) as usize].get(), 42);
+        assert!(WasmPtr::<u32>::new(end - 2).deref(&memory).is_none());
+        assert!(WasmPtr::<u32>::new(2).deref(&memory).is_none());
+        assert!(WasmPtr::<u32>::new(u32::max_value())
+            .deref(&memory)
+            .is_none());
+
+        let array: WasmPtr<u32, Array> = WasmPtr::new(end - 8);
+        assert_eq!(array.deref(&memory, 1, 1).unwrap()[0].get(), 42);
+        assert!(array.deref(&memory, 0, 3).is_none());
+        assert!(array
+            .deref(&memory, u32::max_value(), u32::max_value())
+            .is_none());
+    }
+
+    #[test]
+    fn test_wasm_ptr_strings() {
+        let memory = Memory::new(MemoryDescriptor {
+            minimum: Pages(1),
+            maximum: None,
+            shared: false,
+        })
+        .unwrap();
+        let end = Pages(1).bytes().0 as u32;
+        for (cell, &byte) in memory.view::<u8>()[16..].iter().zip(b"hello\0") {
+            cell.set(byte);
+        }
+
+        let ptr: WasmPtr<u8, Array> = WasmPtr::new(16);
+        assert_eq!(ptr.get_utf8_string(&memory, 5).unwrap(), "hello");
+        assert_eq!(ptr.get_c_string(&memory).unwrap().to_str(), Ok("hello"));
+        assert!(ptr.get_utf8_string(&memory, end).is_none());
+
+        memory.view::<u8>()[end as usize - 1].set(b'!');
+        assert!(WasmPtr::<u8, Array>::new(end - 1)
+            .get_c_string(&memory)
+            .is_none());
+        assert!(WasmPtr::<u8, Array>::new(end + 1)
+            .get_c_string(&memory)
+            .is_none());
+    }
+}
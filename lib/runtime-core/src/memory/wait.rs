@@ -0,0 +1,159 @@
+//! The parking lot behind `memory.atomic.wait` and `memory.atomic.notify`.
+//!
+//! Waiters are keyed by the host address they are waiting on, so every
+//! instance using the same shared memory also sees the same waiters.
+
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+use parking_lot::{Condvar, Mutex};
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// The result of a wait, as seen by webassembly.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u32)]
+pub enum WaitResult {
+    /// Woken by a notify.
+    Ok = 0,
+    /// The loaded value didn't match the expected value.
+    NotEqual = 1,
+    /// The timeout expired before a notify.
+    TimedOut = 2,
+}
+
+struct Waiter {
+    woken: Mutex<bool>,
+    condvar: Condvar,
+}
+
+lazy_static! {
+    static ref WAITERS: Mutex<HashMap<usize, VecDeque<Arc<Waiter>>>> = Mutex::new(HashMap::new());
+}
+
+/// Park the current thread on `addr`, if `should_wait` returns `true`.
+///
+/// `should_wait` runs while holding the same lock as `notify`, so
+/// a notify can't slip in between checking the value and parking.
+/// A `timeout` of `None` waits forever.
+pub(crate) fn wait(
+    addr: usize,
+    should_wait: impl FnOnce() -> bool,
+    timeout: Option<Duration>,
+) -> WaitResult {
+    let waiter = {
+        let mut waiters = WAITERS.lock();
+        if !should_wait() {
+            return WaitResult::NotEqual;
+        }
+
+        let waiter = Arc::new(Waiter {
+            woken: Mutex::new(false),
+            condvar: Condvar::new(),
+        });
+        waiters
+            .entry(addr)
+            .or_default()
+            .push_back(Arc::clone(&waiter));
+        waiter
+    };
+
+    {
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        let mut woken = waiter.woken.lock();
+        while !*woken {
+            match deadline {
+                Some(deadline) => {
+                    if waiter.condvar.wait_until(&mut woken, deadline).timed_out() {
+                        break;
+                    }
+                }
+                None => waiter.condvar.wait(&mut woken),
+            }
+        }
+
+        if *woken {
+            return WaitResult::Ok;
+        }
+    }
+
+    // We timed out, but a notify may have picked us in the meantime.
+    let mut waiters = WAITERS.lock();
+    if *waiter.woken.lock() {
+        return WaitResult::Ok;
+    }
+
+    if let Some(queue) = waiters.get_mut(&addr) {
+        queue.retain(|queued| !Arc::ptr_eq(queued, &waiter));
+        if queue.is_empty() {
+            waiters.remove(&addr);
+        }
+    }
+
+    WaitResult::TimedOut
+}
+
+/// Wake up to `count` threads parked on `addr`, in the order they
+/// started waiting. Returns the number of threads woken.
+pub(crate) fn notify(addr: usize, count: u32) -> u32 {
+    let mut waiters = WAITERS.lock();
+    let mut woken = 0;
+
+    if let Some(queue) = waiters.get_mut(&addr) {
+        while woken < count {
+            match queue.pop_front() {
+                Some(waiter) => {
+                    *waiter.woken.lock() = true;
+                    waiter.condvar.notify_one();
+                    woken += 1;
+                }
+                None => break,
+            }
+        }
+
+        if queue.is_empty() {
+            waiters.remove(&addr);
+        }
+    }
+
+    woken
+}
+
+#[cfg(test)]
+mod wait_tests {
+    use super::{notify, wait, WaitResult};
+    use std::{sync::mpsc, thread, time::Duration};
+
+    #[test]
+    fn test_wait_and_notify() {
+        let addr = 0x1000;
+
+        assert_eq!(wait(addr, || false, None), WaitResult::NotEqual);
+        assert_eq!(
+            wait(addr, || true, Some(Duration::from_millis(10))),
+            WaitResult::TimedOut
+        );
+        assert_eq!(notify(addr, 1), 0);
+
+        let (parked_tx, parked_rx) = mpsc::channel();
+        let waiter = thread::spawn(move || {
+            wait(
+                addr,
+                || {
+                    parked_tx.send(()).unwrap();
+                    true
+                },
+                None,
+            )
+        });
+
+        // `should_wait` runs under the lock, so once it has been
+        // called, `notify` can only run after the waiter is queued.
+        parked_rx.recv().unwrap();
+        assert_eq!(notify(addr, 1), 1);
+
+        assert_eq!(waiter.join().unwrap(), WaitResult::Ok);
+    }
+}
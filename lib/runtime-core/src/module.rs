@@ -1,15 +1,16 @@
 use crate::{
-    backend::{Backend, FuncResolver, ProtectedCaller},
-    cache::{Artifact, Error as CacheError},
+    backend::{Backend, Features, FuncResolver, ProtectedCaller},
+    cache::{Artifact, Error as CacheError, WasmHash},
     error,
     import::ImportObject,
+    snapshot::Snapshot,
     structures::{Map, TypedIndex},
     typed_func::EARLY_TRAPPER,
     types::{
-        FuncIndex, FuncSig, GlobalDescriptor, GlobalIndex, GlobalInit, ImportedFuncIndex,
-        ImportedGlobalIndex, ImportedMemoryIndex, ImportedTableIndex, Initializer,
-        LocalGlobalIndex, LocalMemoryIndex, LocalTableIndex, MemoryDescriptor, MemoryIndex,
-        SigIndex, TableDescriptor, TableIndex,
+        DataIndex, ElemIndex, FuncIndex, FuncSig, GlobalDescriptor, GlobalIndex, GlobalInit,
+        ImportedFuncIndex, ImportedGlobalIndex, ImportedMemoryIndex, ImportedTableIndex,
+        Initializer, LocalGlobalIndex, LocalMemoryIndex, LocalOrImport, LocalTableIndex,
+        MemoryDescriptor, MemoryIndex, SigIndex, TableDescriptor, TableIndex,
     },
     Instance,
 };
@@ -17,7 +18,7 @@ use crate::{
 use crate::backend::CacheGen;
 use hashbrown::HashMap;
 use indexmap::IndexMap;
-use std::sync::Arc;
+use std::{fmt, sync::Arc};
 
 /// This is used to instantiate a new WebAssembly module.
 #[doc(hidden)]
@@ -48,6 +49,14 @@ pub struct ModuleInfo {
     pub data_initializers: Vec<DataInitializer>,
     pub elem_initializers: Vec<TableInitializer>,
 
+    /// The contents of the passive data segments, by their
+    /// index among all of the module's data segments.
+    pub passive_data: HashMap<DataIndex, Vec<u8>>,
+    /// The functions in the passive element segments, by their index
+    /// among all of the module's element segments. `None` is a null
+    /// reference.
+    pub passive_elements: HashMap<ElemIndex, Vec<Option<FuncIndex>>>,
+
     pub start_func: Option<FuncIndex>,
 
     pub func_assoc: Map<FuncIndex, SigIndex>,
@@ -56,6 +65,28 @@ pub struct ModuleInfo {
 
     pub namespace_table: StringTable<NamespaceIndex>,
     pub name_table: StringTable<NameIndex>,
+
+    /// The contents of the custom sections, by name, in the
+    /// order they appear in the module.
+    pub custom_sections: HashMap<String, Vec<Vec<u8>>>,
+
+    /// Function names, from the name section.
+    pub func_names: HashMap<FuncIndex, String>,
+    /// Local names by function and local index, from the name section.
+    pub local_names: HashMap<FuncIndex, HashMap<u32, String>>,
+
+    /// The hash of the wasm binary the module was compiled from.
+    pub wasm_hash: WasmHash,
+
+    /// The proposals the module was compiled with.
+    pub features: Features,
+}
+
+impl ModuleInfo {
+    /// Returns the name of a function from the name section, if it has one.
+    pub fn func_name(&self, func_index: FuncIndex) -> Option<&str> {
+        self.func_names.get(&func_index).map(String::as_str)
+    }
 }
 
 /// A compiled WebAssembly module.
@@ -101,7 +132,45 @@ impl Module {
     /// # }
     /// ```
     pub fn instantiate(&self, import_object: &ImportObject) -> error::Result<Instance> {
-        Instance::new(Arc::clone(&self.inner), import_object)
+        Instance::new(Arc::clone(&self.inner), import_object, None)
+    }
+
+    /// Instantiate a WebAssembly module with the provided [`ImportObject`],
+    /// restoring the state of the instance that `snapshot` was taken of.
+    ///
+    /// The memories, globals and tables of the new instance are the ones
+    /// in the snapshot, so the data and element segments aren't copied
+    /// into them, and the function designated as `start` isn't called.
+    /// The memories, globals and tables that the module imports aren't
+    /// in the snapshot, so `import_object` has to provide them as they
+    /// were when it was taken.
+    ///
+    /// This fails if the snapshot was taken of an instance of another module.
+    ///
+    /// [`ImportObject`]: struct.ImportObject.html
+    ///
+    /// # Usage:
+    /// ```
+    /// # use wasmer_runtime_core::error::Result;
+    /// # use wasmer_runtime_core::{Instance, Module};
+    /// # use wasmer_runtime_core::imports;
+    /// # fn restart(module: &Module, instance: &Instance) -> Result<()> {
+    /// let snapshot = instance.snapshot()?;
+    ///
+    /// let import_object = imports! {
+    ///     // ...
+    /// };
+    /// let restored = module.instantiate_from_snapshot(&import_object, &snapshot)?;
+    /// // ...
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn instantiate_from_snapshot(
+        &self,
+        import_object: &ImportObject,
+        snapshot: &Snapshot,
+    ) -> error::Result<Instance> {
+        Instance::new(Arc::clone(&self.inner), import_object, Some(snapshot))
     }
 
     pub fn cache(&self) -> Result<Artifact, CacheError> {
@@ -112,6 +181,137 @@ impl Module {
     pub fn info(&self) -> &ModuleInfo {
         &self.inner.info
     }
+
+    /// Returns the contents of the custom sections named `name`,
+    /// in the order they appear in the module.
+    ///
+    /// # Usage:
+    /// ```
+    /// # use wasmer_runtime_core::Module;
+    /// # fn producers(module: &Module) {
+    /// for section in module.custom_sections("producers") {
+    ///     println!("{} bytes of producer info", section.len());
+    /// }
+    /// # }
+    /// ```
+    pub fn custom_sections<'a>(&'a self, name: &str) -> impl Iterator<Item = &'a [u8]> {
+        self.inner
+            .info
+            .custom_sections
+            .get(name)
+            .into_iter()
+            .flat_map(|sections| sections.iter().map(Vec::as_slice))
+    }
+
+    /// Returns the imports this module requires to be instantiated.
+    ///
+    /// Imports are listed by kind: functions, then memories,
+    /// tables and globals, each in the order the module declares them.
+    ///
+    /// # Usage:
+    /// ```
+    /// # use wasmer_runtime_core::Module;
+    /// # fn list_imports(module: &Module) {
+    /// for import in module.imports() {
+    ///     println!("{}.{}: {:?}", import.namespace, import.name, import.ty);
+    /// }
+    /// # }
+    /// ```
+    pub fn imports(&self) -> Vec<ImportDescriptor> {
+        let info = &self.inner.info;
+        let import_descriptor = |import_name: &ImportName, ty| ImportDescriptor {
+            namespace: info
+                .namespace_table
+                .get(import_name.namespace_index)
+                .to_string(),
+            name: info.name_table.get(import_name.name_index).to_string(),
+            ty,
+        };
+
+        let functions = info
+            .imported_functions
+            .iter()
+            .map(|(imported_func_index, import_name)| {
+                let sig_index = info.func_assoc[imported_func_index.convert_up(info)];
+                import_descriptor(
+                    import_name,
+                    ExternDescriptor::Function(Arc::clone(&info.signatures[sig_index])),
+                )
+            });
+        let memories = info
+            .imported_memories
+            .iter()
+            .map(|(_, (import_name, desc))| {
+                import_descriptor(import_name, ExternDescriptor::Memory(*desc))
+            });
+        let tables = info.imported_tables.iter().map(|(_, (import_name, desc))| {
+            import_descriptor(import_name, ExternDescriptor::Table(*desc))
+        });
+        let globals = info
+            .imported_globals
+            .iter()
+            .map(|(_, (import_name, desc))| {
+                import_descriptor(import_name, ExternDescriptor::Global(*desc))
+            });
+
+        functions
+            .chain(memories)
+            .chain(tables)
+            .chain(globals)
+            .collect()
+    }
+
+    /// Returns the items this module exports, in no particular order.
+    pub fn exports(&self) -> Vec<ExportDescriptor> {
+        let info = &self.inner.info;
+
+        info.exports
+            .iter()
+            .map(|(name, &export_index)| {
+                let ty = match export_index {
+                    ExportIndex::Func(func_index) => {
+                        let sig_index = info.func_assoc[func_index];
+                        ExternDescriptor::Function(Arc::clone(&info.signatures[sig_index]))
+                    }
+                    ExportIndex::Memory(memory_index) => {
+                        ExternDescriptor::Memory(match memory_index.local_or_import(info) {
+                            LocalOrImport::Local(local_memory_index) => {
+                                info.memories[local_memory_index]
+                            }
+                            LocalOrImport::Import(imported_memory_index) => {
+                                info.imported_memories[imported_memory_index].1
+                            }
+                        })
+                    }
+                    ExportIndex::Table(table_index) => {
+                        ExternDescriptor::Table(match table_index.local_or_import(info) {
+                            LocalOrImport::Local(local_table_index) => {
+                                info.tables[local_table_index]
+                            }
+                            LocalOrImport::Import(imported_table_index) => {
+                                info.imported_tables[imported_table_index].1
+                            }
+                        })
+                    }
+                    ExportIndex::Global(global_index) => {
+                        ExternDescriptor::Global(match global_index.local_or_import(info) {
+                            LocalOrImport::Local(local_global_index) => {
+                                info.globals[local_global_index].desc
+                            }
+                            LocalOrImport::Import(imported_global_index) => {
+                                info.imported_globals[imported_global_index].1
+                            }
+                        })
+                    }
+                };
+
+                ExportDescriptor {
+                    name: name.clone(),
+                    ty,
+                }
+            })
+            .collect()
+    }
 }
 
 impl Clone for Module {
@@ -139,6 +339,50 @@ pub enum ExportIndex {
     Table(TableIndex),
 }
 
+/// The kind of an import or export, along with its type.
+#[derive(Debug, Clone)]
+pub enum ExternDescriptor {
+    Function(Arc<FuncSig>),
+    Memory(MemoryDescriptor),
+    Table(TableDescriptor),
+    Global(GlobalDescriptor),
+}
+
+impl fmt::Display for ExternDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExternDescriptor::Function(signature) => write!(f, "function {}", signature),
+            ExternDescriptor::Memory(desc) => write!(f, "memory {:?}", desc),
+            ExternDescriptor::Table(desc) => write!(f, "table {:?}", desc),
+            ExternDescriptor::Global(desc) => write!(f, "global {:?}", desc),
+        }
+    }
+}
+
+/// An import that a [`Module`] requires.
+///
+/// [`Module`]: struct.Module.html
+#[derive(Debug, Clone)]
+pub struct ImportDescriptor {
+    /// The namespace the import is looked up in, e.g. `"env"`.
+    pub namespace: String,
+    /// The name of the import within its namespace.
+    pub name: String,
+    /// What kind of item is imported, and its type.
+    pub ty: ExternDescriptor,
+}
+
+/// An item that a [`Module`] exports.
+///
+/// [`Module`]: struct.Module.html
+#[derive(Debug, Clone)]
+pub struct ExportDescriptor {
+    /// The name the item is exported under.
+    pub name: String,
+    /// What kind of item is exported, and its type.
+    pub ty: ExternDescriptor,
+}
+
 /// A data initializer for linear memory.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DataInitializer {
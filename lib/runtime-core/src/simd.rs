@@ -0,0 +1,620 @@
+//! The lane-wise semantics of the SIMD instructions behind the SIMD vmcalls.
+//!
+//! Instructions are identified by their opcode after the `0xfd` prefix, and
+//! a `v128` is a `u128` with its first lane in the lowest bits. Scalar
+//! operands and results are passed around as their bits, zero-extended
+//! to 64 bits.
+
+/// Generates functions that split a `v128` into lanes and put them back together.
+macro_rules! lanes {
+    ($($to:ident, $from:ident: [$ty:ty; $count:expr];)*) => {
+        $(
+            fn $to(v: u128) -> [$ty; $count] {
+                const WIDTH: usize = 16 / $count;
+                let bytes = v.to_le_bytes();
+                let mut lanes = [<$ty>::default(); $count];
+                for (lane, chunk) in lanes.iter_mut().zip(bytes.chunks(WIDTH)) {
+                    let mut lane_bytes = [0; WIDTH];
+                    lane_bytes.copy_from_slice(chunk);
+                    *lane = <$ty>::from_le_bytes(lane_bytes);
+                }
+                lanes
+            }
+
+            fn $from(lanes: [$ty; $count]) -> u128 {
+                const WIDTH: usize = 16 / $count;
+                let mut bytes = [0; 16];
+                for (chunk, lane) in bytes.chunks_mut(WIDTH).zip(lanes.iter()) {
+                    chunk.copy_from_slice(&lane.to_le_bytes());
+                }
+                u128::from_le_bytes(bytes)
+            }
+        )*
+    };
+}
+
+lanes! {
+    i8x16, from_i8x16: [i8; 16];
+    u8x16, from_u8x16: [u8; 16];
+    i16x8, from_i16x8: [i16; 8];
+    u16x8, from_u16x8: [u16; 8];
+    i32x4, from_i32x4: [i32; 4];
+    u32x4, from_u32x4: [u32; 4];
+    i64x2, from_i64x2: [i64; 2];
+    u64x2, from_u64x2: [u64; 2];
+    f32x4, from_f32x4: [f32; 4];
+    f64x2, from_f64x2: [f64; 2];
+}
+
+/// Applies `$op` to each lane of `$a`.
+macro_rules! unary {
+    ($a:expr, $lanes:ident => $from:ident, |$x:ident| $op:expr) => {{
+        let mut lanes = $lanes($a);
+        for lane in lanes.iter_mut() {
+            let $x = *lane;
+            *lane = $op;
+        }
+        $from(lanes)
+    }};
+}
+
+/// Applies `$op` to each pair of matching lanes of `$a` and `$b`.
+macro_rules! binary {
+    ($a:expr, $b:expr, $lanes:ident => $from:ident, |$x:ident, $y:ident| $op:expr) => {{
+        let mut lanes = $lanes($a);
+        for (lane, &$y) in lanes.iter_mut().zip($lanes($b).iter()) {
+            let $x = *lane;
+            *lane = $op;
+        }
+        $from(lanes)
+    }};
+}
+
+/// Sets each lane of the result to all ones where `$cond` holds for the
+/// matching lanes of `$a` and `$b`, and to zero where it doesn't.
+macro_rules! compare {
+    ($a:expr, $b:expr, $lanes:ident => $mask:ident, $from_mask:ident, |$x:ident, $y:ident| $cond:expr) => {{
+        let (a, b) = ($lanes($a), $lanes($b));
+        let mut mask = $mask(0);
+        for (i, lane) in mask.iter_mut().enumerate() {
+            let ($x, $y) = (a[i], b[i]);
+            *lane = if $cond { -1 } else { 0 };
+        }
+        $from_mask(mask)
+    }};
+}
+
+/// Narrows the lanes of `$a` followed by those of `$b`
+/// to half their width, saturating them to `$min..=$max`.
+macro_rules! narrow {
+    ($a:expr, $b:expr, $lanes:ident => $from:ident, $min:expr, $max:expr) => {{
+        let (a, b) = ($lanes($a), $lanes($b));
+        let mut narrowed = $from(0);
+        for (lane, &x) in narrowed.iter_mut().zip(a.iter().chain(b.iter())) {
+            *lane = x.max($min as _).min($max as _) as _;
+        }
+        narrowed
+    }};
+}
+
+/// Extends either the low or the high half of the lanes of `$a` to twice
+/// their width, with the signedness of `$lanes`.
+macro_rules! widen {
+    ($a:expr, $high:expr, $lanes:ident => $wide:ident, $from_wide:ident) => {{
+        let lanes = $lanes($a);
+        let mut wide = $wide(0);
+        let half = if $high { &lanes[wide.len()..] } else { &lanes[..wide.len()] };
+        for (lane, &x) in wide.iter_mut().zip(half.iter()) {
+            *lane = x.into();
+        }
+        $from_wide(wide)
+    }};
+}
+
+/// Generates the `min` and `max` of webassembly for a float type, which,
+/// unlike Rust's, return NaN if either operand is NaN and order -0 before +0.
+macro_rules! float_min_max {
+    ($min:ident, $max:ident, $ty:ty) => {
+        fn $min(x: $ty, y: $ty) -> $ty {
+            if x.is_nan() || y.is_nan() {
+                <$ty>::NAN
+            } else if x == y {
+                // Only differs from `x` when they are -0 and +0.
+                <$ty>::from_bits(x.to_bits() | y.to_bits())
+            } else {
+                x.min(y)
+            }
+        }
+
+        fn $max(x: $ty, y: $ty) -> $ty {
+            if x.is_nan() || y.is_nan() {
+                <$ty>::NAN
+            } else if x == y {
+                <$ty>::from_bits(x.to_bits() & y.to_bits())
+            } else {
+                x.max(y)
+            }
+        }
+    };
+}
+
+float_min_max!(f32_min, f32_max, f32);
+float_min_max!(f64_min, f64_max, f64);
+
+/// Generates the `nearest` of webassembly for a float type,
+/// which rounds halfway cases to even, unlike Rust's `round`.
+macro_rules! float_nearest {
+    ($nearest:ident, $ty:ty) => {
+        fn $nearest(x: $ty) -> $ty {
+            let rounded = x.round();
+            if (rounded - x).abs() == 0.5 {
+                2.0 * (x / 2.0).round()
+            } else {
+                rounded
+            }
+        }
+    };
+}
+
+float_nearest!(f32_nearest, f32);
+float_nearest!(f64_nearest, f64);
+
+/// Adds each pair of adjacent lanes of `$a`, widened to twice their width.
+macro_rules! pairwise {
+    ($a:expr, $lanes:ident => $wide:ident, $from_wide:ident) => {{
+        let lanes = $lanes($a);
+        let mut wide = $wide(0);
+        for (lane, pair) in wide.iter_mut().zip(lanes.chunks(2)) {
+            *lane = pair[0].into();
+            *lane = lane.wrapping_add(pair[1].into());
+        }
+        $from_wide(wide)
+    }};
+}
+
+/// Multiplies either the low or the high halves of the lanes of `$a` and
+/// `$b`, extended to twice their width with the signedness of `$lanes`.
+macro_rules! extmul {
+    ($a:expr, $b:expr, $high:expr, $lanes:ident => $wide:ident, $from_wide:ident) => {{
+        let a = widen!($a, $high, $lanes => $wide, $from_wide);
+        let b = widen!($b, $high, $lanes => $wide, $from_wide);
+        binary!(a, b, $wide => $from_wide, |x, y| x.wrapping_mul(y))
+    }};
+}
+
+/// Implements the `splat` instructions.
+pub fn splat(opcode: u32, value: u64) -> u128 {
+    match opcode {
+        0x0f => from_u8x16([value as u8; 16]),
+        0x10 => from_u16x8([value as u16; 8]),
+        0x11 | 0x13 => from_u32x4([value as u32; 4]),
+        0x12 | 0x14 => from_u64x2([value; 2]),
+        _ => unreachable!("invalid splat opcode {:#x}", opcode),
+    }
+}
+
+/// Implements the `extract_lane` instructions, along with the
+/// `any_true`, `all_true` and `bitmask` ones.
+///
+/// Signed lanes are sign-extended to 32 bits.
+pub fn extract_lane(opcode: u32, lane: usize, v: u128) -> u64 {
+    match opcode {
+        0x15 => u64::from(i32::from(i8x16(v)[lane]) as u32),
+        0x16 => u64::from(u8x16(v)[lane]),
+        0x18 => u64::from(i32::from(i16x8(v)[lane]) as u32),
+        0x19 => u64::from(u16x8(v)[lane]),
+        0x1b | 0x1f => u64::from(u32x4(v)[lane]),
+        0x1d | 0x21 => u64x2(v)[lane],
+        // `any_true`
+        0x53 => (v != 0) as u64,
+        // `all_true`
+        0x63 => u8x16(v).iter().all(|&x| x != 0) as u64,
+        0x83 => u16x8(v).iter().all(|&x| x != 0) as u64,
+        0xa3 => u32x4(v).iter().all(|&x| x != 0) as u64,
+        0xc3 => u64x2(v).iter().all(|&x| x != 0) as u64,
+        // `bitmask`
+        0x64 => bitmask(&i8x16(v)),
+        0x84 => bitmask(&i16x8(v)),
+        0xa4 => bitmask(&i32x4(v)),
+        0xc4 => bitmask(&i64x2(v)),
+        _ => unreachable!("invalid extract_lane opcode {:#x}", opcode),
+    }
+}
+
+/// The sign bits of `lanes`, the first lane's in the lowest bit.
+fn bitmask<T: Copy + Default + PartialOrd>(lanes: &[T]) -> u64 {
+    lanes
+        .iter()
+        .enumerate()
+        .filter(|&(_, &x)| x < T::default())
+        .fold(0, |mask, (index, _)| mask | 1 << index)
+}
+
+/// Implements the `replace_lane` instructions, along with the
+/// shifts, which take their shift count as `value` and no lane.
+pub fn replace_lane(opcode: u32, lane: usize, v: u128, value: u64) -> u128 {
+    // Shift counts are taken modulo the lane width by `wrapping_shl`
+    // and `wrapping_shr`, and `shr` is arithmetic on signed lanes.
+    let count = value as u32;
+    match opcode {
+        0x17 => {
+            let mut lanes = u8x16(v);
+            lanes[lane] = value as u8;
+            from_u8x16(lanes)
+        }
+        0x1a => {
+            let mut lanes = u16x8(v);
+            lanes[lane] = value as u16;
+            from_u16x8(lanes)
+        }
+        0x1c | 0x20 => {
+            let mut lanes = u32x4(v);
+            lanes[lane] = value as u32;
+            from_u32x4(lanes)
+        }
+        0x1e | 0x22 => {
+            let mut lanes = u64x2(v);
+            lanes[lane] = value;
+            from_u64x2(lanes)
+        }
+        0x6b => unary!(v, u8x16 => from_u8x16, |x| x.wrapping_shl(count)),
+        0x6c => unary!(v, i8x16 => from_i8x16, |x| x.wrapping_shr(count)),
+        0x6d => unary!(v, u8x16 => from_u8x16, |x| x.wrapping_shr(count)),
+        0x8b => unary!(v, u16x8 => from_u16x8, |x| x.wrapping_shl(count)),
+        0x8c => unary!(v, i16x8 => from_i16x8, |x| x.wrapping_shr(count)),
+        0x8d => unary!(v, u16x8 => from_u16x8, |x| x.wrapping_shr(count)),
+        0xab => unary!(v, u32x4 => from_u32x4, |x| x.wrapping_shl(count)),
+        0xac => unary!(v, i32x4 => from_i32x4, |x| x.wrapping_shr(count)),
+        0xad => unary!(v, u32x4 => from_u32x4, |x| x.wrapping_shr(count)),
+        0xcb => unary!(v, u64x2 => from_u64x2, |x| x.wrapping_shl(count)),
+        0xcc => unary!(v, i64x2 => from_i64x2, |x| x.wrapping_shr(count)),
+        0xcd => unary!(v, u64x2 => from_u64x2, |x| x.wrapping_shr(count)),
+        _ => unreachable!("invalid replace_lane opcode {:#x}", opcode),
+    }
+}
+
+/// Implements the instructions that take one `v128` and return one.
+///
+/// The `load*x*` instructions are implemented on a `v128` with
+/// the loaded 64 bits in its low half, as if it was loaded whole.
+pub fn unary(opcode: u32, a: u128) -> u128 {
+    match opcode {
+        0x4d => !a,
+        0x60 => unary!(a, i8x16 => from_i8x16, |x| x.wrapping_abs()),
+        0x61 => unary!(a, i8x16 => from_i8x16, |x| x.wrapping_neg()),
+        0x62 => unary!(a, u8x16 => from_u8x16, |x| x.count_ones() as u8),
+        0x80 => unary!(a, i16x8 => from_i16x8, |x| x.wrapping_abs()),
+        0x81 => unary!(a, i16x8 => from_i16x8, |x| x.wrapping_neg()),
+        0xa0 => unary!(a, i32x4 => from_i32x4, |x| x.wrapping_abs()),
+        0xa1 => unary!(a, i32x4 => from_i32x4, |x| x.wrapping_neg()),
+        0xc0 => unary!(a, i64x2 => from_i64x2, |x| x.wrapping_abs()),
+        0xc1 => unary!(a, i64x2 => from_i64x2, |x| x.wrapping_neg()),
+        // `abs` and `neg` only touch the sign bit, even of a NaN.
+        0xe0 => unary!(a, u32x4 => from_u32x4, |x| x & !(1 << 31)),
+        0xe1 => unary!(a, u32x4 => from_u32x4, |x| x ^ (1 << 31)),
+        0xe3 => unary!(a, f32x4 => from_f32x4, |x| x.sqrt()),
+        0xec => unary!(a, u64x2 => from_u64x2, |x| x & !(1 << 63)),
+        0xed => unary!(a, u64x2 => from_u64x2, |x| x ^ (1 << 63)),
+        0xef => unary!(a, f64x2 => from_f64x2, |x| x.sqrt()),
+        0x67 => unary!(a, f32x4 => from_f32x4, |x| x.ceil()),
+        0x68 => unary!(a, f32x4 => from_f32x4, |x| x.floor()),
+        0x69 => unary!(a, f32x4 => from_f32x4, |x| x.trunc()),
+        0x6a => unary!(a, f32x4 => from_f32x4, |x| f32_nearest(x)),
+        0x74 => unary!(a, f64x2 => from_f64x2, |x| x.ceil()),
+        0x75 => unary!(a, f64x2 => from_f64x2, |x| x.floor()),
+        0x7a => unary!(a, f64x2 => from_f64x2, |x| x.trunc()),
+        0x94 => unary!(a, f64x2 => from_f64x2, |x| f64_nearest(x)),
+        0x7c => pairwise!(a, i8x16 => i16x8, from_i16x8),
+        0x7d => pairwise!(a, u8x16 => u16x8, from_u16x8),
+        0x7e => pairwise!(a, i16x8 => i32x4, from_i32x4),
+        0x7f => pairwise!(a, u16x8 => u32x4, from_u32x4),
+        // `extend_low` and `extend_high`, and the `load*x*` instructions.
+        0x87 | 0x01 => widen!(a, false, i8x16 => i16x8, from_i16x8),
+        0x88 => widen!(a, true, i8x16 => i16x8, from_i16x8),
+        0x89 | 0x02 => widen!(a, false, u8x16 => u16x8, from_u16x8),
+        0x8a => widen!(a, true, u8x16 => u16x8, from_u16x8),
+        0xa7 | 0x03 => widen!(a, false, i16x8 => i32x4, from_i32x4),
+        0xa8 => widen!(a, true, i16x8 => i32x4, from_i32x4),
+        0xa9 | 0x04 => widen!(a, false, u16x8 => u32x4, from_u32x4),
+        0xaa => widen!(a, true, u16x8 => u32x4, from_u32x4),
+        0xc7 | 0x05 => widen!(a, false, i32x4 => i64x2, from_i64x2),
+        0xc8 => widen!(a, true, i32x4 => i64x2, from_i64x2),
+        0xc9 | 0x06 => widen!(a, false, u32x4 => u64x2, from_u64x2),
+        0xca => widen!(a, true, u32x4 => u64x2, from_u64x2),
+        // Float to integer casts saturate, and turn NaN into 0. The
+        // `_zero` ones and `demote` set the lanes they don't fill to 0.
+        0xf8 => from_i32x4(unary_lanes(f32x4(a), |x| x as i32)),
+        0xf9 => from_u32x4(unary_lanes(f32x4(a), |x| x as u32)),
+        0xfa => from_f32x4(unary_lanes(i32x4(a), |x| x as f32)),
+        0xfb => from_f32x4(unary_lanes(u32x4(a), |x| x as f32)),
+        0xfc => from_i32x4(unary_lanes(f64x2(a), |x| x as i32)),
+        0xfd => from_u32x4(unary_lanes(f64x2(a), |x| x as u32)),
+        0xfe => from_f64x2(unary_lanes(i32x4(a), f64::from)),
+        0xff => from_f64x2(unary_lanes(u32x4(a), f64::from)),
+        0x5e => from_f32x4(unary_lanes(f64x2(a), |x| x as f32)),
+        0x5f => from_f64x2(unary_lanes(f32x4(a), f64::from)),
+        _ => unreachable!("invalid unary SIMD opcode {:#x}", opcode),
+    }
+}
+
+/// Maps lanes to lanes of another type, as many as both have,
+/// leaving the rest of the result's lanes 0.
+fn unary_lanes<T: Copy, U: Default + Copy, A, B>(lanes: A, op: impl Fn(T) -> U) -> B
+where
+    A: AsRef<[T]>,
+    B: Default + AsMut<[U]>,
+{
+    let mut mapped = B::default();
+    for (lane, &x) in mapped.as_mut().iter_mut().zip(lanes.as_ref().iter()) {
+        *lane = op(x);
+    }
+    mapped
+}
+
+/// Implements the instructions that take two `v128`s and return one.
+pub fn binary(opcode: u32, a: u128, b: u128) -> u128 {
+    match opcode {
+        0x23 => compare!(a, b, i8x16 => i8x16, from_i8x16, |x, y| x == y),
+        0x24 => compare!(a, b, i8x16 => i8x16, from_i8x16, |x, y| x != y),
+        0x25 => compare!(a, b, i8x16 => i8x16, from_i8x16, |x, y| x < y),
+        0x26 => compare!(a, b, u8x16 => i8x16, from_i8x16, |x, y| x < y),
+        0x27 => compare!(a, b, i8x16 => i8x16, from_i8x16, |x, y| x > y),
+        0x28 => compare!(a, b, u8x16 => i8x16, from_i8x16, |x, y| x > y),
+        0x29 => compare!(a, b, i8x16 => i8x16, from_i8x16, |x, y| x <= y),
+        0x2a => compare!(a, b, u8x16 => i8x16, from_i8x16, |x, y| x <= y),
+        0x2b => compare!(a, b, i8x16 => i8x16, from_i8x16, |x, y| x >= y),
+        0x2c => compare!(a, b, u8x16 => i8x16, from_i8x16, |x, y| x >= y),
+        0x2d => compare!(a, b, i16x8 => i16x8, from_i16x8, |x, y| x == y),
+        0x2e => compare!(a, b, i16x8 => i16x8, from_i16x8, |x, y| x != y),
+        0x2f => compare!(a, b, i16x8 => i16x8, from_i16x8, |x, y| x < y),
+        0x30 => compare!(a, b, u16x8 => i16x8, from_i16x8, |x, y| x < y),
+        0x31 => compare!(a, b, i16x8 => i16x8, from_i16x8, |x, y| x > y),
+        0x32 => compare!(a, b, u16x8 => i16x8, from_i16x8, |x, y| x > y),
+        0x33 => compare!(a, b, i16x8 => i16x8, from_i16x8, |x, y| x <= y),
+        0x34 => compare!(a, b, u16x8 => i16x8, from_i16x8, |x, y| x <= y),
+        0x35 => compare!(a, b, i16x8 => i16x8, from_i16x8, |x, y| x >= y),
+        0x36 => compare!(a, b, u16x8 => i16x8, from_i16x8, |x, y| x >= y),
+        0x37 => compare!(a, b, i32x4 => i32x4, from_i32x4, |x, y| x == y),
+        0x38 => compare!(a, b, i32x4 => i32x4, from_i32x4, |x, y| x != y),
+        0x39 => compare!(a, b, i32x4 => i32x4, from_i32x4, |x, y| x < y),
+        0x3a => compare!(a, b, u32x4 => i32x4, from_i32x4, |x, y| x < y),
+        0x3b => compare!(a, b, i32x4 => i32x4, from_i32x4, |x, y| x > y),
+        0x3c => compare!(a, b, u32x4 => i32x4, from_i32x4, |x, y| x > y),
+        0x3d => compare!(a, b, i32x4 => i32x4, from_i32x4, |x, y| x <= y),
+        0x3e => compare!(a, b, u32x4 => i32x4, from_i32x4, |x, y| x <= y),
+        0x3f => compare!(a, b, i32x4 => i32x4, from_i32x4, |x, y| x >= y),
+        0x40 => compare!(a, b, u32x4 => i32x4, from_i32x4, |x, y| x >= y),
+        0xd6 => compare!(a, b, i64x2 => i64x2, from_i64x2, |x, y| x == y),
+        0xd7 => compare!(a, b, i64x2 => i64x2, from_i64x2, |x, y| x != y),
+        0xd8 => compare!(a, b, i64x2 => i64x2, from_i64x2, |x, y| x < y),
+        0xd9 => compare!(a, b, i64x2 => i64x2, from_i64x2, |x, y| x > y),
+        0xda => compare!(a, b, i64x2 => i64x2, from_i64x2, |x, y| x <= y),
+        0xdb => compare!(a, b, i64x2 => i64x2, from_i64x2, |x, y| x >= y),
+        0x41 => compare!(a, b, f32x4 => i32x4, from_i32x4, |x, y| x == y),
+        0x42 => compare!(a, b, f32x4 => i32x4, from_i32x4, |x, y| x != y),
+        0x43 => compare!(a, b, f32x4 => i32x4, from_i32x4, |x, y| x < y),
+        0x44 => compare!(a, b, f32x4 => i32x4, from_i32x4, |x, y| x > y),
+        0x45 => compare!(a, b, f32x4 => i32x4, from_i32x4, |x, y| x <= y),
+        0x46 => compare!(a, b, f32x4 => i32x4, from_i32x4, |x, y| x >= y),
+        0x47 => compare!(a, b, f64x2 => i64x2, from_i64x2, |x, y| x == y),
+        0x48 => compare!(a, b, f64x2 => i64x2, from_i64x2, |x, y| x != y),
+        0x49 => compare!(a, b, f64x2 => i64x2, from_i64x2, |x, y| x < y),
+        0x4a => compare!(a, b, f64x2 => i64x2, from_i64x2, |x, y| x > y),
+        0x4b => compare!(a, b, f64x2 => i64x2, from_i64x2, |x, y| x <= y),
+        0x4c => compare!(a, b, f64x2 => i64x2, from_i64x2, |x, y| x >= y),
+        0x4e => a & b,
+        0x4f => a & !b,
+        0x50 => a | b,
+        0x51 => a ^ b,
+        0x6e => binary!(a, b, i8x16 => from_i8x16, |x, y| x.wrapping_add(y)),
+        0x6f => binary!(a, b, i8x16 => from_i8x16, |x, y| x.saturating_add(y)),
+        0x70 => binary!(a, b, u8x16 => from_u8x16, |x, y| x.saturating_add(y)),
+        0x71 => binary!(a, b, i8x16 => from_i8x16, |x, y| x.wrapping_sub(y)),
+        0x72 => binary!(a, b, i8x16 => from_i8x16, |x, y| x.saturating_sub(y)),
+        0x73 => binary!(a, b, u8x16 => from_u8x16, |x, y| x.saturating_sub(y)),
+        0x76 => binary!(a, b, i8x16 => from_i8x16, |x, y| x.min(y)),
+        0x77 => binary!(a, b, u8x16 => from_u8x16, |x, y| x.min(y)),
+        0x78 => binary!(a, b, i8x16 => from_i8x16, |x, y| x.max(y)),
+        0x79 => binary!(a, b, u8x16 => from_u8x16, |x, y| x.max(y)),
+        0x7b => binary!(a, b, u8x16 => from_u8x16, |x, y| {
+            ((u16::from(x) + u16::from(y) + 1) / 2) as u8
+        }),
+        0x8e => binary!(a, b, i16x8 => from_i16x8, |x, y| x.wrapping_add(y)),
+        0x8f => binary!(a, b, i16x8 => from_i16x8, |x, y| x.saturating_add(y)),
+        0x90 => binary!(a, b, u16x8 => from_u16x8, |x, y| x.saturating_add(y)),
+        0x91 => binary!(a, b, i16x8 => from_i16x8, |x, y| x.wrapping_sub(y)),
+        0x92 => binary!(a, b, i16x8 => from_i16x8, |x, y| x.saturating_sub(y)),
+        0x93 => binary!(a, b, u16x8 => from_u16x8, |x, y| x.saturating_sub(y)),
+        0x95 => binary!(a, b, i16x8 => from_i16x8, |x, y| x.wrapping_mul(y)),
+        0x96 => binary!(a, b, i16x8 => from_i16x8, |x, y| x.min(y)),
+        0x97 => binary!(a, b, u16x8 => from_u16x8, |x, y| x.min(y)),
+        0x98 => binary!(a, b, i16x8 => from_i16x8, |x, y| x.max(y)),
+        0x99 => binary!(a, b, u16x8 => from_u16x8, |x, y| x.max(y)),
+        0x9b => binary!(a, b, u16x8 => from_u16x8, |x, y| {
+            ((u32::from(x) + u32::from(y) + 1) / 2) as u16
+        }),
+        // `q15mulr_sat_s`, which only overflows for -1 times -1.
+        0x82 => binary!(a, b, i16x8 => from_i16x8, |x, y| {
+            let product = (i32::from(x) * i32::from(y) + 0x4000) >> 15;
+            product.min(i32::from(i16::max_value())) as i16
+        }),
+        0xae => binary!(a, b, i32x4 => from_i32x4, |x, y| x.wrapping_add(y)),
+        0xb1 => binary!(a, b, i32x4 => from_i32x4, |x, y| x.wrapping_sub(y)),
+        0xb5 => binary!(a, b, i32x4 => from_i32x4, |x, y| x.wrapping_mul(y)),
+        0xb6 => binary!(a, b, i32x4 => from_i32x4, |x, y| x.min(y)),
+        0xb7 => binary!(a, b, u32x4 => from_u32x4, |x, y| x.min(y)),
+        0xb8 => binary!(a, b, i32x4 => from_i32x4, |x, y| x.max(y)),
+        0xb9 => binary!(a, b, u32x4 => from_u32x4, |x, y| x.max(y)),
+        0xba => {
+            // `dot_i16x8_s`: the sums of the products of pairs of lanes.
+            let products = extmul!(a, b, false, i16x8 => i32x4, from_i32x4);
+            let high_products = extmul!(a, b, true, i16x8 => i32x4, from_i32x4);
+            let (low, high) = (i32x4(products), i32x4(high_products));
+            from_i32x4([
+                low[0].wrapping_add(low[1]),
+                low[2].wrapping_add(low[3]),
+                high[0].wrapping_add(high[1]),
+                high[2].wrapping_add(high[3]),
+            ])
+        }
+        0xce => binary!(a, b, i64x2 => from_i64x2, |x, y| x.wrapping_add(y)),
+        0xd1 => binary!(a, b, i64x2 => from_i64x2, |x, y| x.wrapping_sub(y)),
+        0xd5 => binary!(a, b, i64x2 => from_i64x2, |x, y| x.wrapping_mul(y)),
+        0x9c => extmul!(a, b, false, i8x16 => i16x8, from_i16x8),
+        0x9d => extmul!(a, b, true, i8x16 => i16x8, from_i16x8),
+        0x9e => extmul!(a, b, false, u8x16 => u16x8, from_u16x8),
+        0x9f => extmul!(a, b, true, u8x16 => u16x8, from_u16x8),
+        0xbc => extmul!(a, b, false, i16x8 => i32x4, from_i32x4),
+        0xbd => extmul!(a, b, true, i16x8 => i32x4, from_i32x4),
+        0xbe => extmul!(a, b, false, u16x8 => u32x4, from_u32x4),
+        0xbf => extmul!(a, b, true, u16x8 => u32x4, from_u32x4),
+        0xdc => extmul!(a, b, false, i32x4 => i64x2, from_i64x2),
+        0xdd => extmul!(a, b, true, i32x4 => i64x2, from_i64x2),
+        0xde => extmul!(a, b, false, u32x4 => u64x2, from_u64x2),
+        0xdf => extmul!(a, b, true, u32x4 => u64x2, from_u64x2),
+        0xe4 => binary!(a, b, f32x4 => from_f32x4, |x, y| x + y),
+        0xe5 => binary!(a, b, f32x4 => from_f32x4, |x, y| x - y),
+        0xe6 => binary!(a, b, f32x4 => from_f32x4, |x, y| x * y),
+        0xe7 => binary!(a, b, f32x4 => from_f32x4, |x, y| x / y),
+        0xe8 => binary!(a, b, f32x4 => from_f32x4, |x, y| f32_min(x, y)),
+        0xe9 => binary!(a, b, f32x4 => from_f32x4, |x, y| f32_max(x, y)),
+        // `pmin` and `pmax` are `<` with the first operand as the default.
+        0xea => binary!(a, b, f32x4 => from_f32x4, |x, y| if y < x { y } else { x }),
+        0xeb => binary!(a, b, f32x4 => from_f32x4, |x, y| if x < y { y } else { x }),
+        0xf0 => binary!(a, b, f64x2 => from_f64x2, |x, y| x + y),
+        0xf1 => binary!(a, b, f64x2 => from_f64x2, |x, y| x - y),
+        0xf2 => binary!(a, b, f64x2 => from_f64x2, |x, y| x * y),
+        0xf3 => binary!(a, b, f64x2 => from_f64x2, |x, y| x / y),
+        0xf4 => binary!(a, b, f64x2 => from_f64x2, |x, y| f64_min(x, y)),
+        0xf5 => binary!(a, b, f64x2 => from_f64x2, |x, y| f64_max(x, y)),
+        0xf6 => binary!(a, b, f64x2 => from_f64x2, |x, y| if y < x { y } else { x }),
+        0xf7 => binary!(a, b, f64x2 => from_f64x2, |x, y| if x < y { y } else { x }),
+        0x0e => {
+            // `swizzle`: lanes of `a` picked by the lanes of
+            // `b`, with 0 for indices that are out of range.
+            let lanes = u8x16(a);
+            let swizzled = unary_lanes(u8x16(b), |index| {
+                lanes.get(index as usize).cloned().unwrap_or(0)
+            });
+            from_u8x16(swizzled)
+        }
+        0x65 => from_i8x16(narrow!(a, b, i16x8 => i8x16, i8::min_value(), i8::max_value())),
+        0x66 => from_u8x16(narrow!(a, b, i16x8 => u8x16, 0, u8::max_value())),
+        0x85 => from_i16x8(narrow!(a, b, i32x4 => i16x8, i16::min_value(), i16::max_value())),
+        0x86 => from_u16x8(narrow!(a, b, i32x4 => u16x8, 0, u16::max_value())),
+        _ => unreachable!("invalid binary SIMD opcode {:#x}", opcode),
+    }
+}
+
+/// Implements the instructions that take three `v128`s and return one.
+///
+/// `shuffle` is given its lane indices as the third `v128`.
+pub fn ternary(opcode: u32, a: u128, b: u128, c: u128) -> u128 {
+    match opcode {
+        0x52 => (a & c) | (b & !c),
+        0x0d => {
+            let lanes: Vec<u8> = u8x16(a).iter().chain(u8x16(b).iter()).cloned().collect();
+            from_u8x16(unary_lanes(u8x16(c), |index| lanes[index as usize]))
+        }
+        _ => unreachable!("invalid ternary SIMD opcode {:#x}", opcode),
+    }
+}
+
+#[cfg(test)]
+mod simd_tests {
+    use super::{
+        binary, extract_lane, from_f32x4, from_f64x2, from_i16x8, from_i32x4, from_i8x16,
+        replace_lane, splat, unary,
+    };
+
+    #[test]
+    fn test_lanes() {
+        let v = 0x0f0e_0d0c_0b0a_0908_0706_0504_0302_0180;
+
+        // i8x16.extract_lane_s and _u
+        assert_eq!(extract_lane(0x15, 0, v), 0xffff_ff80);
+        assert_eq!(extract_lane(0x16, 0, v), 0x80);
+        // i64x2.extract_lane
+        assert_eq!(extract_lane(0x1d, 1, v), 0x0f0e_0d0c_0b0a_0908);
+
+        // i32x4.replace_lane
+        assert_eq!(
+            replace_lane(0x1c, 3, v, 0xdead_beef),
+            0xdead_beef_0b0a_0908_0706_0504_0302_0180
+        );
+        // i16x8.splat
+        assert_eq!(splat(0x10, 0x1_0203), 0x0203_0203_0203_0203_0203_0203_0203_0203);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = from_i16x8([i16::max_value(), -3, 0, 0, 0, 0, 0, 0]);
+        let b = from_i16x8([1, 5, 0, 0, 0, 0, 0, 0]);
+
+        // i16x8.add wraps, i16x8.add_sat_s doesn't.
+        assert_eq!(
+            binary(0x8e, a, b),
+            from_i16x8([i16::min_value(), 2, 0, 0, 0, 0, 0, 0])
+        );
+        assert_eq!(
+            binary(0x8f, a, b),
+            from_i16x8([i16::max_value(), 2, 0, 0, 0, 0, 0, 0])
+        );
+        // i16x8.lt_s
+        assert_eq!(binary(0x2f, a, b), from_i16x8([0, -1, 0, 0, 0, 0, 0, 0]));
+        // i8x16.shr_s takes the count modulo 8.
+        assert_eq!(replace_lane(0x6c, 0, 0x80, 9), 0xc0);
+    }
+
+    #[test]
+    fn test_floats() {
+        let a = from_f32x4([-0.0, 1.0, std::f32::NAN, -2.5]);
+        let b = from_f32x4([0.0, 2.0, 1.0, 7.0]);
+
+        // f32x4.min
+        let min = binary(0xe8, a, b);
+        assert_eq!(extract_lane(0x1f, 0, min), (-0.0f32).to_bits().into());
+        assert_eq!(extract_lane(0x1f, 1, min), 1.0f32.to_bits().into());
+        assert!(f32::from_bits(extract_lane(0x1f, 2, min) as u32).is_nan());
+        // f32x4.abs
+        assert_eq!(unary(0xe0, a) >> 96, u128::from(2.5f32.to_bits()));
+        // i32x4.trunc_sat_f32x4_s turns NaN into 0.
+        assert_eq!(extract_lane(0x1b, 2, unary(0xf8, a)), 0);
+        // f32x4.nearest rounds halfway cases to even.
+        assert_eq!(
+            unary(0x6a, from_f32x4([0.5, 1.5, 2.5, -2.5])),
+            from_f32x4([0.0, 2.0, 2.0, -2.0])
+        );
+        // f32x4.demote_f64x2_zero
+        assert_eq!(
+            unary(0x5e, from_f64x2([1.5, -3.0])),
+            from_f32x4([1.5, -3.0, 0.0, 0.0])
+        );
+    }
+
+    #[test]
+    fn test_widening() {
+        let a = from_i16x8([1, 2, 3, 4, -5, 6, i16::min_value(), i16::min_value()]);
+        let b = from_i16x8([7, 8, 9, 10, 11, 12, i16::min_value(), i16::min_value()]);
+
+        // i32x4.dot_i16x8_s wraps when both products are 2^30.
+        assert_eq!(
+            binary(0xba, a, b),
+            from_i32x4([23, 67, 17, i32::min_value()])
+        );
+        // i32x4.extmul_high_i16x8_s
+        assert_eq!(binary(0xbd, a, b), from_i32x4([-55, 72, 1 << 30, 1 << 30]));
+        // i32x4.extadd_pairwise_i16x8_s
+        assert_eq!(unary(0x7e, a), from_i32x4([3, 7, 1, -(1 << 16)]));
+        // i16x8.q15mulr_sat_s saturates -1 times -1.
+        assert_eq!(extract_lane(0x18, 7, binary(0x82, a, b)), 0x7fff);
+
+        // i8x16.bitmask and i8x16.popcnt
+        let v = from_i8x16([-1, 0, 3, -128, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 7]);
+        assert_eq!(extract_lane(0x64, 0, v), 0b1001);
+        assert_eq!(
+            unary(0x62, v),
+            from_i8x16([8, 0, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3])
+        );
+    }
+}
@@ -0,0 +1,445 @@
+//! Snapshots of the state of an instance, which the same module
+//! can be instantiated from later, in this process or in another one.
+//!
+//! A [`Snapshot`] holds the memories, mutable globals and tables that
+//! an instance defines itself. The ones it imports belong to the host,
+//! which has to provide them again when instantiating from the snapshot.
+
+use crate::{
+    backing::{anyfunc, FuncRefs, ImportBacking, LocalBacking},
+    cache::WasmHash,
+    error::SnapshotError,
+    module::ModuleInner,
+    structures::{Map, TypedIndex},
+    table::Table,
+    types::{
+        AnyRef, DataIndex, ElemIndex, ElementType, FuncIndex, LocalGlobalIndex, LocalMemoryIndex,
+        LocalTableIndex,
+    },
+    units::{Bytes, Pages},
+    vm,
+};
+use hashbrown::{HashMap, HashSet};
+use std::{cell::Cell, convert::TryFrom};
+
+const CURRENT_SNAPSHOT_VERSION: u64 = 1;
+static WASMER_SNAPSHOT_MAGIC: [u8; 8] = *b"WASMER\0S";
+const HEADER_LEN: usize = 16;
+
+/// A reference to a function of the module, by its index,
+/// or `None` for a null reference.
+type FuncRef = Option<FuncIndex>;
+
+#[derive(Serialize, Deserialize)]
+struct MemoryContents(#[serde(with = "serde_bytes")] Vec<u8>);
+
+#[derive(Serialize, Deserialize)]
+enum GlobalValue {
+    /// The bits of a number.
+    Bits(u64),
+    Ref(FuncRef),
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotInner {
+    wasm_hash: WasmHash,
+    memories: Map<LocalMemoryIndex, MemoryContents>,
+    tables: Map<LocalTableIndex, Vec<FuncRef>>,
+    /// Only the mutable globals, since the others
+    /// are the same in every instance.
+    globals: HashMap<LocalGlobalIndex, GlobalValue>,
+    dropped_data: HashSet<DataIndex>,
+    dropped_elements: HashSet<ElemIndex>,
+}
+
+/// The state of an [`Instance`], taken with [`Instance::snapshot`].
+///
+/// Instantiating the module with [`Module::instantiate_from_snapshot`]
+/// restores that state, instead of running the data and element
+/// segment initializers and the start function.
+///
+/// A snapshot can only hold references to functions of the instance
+/// or its imports, so taking one fails when a table or global holds
+/// a host reference, or a function of another instance.
+///
+/// [`Instance`]: ../struct.Instance.html
+/// [`Instance::snapshot`]: ../struct.Instance.html#method.snapshot
+/// [`Module::instantiate_from_snapshot`]: ../struct.Module.html#method.instantiate_from_snapshot
+pub struct Snapshot {
+    inner: SnapshotInner,
+}
+
+impl Snapshot {
+    /// The hash of the wasm binary of the module that the
+    /// instance this was taken of was instantiated from.
+    pub fn wasm_hash(&self) -> WasmHash {
+        self.inner.wasm_hash
+    }
+
+    /// Writes the snapshot as the magic bytes `WASMER\0S`, the format
+    /// version as a little endian `u64`, then the snapshot itself.
+    /// Fails with `SnapshotError::SerializeError` if it can't be encoded.
+    pub fn serialize(&self) -> Result<Vec<u8>, SnapshotError> {
+        let mut buffer = Vec::with_capacity(HEADER_LEN);
+        buffer.extend_from_slice(&WASMER_SNAPSHOT_MAGIC);
+        buffer.extend_from_slice(&CURRENT_SNAPSHOT_VERSION.to_le_bytes());
+
+        serde_bench::serialize(&mut buffer, &self.inner)
+            .map_err(|e| SnapshotError::SerializeError(e.to_string()))?;
+
+        Ok(buffer)
+    }
+
+    /// Reads a snapshot written by [`serialize`]. Fails with `SnapshotError::InvalidFile`
+    /// if `bytes` don't start with the magic bytes, `UnsupportedVersion` if they're in
+    /// another version of the format, and `DeserializeError` if the snapshot is corrupt.
+    ///
+    /// [`serialize`]: #method.serialize
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        if bytes.len() < HEADER_LEN || bytes[..8] != WASMER_SNAPSHOT_MAGIC {
+            return Err(SnapshotError::InvalidFile);
+        }
+
+        let mut version = [0u8; 8];
+        version.copy_from_slice(&bytes[8..HEADER_LEN]);
+        let version = u64::from_le_bytes(version);
+        if version != CURRENT_SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let inner = serde_bench::deserialize(&bytes[HEADER_LEN..])
+            .map_err(|e| SnapshotError::DeserializeError(format!("{:#?}", e)))?;
+
+        Ok(Snapshot { inner })
+    }
+
+    pub(crate) fn take(
+        module: &ModuleInner,
+        imports: &ImportBacking,
+        backing: &LocalBacking,
+        vmctx: *mut vm::Ctx,
+    ) -> Result<Self, SnapshotError> {
+        let funcs = FuncIndexes::new(module, imports, &backing.func_refs, vmctx);
+
+        let memories = backing
+            .memories
+            .iter()
+            .map(|(_, memory)| MemoryContents(memory.view().iter().map(Cell::get).collect()))
+            .collect();
+
+        let mut tables = Map::with_capacity(backing.tables.len());
+        for (local_table_index, table) in backing.tables.iter() {
+            let unsupported = |index: usize| SnapshotError::UnsupportedTableElement {
+                table: local_table_index.convert_up(&module.info),
+                index: index as u32,
+            };
+
+            let elements = match table.descriptor().element {
+                ElementType::Anyfunc => table.anyfunc_direct_access_mut(|elements| {
+                    elements
+                        .iter()
+                        .enumerate()
+                        .map(|(i, element)| funcs.anyfunc(element).ok_or_else(|| unsupported(i)))
+                        .collect::<Result<Vec<_>, _>>()
+                }),
+                ElementType::AnyRef => table.anyref_direct_access_mut(|elements| {
+                    elements
+                        .iter()
+                        .enumerate()
+                        .map(|(i, element)| {
+                            funcs
+                                .func_ref(element.to_bits())
+                                .ok_or_else(|| unsupported(i))
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                }),
+            }?;
+            tables.push(elements);
+        }
+
+        let mut globals = HashMap::new();
+        for (local_global_index, global_init) in module.info.globals.iter() {
+            if !global_init.desc.mutable {
+                continue;
+            }
+
+            let data = unsafe { (*backing.vm_globals[local_global_index]).data };
+            let value = if global_init.desc.ty.is_reference() {
+                GlobalValue::Ref(funcs.func_ref(data).ok_or_else(|| {
+                    SnapshotError::UnsupportedGlobal {
+                        global: local_global_index.convert_up(&module.info),
+                    }
+                })?)
+            } else {
+                GlobalValue::Bits(data)
+            };
+            globals.insert(local_global_index, value);
+        }
+
+        Ok(Snapshot {
+            inner: SnapshotInner {
+                wasm_hash: module.info.wasm_hash,
+                memories,
+                tables,
+                globals,
+                dropped_data: backing.dropped_data.clone(),
+                dropped_elements: backing.dropped_elements.clone(),
+            },
+        })
+    }
+
+    /// Checks that this can be restored into an instance of `module`,
+    /// before anything is allocated.
+    pub(crate) fn check(&self, module: &ModuleInner) -> Result<(), SnapshotError> {
+        let info = &module.info;
+        let inner = &self.inner;
+
+        if inner.wasm_hash != info.wasm_hash {
+            return Err(SnapshotError::WrongModule);
+        }
+
+        if inner.memories.len() != info.memories.len() || inner.tables.len() != info.tables.len() {
+            return Err(SnapshotError::Incompatible(
+                "the number of memories or tables is different".to_string(),
+            ));
+        }
+
+        for (local_memory_index, desc) in &info.memories {
+            let len = inner.memories[local_memory_index].0.len();
+            let pages = Pages::from(Bytes(len));
+            let fits = pages.bytes().0 == len
+                && pages >= desc.minimum
+                && desc.maximum.iter().all(|&maximum| pages <= maximum);
+            if !fits {
+                return Err(SnapshotError::Incompatible(format!(
+                    "memory {} can't be {} bytes",
+                    local_memory_index.convert_up(info).index(),
+                    len
+                )));
+            }
+        }
+
+        let func_count = info.func_assoc.len();
+        let valid_func_ref = |func_ref: &FuncRef| func_ref.iter().all(|f| f.index() < func_count);
+
+        for (local_table_index, desc) in &info.tables {
+            let elements = &inner.tables[local_table_index];
+            let fits = match u32::try_from(elements.len()) {
+                Ok(len) => {
+                    len >= desc.minimum && desc.maximum.iter().all(|&maximum| len <= maximum)
+                }
+                Err(_) => false,
+            };
+            if !fits || !elements.iter().all(&valid_func_ref) {
+                return Err(SnapshotError::Incompatible(format!(
+                    "table {} doesn't fit",
+                    local_table_index.convert_up(info).index()
+                )));
+            }
+        }
+
+        for (local_global_index, value) in &inner.globals {
+            let global_init = match info.globals.get(*local_global_index) {
+                Some(global_init) if global_init.desc.mutable => global_init,
+                _ => {
+                    return Err(SnapshotError::Incompatible(format!(
+                        "global {} isn't mutable",
+                        local_global_index.convert_up(info).index()
+                    )))
+                }
+            };
+            let fits = match value {
+                GlobalValue::Bits(_) => !global_init.desc.ty.is_reference(),
+                GlobalValue::Ref(func_ref) => {
+                    global_init.desc.ty.is_reference() && valid_func_ref(func_ref)
+                }
+            };
+            if !fits {
+                return Err(SnapshotError::Incompatible(format!(
+                    "global {} doesn't fit",
+                    local_global_index.convert_up(info).index()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The size of the memory at `local_memory_index`.
+    pub(crate) fn memory_pages(&self, local_memory_index: LocalMemoryIndex) -> Pages {
+        Pages::from(Bytes(self.inner.memories[local_memory_index].0.len()))
+    }
+
+    /// The size of the table at `local_table_index`.
+    pub(crate) fn table_elements(&self, local_table_index: LocalTableIndex) -> u32 {
+        self.inner.tables[local_table_index].len() as u32
+    }
+
+    /// Restores the snapshot into `backing`, which must have been
+    /// created for `module` without running any initializers.
+    pub(crate) fn restore(
+        &self,
+        module: &ModuleInner,
+        imports: &ImportBacking,
+        backing: &mut LocalBacking,
+        vmctx: *mut vm::Ctx,
+    ) {
+        for (local_memory_index, contents) in self.inner.memories.iter() {
+            let memory = &backing.memories[local_memory_index];
+            let delta = self.memory_pages(local_memory_index).0 - memory.size().0;
+            memory
+                .grow(Pages(delta))
+                .expect("unable to grow memory to the size in the snapshot");
+
+            for (mem_byte, &byte) in memory.view().iter().zip(contents.0.iter()) {
+                mem_byte.set(byte);
+            }
+        }
+
+        for (local_table_index, elements) in self.inner.tables.iter() {
+            let table: &Table = &backing.tables[local_table_index];
+            table
+                .grow(elements.len() as u32 - table.size())
+                .expect("unable to grow table to the size in the snapshot");
+
+            match table.descriptor().element {
+                ElementType::Anyfunc => table.anyfunc_direct_access_mut(|table_elements| {
+                    for (table_element, func_ref) in table_elements.iter_mut().zip(elements) {
+                        *table_element = match *func_ref {
+                            Some(func_index) => anyfunc(module, imports, vmctx, func_index),
+                            None => vm::Anyfunc::null(),
+                        };
+                    }
+                }),
+                ElementType::AnyRef => {
+                    let func_refs = &mut backing.func_refs;
+                    table.anyref_direct_access_mut(|table_elements| {
+                        for (table_element, func_ref) in table_elements.iter_mut().zip(elements) {
+                            let bits =
+                                restore_func_ref(module, imports, func_refs, vmctx, *func_ref);
+                            *table_element = unsafe { AnyRef::from_bits(bits) };
+                        }
+                    })
+                }
+            }
+        }
+
+        for (&local_global_index, value) in &self.inner.globals {
+            let data = match *value {
+                GlobalValue::Bits(bits) => bits,
+                GlobalValue::Ref(func_ref) => {
+                    restore_func_ref(module, imports, &mut backing.func_refs, vmctx, func_ref)
+                }
+            };
+            unsafe { (*backing.vm_globals[local_global_index]).data = data };
+        }
+
+        backing.dropped_data = self.inner.dropped_data.clone();
+        backing.dropped_elements = self.inner.dropped_elements.clone();
+    }
+}
+
+/// The funcref value that refers to `func_ref` in this instance.
+fn restore_func_ref(
+    module: &ModuleInner,
+    imports: &ImportBacking,
+    func_refs: &mut FuncRefs,
+    vmctx: *mut vm::Ctx,
+    func_ref: FuncRef,
+) -> u64 {
+    match func_ref {
+        Some(func_index) => func_refs.get(anyfunc(module, imports, vmctx, func_index)),
+        None => 0,
+    }
+}
+
+/// Maps the table elements and funcref values that refer to the
+/// functions of an instance back to the indices of those functions.
+struct FuncIndexes {
+    anyfuncs: HashMap<(usize, usize, u32), FuncIndex>,
+    func_refs: HashMap<u64, FuncIndex>,
+}
+
+impl FuncIndexes {
+    fn new(
+        module: &ModuleInner,
+        imports: &ImportBacking,
+        func_refs: &FuncRefs,
+        vmctx: *mut vm::Ctx,
+    ) -> Self {
+        let anyfuncs: HashMap<_, _> = (0..module.info.func_assoc.len())
+            .map(FuncIndex::new)
+            .map(|func_index| {
+                let key = FuncRefs::key(&anyfunc(module, imports, vmctx, func_index));
+                (key, func_index)
+            })
+            .collect();
+
+        let func_refs = func_refs
+            .iter()
+            .filter_map(|(func_ref, element)| {
+                anyfuncs
+                    .get(&FuncRefs::key(element))
+                    .map(|&func_index| (func_ref, func_index))
+            })
+            .collect();
+
+        Self {
+            anyfuncs,
+            func_refs,
+        }
+    }
+
+    /// Returns `None` if `element` refers to a function
+    /// that isn't one of the instance's.
+    fn anyfunc(&self, element: &vm::Anyfunc) -> Option<FuncRef> {
+        if element.func.is_null() {
+            Some(None)
+        } else {
+            self.anyfuncs
+                .get(&FuncRefs::key(element))
+                .cloned()
+                .map(Some)
+        }
+    }
+
+    /// Returns `None` if `func_ref` isn't a funcref value
+    /// that refers to one of the instance's functions.
+    fn func_ref(&self, func_ref: u64) -> Option<FuncRef> {
+        if func_ref == 0 {
+            Some(None)
+        } else {
+            self.func_refs.get(&func_ref).cloned().map(Some)
+        }
+    }
+}
+
+#[cfg(test)]
+mod snapshot_tests {
+    use super::{Snapshot, CURRENT_SNAPSHOT_VERSION, WASMER_SNAPSHOT_MAGIC};
+    use crate::error::SnapshotError;
+
+    #[test]
+    fn test_deserialize_checks_header() {
+        match Snapshot::deserialize(b"\0asm\x01\0\0\0") {
+            Err(SnapshotError::InvalidFile) => {}
+            _ => panic!("a wasm header isn't a snapshot"),
+        }
+
+        let mut bytes = WASMER_SNAPSHOT_MAGIC.to_vec();
+        bytes.extend_from_slice(&(CURRENT_SNAPSHOT_VERSION + 1).to_le_bytes());
+        match Snapshot::deserialize(&bytes) {
+            Err(SnapshotError::UnsupportedVersion(version)) => {
+                assert_eq!(version, CURRENT_SNAPSHOT_VERSION + 1)
+            }
+            _ => panic!("the version should be checked"),
+        }
+
+        bytes.truncate(8);
+        bytes.extend_from_slice(&CURRENT_SNAPSHOT_VERSION.to_le_bytes());
+        match Snapshot::deserialize(&bytes) {
+            Err(SnapshotError::DeserializeError(_)) => {}
+            _ => panic!("a snapshot needs a body"),
+        }
+    }
+}
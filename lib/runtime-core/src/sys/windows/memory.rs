@@ -146,10 +146,17 @@ impl Memory {
     }
 
     pub unsafe fn as_slice(&self) -> &[u8] {
+        // An empty mapping has a null pointer, which no slice may have.
+        if self.size == 0 {
+            return &[];
+        }
         slice::from_raw_parts(self.ptr, self.size)
     }
 
     pub unsafe fn as_slice_mut(&mut self) -> &mut [u8] {
+        if self.size == 0 {
+            return &mut [];
+        }
         slice::from_raw_parts_mut(self.ptr, self.size)
     }
 
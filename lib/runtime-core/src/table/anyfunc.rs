@@ -3,7 +3,7 @@ use crate::{
     instance::DynFunc,
     sig_registry::SigRegistry,
     structures::TypedIndex,
-    types::{FuncSig, TableDescriptor},
+    types::{FuncSig, SigIndex, TableDescriptor},
     vm,
 };
 
@@ -15,6 +15,8 @@ enum AnyfuncInner<'a> {
         signature: Arc<FuncSig>,
     },
     Managed(DynFunc<'a>),
+    /// An element read out of a table.
+    Raw(vm::Anyfunc),
 }
 
 pub struct Anyfunc<'a> {
@@ -33,6 +35,36 @@ impl<'a> Anyfunc<'a> {
             },
         }
     }
+
+    /// The null function reference, which traps when called.
+    pub fn null() -> Self {
+        Self {
+            inner: AnyfuncInner::Raw(vm::Anyfunc::null()),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        match &self.inner {
+            AnyfuncInner::Host { ptr, .. } => ptr.is_null(),
+            AnyfuncInner::Managed(_) => false,
+            AnyfuncInner::Raw(anyfunc) => anyfunc.func.is_null(),
+        }
+    }
+
+    /// The signature of the function, or `None` if this is null.
+    pub fn signature(&self) -> Option<Arc<FuncSig>> {
+        if self.is_null() {
+            return None;
+        }
+
+        Some(match &self.inner {
+            AnyfuncInner::Host { signature, .. } => Arc::clone(signature),
+            AnyfuncInner::Managed(func) => Arc::clone(&func.signature),
+            AnyfuncInner::Raw(anyfunc) => {
+                SigRegistry.lookup_signature(SigIndex::new(anyfunc.sig_id.0 as usize))
+            }
+        })
+    }
 }
 
 impl<'a> From<DynFunc<'a>> for Anyfunc<'a> {
@@ -96,6 +128,12 @@ impl AnyfuncTable {
         Some(starting_len)
     }
 
+    pub fn get(&self, index: u32) -> Option<Anyfunc<'static>> {
+        self.backing.get(index as usize).map(|&anyfunc| Anyfunc {
+            inner: AnyfuncInner::Raw(anyfunc),
+        })
+    }
+
     pub fn set(&mut self, index: u32, element: Anyfunc) -> Result<(), ()> {
         if let Some(slot) = self.backing.get_mut(index as usize) {
             let anyfunc = match element.inner {
@@ -119,6 +157,7 @@ impl AnyfuncTable {
                         sig_id,
                     }
                 }
+                AnyfuncInner::Raw(anyfunc) => anyfunc,
             };
 
             *slot = anyfunc;
@@ -0,0 +1,69 @@
+use crate::{error::CreationError, types::AnyRef, types::TableDescriptor, vm};
+
+pub struct AnyRefTable {
+    backing: Vec<AnyRef>,
+    max: Option<u32>,
+}
+
+impl AnyRefTable {
+    pub fn new(
+        desc: TableDescriptor,
+        local: &mut vm::LocalTable,
+    ) -> Result<Box<Self>, CreationError> {
+        let initial_table_backing_len = desc.minimum as usize;
+
+        let mut storage = Box::new(AnyRefTable {
+            backing: vec![AnyRef::null(); initial_table_backing_len],
+            max: desc.maximum,
+        });
+
+        let storage_ptr: *mut AnyRefTable = &mut *storage;
+
+        local.base = storage.backing.as_mut_ptr() as *mut u8;
+        local.count = storage.backing.len();
+        local.table = storage_ptr as *mut ();
+
+        Ok(storage)
+    }
+
+    pub fn current_size(&self) -> u32 {
+        self.backing.len() as u32
+    }
+
+    pub fn internal_buffer(&mut self) -> &mut [AnyRef] {
+        &mut self.backing
+    }
+
+    pub fn grow(&mut self, delta: u32, local: &mut vm::LocalTable) -> Option<u32> {
+        let starting_len = self.backing.len() as u32;
+
+        let new_len = starting_len.checked_add(delta)?;
+
+        if let Some(max) = self.max {
+            if new_len > max {
+                return None;
+            }
+        }
+
+        self.backing.resize(new_len as usize, AnyRef::null());
+
+        local.base = self.backing.as_mut_ptr() as *mut u8;
+        local.count = self.backing.len();
+
+        Some(starting_len)
+    }
+
+    pub fn get(&self, index: u32) -> Option<AnyRef> {
+        self.backing.get(index as usize).cloned()
+    }
+
+    pub fn set(&mut self, index: u32, element: AnyRef) -> Result<(), ()> {
+        match self.backing.get_mut(index as usize) {
+            Some(slot) => {
+                *slot = element;
+                Ok(())
+            }
+            None => Err(()),
+        }
+    }
+}
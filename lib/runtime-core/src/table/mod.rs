@@ -2,25 +2,29 @@ use crate::{
     error::CreationError,
     export::Export,
     import::IsExport,
-    types::{ElementType, TableDescriptor},
+    types::{AnyRef, ElementType, TableDescriptor},
     vm,
 };
 use std::{cell::RefCell, fmt, ptr, rc::Rc};
 
 mod anyfunc;
+mod anyref;
 
 pub use self::anyfunc::Anyfunc;
 use self::anyfunc::AnyfuncTable;
+use self::anyref::AnyRefTable;
 use crate::error::GrowError;
 
 pub enum Element<'a> {
     Anyfunc(Anyfunc<'a>),
+    AnyRef(AnyRef),
 }
 
 // #[derive(Debug)]
 pub enum TableStorage {
     /// This is intended to be a caller-checked Anyfunc.
     Anyfunc(Box<AnyfuncTable>),
+    AnyRef(Box<AnyRefTable>),
 }
 
 pub struct Table {
@@ -67,6 +71,7 @@ impl Table {
 
         let storage = match desc.element {
             ElementType::Anyfunc => TableStorage::Anyfunc(AnyfuncTable::new(desc, &mut local)?),
+            ElementType::AnyRef => TableStorage::AnyRef(AnyRefTable::new(desc, &mut local)?),
         };
 
         Ok(Self {
@@ -80,15 +85,53 @@ impl Table {
         self.desc
     }
 
+    /// Get the element at index, or `None` if it is out of bounds.
+    ///
+    /// Usage:
+    ///
+    /// ```
+    /// # use wasmer_runtime_core::types::{TableDescriptor, ElementType};
+    /// # use wasmer_runtime_core::table::{Element, Table};
+    /// # use wasmer_runtime_core::error::Result;
+    /// # fn get_element() -> Result<()> {
+    /// let table = Table::new(TableDescriptor {
+    ///     element: ElementType::Anyfunc,
+    ///     minimum: 1,
+    ///     maximum: None,
+    /// })?;
+    ///
+    /// match table.get(0) {
+    ///     Some(Element::Anyfunc(anyfunc)) => assert!(anyfunc.is_null()),
+    ///     _ => unreachable!(),
+    /// }
+    /// assert!(table.get(1).is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get(&self, index: u32) -> Option<Element<'_>> {
+        match &*self.storage.borrow() {
+            (TableStorage::Anyfunc(ref anyfunc_table), _) => {
+                anyfunc_table.get(index).map(Element::Anyfunc)
+            }
+            (TableStorage::AnyRef(ref anyref_table), _) => {
+                anyref_table.get(index).map(Element::AnyRef)
+            }
+        }
+    }
+
     /// Set the element at index.
+    ///
+    /// Fails if the index is out of bounds, or if the
+    /// element doesn't have the table's element type.
     pub fn set(&self, index: u32, element: Element) -> Result<(), ()> {
-        match &mut *self.storage.borrow_mut() {
-            (TableStorage::Anyfunc(ref mut anyfunc_table), _) => {
-                match element {
-                    Element::Anyfunc(anyfunc) => anyfunc_table.set(index, anyfunc),
-                    // _ => panic!("wrong element type for anyfunc table"),
-                }
+        match (&mut *self.storage.borrow_mut(), element) {
+            ((TableStorage::Anyfunc(ref mut anyfunc_table), _), Element::Anyfunc(anyfunc)) => {
+                anyfunc_table.set(index, anyfunc)
             }
+            ((TableStorage::AnyRef(ref mut anyref_table), _), Element::AnyRef(anyref)) => {
+                anyref_table.set(index, anyref)
+            }
+            _ => Err(()),
         }
     }
 
@@ -98,6 +141,17 @@ impl Table {
     {
         match &mut *self.storage.borrow_mut() {
             (TableStorage::Anyfunc(ref mut anyfunc_table), _) => f(anyfunc_table.internal_buffer()),
+            (TableStorage::AnyRef(_), _) => panic!("not an anyfunc table"),
+        }
+    }
+
+    pub(crate) fn anyref_direct_access_mut<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut [AnyRef]) -> R,
+    {
+        match &mut *self.storage.borrow_mut() {
+            (TableStorage::AnyRef(ref mut anyref_table), _) => f(anyref_table.internal_buffer()),
+            (TableStorage::Anyfunc(_), _) => panic!("not an anyref table"),
         }
     }
 
@@ -105,6 +159,7 @@ impl Table {
     pub fn size(&self) -> u32 {
         match &*self.storage.borrow() {
             (TableStorage::Anyfunc(ref anyfunc_table), _) => anyfunc_table.current_size(),
+            (TableStorage::AnyRef(ref anyref_table), _) => anyref_table.current_size(),
         }
     }
 
@@ -118,6 +173,9 @@ impl Table {
             (TableStorage::Anyfunc(ref mut anyfunc_table), ref mut local) => anyfunc_table
                 .grow(delta, local)
                 .ok_or(GrowError::TableGrowError),
+            (TableStorage::AnyRef(ref mut anyref_table), ref mut local) => anyref_table
+                .grow(delta, local)
+                .ok_or(GrowError::TableGrowError),
         }
     }
 
@@ -153,7 +211,7 @@ impl fmt::Debug for Table {
 #[cfg(test)]
 mod table_tests {
 
-    use super::{ElementType, Table, TableDescriptor};
+    use super::{AnyRef, Anyfunc, Element, ElementType, Table, TableDescriptor};
 
     #[test]
     fn test_initial_table_size() {
@@ -166,4 +224,30 @@ mod table_tests {
         assert_eq!(table.size(), 10);
     }
 
+    #[test]
+    fn test_anyref_table() {
+        let table = Table::new(TableDescriptor {
+            element: ElementType::AnyRef,
+            minimum: 1,
+            maximum: Some(2),
+        })
+        .unwrap();
+
+        let value = 42;
+        let anyref = unsafe { AnyRef::new(&value) };
+        table.set(0, Element::AnyRef(anyref)).unwrap();
+        assert!(table.set(0, Element::Anyfunc(Anyfunc::null())).is_err());
+
+        assert_eq!(table.grow(1).unwrap(), 1);
+        match (table.get(0), table.get(1)) {
+            (Some(Element::AnyRef(first)), Some(Element::AnyRef(second))) => {
+                assert_eq!(unsafe { *first.as_ptr::<i32>() }, 42);
+                assert!(second.is_null());
+            }
+            _ => panic!("expected two references"),
+        }
+        assert!(table.get(2).is_none());
+        assert!(table.grow(1).is_err());
+    }
+
 }
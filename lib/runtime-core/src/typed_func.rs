@@ -1,10 +1,10 @@
 use crate::{
     backend::UserTrapper,
-    error::RuntimeError,
-    export::{Context, Export, FuncPointer},
+    error::{ExitCode, RuntimeError},
+    export::{Context, Export, FuncEnv, FuncPointer},
     import::IsExport,
-    types::{FuncSig, Type, WasmExternType},
-    vm::Ctx,
+    types::{AnyRef, FuncRef, FuncSig, Type, Value, WasmExternType},
+    vm::{Ctx, FuncCtx},
 };
 use std::{cell::UnsafeCell, fmt, marker::PhantomData, mem, panic, ptr, sync::Arc};
 
@@ -18,10 +18,23 @@ pub struct Unsafe;
 impl Safeness for Safe {}
 impl Safeness for Unsafe {}
 
+/// A list of values that functions take or return.
+///
+/// Functions that return more than one value don't return them as a
+/// `CStruct`. Instead, they take a pointer to a buffer for them as their
+/// last argument, with a `u64` for each value.
+///
+/// There's no `WasmExternType` for `v128`, since no calling convention
+/// passes one the way compiled code does. Functions with `v128`s in their
+/// signature can only be called with `Value`s, or imported as a `DynamicFunc`.
 pub trait WasmTypeList {
     type CStruct;
     fn from_c_struct(c_struct: Self::CStruct) -> Self;
     fn into_c_struct(self) -> Self::CStruct;
+    /// Reads the values from a buffer with a `u64` for each.
+    unsafe fn from_buffer(buffer: *const u64) -> Self;
+    /// Writes the values to a buffer with a `u64` for each.
+    unsafe fn into_buffer(self, buffer: *mut u64);
     fn types() -> &'static [Type];
     unsafe fn call<Rets>(self, f: *const (), ctx: *mut Ctx) -> Rets
     where
@@ -33,21 +46,23 @@ where
     Args: WasmTypeList,
     Rets: WasmTypeList,
 {
-    fn to_raw(&self) -> *const ();
+    /// Returns the function to call, along with the state
+    /// it captured, if it captured any.
+    fn to_raw(self) -> (*const (), Option<FuncEnv>);
 }
 
 pub trait TrapEarly<Rets>
 where
     Rets: WasmTypeList,
 {
-    fn report(self) -> Result<Rets, String>;
+    fn report(self) -> Result<Rets, RuntimeError>;
 }
 
 impl<Rets> TrapEarly<Rets> for Rets
 where
     Rets: WasmTypeList,
 {
-    fn report(self) -> Result<Rets, String> {
+    fn report(self) -> Result<Rets, RuntimeError> {
         Ok(self)
     }
 }
@@ -57,11 +72,56 @@ where
     Rets: WasmTypeList,
     E: fmt::Debug,
 {
-    fn report(self) -> Result<Rets, String> {
-        self.map_err(|err| format!("Error: {:?}", err))
+    fn report(self) -> Result<Rets, RuntimeError> {
+        self.map_err(|err| RuntimeError::User {
+            msg: format!("Error: {:?}", err),
+        })
     }
 }
 
+impl<Rets> TrapEarly<Rets> for Result<Rets, ExitCode>
+where
+    Rets: WasmTypeList,
+{
+    fn report(self) -> Result<Rets, RuntimeError> {
+        self.map_err(|ExitCode(code)| RuntimeError::Exit { code })
+    }
+}
+
+/// Runs a host function, trapping if it returned an error or panicked.
+fn call_host_function<T>(f: impl FnOnce() -> Result<T, RuntimeError>) -> T {
+    let error = match panic::catch_unwind(panic::AssertUnwindSafe(f)) {
+        Ok(Ok(returns)) => return returns,
+        Ok(Err(error)) => error,
+        Err(err) => {
+            let msg = if let Some(s) = err.downcast_ref::<&str>() {
+                s.to_string()
+            } else if let Some(s) = err.downcast_ref::<String>() {
+                s.clone()
+            } else {
+                "a panic occurred, but no additional information is available".to_string()
+            };
+            RuntimeError::User { msg }
+        }
+    };
+
+    unsafe {
+        if let Some(early_trapper) = &*EARLY_TRAPPER.with(|ucell| ucell.get()) {
+            early_trapper.do_early_trap(error)
+        } else {
+            eprintln!("panic handling not setup");
+            std::process::exit(1)
+        }
+    }
+}
+
+/// The number of `u64`s that values of `types` take up in a buffer.
+///
+/// Functions that return more than one take a buffer for them.
+fn returns_buffer_len(types: &[Type]) -> usize {
+    types.iter().map(|ty| ty.buffer_len()).sum()
+}
+
 // pub fn Func<'a, Args, Rets, F>(f: F) -> Func<'a, Args, Rets, Unsafe>
 // where
 //     Args: WasmTypeList,
@@ -74,6 +134,7 @@ where
 pub struct Func<'a, Args = (), Rets = (), Safety: Safeness = Safe> {
     f: *const (),
     ctx: *mut Ctx,
+    env: Option<FuncEnv>,
     _phantom: PhantomData<(&'a (), Safety, Args, Rets)>,
 }
 
@@ -86,6 +147,7 @@ where
         Func {
             f,
             ctx,
+            env: None,
             _phantom: PhantomData,
         }
     }
@@ -96,13 +158,16 @@ where
     Args: WasmTypeList,
     Rets: WasmTypeList,
 {
+    /// Wraps a host function, which can be a closure that captures state.
     pub fn new<F>(f: F) -> Func<'a, Args, Rets, Unsafe>
     where
         F: ExternalFunction<Args, Rets>,
     {
+        let (f, env) = f.to_raw();
         Func {
-            f: f.to_raw(),
+            f,
             ctx: ptr::null_mut(),
+            env,
             _phantom: PhantomData,
         }
     }
@@ -133,14 +198,28 @@ impl<A: WasmExternType> WasmTypeList for (A,) {
         let (a,) = self;
         S1(a)
     }
+    unsafe fn from_buffer(buffer: *const u64) -> Self {
+        (ptr::read_unaligned(buffer as *const A),)
+    }
+    unsafe fn into_buffer(self, buffer: *mut u64) {
+        let (a,) = self;
+        ptr::write_unaligned(buffer as *mut A, a);
+    }
     fn types() -> &'static [Type] {
         &[A::TYPE]
     }
     #[allow(non_snake_case)]
     unsafe fn call<Rets: WasmTypeList>(self, f: *const (), ctx: *mut Ctx) -> Rets {
-        let f: extern "C" fn(*mut Ctx, A) -> Rets = mem::transmute(f);
         let (a,) = self;
-        f(ctx, a)
+        if returns_buffer_len(Rets::types()) > 1 {
+            let f: extern "C" fn(*mut Ctx, A, *mut u64) = mem::transmute(f);
+            let mut returns = vec![0; returns_buffer_len(Rets::types())];
+            f(ctx, a, returns.as_mut_ptr());
+            Rets::from_buffer(returns.as_ptr())
+        } else {
+            let f: extern "C" fn(*mut Ctx, A) -> Rets::CStruct = mem::transmute(f);
+            Rets::from_c_struct(f(ctx, a))
+        }
     }
 }
 
@@ -170,54 +249,83 @@ macro_rules! impl_traits {
                 let ( $( $x ),* ) = self;
                 $struct_name ( $( $x ),* )
             }
+            #[allow(unused_parens, unused_mut, unused_variables, unused_assignments)]
+            unsafe fn from_buffer(buffer: *const u64) -> Self {
+                let mut index = 0;
+                ( $( {
+                    let value = ptr::read_unaligned(buffer.add(index) as *const $x);
+                    index += $x::TYPE.buffer_len();
+                    value
+                } ),* )
+            }
+            #[allow(unused_parens, non_snake_case, unused_mut, unused_variables, unused_assignments)]
+            unsafe fn into_buffer(self, buffer: *mut u64) {
+                let ( $( $x ),* ) = self;
+                let mut index = 0;
+                $(
+                    ptr::write_unaligned(buffer.add(index) as *mut $x, $x);
+                    index += $x::TYPE.buffer_len();
+                )*
+            }
             fn types() -> &'static [Type] {
                 &[$( $x::TYPE, )*]
             }
             #[allow(non_snake_case)]
             unsafe fn call<Rets: WasmTypeList>(self, f: *const (), ctx: *mut Ctx) -> Rets {
-                let f: extern fn(*mut Ctx $( ,$x )*) -> Rets::CStruct = mem::transmute(f);
                 #[allow(unused_parens)]
                 let ( $( $x ),* ) = self;
-                let c_struct = f(ctx $( ,$x )*);
-                Rets::from_c_struct(c_struct)
+                if returns_buffer_len(Rets::types()) > 1 {
+                    let f: extern "C" fn(*mut Ctx $( ,$x )*, *mut u64) = mem::transmute(f);
+                    let mut returns = vec![0; returns_buffer_len(Rets::types())];
+                    f(ctx $( ,$x )*, returns.as_mut_ptr());
+                    Rets::from_buffer(returns.as_ptr())
+                } else {
+                    let f: extern fn(*mut Ctx $( ,$x )*) -> Rets::CStruct = mem::transmute(f);
+                    let c_struct = f(ctx $( ,$x )*);
+                    Rets::from_c_struct(c_struct)
+                }
             }
         }
 
-        impl< $( $x: WasmExternType, )* Rets: WasmTypeList, Trap: TrapEarly<Rets>, FN: Fn( &mut Ctx $( ,$x )* ) -> Trap> ExternalFunction<($( $x ),*), Rets> for FN {
+        impl< $( $x: WasmExternType, )* Rets: WasmTypeList, Trap: TrapEarly<Rets>, FN: Fn( &mut Ctx $( ,$x )* ) -> Trap + Send + 'static> ExternalFunction<($( $x ),*), Rets> for FN {
             #[allow(non_snake_case)]
-            fn to_raw(&self) -> *const () {
-                assert_eq!(mem::size_of::<Self>(), 0, "you cannot use a closure that captures state for `Func`.");
-
+            fn to_raw(self) -> (*const (), Option<FuncEnv>) {
                 extern fn wrap<$( $x: WasmExternType, )* Rets: WasmTypeList, Trap: TrapEarly<Rets>, FN: Fn( &mut Ctx $( ,$x )* ) -> Trap>( ctx: &mut Ctx $( ,$x: $x )* ) -> Rets::CStruct {
                     let f: FN = unsafe { mem::transmute_copy(&()) };
+                    call_host_function(|| f( ctx $( ,$x )* ).report()).into_c_struct()
+                }
 
-                    let msg = match panic::catch_unwind(panic::AssertUnwindSafe(|| {
-                        f( ctx $( ,$x )* ).report()
-                    })) {
-                        Ok(Ok(returns)) => return returns.into_c_struct(),
-                        Ok(Err(err)) => err,
-                        Err(err) => {
-                            if let Some(s) = err.downcast_ref::<&str>() {
-                                s.to_string()
-                            } else if let Some(s) = err.downcast_ref::<String>() {
-                                s.clone()
-                            } else {
-                                "a panic occurred, but no additional information is available".to_string()
-                            }
-                        },
-                    };
-
-                    unsafe {
-                        if let Some(early_trapper) = &*EARLY_TRAPPER.with(|ucell| ucell.get()) {
-                            early_trapper.do_early_trap(msg)
-                        } else {
-                            eprintln!("panic handling not setup");
-                            std::process::exit(1)
-                        }
-                    }
+                extern fn wrap_env<$( $x: WasmExternType, )* Rets: WasmTypeList, Trap: TrapEarly<Rets>, FN: Fn( &mut Ctx $( ,$x )* ) -> Trap>( func_ctx: &mut FuncCtx $( ,$x: $x )* ) -> Rets::CStruct {
+                    let f = unsafe { &*(func_ctx.func_env as *const FN) };
+                    let ctx = unsafe { &mut *func_ctx.vmctx };
+                    call_host_function(|| f( ctx $( ,$x )* ).report()).into_c_struct()
+                }
+
+                extern "C" fn wrap_multi_value<$( $x: WasmExternType, )* Rets: WasmTypeList, Trap: TrapEarly<Rets>, FN: Fn( &mut Ctx $( ,$x )* ) -> Trap>( ctx: &mut Ctx $( ,$x: $x )*, returns: *mut u64 ) {
+                    let f: FN = unsafe { mem::transmute_copy(&()) };
+                    let rets = call_host_function(|| f( ctx $( ,$x )* ).report());
+                    unsafe { rets.into_buffer(returns) }
+                }
+
+                extern "C" fn wrap_env_multi_value<$( $x: WasmExternType, )* Rets: WasmTypeList, Trap: TrapEarly<Rets>, FN: Fn( &mut Ctx $( ,$x )* ) -> Trap>( func_ctx: &mut FuncCtx $( ,$x: $x )*, returns: *mut u64 ) {
+                    let f = unsafe { &*(func_ctx.func_env as *const FN) };
+                    let ctx = unsafe { &mut *func_ctx.vmctx };
+                    let rets = call_host_function(|| f( ctx $( ,$x )* ).report());
+                    unsafe { rets.into_buffer(returns) }
                 }
 
-                wrap::<$( $x, )* Rets, Trap, Self> as *const ()
+                let multi_value = returns_buffer_len(Rets::types()) > 1;
+                if mem::size_of::<Self>() == 0 {
+                    if multi_value {
+                        (wrap_multi_value::<$( $x, )* Rets, Trap, Self> as *const (), None)
+                    } else {
+                        (wrap::<$( $x, )* Rets, Trap, Self> as *const (), None)
+                    }
+                } else if multi_value {
+                    (wrap_env_multi_value::<$( $x, )* Rets, Trap, Self> as *const (), Some(FuncEnv::new(self)))
+                } else {
+                    (wrap_env::<$( $x, )* Rets, Trap, Self> as *const (), Some(FuncEnv::new(self)))
+                }
             }
         }
 
@@ -256,7 +364,10 @@ where
 {
     fn to_export(&self) -> Export {
         let func = unsafe { FuncPointer::new(self.f as _) };
-        let ctx = Context::Internal;
+        let ctx = match &self.env {
+            Some(env) => Context::Env(env.clone()),
+            None => Context::Internal,
+        };
         let signature = Arc::new(FuncSig::new(Args::types(), Rets::types()));
 
         Export::Function {
@@ -267,6 +378,154 @@ where
     }
 }
 
+/// A host function whose signature is only known at runtime.
+///
+/// This is the import-side counterpart of [`DynFunc`]: the arguments
+/// and return values are passed around as [`Value`]s.
+///
+/// [`DynFunc`]: struct.DynFunc.html
+/// [`Value`]: types/enum.Value.html
+///
+/// # Usage:
+/// ```
+/// # use std::sync::Arc;
+/// # use wasmer_runtime_core::{imports, DynamicFunc, types::{FuncSig, Type, Value}};
+/// let add_one = DynamicFunc::new(
+///     Arc::new(FuncSig::new(vec![Type::I32], vec![Type::I32])),
+///     |_ctx, args| match args {
+///         [Value::I32(x)] => Ok(vec![Value::I32(x + 1)]),
+///         _ => Err("unexpected arguments"),
+///     },
+/// );
+///
+/// let import_object = imports! {
+///     "env" => {
+///         "add_one" => add_one,
+///     },
+/// };
+/// ```
+pub struct DynamicFunc {
+    signature: Arc<FuncSig>,
+    env: FuncEnv,
+}
+
+type DynamicCallback = dyn Fn(&mut Ctx, &[Value]) -> Result<Vec<Value>, String> + Send;
+
+/// The environment of a `DynamicFunc`.
+#[repr(C)]
+struct DynamicEnv {
+    /// This is what the backend's trampoline calls, so it has to come first.
+    call: extern "C" fn(&mut FuncCtx, *const u64, *mut u64),
+    signature: Arc<FuncSig>,
+    callback: Box<DynamicCallback>,
+}
+
+impl DynamicFunc {
+    pub fn new<F, E>(signature: Arc<FuncSig>, f: F) -> Self
+    where
+        F: Fn(&mut Ctx, &[Value]) -> Result<Vec<Value>, E> + Send + 'static,
+        E: fmt::Debug,
+    {
+        let env = DynamicEnv {
+            call: call_dynamic,
+            signature: Arc::clone(&signature),
+            callback: Box::new(move |ctx, args| {
+                f(ctx, args).map_err(|err| format!("Error: {:?}", err))
+            }),
+        };
+
+        DynamicFunc {
+            signature,
+            env: FuncEnv::new(env),
+        }
+    }
+
+    pub fn signature(&self) -> &FuncSig {
+        &self.signature
+    }
+}
+
+/// Converts the arguments a `DynamicFunc` is called with into `Value`s,
+/// calls it, and writes its return values back.
+///
+/// Every argument and return value takes up a `u64`, or two for a `v128`.
+extern "C" fn call_dynamic(func_ctx: &mut FuncCtx, args: *const u64, returns: *mut u64) {
+    let env = unsafe { &*(func_ctx.func_env as *const DynamicEnv) };
+    let ctx = unsafe { &mut *func_ctx.vmctx };
+
+    let mut index = 0;
+    let args: Vec<Value> = env
+        .signature
+        .params()
+        .iter()
+        .map(|ty| {
+            let bits = unsafe { *args.add(index) };
+            index += ty.buffer_len();
+            match ty {
+                Type::I32 => Value::I32(bits as i32),
+                Type::I64 => Value::I64(bits as i64),
+                Type::F32 => Value::F32(f32::from_bits(bits as u32)),
+                Type::F64 => Value::F64(f64::from_bits(bits)),
+                Type::AnyRef => Value::AnyRef(unsafe { AnyRef::from_bits(bits) }),
+                Type::FuncRef => Value::FuncRef(unsafe { FuncRef::from_bits(bits) }),
+                Type::V128 => {
+                    let high = unsafe { *args.add(index - 1) };
+                    Value::V128(u128::from(high) << 64 | u128::from(bits))
+                }
+            }
+        })
+        .collect();
+
+    let values = call_host_function(|| {
+        let values = (env.callback)(ctx, &args).map_err(|msg| RuntimeError::User { msg })?;
+        if values
+            .iter()
+            .map(Value::ty)
+            .eq(env.signature.returns().iter().cloned())
+        {
+            Ok(values)
+        } else {
+            Err(RuntimeError::User {
+                msg: format!(
+                    "returned {:?}, which doesn't match the signature {}",
+                    values, env.signature
+                ),
+            })
+        }
+    });
+
+    let mut index = 0;
+    for value in values {
+        let bits = match value {
+            Value::I32(x) => x as u32 as u64,
+            Value::I64(x) => x as u64,
+            Value::F32(x) => x.to_bits() as u64,
+            Value::F64(x) => x.to_bits(),
+            Value::AnyRef(x) => x.to_bits(),
+            Value::FuncRef(x) => x.to_bits(),
+            Value::V128(x) => {
+                unsafe { *returns.add(index + 1) = (x >> 64) as u64 };
+                x as u64
+            }
+        };
+        unsafe { *returns.add(index) = bits };
+        index += value.ty().buffer_len();
+    }
+}
+
+impl IsExport for DynamicFunc {
+    fn to_export(&self) -> Export {
+        // The backend provides the function when this is imported.
+        let func = unsafe { FuncPointer::new(ptr::null()) };
+
+        Export::Function {
+            func,
+            ctx: Context::Dynamic(self.env.clone()),
+            signature: Arc::clone(&self.signature),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -279,6 +538,20 @@ mod tests {
         let _f = Func::new(foo);
     }
 
+    #[test]
+    fn test_multi_value_buffer() {
+        let mut buffer = [0u64; 3];
+        unsafe {
+            (-1i32, 2u64, 1.5f32).into_buffer(buffer.as_mut_ptr());
+            assert_eq!(
+                <(i32, u64, f32)>::from_buffer(buffer.as_ptr()),
+                (-1, 2, 1.5)
+            );
+        }
+        assert_eq!(buffer[1], 2);
+        assert_eq!(buffer[2] as u32, 1.5f32.to_bits());
+    }
+
     #[test]
     fn test_imports() {
         use crate::{func, imports};
@@ -293,4 +566,63 @@ mod tests {
             },
         };
     }
+
+    #[test]
+    fn test_closure_imports() {
+        use crate::{func, import::LikeNamespace, imports};
+
+        let offset = 42;
+        fn no_state(_ctx: &mut Ctx, a: i32) -> i32 {
+            a
+        }
+
+        let import_object = imports! {
+            "env" => {
+                "with_state" => func!(move |_ctx: &mut Ctx, a: i32| -> i32 { a + offset }),
+                "no_state" => func!(no_state),
+            },
+        };
+
+        let namespace = import_object.get_namespace("env").unwrap();
+        match namespace.get_export("with_state") {
+            Some(Export::Function {
+                ctx: Context::Env(_),
+                ..
+            }) => {}
+            _ => panic!("a closure that captures state should carry its environment"),
+        }
+        match namespace.get_export("no_state") {
+            Some(Export::Function {
+                ctx: Context::Internal,
+                ..
+            }) => {}
+            _ => panic!("a plain function shouldn't have an environment"),
+        }
+    }
+    #[test]
+    fn test_dynamic_imports() {
+        use crate::{import::LikeNamespace, imports};
+
+        let signature = Arc::new(FuncSig::new(vec![Type::I32, Type::F64], vec![Type::I64]));
+        let import_object = imports! {
+            "env" => {
+                "dynamic" => DynamicFunc::new(Arc::clone(&signature), |_ctx, _args| -> Result<_, ()> {
+                    Ok(vec![Value::I64(0)])
+                }),
+            },
+        };
+
+        match import_object
+            .get_namespace("env")
+            .unwrap()
+            .get_export("dynamic")
+        {
+            Some(Export::Function {
+                ctx: Context::Dynamic(_),
+                signature: found,
+                ..
+            }) => assert_eq!(found, signature),
+            _ => panic!("a dynamic function should be resolved by the backend"),
+        }
+    }
 }
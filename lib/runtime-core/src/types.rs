@@ -1,6 +1,9 @@
 use crate::{memory::MemoryType, module::ModuleInfo, structures::TypedIndex, units::Pages};
+use serde::{de, Deserialize, Deserializer};
 use std::{borrow::Cow, mem};
 
+pub use wasmer_runtime_core_derive::ValueType;
+
 /// Represents a WebAssembly type.
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Type {
@@ -12,6 +15,31 @@ pub enum Type {
     F32,
     /// The `f64` type.
     F64,
+    /// The `anyref` type, which `funcref` values also have.
+    AnyRef,
+    /// The `funcref` type.
+    FuncRef,
+    /// The `v128` type of the SIMD proposal.
+    V128,
+}
+
+impl Type {
+    /// The number of `u64`s that a value of this type takes up in the
+    /// buffers that arguments and return values are passed around in.
+    pub fn buffer_len(self) -> usize {
+        match self {
+            Type::V128 => 2,
+            _ => 1,
+        }
+    }
+
+    /// Whether this is `anyref` or `funcref`.
+    pub fn is_reference(self) -> bool {
+        match self {
+            Type::AnyRef | Type::FuncRef => true,
+            _ => false,
+        }
+    }
 }
 
 impl std::fmt::Display for Type {
@@ -34,6 +62,12 @@ pub enum Value {
     F32(f32),
     /// The `f64` type.
     F64(f64),
+    /// The `anyref` type.
+    AnyRef(AnyRef),
+    /// The `funcref` type.
+    FuncRef(FuncRef),
+    /// The `v128` type, with the first lane in the lowest bits.
+    V128(u128),
 }
 
 impl Value {
@@ -43,10 +77,150 @@ impl Value {
             Value::I64(_) => Type::I64,
             Value::F32(_) => Type::F32,
             Value::F64(_) => Type::F64,
+            Value::AnyRef(_) => Type::AnyRef,
+            Value::FuncRef(_) => Type::FuncRef,
+            Value::V128(_) => Type::V128,
         }
     }
 }
 
+/// An opaque reference, the value of an `anyref`.
+///
+/// WebAssembly code can only pass references around and store them in
+/// tables. What a reference made by the host points to is up to the host,
+/// which has to keep it alive for as long as an instance might use it.
+/// References made by WebAssembly code point to functions.
+///
+/// Only null references can be deserialized, since what the others
+/// point to doesn't outlive the process that serialized them.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct AnyRef(usize);
+
+impl AnyRef {
+    /// The null reference.
+    pub fn null() -> Self {
+        AnyRef(0)
+    }
+
+    /// Creates a reference to `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must stay valid for as long as an instance can see the
+    /// reference, and it must never be passed where a `funcref`
+    /// is expected, unless WebAssembly code made it.
+    pub unsafe fn new<T>(ptr: *const T) -> Self {
+        AnyRef(ptr as usize)
+    }
+
+    pub fn is_null(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn as_ptr<T>(self) -> *const T {
+        self.0 as *const T
+    }
+
+    /// The bits that represent this reference in WebAssembly code.
+    pub fn to_bits(self) -> u64 {
+        self.0 as u64
+    }
+
+    /// The reference that `bits` represent in WebAssembly code.
+    ///
+    /// # Safety
+    ///
+    /// The same as for [`new`].
+    ///
+    /// [`new`]: #method.new
+    pub unsafe fn from_bits(bits: u64) -> Self {
+        AnyRef(bits as usize)
+    }
+}
+
+impl Default for AnyRef {
+    fn default() -> Self {
+        Self::null()
+    }
+}
+
+/// A reference to a function, the value of a `funcref`.
+///
+/// Only WebAssembly code can make one that isn't null, and it stays
+/// valid for as long as the instance that made it. The host can pass
+/// it back to WebAssembly code, or use it as an [`AnyRef`].
+///
+/// Like an [`AnyRef`], only a null one can be deserialized.
+///
+/// [`AnyRef`]: struct.AnyRef.html
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct FuncRef(usize);
+
+impl FuncRef {
+    /// The null reference.
+    pub fn null() -> Self {
+        FuncRef(0)
+    }
+
+    pub fn is_null(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The bits that represent this reference in WebAssembly code.
+    pub fn to_bits(self) -> u64 {
+        self.0 as u64
+    }
+
+    /// The reference that `bits` represent in WebAssembly code.
+    ///
+    /// # Safety
+    ///
+    /// `bits` must be null, or have been given to the host by
+    /// WebAssembly code as a `funcref`, of an instance that's alive.
+    pub unsafe fn from_bits(bits: u64) -> Self {
+        FuncRef(bits as usize)
+    }
+}
+
+impl Default for FuncRef {
+    fn default() -> Self {
+        Self::null()
+    }
+}
+
+impl From<FuncRef> for AnyRef {
+    fn from(r: FuncRef) -> Self {
+        AnyRef(r.0)
+    }
+}
+
+/// Deserializes a null reference, and rejects the others.
+macro_rules! deserialize_null_ref {
+    ($ty:ident) => {
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                // Serialized the way the derived `Serialize` does it.
+                #[derive(Deserialize)]
+                struct Bits(usize);
+
+                match Bits::deserialize(deserializer)? {
+                    Bits(0) => Ok($ty::null()),
+                    _ => Err(de::Error::custom(
+                        "only null references can be deserialized",
+                    )),
+                }
+            }
+        }
+    };
+}
+deserialize_null_ref!(AnyRef);
+deserialize_null_ref!(FuncRef);
+
 impl From<i32> for Value {
     fn from(i: i32) -> Self {
         Value::I32(i)
@@ -71,6 +245,24 @@ impl From<f64> for Value {
     }
 }
 
+impl From<AnyRef> for Value {
+    fn from(r: AnyRef) -> Self {
+        Value::AnyRef(r)
+    }
+}
+
+impl From<FuncRef> for Value {
+    fn from(r: FuncRef) -> Self {
+        Value::FuncRef(r)
+    }
+}
+
+impl From<u128> for Value {
+    fn from(v: u128) -> Self {
+        Value::V128(v)
+    }
+}
+
 pub unsafe trait WasmExternType: Copy + Clone
 where
     Self: Sized,
@@ -95,6 +287,12 @@ unsafe impl WasmExternType for f32 {
 unsafe impl WasmExternType for f64 {
     const TYPE: Type = Type::F64;
 }
+unsafe impl WasmExternType for AnyRef {
+    const TYPE: Type = Type::AnyRef;
+}
+unsafe impl WasmExternType for FuncRef {
+    const TYPE: Type = Type::FuncRef;
+}
 
 // pub trait IntegerAtomic
 // where
@@ -117,6 +315,44 @@ pub enum ValueError {
     BufferTooSmall,
 }
 
+/// A type that can be read from and written to a wasm
+/// linear memory, through a [`MemoryView`] or a [`WasmPtr`].
+///
+/// Besides the primitive numbers, it can be derived for `#[repr(C)]`
+/// structs without padding, whose fields are all `ValueType`s:
+///
+/// ```
+/// use wasmer_runtime_core::types::ValueType;
+///
+/// #[derive(Debug, Copy, Clone, PartialEq, ValueType)]
+/// #[repr(C)]
+/// struct GuestIovec {
+///     base: u32,
+///     len: u32,
+/// }
+///
+/// let iovec = GuestIovec { base: 0x10, len: 4 };
+/// let mut buffer = [0; 8];
+/// iovec.into_le(&mut buffer);
+/// assert_eq!(buffer, [0x10, 0, 0, 0, 4, 0, 0, 0]);
+/// assert_eq!(GuestIovec::from_le(&buffer).ok(), Some(iovec));
+/// ```
+///
+/// Deriving it fails to compile if there's padding, like
+/// the three bytes after `tag` here:
+///
+/// ```compile_fail
+/// # use wasmer_runtime_core::types::ValueType;
+/// #[derive(Copy, Clone, ValueType)]
+/// #[repr(C)]
+/// struct Tagged {
+///     tag: u8,
+///     value: u32,
+/// }
+/// ```
+///
+/// [`MemoryView`]: ../memory/struct.MemoryView.html
+/// [`WasmPtr`]: ../memory/struct.WasmPtr.html
 pub trait ValueType: Copy
 where
     Self: Sized,
@@ -149,7 +385,7 @@ macro_rules! convert_value_impl {
     };
 }
 
-convert_value_impl!(u8, i8, u16, i16, u32, i32, u64, i64);
+convert_value_impl!(u8, i8, u16, i16, u32, i32, u64, i64, u128);
 
 impl ValueType for f32 {
     fn into_le(self, buffer: &mut [u8]) {
@@ -173,6 +409,10 @@ impl ValueType for f64 {
 pub enum ElementType {
     /// Any wasm function.
     Anyfunc,
+    /// Any reference. See [`AnyRef`].
+    ///
+    /// [`AnyRef`]: struct.AnyRef.html
+    AnyRef,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
@@ -351,6 +591,9 @@ define_map_index![
     | imported: ImportedFuncIndex, ImportedMemoryIndex, ImportedTableIndex, ImportedGlobalIndex,
 ];
 
+define_map_index!(DataIndex);
+define_map_index!(ElemIndex);
+
 #[rustfmt::skip]
 macro_rules! define_local_or_import {
     ($ty:ident, $local_ty:ident, $imported_ty:ident, $imports:ident) => {
@@ -431,3 +674,31 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod types_tests {
+    use super::{AnyRef, FuncRef, Value};
+
+    fn round_trip(value: &Value) -> Result<Value, serde_bench::Error> {
+        let mut bytes = vec![];
+        serde_bench::serialize(&mut bytes, value).unwrap();
+        serde_bench::deserialize(&bytes)
+    }
+
+    #[test]
+    fn test_only_null_refs_deserialize() {
+        let null_refs = [
+            Value::AnyRef(AnyRef::null()),
+            Value::FuncRef(FuncRef::null()),
+        ];
+        for value in &null_refs {
+            assert_eq!(round_trip(value).unwrap(), *value);
+        }
+
+        let x = 42;
+        let anyref = unsafe { AnyRef::new(&x) };
+        assert!(round_trip(&Value::AnyRef(anyref)).is_err());
+        let funcref = unsafe { FuncRef::from_bits(anyref.to_bits()) };
+        assert!(round_trip(&Value::FuncRef(funcref)).is_err());
+    }
+}
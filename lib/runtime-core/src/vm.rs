@@ -1,11 +1,11 @@
 pub use crate::backing::{ImportBacking, LocalBacking};
 use crate::{
     memory::Memory,
-    module::ModuleInner,
+    module::{ModuleInfo, ModuleInner},
     structures::TypedIndex,
     types::{LocalOrImport, MemoryIndex},
 };
-use std::{ffi::c_void, mem, ptr};
+use std::{ffi::c_void, mem, ptr, sync::atomic::AtomicBool};
 
 /// The context of the currently running WebAssembly instance.
 ///
@@ -34,9 +34,15 @@ pub struct Ctx {
     /// A pointer to an array of imported functions, indexed by `FuncIndex`.
     pub(crate) imported_funcs: *mut ImportedFunc,
 
-    local_backing: *mut LocalBacking,
-    import_backing: *mut ImportBacking,
-    module: *const ModuleInner,
+    /// The fuel left for metered code to run on.
+    pub(crate) fuel: u64,
+
+    /// Checked by compiled code to see if it should stop running.
+    pub(crate) interrupted: *const AtomicBool,
+
+    pub(crate) local_backing: *mut LocalBacking,
+    pub(crate) import_backing: *mut ImportBacking,
+    pub(crate) module: *const ModuleInner,
 
     pub data: *mut c_void,
     pub data_finalizer: Option<extern "C" fn(data: *mut c_void)>,
@@ -59,6 +65,9 @@ impl Ctx {
             imported_globals: import_backing.vm_globals.as_mut_ptr(),
             imported_funcs: import_backing.vm_functions.as_mut_ptr(),
 
+            fuel: u64::MAX,
+            interrupted: &*local_backing.interrupted,
+
             local_backing,
             import_backing,
             module,
@@ -86,6 +95,9 @@ impl Ctx {
             imported_globals: import_backing.vm_globals.as_mut_ptr(),
             imported_funcs: import_backing.vm_functions.as_mut_ptr(),
 
+            fuel: u64::MAX,
+            interrupted: &*local_backing.interrupted,
+
             local_backing,
             import_backing,
             module,
@@ -131,6 +143,10 @@ impl Ctx {
 
 #[doc(hidden)]
 impl Ctx {
+    pub fn module_info(&self) -> &ModuleInfo {
+        unsafe { &(*self.module).info }
+    }
+
     #[allow(clippy::erasing_op)] // TODO
     pub fn offset_memories() -> u8 {
         0 * (mem::size_of::<usize>() as u8)
@@ -160,6 +176,14 @@ impl Ctx {
         6 * (mem::size_of::<usize>() as u8)
     }
 
+    pub fn offset_fuel() -> u8 {
+        7 * (mem::size_of::<usize>() as u8)
+    }
+
+    pub fn offset_interrupted() -> u8 {
+        Self::offset_fuel() + mem::size_of::<u64>() as u8
+    }
+
     pub fn offset_signatures() -> u8 {
         7 * (mem::size_of::<usize>() as u8)
     }
@@ -195,6 +219,30 @@ impl ImportedFunc {
     }
 }
 
+/// What an imported host function that captures state is called with,
+/// in place of the `Ctx` of the instance importing it.
+///
+/// The `vmctx` of the `ImportedFunc` points to one of these.
+#[derive(Debug)]
+#[repr(C)]
+pub struct FuncCtx {
+    /// The instance that imports the function.
+    pub vmctx: *mut Ctx,
+    /// The state the function captured.
+    pub func_env: *const c_void,
+}
+
+impl FuncCtx {
+    #[allow(clippy::erasing_op)]
+    pub fn offset_vmctx() -> u8 {
+        0 * (mem::size_of::<usize>() as u8)
+    }
+
+    pub fn offset_func_env() -> u8 {
+        1 * (mem::size_of::<usize>() as u8)
+    }
+}
+
 /// Definition of a table used by the VM. (obviously)
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
@@ -315,7 +363,7 @@ impl Anyfunc {
 
 #[cfg(test)]
 mod vm_offset_tests {
-    use super::{Anyfunc, Ctx, ImportedFunc, LocalGlobal, LocalMemory, LocalTable};
+    use super::{Anyfunc, Ctx, FuncCtx, ImportedFunc, LocalGlobal, LocalMemory, LocalTable};
 
     #[test]
     fn vmctx() {
@@ -353,6 +401,16 @@ mod vm_offset_tests {
             Ctx::offset_imported_funcs() as usize,
             offset_of!(Ctx => imported_funcs).get_byte_offset(),
         );
+
+        assert_eq!(
+            Ctx::offset_fuel() as usize,
+            offset_of!(Ctx => fuel).get_byte_offset(),
+        );
+
+        assert_eq!(
+            Ctx::offset_interrupted() as usize,
+            offset_of!(Ctx => interrupted).get_byte_offset(),
+        );
     }
 
     #[test]
@@ -368,6 +426,19 @@ mod vm_offset_tests {
         );
     }
 
+    #[test]
+    fn func_ctx() {
+        assert_eq!(
+            FuncCtx::offset_vmctx() as usize,
+            offset_of!(FuncCtx => vmctx).get_byte_offset(),
+        );
+
+        assert_eq!(
+            FuncCtx::offset_func_env() as usize,
+            offset_of!(FuncCtx => func_env).get_byte_offset(),
+        );
+    }
+
     #[test]
     fn local_table() {
         assert_eq!(
@@ -426,7 +497,9 @@ mod vm_ctx_tests {
     use super::{Ctx, ImportBacking, LocalBacking};
     use crate::module::{ModuleInfo, ModuleInner, StringTable};
     use crate::structures::Map;
+    use hashbrown::HashSet;
     use std::ffi::c_void;
+    use std::sync::{atomic::AtomicBool, Arc};
 
     struct TestData {
         x: u32,
@@ -458,12 +531,22 @@ mod vm_ctx_tests {
             vm_memories: Map::new().into_boxed_map(),
             vm_tables: Map::new().into_boxed_map(),
             vm_globals: Map::new().into_boxed_map(),
+
+            interrupted: Arc::new(AtomicBool::new(false)),
+
+            dropped_data: HashSet::new(),
+            dropped_elements: HashSet::new(),
+
+            func_refs: Default::default(),
+
+            limiter: None,
         };
         let mut import_backing = ImportBacking {
             memories: Map::new().into_boxed_map(),
             tables: Map::new().into_boxed_map(),
             globals: Map::new().into_boxed_map(),
 
+            func_envs: Vec::new(),
             vm_functions: Map::new().into_boxed_map(),
             vm_memories: Map::new().into_boxed_map(),
             vm_tables: Map::new().into_boxed_map(),
@@ -495,11 +578,11 @@ mod vm_ctx_tests {
     fn generate_module() -> ModuleInner {
         use super::Func;
         use crate::backend::{
-            sys::Memory, Backend, CacheGen, FuncResolver, ProtectedCaller, Token, UserTrapper,
+            sys::Memory, Backend, CacheGen, Features, FuncResolver, ProtectedCaller, Token, UserTrapper,
         };
         use crate::cache::{Error as CacheError, WasmHash};
         use crate::error::RuntimeResult;
-        use crate::types::{FuncIndex, LocalFuncIndex, Value};
+        use crate::types::{FuncIndex, LocalFuncIndex, SigIndex, Value};
         use hashbrown::HashMap;
         use std::ptr::NonNull;
         struct Placeholder;
@@ -527,6 +610,9 @@ mod vm_ctx_tests {
             fn get_early_trapper(&self) -> Box<dyn UserTrapper> {
                 unimplemented!()
             }
+            fn get_dynamic_trampoline(&self, _sig_index: SigIndex) -> Option<NonNull<Func>> {
+                None
+            }
         }
         impl CacheGen for Placeholder {
             fn generate_cache(
@@ -557,6 +643,9 @@ mod vm_ctx_tests {
                 data_initializers: Vec::new(),
                 elem_initializers: Vec::new(),
 
+                passive_data: HashMap::new(),
+                passive_elements: HashMap::new(),
+
                 start_func: None,
 
                 func_assoc: Map::new(),
@@ -565,6 +654,15 @@ mod vm_ctx_tests {
 
                 namespace_table: StringTable::new(),
                 name_table: StringTable::new(),
+
+                custom_sections: HashMap::new(),
+
+                func_names: HashMap::new(),
+                local_names: HashMap::new(),
+
+                wasm_hash: WasmHash::generate(&[]),
+
+                features: Features::default(),
             },
         }
     }
@@ -1,12 +1,40 @@
 #![allow(clippy::cast_ptr_alignment)]
 
 use crate::{
-    memory::{DynamicMemory, StaticMemory},
+    backing,
+    memory::{self, DynamicMemory, SharedStaticMemory, StaticMemory},
+    simd,
     structures::TypedIndex,
-    types::{ImportedMemoryIndex, LocalMemoryIndex, LocalTableIndex},
+    table::Table,
+    types::{
+        AnyRef, DataIndex, ElemIndex, ElementType, FuncIndex, ImportedMemoryIndex,
+        LocalMemoryIndex, LocalOrImport, MemoryIndex, TableIndex,
+    },
     units::Pages,
     vm,
 };
+use std::{
+    mem, ptr,
+    sync::atomic::{AtomicU16, AtomicU32, AtomicU64, AtomicU8, Ordering},
+    time::Duration,
+};
+
+/// Ask the instance's limiter, if it has one, whether
+/// `memory` may grow by `delta` pages from `current`.
+unsafe fn memory_may_grow(
+    ctx: &mut vm::Ctx,
+    memory: MemoryIndex,
+    current: Pages,
+    delta: Pages,
+) -> bool {
+    match &mut (*ctx.local_backing).limiter {
+        Some(limiter) if delta.0 > 0 => match current.0.checked_add(delta.0) {
+            Some(desired) => limiter.memory_growing(memory, current, Pages(desired)),
+            None => false,
+        },
+        _ => true,
+    }
+}
 
 // +*****************************+
 // |       LOCAL MEMORIES        |
@@ -20,6 +48,11 @@ pub unsafe extern "C" fn local_static_memory_grow(
     let local_memory = *ctx.memories.add(memory_index.index());
     let memory = (*local_memory).memory as *mut StaticMemory;
 
+    let index = memory_index.convert_up(ctx.module_info());
+    if !memory_may_grow(ctx, index, (*memory).size(), delta) {
+        return -1;
+    }
+
     match (*memory).grow(delta, &mut *local_memory) {
         Ok(old) => old.0 as i32,
         Err(_) => -1,
@@ -36,6 +69,35 @@ pub unsafe extern "C" fn local_static_memory_size(
     (*memory).size()
 }
 
+pub unsafe extern "C" fn local_shared_static_memory_grow(
+    ctx: &mut vm::Ctx,
+    memory_index: LocalMemoryIndex,
+    delta: Pages,
+) -> i32 {
+    let local_memory = *ctx.memories.add(memory_index.index());
+    let memory = (*local_memory).memory as *mut SharedStaticMemory;
+
+    let index = memory_index.convert_up(ctx.module_info());
+    if !memory_may_grow(ctx, index, (*memory).size(), delta) {
+        return -1;
+    }
+
+    match (*memory).grow(delta, local_memory) {
+        Ok(old) => old.0 as i32,
+        Err(_) => -1,
+    }
+}
+
+pub unsafe extern "C" fn local_shared_static_memory_size(
+    ctx: &vm::Ctx,
+    memory_index: LocalMemoryIndex,
+) -> Pages {
+    let local_memory = *ctx.memories.add(memory_index.index());
+    let memory = (*local_memory).memory as *mut SharedStaticMemory;
+
+    (*memory).size()
+}
+
 pub unsafe extern "C" fn local_dynamic_memory_grow(
     ctx: &mut vm::Ctx,
     memory_index: LocalMemoryIndex,
@@ -44,6 +106,11 @@ pub unsafe extern "C" fn local_dynamic_memory_grow(
     let local_memory = *ctx.memories.add(memory_index.index());
     let memory = (*local_memory).memory as *mut DynamicMemory;
 
+    let index = memory_index.convert_up(ctx.module_info());
+    if !memory_may_grow(ctx, index, (*memory).size(), delta) {
+        return -1;
+    }
+
     match (*memory).grow(delta, &mut *local_memory) {
         Ok(old) => old.0 as i32,
         Err(_) => -1,
@@ -72,6 +139,11 @@ pub unsafe extern "C" fn imported_static_memory_grow(
     let local_memory = *ctx.imported_memories.add(import_memory_index.index());
     let memory = (*local_memory).memory as *mut StaticMemory;
 
+    let index = import_memory_index.convert_up(ctx.module_info());
+    if !memory_may_grow(ctx, index, (*memory).size(), delta) {
+        return -1;
+    }
+
     match (*memory).grow(delta, &mut *local_memory) {
         Ok(old) => old.0 as i32,
         Err(_) => -1,
@@ -88,6 +160,35 @@ pub unsafe extern "C" fn imported_static_memory_size(
     (*memory).size()
 }
 
+pub unsafe extern "C" fn imported_shared_static_memory_grow(
+    ctx: &mut vm::Ctx,
+    import_memory_index: ImportedMemoryIndex,
+    delta: Pages,
+) -> i32 {
+    let local_memory = *ctx.imported_memories.add(import_memory_index.index());
+    let memory = (*local_memory).memory as *mut SharedStaticMemory;
+
+    let index = import_memory_index.convert_up(ctx.module_info());
+    if !memory_may_grow(ctx, index, (*memory).size(), delta) {
+        return -1;
+    }
+
+    match (*memory).grow(delta, local_memory) {
+        Ok(old) => old.0 as i32,
+        Err(_) => -1,
+    }
+}
+
+pub unsafe extern "C" fn imported_shared_static_memory_size(
+    ctx: &vm::Ctx,
+    import_memory_index: ImportedMemoryIndex,
+) -> Pages {
+    let local_memory = *ctx.imported_memories.add(import_memory_index.index());
+    let memory = (*local_memory).memory as *mut SharedStaticMemory;
+
+    (*memory).size()
+}
+
 pub unsafe extern "C" fn imported_dynamic_memory_grow(
     ctx: &mut vm::Ctx,
     memory_index: ImportedMemoryIndex,
@@ -96,6 +197,11 @@ pub unsafe extern "C" fn imported_dynamic_memory_grow(
     let local_memory = *ctx.imported_memories.add(memory_index.index());
     let memory = (*local_memory).memory as *mut DynamicMemory;
 
+    let index = memory_index.convert_up(ctx.module_info());
+    if !memory_may_grow(ctx, index, (*memory).size(), delta) {
+        return -1;
+    }
+
     match (*memory).grow(delta, &mut *local_memory) {
         Ok(old) => old.0 as i32,
         Err(_) => -1,
@@ -113,22 +219,508 @@ pub unsafe extern "C" fn imported_dynamic_memory_size(
 }
 
 // +*****************************+
-// |        LOCAL TABLES         |
+// |          ATOMICS            |
+// +****************************+
+
+// The compiler checks bounds and alignment before calling these, so
+// `addr` is always a valid host address, aligned to `width` bytes.
+// Values are zero-extended to (and truncated from) 64 bits.
+
+/// Evaluates `$body` with `$atomic` bound to the `$width`-byte
+/// atomic at `$addr`.
+macro_rules! with_atomic {
+    ($addr:expr, $width:expr, |$atomic:ident| $body:expr) => {
+        match $width {
+            1 => with_atomic!(@ $addr, AtomicU8, $atomic, $body),
+            2 => with_atomic!(@ $addr, AtomicU16, $atomic, $body),
+            4 => with_atomic!(@ $addr, AtomicU32, $atomic, $body),
+            8 => with_atomic!(@ $addr, AtomicU64, $atomic, $body),
+            _ => unreachable!("invalid atomic access width"),
+        }
+    };
+    (@ $addr:expr, $atomic_ty:ty, $atomic:ident, $body:expr) => {{
+        let $atomic = &*($addr as *const $atomic_ty);
+        let result = $body;
+        result as u64
+    }};
+}
+
+pub unsafe extern "C" fn atomic_load(addr: *mut u8, width: u32) -> u64 {
+    with_atomic!(addr, width, |atomic| atomic.load(Ordering::SeqCst))
+}
+
+pub unsafe extern "C" fn atomic_store(addr: *mut u8, width: u32, value: u64) {
+    with_atomic!(addr, width, |atomic| {
+        atomic.store(value as _, Ordering::SeqCst);
+        0
+    });
+}
+
+macro_rules! atomic_rmw {
+    ($($name:ident => $method:ident,)*) => {
+        $(
+            /// Returns the value that was in memory before the operation.
+            pub unsafe extern "C" fn $name(addr: *mut u8, width: u32, value: u64) -> u64 {
+                with_atomic!(addr, width, |atomic| atomic.$method(value as _, Ordering::SeqCst))
+            }
+        )*
+    };
+}
+
+atomic_rmw! {
+    atomic_rmw_add => fetch_add,
+    atomic_rmw_sub => fetch_sub,
+    atomic_rmw_and => fetch_and,
+    atomic_rmw_or => fetch_or,
+    atomic_rmw_xor => fetch_xor,
+    atomic_rmw_xchg => swap,
+}
+
+/// Returns the value that was in memory before the operation.
+pub unsafe extern "C" fn atomic_cmpxchg(
+    addr: *mut u8,
+    width: u32,
+    expected: u64,
+    replacement: u64,
+) -> u64 {
+    with_atomic!(addr, width, |atomic| {
+        match atomic.compare_exchange(
+            expected as _,
+            replacement as _,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        ) {
+            Ok(previous) | Err(previous) => previous,
+        }
+    })
+}
+
+/// Implements `memory.atomic.wait`. A negative `timeout`, in
+/// nanoseconds, waits forever.
+///
+/// Returns 0 when woken by a notify, 1 if the value in memory
+/// wasn't `expected` and 2 if the timeout expired.
+pub unsafe extern "C" fn atomic_wait(
+    addr: *mut u8,
+    width: u32,
+    expected: u64,
+    timeout: i64,
+) -> u32 {
+    let timeout = if timeout < 0 {
+        None
+    } else {
+        Some(Duration::from_nanos(timeout as u64))
+    };
+
+    memory::wait(
+        addr as usize,
+        || atomic_load(addr, width) == expected,
+        timeout,
+    ) as u32
+}
+
+/// Implements `memory.atomic.notify`. Returns the number of waiters woken.
+pub unsafe extern "C" fn atomic_notify(addr: *mut u8, count: u32) -> u32 {
+    memory::notify(addr as usize, count)
+}
+
+// +*****************************+
+// |         BULK MEMORY         |
+// +****************************+
+
+// These return 1 without changing anything when part of the accessed range
+// is out of bounds, which the compiler turns into a trap, and 0 otherwise.
+
+/// Whether `len` bytes (or elements) starting at `start` fit in `bound`.
+fn in_bounds(start: u32, len: u32, bound: usize) -> bool {
+    start as usize + len as usize <= bound
+}
+
+/// Returns the memory or table at `index`, local or imported.
+macro_rules! vm_item {
+    ($ctx:expr, $index_ty:ident, $index:expr, $locals:ident, $imports:ident) => {{
+        let index = $index_ty::new($index as usize);
+        match index.local_or_import($ctx.module_info()) {
+            LocalOrImport::Local(local_index) => &mut **$ctx.$locals.add(local_index.index()),
+            LocalOrImport::Import(import_index) => &mut **$ctx.$imports.add(import_index.index()),
+        }
+    }};
+}
+
+/// Implements `memory.copy` on the memory at `base`, which is `bound` bytes long.
+pub unsafe extern "C" fn memory_copy(
+    base: *mut u8,
+    bound: usize,
+    dst: u32,
+    src: u32,
+    len: u32,
+) -> u32 {
+    if !in_bounds(dst, len, bound) || !in_bounds(src, len, bound) {
+        return 1;
+    }
+
+    ptr::copy(base.add(src as usize), base.add(dst as usize), len as usize);
+    0
+}
+
+/// Implements `memory.fill` on the memory at `base`, which is `bound` bytes long.
+pub unsafe extern "C" fn memory_fill(
+    base: *mut u8,
+    bound: usize,
+    dst: u32,
+    value: u32,
+    len: u32,
+) -> u32 {
+    if !in_bounds(dst, len, bound) {
+        return 1;
+    }
+
+    ptr::write_bytes(base.add(dst as usize), value as u8, len as usize);
+    0
+}
+
+pub unsafe extern "C" fn memory_init(
+    ctx: &mut vm::Ctx,
+    memory_index: u32,
+    segment: u32,
+    dst: u32,
+    src: u32,
+    len: u32,
+) -> u32 {
+    // Active segments are dropped once they've been written to memory.
+    let segment = DataIndex::new(segment as usize);
+    let data = match ctx.module_info().passive_data.get(&segment) {
+        Some(data) if !(*ctx.local_backing).dropped_data.contains(&segment) => &data[..],
+        _ => &[],
+    };
+
+    let local_memory: &mut vm::LocalMemory =
+        vm_item!(ctx, MemoryIndex, memory_index, memories, imported_memories);
+    if !in_bounds(dst, len, local_memory.bound) || !in_bounds(src, len, data.len()) {
+        return 1;
+    }
+
+    ptr::copy_nonoverlapping(
+        data.as_ptr().add(src as usize),
+        local_memory.base.add(dst as usize),
+        len as usize,
+    );
+    0
+}
+
+pub unsafe extern "C" fn data_drop(ctx: &mut vm::Ctx, segment: u32) {
+    (*ctx.local_backing)
+        .dropped_data
+        .insert(DataIndex::new(segment as usize));
+}
+
+pub unsafe extern "C" fn table_init(
+    ctx: &mut vm::Ctx,
+    table_index: u32,
+    segment: u32,
+    dst: u32,
+    src: u32,
+    len: u32,
+) -> u32 {
+    // Active segments are dropped once they've been written to the table.
+    let segment = ElemIndex::new(segment as usize);
+    let module = &*ctx.module;
+    let elements = match module.info.passive_elements.get(&segment) {
+        Some(elements) if !(*ctx.local_backing).dropped_elements.contains(&segment) => {
+            &elements[..]
+        }
+        _ => &[],
+    };
+
+    let table = vm_table(ctx, table_index);
+    if !in_bounds(dst, len, table.size() as usize) || !in_bounds(src, len, elements.len()) {
+        return 1;
+    }
+
+    let elements = &elements[src as usize..(src + len) as usize];
+    match table.descriptor().element {
+        ElementType::Anyfunc => table.anyfunc_direct_access_mut(|table| {
+            for (anyfunc, element) in table[dst as usize..].iter_mut().zip(elements) {
+                *anyfunc = match *element {
+                    Some(func_index) => {
+                        backing::anyfunc(module, &*ctx.import_backing, ctx, func_index)
+                    }
+                    None => vm::Anyfunc::null(),
+                };
+            }
+        }),
+        ElementType::AnyRef => {
+            for (i, element) in elements.iter().enumerate() {
+                let value = match *element {
+                    Some(func_index) => func_ref(ctx, func_index),
+                    None => 0,
+                };
+                set_ref(table, dst as usize + i, value);
+            }
+        }
+    }
+    0
+}
+
+pub unsafe extern "C" fn elem_drop(ctx: &mut vm::Ctx, segment: u32) {
+    (*ctx.local_backing)
+        .dropped_elements
+        .insert(ElemIndex::new(segment as usize));
+}
+
+pub unsafe extern "C" fn table_copy(
+    ctx: &mut vm::Ctx,
+    dst_table_index: u32,
+    src_table_index: u32,
+    dst: u32,
+    src: u32,
+    len: u32,
+) -> u32 {
+    let dst_table: &mut vm::LocalTable =
+        vm_item!(ctx, TableIndex, dst_table_index, tables, imported_tables);
+    let (dst_base, dst_count) = (dst_table.base, dst_table.count);
+    let src_table: &mut vm::LocalTable =
+        vm_item!(ctx, TableIndex, src_table_index, tables, imported_tables);
+    let (src_base, src_count) = (src_table.base, src_table.count);
+
+    if !in_bounds(dst, len, dst_count) || !in_bounds(src, len, src_count) {
+        return 1;
+    }
+
+    let dst_table = vm_table(ctx, dst_table_index);
+    let src_table = vm_table(ctx, src_table_index);
+    let element_size = match (
+        dst_table.descriptor().element,
+        src_table.descriptor().element,
+    ) {
+        (ElementType::Anyfunc, ElementType::Anyfunc) => mem::size_of::<vm::Anyfunc>(),
+        (ElementType::AnyRef, ElementType::AnyRef) => mem::size_of::<AnyRef>(),
+        // Functions copied into an `anyref` table become funcref values.
+        _ => {
+            let values: Vec<u64> = (src..src + len)
+                .map(|i| get_ref(ctx, src_table, i as usize))
+                .collect();
+            for (i, value) in values.into_iter().enumerate() {
+                set_ref(dst_table, dst as usize + i, value);
+            }
+            return 0;
+        }
+    };
+
+    // The tables may be the same one, so the ranges can overlap.
+    ptr::copy(
+        src_base.add(src as usize * element_size),
+        dst_base.add(dst as usize * element_size),
+        len as usize * element_size,
+    );
+    0
+}
+
+// +*****************************+
+// |       REFERENCE TYPES       |
 // +****************************+
 
-pub unsafe extern "C" fn local_table_grow(
+// A reference value is passed around as a 64-bit integer. A funcref is a
+// pointer to a table element interned in `LocalBacking::func_refs`, an
+// anyref is whatever `AnyRef::to_bits` returned, and null is 0 for both.
+
+/// Returns the table at `index`, local or imported.
+unsafe fn vm_table<'a>(ctx: &vm::Ctx, table_index: u32) -> &'a Table {
+    match TableIndex::new(table_index as usize).local_or_import(ctx.module_info()) {
+        LocalOrImport::Local(local_index) => &(&(*ctx.local_backing).tables)[local_index],
+        LocalOrImport::Import(import_index) => &(&(*ctx.import_backing).tables)[import_index],
+    }
+}
+
+/// Returns the funcref value of the function at `func_index`.
+unsafe fn func_ref(ctx: &mut vm::Ctx, func_index: FuncIndex) -> u64 {
+    let anyfunc = backing::anyfunc(&*ctx.module, &*ctx.import_backing, ctx, func_index);
+    (*ctx.local_backing).func_refs.get(anyfunc)
+}
+
+/// Reads the element at `index` in `table` as a reference value.
+unsafe fn get_ref(ctx: &vm::Ctx, table: &Table, index: usize) -> u64 {
+    match table.descriptor().element {
+        ElementType::Anyfunc => {
+            let anyfunc = table.anyfunc_direct_access_mut(|elements| elements[index]);
+            (*ctx.local_backing).func_refs.get(anyfunc)
+        }
+        ElementType::AnyRef => table.anyref_direct_access_mut(|elements| elements[index].to_bits()),
+    }
+}
+
+/// Writes a reference value to the element at `index` in `table`.
+///
+/// Validation guarantees that only funcref values are written
+/// to `anyfunc` tables.
+unsafe fn set_ref(table: &Table, index: usize, value: u64) {
+    match table.descriptor().element {
+        ElementType::Anyfunc => {
+            let anyfunc = if value == 0 {
+                vm::Anyfunc::null()
+            } else {
+                *(value as *const vm::Anyfunc)
+            };
+            table.anyfunc_direct_access_mut(|elements| elements[index] = anyfunc);
+        }
+        ElementType::AnyRef => {
+            table.anyref_direct_access_mut(|elements| elements[index] = AnyRef::from_bits(value))
+        }
+    }
+}
+
+pub unsafe extern "C" fn ref_func(ctx: &mut vm::Ctx, func_index: u32) -> u64 {
+    func_ref(ctx, FuncIndex::new(func_index as usize))
+}
+
+/// Implements `table.get`. The compiler checks that `index` is in bounds.
+pub unsafe extern "C" fn table_get(ctx: &mut vm::Ctx, table_index: u32, index: u32) -> u64 {
+    get_ref(ctx, vm_table(ctx, table_index), index as usize)
+}
+
+/// Implements `table.set`. The compiler checks that `index` is in bounds.
+pub unsafe extern "C" fn table_set(ctx: &mut vm::Ctx, table_index: u32, index: u32, value: u64) {
+    set_ref(vm_table(ctx, table_index), index as usize, value)
+}
+
+pub unsafe extern "C" fn table_size(ctx: &mut vm::Ctx, table_index: u32) -> u32 {
+    vm_table(ctx, table_index).size()
+}
+
+/// Ask the instance's limiter, if it has one, whether
+/// `table` may grow by `delta` elements from `current`.
+unsafe fn table_may_grow(ctx: &mut vm::Ctx, table: TableIndex, current: u32, delta: u32) -> bool {
+    match &mut (*ctx.local_backing).limiter {
+        Some(limiter) if delta > 0 => match current.checked_add(delta) {
+            Some(desired) => limiter.table_growing(table, current, desired),
+            None => false,
+        },
+        _ => true,
+    }
+}
+
+/// Implements `table.grow`, which fills the new elements with `value`.
+///
+/// Returns the previous size of the table, or -1 if it can't grow.
+pub unsafe extern "C" fn table_grow(
     ctx: &mut vm::Ctx,
-    table_index: LocalTableIndex,
+    table_index: u32,
+    value: u64,
     delta: u32,
 ) -> i32 {
-    let _ = table_index;
-    let _ = delta;
-    let _ = ctx;
-    unimplemented!()
+    let table = vm_table(ctx, table_index);
+    let index = TableIndex::new(table_index as usize);
+    if !table_may_grow(ctx, index, table.size(), delta) {
+        return -1;
+    }
+
+    match table.grow(delta) {
+        Ok(old_size) => {
+            for index in old_size..old_size + delta {
+                set_ref(table, index as usize, value);
+            }
+            old_size as i32
+        }
+        Err(_) => -1,
+    }
+}
+
+/// Implements `table.fill`. Like the bulk memory vmcalls, returns 1 without
+/// changing anything when part of the range is out of bounds, and 0 otherwise.
+pub unsafe extern "C" fn table_fill(
+    ctx: &mut vm::Ctx,
+    table_index: u32,
+    dst: u32,
+    value: u64,
+    len: u32,
+) -> u32 {
+    let table = vm_table(ctx, table_index);
+    if !in_bounds(dst, len, table.size() as usize) {
+        return 1;
+    }
+
+    for index in dst..dst + len {
+        set_ref(table, index as usize, value);
+    }
+    0
+}
+
+// +*****************************+
+// |            SIMD             |
+// +****************************+
+
+// A `v128` is passed as two 64-bit halves, the low one first, and these
+// write one that they return to `returns`. `opcode` is the opcode of the
+// instruction after the `0xfd` prefix (see the `simd` module).
+
+/// Writes `value` to the return buffer of a SIMD vmcall.
+unsafe fn return_v128(returns: *mut u64, value: u128) {
+    *returns = value as u64;
+    *returns.add(1) = (value >> 64) as u64;
+}
+
+fn v128(low: u64, high: u64) -> u128 {
+    u128::from(low) | u128::from(high) << 64
+}
+
+pub unsafe extern "C" fn simd_splat(opcode: u32, value: u64, returns: *mut u64) {
+    return_v128(returns, simd::splat(opcode, value));
+}
+
+pub unsafe extern "C" fn simd_extract_lane(opcode: u32, lane: u32, low: u64, high: u64) -> u64 {
+    simd::extract_lane(opcode, lane as usize, v128(low, high))
+}
+
+pub unsafe extern "C" fn simd_replace_lane(
+    opcode: u32,
+    lane: u32,
+    low: u64,
+    high: u64,
+    value: u64,
+    returns: *mut u64,
+) {
+    return_v128(
+        returns,
+        simd::replace_lane(opcode, lane as usize, v128(low, high), value),
+    );
+}
+
+pub unsafe extern "C" fn simd_unary(opcode: u32, low: u64, high: u64, returns: *mut u64) {
+    return_v128(returns, simd::unary(opcode, v128(low, high)));
+}
+
+pub unsafe extern "C" fn simd_binary(
+    opcode: u32,
+    a_low: u64,
+    a_high: u64,
+    b_low: u64,
+    b_high: u64,
+    returns: *mut u64,
+) {
+    return_v128(
+        returns,
+        simd::binary(opcode, v128(a_low, a_high), v128(b_low, b_high)),
+    );
 }
 
-pub unsafe extern "C" fn local_table_size(ctx: &vm::Ctx, table_index: LocalTableIndex) -> u32 {
-    let _ = table_index;
-    let _ = ctx;
-    unimplemented!()
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn simd_ternary(
+    opcode: u32,
+    a_low: u64,
+    a_high: u64,
+    b_low: u64,
+    b_high: u64,
+    c_low: u64,
+    c_high: u64,
+    returns: *mut u64,
+) {
+    return_v128(
+        returns,
+        simd::ternary(
+            opcode,
+            v128(a_low, a_high),
+            v128(b_low, b_high),
+            v128(c_low, c_high),
+        ),
+    );
 }
@@ -74,28 +74,36 @@
 //! [`wasmer-clif-backend`]: https://crates.io/crates/wasmer-clif-backend
 //! [`compile_with`]: fn.compile_with.html
 
+pub use wasmer_runtime_core::backend::{CompilerConfig, Features};
 pub use wasmer_runtime_core::global::Global;
 pub use wasmer_runtime_core::import::ImportObject;
-pub use wasmer_runtime_core::instance::{DynFunc, Instance};
+pub use wasmer_runtime_core::instance::{DynFunc, Instance, InterruptHandle};
+pub use wasmer_runtime_core::limits::{InstanceLimits, ResourceLimiter};
 pub use wasmer_runtime_core::memory::Memory;
 pub use wasmer_runtime_core::module::Module;
+pub use wasmer_runtime_core::snapshot::Snapshot;
 pub use wasmer_runtime_core::table::Table;
 pub use wasmer_runtime_core::types::Value;
 pub use wasmer_runtime_core::vm::Ctx;
 
-pub use wasmer_runtime_core::Func;
 pub use wasmer_runtime_core::{compile_with, validate};
 pub use wasmer_runtime_core::{func, imports};
+pub use wasmer_runtime_core::{DynamicFunc, Func};
 
 pub mod memory {
-    pub use wasmer_runtime_core::memory::{Atomic, Atomically, Memory, MemoryView};
+    pub use wasmer_runtime_core::memory::{
+        Array, Atomic, Atomically, Item, Memory, MemoryView, WasmPtr,
+    };
 }
 
 pub mod wasm {
     //! Various types exposed by the Wasmer Runtime.
     pub use wasmer_runtime_core::global::Global;
+    pub use wasmer_runtime_core::module::{ExportDescriptor, ExternDescriptor, ImportDescriptor};
     pub use wasmer_runtime_core::table::Table;
-    pub use wasmer_runtime_core::types::{FuncSig, MemoryDescriptor, TableDescriptor, Type, Value};
+    pub use wasmer_runtime_core::types::{
+        AnyRef, FuncRef, FuncSig, GlobalDescriptor, MemoryDescriptor, TableDescriptor, Type, Value,
+    };
 }
 
 pub mod error {
@@ -129,6 +137,19 @@ pub fn compile(wasm: &[u8]) -> error::CompileResult<Module> {
     wasmer_runtime_core::compile_with(&wasm[..], default_compiler())
 }
 
+/// The same as [`compile`] but takes a [`CompilerConfig`]
+/// for the purpose of changing the compiler's behavior,
+/// for example, to enable fuel metering.
+///
+/// [`compile`]: fn.compile.html
+/// [`CompilerConfig`]: struct.CompilerConfig.html
+pub fn compile_with_config(
+    wasm: &[u8],
+    compiler_config: CompilerConfig,
+) -> error::CompileResult<Module> {
+    wasmer_runtime_core::compile_with_config(wasm, default_compiler(), compiler_config)
+}
+
 /// Compile and instantiate WebAssembly code without
 /// creating a [`Module`].
 ///
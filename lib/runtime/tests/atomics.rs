@@ -0,0 +1,31 @@
+use wasmer_runtime::{compile, error::CompileError, imports, instantiate, Value};
+
+#[test]
+fn wait_on_unshared_memory_is_rejected() {
+    let wasm = wat::parse_str(
+        r#"(module
+             (memory 1)
+             (func (export "wait") (result i32)
+               (memory.atomic.wait32 (i32.const 0) (i32.const 0) (i64.const -1))))"#,
+    )
+    .unwrap();
+
+    match compile(&wasm) {
+        Err(CompileError::ValidationError { .. }) => {}
+        result => panic!("expected a validation error, got {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn wait_on_shared_memory_returns_when_not_equal() {
+    let wasm = wat::parse_str(
+        r#"(module
+             (memory 1 1 shared)
+             (func (export "wait") (result i32)
+               (memory.atomic.wait32 (i32.const 0) (i32.const 1) (i64.const -1))))"#,
+    )
+    .unwrap();
+    let instance = instantiate(&wasm, &imports! {}).unwrap();
+
+    assert_eq!(instance.call("wait", &[]).unwrap(), vec![Value::I32(1)]);
+}
@@ -0,0 +1,66 @@
+use wasmer_runtime::{
+    error::{CallError, RuntimeError, TrapKind},
+    func, imports, instantiate, Ctx,
+};
+use wasmer_runtime_core::structures::TypedIndex;
+
+fn nop(_ctx: &mut Ctx) {}
+
+#[test]
+fn backtrace_lists_the_calls_that_trapped() {
+    let wasm = wat::parse_str(
+        r#"(module
+             (import "env" "nop" (func $nop))
+             (func $outer (export "outer")
+               (call $nop)
+               (call $inner))
+             (func $inner
+               (unreachable)))"#,
+    )
+    .unwrap();
+    let import_object = imports! {
+        "env" => {
+            "nop" => func!(nop),
+        },
+    };
+    let instance = instantiate(&wasm, &import_object).unwrap();
+
+    let backtrace = match instance.call("outer", &[]) {
+        Err(CallError::Runtime(RuntimeError::Trap {
+            kind: TrapKind::Unreachable,
+            backtrace,
+        })) => backtrace,
+        result => panic!("expected a trap, got {:?}", result),
+    };
+
+    assert_eq!(backtrace.len(), 2);
+    assert_eq!(backtrace[0].func_index.index(), 2);
+    assert_eq!(backtrace[0].func_name.as_ref().unwrap(), "inner");
+    assert!(backtrace[0].module_offset.is_some());
+    assert_eq!(backtrace[1].func_index.index(), 1);
+    assert_eq!(backtrace[1].func_name.as_ref().unwrap(), "outer");
+}
+
+#[test]
+fn stack_overflow_backtrace_is_capped() {
+    let wasm = wat::parse_str(
+        r#"(module
+             (func $recurse (export "recurse")
+               (call $recurse)))"#,
+    )
+    .unwrap();
+    let instance = instantiate(&wasm, &imports! {}).unwrap();
+
+    let backtrace = match instance.call("recurse", &[]) {
+        Err(CallError::Runtime(RuntimeError::Trap {
+            kind: TrapKind::StackOverflow,
+            backtrace,
+        })) => backtrace,
+        result => panic!("expected a stack overflow, got {:?}", result),
+    };
+
+    assert_eq!(backtrace.len(), 1000);
+    assert!(backtrace
+        .iter()
+        .all(|frame| frame.func_name.as_ref().unwrap() == "recurse"));
+}
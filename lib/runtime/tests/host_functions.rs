@@ -0,0 +1,97 @@
+use std::sync::{
+    atomic::{AtomicI32, Ordering},
+    Arc,
+};
+use wasmer_runtime::{
+    error::{CallError, ExitCode, RuntimeError},
+    func, imports, instantiate,
+    wasm::{FuncSig, Type},
+    Ctx, DynamicFunc, Value,
+};
+
+static WAT: &str = r#"(module
+  (import "env" "add" (func $add (param i32) (result i32)))
+  (func (export "add_twice") (param i32) (result i32)
+    (call $add (local.get 0))
+    (drop)
+    (call $add (local.get 0))))"#;
+
+#[test]
+fn wasm_calls_a_capturing_closure() {
+    let total = Arc::new(AtomicI32::new(100));
+    let import_object = {
+        let total = Arc::clone(&total);
+        imports! {
+            "env" => {
+                "add" => func!(move |_ctx: &mut Ctx, n: i32| -> i32 {
+                    total.fetch_add(n, Ordering::SeqCst) + n
+                }),
+            },
+        }
+    };
+    let wasm = wat::parse_str(WAT).unwrap();
+    let instance = instantiate(&wasm, &import_object).unwrap();
+
+    assert_eq!(
+        instance.call("add_twice", &[Value::I32(5)]).unwrap(),
+        vec![Value::I32(110)]
+    );
+    assert_eq!(total.load(Ordering::SeqCst), 110);
+}
+
+#[test]
+fn wasm_calls_a_dynamic_function() {
+    let mul_add = DynamicFunc::new(
+        Arc::new(FuncSig::new(
+            vec![Type::I64, Type::F64, Type::I32],
+            vec![Type::F64],
+        )),
+        |_ctx, args| match args {
+            [Value::I64(a), Value::F64(b), Value::I32(c)] => {
+                Ok(vec![Value::F64(*a as f64 * b + f64::from(*c))])
+            }
+            _ => Err("unexpected arguments"),
+        },
+    );
+    let import_object = imports! {
+        "env" => {
+            "mul_add" => mul_add,
+        },
+    };
+    let wasm = wat::parse_str(
+        r#"(module
+             (import "env" "mul_add" (func $mul_add (param i64 f64 i32) (result f64)))
+             (func (export "run") (result f64)
+               (call $mul_add (i64.const 3) (f64.const 1.5) (i32.const -2))))"#,
+    )
+    .unwrap();
+    let instance = instantiate(&wasm, &import_object).unwrap();
+
+    assert_eq!(instance.call("run", &[]).unwrap(), vec![Value::F64(2.5)]);
+}
+
+#[test]
+fn host_function_exits_with_a_code() {
+    let import_object = imports! {
+        "env" => {
+            "add" => func!(|_ctx: &mut Ctx, n: i32| -> Result<i32, ExitCode> {
+                if n < 0 {
+                    Err(ExitCode(-n))
+                } else {
+                    Ok(n)
+                }
+            }),
+        },
+    };
+    let wasm = wat::parse_str(WAT).unwrap();
+    let instance = instantiate(&wasm, &import_object).unwrap();
+
+    assert_eq!(
+        instance.call("add_twice", &[Value::I32(5)]).unwrap(),
+        vec![Value::I32(5)]
+    );
+    match instance.call("add_twice", &[Value::I32(-3)]) {
+        Err(CallError::Runtime(RuntimeError::Exit { code })) => assert_eq!(code, 3),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
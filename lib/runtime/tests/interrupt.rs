@@ -0,0 +1,57 @@
+use std::{thread, time::Duration};
+use wasmer_runtime::{
+    compile_with_config,
+    error::{CallError, RuntimeError, TrapKind},
+    imports, CompilerConfig, Instance, Value,
+};
+
+static WAT: &str = r#"(module
+  (func (export "spin")
+    (loop $forever (br $forever)))
+  (func (export "one") (result i32)
+    (i32.const 1)))"#;
+
+fn instantiate_interruptible() -> Instance {
+    let wasm = wat::parse_str(WAT).unwrap();
+    let config = CompilerConfig {
+        interruptible: true,
+        ..Default::default()
+    };
+    let module = compile_with_config(&wasm, config).unwrap();
+    module.instantiate(&imports! {}).unwrap()
+}
+
+fn assert_interrupted(result: Result<Vec<Value>, CallError>) {
+    match result {
+        Err(CallError::Runtime(RuntimeError::Trap {
+            kind: TrapKind::Interrupted,
+            ..
+        })) => {}
+        result => panic!("expected an interrupt, got {:?}", result),
+    }
+}
+
+#[test]
+fn interrupt_stops_a_running_loop() {
+    let instance = instantiate_interruptible();
+    let handle = instance.interrupt_handle();
+
+    let interrupter = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        handle.interrupt();
+    });
+    assert_interrupted(instance.call("spin", &[]));
+    interrupter.join().unwrap();
+
+    // Trapping cleared the interrupt.
+    assert_eq!(instance.call("one", &[]).unwrap(), vec![Value::I32(1)]);
+}
+
+#[test]
+fn pending_interrupt_stops_the_next_call_only() {
+    let instance = instantiate_interruptible();
+
+    instance.interrupt_handle().interrupt();
+    assert_interrupted(instance.call("one", &[]));
+    assert_eq!(instance.call("one", &[]).unwrap(), vec![Value::I32(1)]);
+}
@@ -0,0 +1,126 @@
+use wasmer_runtime::{
+    compile_with_config, error::Error, error::LinkError, imports, units::Pages, CompilerConfig,
+    Features, ImportObject, Instance, InstanceLimits, ResourceLimiter, Value,
+};
+use wasmer_runtime_core::{
+    structures::TypedIndex,
+    types::{MemoryIndex, TableIndex},
+};
+
+fn instantiate_limited(wat: &str, import_object: &ImportObject) -> Result<Instance, Error> {
+    let wasm = wat::parse_str(wat).unwrap();
+    let config = CompilerConfig {
+        features: Features {
+            // For `table.grow`.
+            bulk_memory: true,
+            reference_types: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let module = compile_with_config(&wasm, config).unwrap();
+    module.instantiate(import_object)
+}
+
+fn call_i32(instance: &Instance, name: &str, args: &[Value]) -> i32 {
+    match instance.call(name, args).unwrap()[..] {
+        [Value::I32(x)] => x,
+        ref results => panic!("{} returned {:?}", name, results),
+    }
+}
+
+fn limited_imports(max_memory_pages: u32, max_table_elements: u32) -> ImportObject {
+    let mut import_object = imports! {};
+    import_object
+        .set_limiter(move || InstanceLimits::new(Pages(max_memory_pages), max_table_elements));
+    import_object
+}
+
+static MEMORY_WAT: &str = r#"(module
+  (memory 1)
+  (func (export "grow") (param i32) (result i32)
+    (memory.grow (local.get 0)))
+  (func (export "size") (result i32)
+    (memory.size)))"#;
+
+static TABLE_WAT: &str = r#"(module
+  (table $funcs 2 funcref)
+  (func $f)
+  (func (export "grow") (param i32) (result i32)
+    (table.grow $funcs (ref.func $f) (local.get 0)))
+  (func (export "size") (result i32)
+    (table.size $funcs)))"#;
+
+#[test]
+fn memory_grow_beyond_the_limit() {
+    let instance = instantiate_limited(MEMORY_WAT, &limited_imports(3, 0)).unwrap();
+
+    assert_eq!(call_i32(&instance, "grow", &[Value::I32(2)]), 1);
+    assert_eq!(call_i32(&instance, "grow", &[Value::I32(1)]), -1);
+    assert_eq!(call_i32(&instance, "size", &[]), 3);
+    // Growing by nothing is always allowed.
+    assert_eq!(call_i32(&instance, "grow", &[Value::I32(0)]), 3);
+
+    // Every instance gets a limiter of its own.
+    let other = instantiate_limited(MEMORY_WAT, &limited_imports(3, 0)).unwrap();
+    assert_eq!(call_i32(&other, "grow", &[Value::I32(2)]), 1);
+}
+
+#[test]
+fn table_grow_beyond_the_limit() {
+    let instance = instantiate_limited(TABLE_WAT, &limited_imports(0, 4)).unwrap();
+
+    assert_eq!(call_i32(&instance, "grow", &[Value::I32(2)]), 2);
+    assert_eq!(call_i32(&instance, "grow", &[Value::I32(1)]), -1);
+    assert_eq!(call_i32(&instance, "size", &[]), 4);
+}
+
+#[test]
+fn limiter_vetoes_a_grow() {
+    struct OddPagesOnly;
+
+    impl ResourceLimiter for OddPagesOnly {
+        fn memory_growing(&mut self, _: MemoryIndex, _: Pages, desired: Pages) -> bool {
+            desired.0 % 2 == 1
+        }
+
+        fn table_growing(&mut self, _: TableIndex, _: u32, _: u32) -> bool {
+            true
+        }
+    }
+
+    let mut import_object = imports! {};
+    import_object.set_limiter(|| OddPagesOnly);
+    let instance = instantiate_limited(MEMORY_WAT, &import_object).unwrap();
+
+    assert_eq!(call_i32(&instance, "grow", &[Value::I32(1)]), -1);
+    assert_eq!(call_i32(&instance, "grow", &[Value::I32(2)]), 1);
+    assert_eq!(call_i32(&instance, "size", &[]), 3);
+}
+
+#[test]
+fn instantiation_beyond_the_limit() {
+    match instantiate_limited(MEMORY_WAT, &limited_imports(0, 0)) {
+        Err(Error::LinkError(errors)) => match errors[..] {
+            [LinkError::MemoryLimitExceeded { memory, pages }] => {
+                assert_eq!(memory.index(), 0);
+                assert_eq!(pages, Pages(1));
+            }
+            ref errors => panic!("unexpected link errors {:?}", errors),
+        },
+        Err(error) => panic!("unexpected error {:?}", error),
+        Ok(_) => panic!("instantiated beyond the memory limit"),
+    }
+
+    match instantiate_limited(TABLE_WAT, &limited_imports(0, 1)) {
+        Err(Error::LinkError(errors)) => match errors[..] {
+            [LinkError::TableLimitExceeded { table, elements }] => {
+                assert_eq!(table.index(), 0);
+                assert_eq!(elements, 2);
+            }
+            ref errors => panic!("unexpected link errors {:?}", errors),
+        },
+        Err(error) => panic!("unexpected error {:?}", error),
+        Ok(_) => panic!("instantiated beyond the table limit"),
+    }
+}
@@ -0,0 +1,49 @@
+use wasmer_runtime::{compile, Module, Value};
+use wasmer_runtime_core::linker::Linker;
+
+fn lib(value: i32) -> Module {
+    let wasm = wat::parse_str(format!(
+        r#"(module
+             (func (export "get") (result i32)
+               (i32.const {})))"#,
+        value
+    ))
+    .unwrap();
+    compile(&wasm).unwrap()
+}
+
+fn app() -> Module {
+    let wasm = wat::parse_str(
+        r#"(module
+             (import "lib" "get" (func $get (result i32)))
+             (func (export "run") (result i32)
+               (call $get)))"#,
+    )
+    .unwrap();
+    compile(&wasm).unwrap()
+}
+
+#[test]
+fn instances_outlive_replaced_registrations() {
+    let mut linker = Linker::new();
+    let app = app();
+
+    let lib_instance = linker.instantiate(&lib(1)).unwrap();
+    linker.register("lib", lib_instance);
+    let first = linker.instantiate(&app).unwrap();
+    linker.register("app", linker.instantiate(&app).unwrap());
+
+    // This drops the `Linker`'s references to the first `lib` instance.
+    let lib_instance = linker.instantiate(&lib(2)).unwrap();
+    linker.register("lib", lib_instance);
+    let second = linker.instantiate(&app).unwrap();
+
+    assert_eq!(
+        linker.instance("app").unwrap().call("run", &[]).unwrap(),
+        vec![Value::I32(1)]
+    );
+    drop(linker);
+
+    assert_eq!(first.call("run", &[]).unwrap(), vec![Value::I32(1)]);
+    assert_eq!(second.call("run", &[]).unwrap(), vec![Value::I32(2)]);
+}
@@ -0,0 +1,44 @@
+use wasmer_runtime::{
+    compile_with_config,
+    error::{CallError, RuntimeError, TrapKind},
+    imports, CompilerConfig, Value,
+};
+
+#[test]
+fn refilled_instance_runs_after_out_of_fuel() {
+    let wasm = wat::parse_str(
+        r#"(module
+             (func (export "count") (param $n i32) (result i32)
+               (local $i i32)
+               (block $done
+                 (loop $next
+                   (br_if $done (i32.ge_u (local.get $i) (local.get $n)))
+                   (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                   (br $next)))
+               (local.get $i)))"#,
+    )
+    .unwrap();
+    let config = CompilerConfig {
+        metering: true,
+        ..Default::default()
+    };
+    let module = compile_with_config(&wasm, config).unwrap();
+    let mut instance = module.instantiate(&imports! {}).unwrap();
+
+    instance.set_fuel(100);
+    match instance.call("count", &[Value::I32(1_000)]) {
+        Err(CallError::Runtime(RuntimeError::Trap {
+            kind: TrapKind::OutOfFuel,
+            ..
+        })) => {}
+        result => panic!("expected to run out of fuel, got {:?}", result),
+    }
+    assert!(instance.fuel() < 100);
+
+    instance.set_fuel(1_000_000);
+    assert_eq!(
+        instance.call("count", &[Value::I32(1_000)]).unwrap(),
+        vec![Value::I32(1_000)]
+    );
+    assert!(instance.fuel() < 1_000_000);
+}
@@ -0,0 +1,175 @@
+use tempfile::tempdir;
+use wasmer_runtime::{
+    cache::{Cache, FileSystemCache, WasmHash},
+    compile, compile_with_config,
+    units::Pages,
+    wasm::{ExternDescriptor, FuncSig, GlobalDescriptor, MemoryDescriptor, Type},
+    CompilerConfig, Features, Module,
+};
+use wasmer_runtime_core::types::ElementType;
+
+fn compile_wat(wat: &str) -> Module {
+    let wasm = wat::parse_str(wat).unwrap();
+    compile(&wasm).unwrap()
+}
+
+// Declares its imports out of kind order, to check how they're listed.
+static IMPORTS_WAT: &str = r#"(module
+  (import "env" "table" (table 2 10 funcref))
+  (import "env" "print" (func $print (param i32)))
+  (import "env" "memory" (memory 1 4 shared))
+  (import "globals" "offset" (global $offset i32))
+  (import "env" "now" (func $now (result f64)))
+  (global $counter (mut i64) (i64.const 0))
+  (func $run (param f32) (result i32)
+    (global.get $offset))
+
+  (export "run" (func $run))
+  (export "print" (func $print))
+  (export "memory" (memory 0))
+  (export "table" (table 0))
+  (export "counter" (global $counter))
+  (export "offset" (global $offset)))"#;
+
+fn assert_function(ty: &ExternDescriptor, params: &[Type], returns: &[Type]) {
+    match ty {
+        ExternDescriptor::Function(sig) => {
+            assert_eq!(**sig, FuncSig::new(params.to_vec(), returns.to_vec()))
+        }
+        ty => panic!("expected a function, got {:?}", ty),
+    }
+}
+
+fn assert_memory(ty: &ExternDescriptor) {
+    match ty {
+        ExternDescriptor::Memory(desc) => assert_eq!(
+            *desc,
+            MemoryDescriptor {
+                minimum: Pages(1),
+                maximum: Some(Pages(4)),
+                shared: true,
+            }
+        ),
+        ty => panic!("expected a memory, got {:?}", ty),
+    }
+}
+
+fn assert_table(ty: &ExternDescriptor) {
+    match ty {
+        ExternDescriptor::Table(desc) => {
+            assert_eq!(desc.element, ElementType::Anyfunc);
+            assert_eq!(desc.minimum, 2);
+            assert_eq!(desc.maximum, Some(10));
+        }
+        ty => panic!("expected a table, got {:?}", ty),
+    }
+}
+
+fn assert_global(ty: &ExternDescriptor, mutable: bool, global_ty: Type) {
+    match ty {
+        ExternDescriptor::Global(desc) => assert_eq!(
+            *desc,
+            GlobalDescriptor {
+                mutable,
+                ty: global_ty,
+            }
+        ),
+        ty => panic!("expected a global, got {:?}", ty),
+    }
+}
+
+#[test]
+fn module_imports() {
+    let module = compile_wat(IMPORTS_WAT);
+    let imports = module.imports();
+
+    let names: Vec<_> = imports
+        .iter()
+        .map(|import| (import.namespace.as_str(), import.name.as_str()))
+        .collect();
+    assert_eq!(
+        names,
+        [
+            ("env", "print"),
+            ("env", "now"),
+            ("env", "memory"),
+            ("env", "table"),
+            ("globals", "offset"),
+        ]
+    );
+
+    assert_function(&imports[0].ty, &[Type::I32], &[]);
+    assert_function(&imports[1].ty, &[], &[Type::F64]);
+    assert_memory(&imports[2].ty);
+    assert_table(&imports[3].ty);
+    assert_global(&imports[4].ty, false, Type::I32);
+}
+
+#[test]
+fn module_exports() {
+    let module = compile_wat(IMPORTS_WAT);
+    let mut exports = module.exports();
+    exports.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let names: Vec<_> = exports.iter().map(|export| export.name.as_str()).collect();
+    assert_eq!(
+        names,
+        ["counter", "memory", "offset", "print", "run", "table"]
+    );
+
+    assert_global(&exports[0].ty, true, Type::I64);
+    // The imported memory, table and globals are exported with their types.
+    assert_memory(&exports[1].ty);
+    assert_global(&exports[2].ty, false, Type::I32);
+    assert_function(&exports[3].ty, &[Type::I32], &[]);
+    assert_function(&exports[4].ty, &[Type::F32], &[Type::I32]);
+    assert_table(&exports[5].ty);
+}
+
+/// Appends a custom section named `name` to `wasm`.
+fn push_custom_section(wasm: &mut Vec<u8>, name: &str, contents: &[u8]) {
+    // The names and contents are short enough for one byte LEB128 sizes.
+    wasm.push(0);
+    wasm.push((1 + name.len() + contents.len()) as u8);
+    wasm.push(name.len() as u8);
+    wasm.extend_from_slice(name.as_bytes());
+    wasm.extend_from_slice(contents);
+}
+
+fn notes(module: &Module) -> Vec<&[u8]> {
+    module.custom_sections("note").collect()
+}
+
+#[test]
+fn module_custom_sections() {
+    // Returning two values has the module lowered before it's translated.
+    let mut wasm = wat::parse_str(
+        r#"(module
+             (func (export "pair") (result i32 i32)
+               (i32.const 1)
+               (i32.const 2)))"#,
+    )
+    .unwrap();
+    push_custom_section(&mut wasm, "note", b"first");
+    push_custom_section(&mut wasm, "other", b"skipped");
+    push_custom_section(&mut wasm, "note", b"second");
+
+    let config = CompilerConfig {
+        features: Features {
+            multi_value: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let module = compile_with_config(&wasm, config).unwrap();
+    assert_eq!(notes(&module), [&b"first"[..], &b"second"[..]]);
+    assert_eq!(module.custom_sections("missing").count(), 0);
+
+    // The custom sections are kept in the cache.
+    let cache_dir = tempdir().unwrap();
+    let mut cache = unsafe { FileSystemCache::new(cache_dir.path()).unwrap() };
+    let key = WasmHash::generate(&wasm);
+    cache.store(key, module).unwrap();
+    let cached = cache.load(key).unwrap();
+    assert_eq!(notes(&cached), [&b"first"[..], &b"second"[..]]);
+}
@@ -0,0 +1,159 @@
+use std::sync::Arc;
+use wasmer_runtime::{
+    compile, compile_with_config,
+    error::CompileError,
+    func, imports,
+    wasm::{FuncSig, Type},
+    CompilerConfig, Ctx, DynamicFunc, Features, Func, ImportObject, Instance, Value,
+};
+
+fn instantiate_multi_value(wat: &str, import_object: &ImportObject) -> Instance {
+    let wasm = wat::parse_str(wat).unwrap();
+    let config = CompilerConfig {
+        features: Features {
+            multi_value: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let module = compile_with_config(&wasm, config).unwrap();
+    module.instantiate(import_object).unwrap()
+}
+
+#[test]
+fn typed_func_returns_a_tuple() {
+    let instance = instantiate_multi_value(
+        r#"(module
+             (func (export "split") (param i32) (result i32 i64 f64)
+               (local.get 0)
+               (i64.extend_i32_s (i32.mul (local.get 0) (i32.const 2)))
+               (f64.convert_i32_s (i32.mul (local.get 0) (i32.const 3)))))"#,
+        &imports! {},
+    );
+
+    let split: Func<i32, (i32, i64, f64)> = instance.func("split").unwrap();
+    assert_eq!(split.call(-7).unwrap(), (-7, -14, -21.0));
+    assert_eq!(
+        instance.call("split", &[Value::I32(2)]).unwrap(),
+        vec![Value::I32(2), Value::I64(4), Value::F64(6.0)]
+    );
+}
+
+fn div_rem(_ctx: &mut Ctx, a: i32, b: i32) -> (i32, i32) {
+    (a / b, a % b)
+}
+
+static IMPORTED_WAT: &str = r#"(module
+  (import "env" "div_rem" (func $div_rem (param i32 i32) (result i32 i32)))
+  (func (export "div_rem_sum") (param i32 i32) (result i32)
+    (i32.add (call $div_rem (local.get 0) (local.get 1)))))"#;
+
+#[test]
+fn wasm_calls_a_host_function_returning_a_tuple() {
+    let import_object = imports! {
+        "env" => {
+            "div_rem" => func!(div_rem),
+        },
+    };
+    let instance = instantiate_multi_value(IMPORTED_WAT, &import_object);
+
+    assert_eq!(
+        instance
+            .call("div_rem_sum", &[Value::I32(17), Value::I32(5)])
+            .unwrap(),
+        vec![Value::I32(3 + 2)]
+    );
+}
+
+#[test]
+fn wasm_calls_a_dynamic_function_returning_several_values() {
+    let div_rem = DynamicFunc::new(
+        Arc::new(FuncSig::new(
+            vec![Type::I32, Type::I32],
+            vec![Type::I32, Type::I32],
+        )),
+        |_ctx, args| match args {
+            [Value::I32(a), Value::I32(b)] => Ok(vec![Value::I32(a / b), Value::I32(a % b)]),
+            _ => Err("unexpected arguments"),
+        },
+    );
+    let import_object = imports! {
+        "env" => {
+            "div_rem" => div_rem,
+        },
+    };
+    let instance = instantiate_multi_value(IMPORTED_WAT, &import_object);
+
+    assert_eq!(
+        instance
+            .call("div_rem_sum", &[Value::I32(23), Value::I32(4)])
+            .unwrap(),
+        vec![Value::I32(5 + 3)]
+    );
+}
+
+#[test]
+fn call_indirect_returns_several_values() {
+    let instance = instantiate_multi_value(
+        r#"(module
+             (type $pair (func (param i64) (result i64 i64)))
+             (func $dup (type $pair) (local.get 0) (local.get 0))
+             (func $neg (type $pair)
+               (local.get 0)
+               (i64.sub (i64.const 0) (local.get 0)))
+             (table funcref (elem $dup $neg))
+             (func (export "call") (param i32 i64) (result i64 i64)
+               (call_indirect (type $pair) (local.get 1) (local.get 0))))"#,
+        &imports! {},
+    );
+
+    assert_eq!(
+        instance
+            .call("call", &[Value::I32(0), Value::I64(9)])
+            .unwrap(),
+        vec![Value::I64(9), Value::I64(9)]
+    );
+    assert_eq!(
+        instance
+            .call("call", &[Value::I32(1), Value::I64(9)])
+            .unwrap(),
+        vec![Value::I64(9), Value::I64(-9)]
+    );
+}
+
+#[test]
+fn multi_value_is_rejected_unless_enabled() {
+    let wasm = wat::parse_str(
+        r#"(module
+             (func (export "pair") (result i32 i32)
+               (i32.const 1) (i32.const 2)))"#,
+    )
+    .unwrap();
+    match compile(&wasm) {
+        Err(CompileError::ValidationError { .. }) => {}
+        result => panic!("expected a validation error, got {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn blocks_with_function_types_are_rejected() {
+    let wasm = wat::parse_str(
+        r#"(module
+             (func (export "block") (result i32)
+               (block (result i32 i32)
+                 (i32.const 1) (i32.const 2))
+               (i32.add)))"#,
+    )
+    .unwrap();
+    let config = CompilerConfig {
+        features: Features {
+            multi_value: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    match compile_with_config(&wasm, config) {
+        Err(CompileError::ValidationError { msg }) => assert!(msg.contains("not supported")),
+        result => panic!("expected a validation error, got {:?}", result.map(|_| ())),
+    }
+}
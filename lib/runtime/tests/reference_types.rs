@@ -0,0 +1,191 @@
+use wasmer_runtime::{
+    compile_with_config, imports,
+    wasm::{AnyRef, FuncRef},
+    CompilerConfig, Features, Instance, Value,
+};
+
+fn instantiate_reference_types(wat: &str) -> Instance {
+    let wasm = wat::parse_str(wat).unwrap();
+    let config = CompilerConfig {
+        features: Features {
+            // The reference types proposal builds on the bulk memory one.
+            bulk_memory: true,
+            reference_types: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let module = compile_with_config(&wasm, config).unwrap();
+    module.instantiate(&imports! {}).unwrap()
+}
+
+fn call_i32(instance: &Instance, name: &str, args: &[Value]) -> i32 {
+    match instance.call(name, args).unwrap()[..] {
+        [Value::I32(x)] => x,
+        ref results => panic!("{} returned {:?}", name, results),
+    }
+}
+
+fn call_funcref(instance: &Instance, name: &str, args: &[Value]) -> FuncRef {
+    match instance.call(name, args).unwrap()[..] {
+        [Value::FuncRef(x)] => x,
+        ref results => panic!("{} returned {:?}", name, results),
+    }
+}
+
+// `ref.null` is left out, since the text format now gives it a type
+// that the binary format of the proposal this supports doesn't have.
+static TABLE_WAT: &str = r#"(module
+  (type $ret_i32 (func (result i32)))
+  (table $funcs 2 10 funcref)
+  (elem (i32.const 0) $seven)
+  (func $seven (result i32) (i32.const 7))
+  (func $eight (result i32) (i32.const 8))
+
+  (func (export "size") (result i32)
+    (table.size $funcs))
+  (func (export "grow") (param i32) (result i32)
+    (table.grow $funcs (ref.func $eight) (local.get 0)))
+  (func (export "fill") (param i32 i32)
+    (table.fill $funcs (local.get 0) (ref.func $eight) (local.get 1)))
+  (func (export "get") (param i32) (result funcref)
+    (table.get $funcs (local.get 0)))
+  (func (export "set") (param i32 funcref)
+    (table.set $funcs (local.get 0) (local.get 1)))
+  (func (export "is_null") (param i32) (result i32)
+    (ref.is_null (table.get $funcs (local.get 0))))
+  (func (export "ref_func") (result funcref)
+    (ref.func $eight))
+  (func (export "call") (param i32) (result i32)
+    (call_indirect $funcs (type $ret_i32) (local.get 0))))"#;
+
+#[test]
+fn table_get_set_and_size() {
+    let instance = instantiate_reference_types(TABLE_WAT);
+
+    assert_eq!(call_i32(&instance, "size", &[]), 2);
+    assert_eq!(call_i32(&instance, "is_null", &[Value::I32(0)]), 0);
+    assert_eq!(call_i32(&instance, "is_null", &[Value::I32(1)]), 1);
+
+    let seven = call_funcref(&instance, "get", &[Value::I32(0)]);
+    assert!(!seven.is_null());
+    assert!(call_funcref(&instance, "get", &[Value::I32(1)]).is_null());
+
+    instance
+        .call("set", &[Value::I32(1), Value::FuncRef(seven)])
+        .unwrap();
+    assert_eq!(call_i32(&instance, "call", &[Value::I32(1)]), 7);
+
+    instance
+        .call("set", &[Value::I32(0), Value::FuncRef(FuncRef::null())])
+        .unwrap();
+    assert_eq!(call_i32(&instance, "is_null", &[Value::I32(0)]), 1);
+    assert!(instance.call("call", &[Value::I32(0)]).is_err());
+
+    assert!(instance.call("get", &[Value::I32(2)]).is_err());
+    assert!(instance
+        .call("set", &[Value::I32(2), Value::FuncRef(seven)])
+        .is_err());
+}
+
+#[test]
+fn table_grow_and_fill() {
+    let instance = instantiate_reference_types(TABLE_WAT);
+
+    assert_eq!(call_i32(&instance, "grow", &[Value::I32(3)]), 2);
+    assert_eq!(call_i32(&instance, "size", &[]), 5);
+    assert_eq!(call_i32(&instance, "call", &[Value::I32(4)]), 8);
+
+    // The maximum is 10.
+    assert_eq!(call_i32(&instance, "grow", &[Value::I32(6)]), -1);
+    assert_eq!(call_i32(&instance, "size", &[]), 5);
+
+    instance
+        .call("fill", &[Value::I32(0), Value::I32(2)])
+        .unwrap();
+    assert_eq!(call_i32(&instance, "call", &[Value::I32(0)]), 8);
+    assert_eq!(call_i32(&instance, "call", &[Value::I32(1)]), 8);
+
+    // Nothing is written when part of the range is out of bounds.
+    instance
+        .call("set", &[Value::I32(4), Value::FuncRef(FuncRef::null())])
+        .unwrap();
+    assert!(instance
+        .call("fill", &[Value::I32(4), Value::I32(2)])
+        .is_err());
+    assert_eq!(call_i32(&instance, "is_null", &[Value::I32(4)]), 1);
+}
+
+#[test]
+fn ref_func_can_be_stored_and_called() {
+    let instance = instantiate_reference_types(TABLE_WAT);
+
+    let eight = call_funcref(&instance, "ref_func", &[]);
+    assert!(!eight.is_null());
+    instance
+        .call("set", &[Value::I32(1), Value::FuncRef(eight)])
+        .unwrap();
+    assert_eq!(call_i32(&instance, "call", &[Value::I32(1)]), 8);
+    assert_eq!(call_funcref(&instance, "get", &[Value::I32(1)]), eight);
+}
+
+#[test]
+fn call_indirect_through_a_second_table() {
+    let instance = instantiate_reference_types(
+        r#"(module
+             (type $ret_i32 (func (result i32)))
+             (table $first 1 funcref)
+             (table $second 2 funcref)
+             (elem (i32.const 0) $one)
+             (func $one (result i32) (i32.const 1))
+             (func $two (result i32) (i32.const 2))
+
+             (func (export "init")
+               (table.set $second (i32.const 1) (ref.func $two)))
+             (func (export "call_first") (param i32) (result i32)
+               (call_indirect $first (type $ret_i32) (local.get 0)))
+             (func (export "call_second") (param i32) (result i32)
+               (call_indirect $second (type $ret_i32) (local.get 0))))"#,
+    );
+
+    instance.call("init", &[]).unwrap();
+    assert_eq!(call_i32(&instance, "call_first", &[Value::I32(0)]), 1);
+    assert_eq!(call_i32(&instance, "call_second", &[Value::I32(1)]), 2);
+
+    // The second table's first element is null, and it has no third one.
+    assert!(instance.call("call_second", &[Value::I32(0)]).is_err());
+    assert!(instance.call("call_second", &[Value::I32(2)]).is_err());
+    assert!(instance.call("call_first", &[Value::I32(1)]).is_err());
+}
+
+#[test]
+fn host_references_round_trip_through_an_anyref_table() {
+    let instance = instantiate_reference_types(
+        r#"(module
+             (table $refs 1 externref)
+             (func (export "store") (param externref)
+               (table.set $refs (i32.const 0) (local.get 0)))
+             (func (export "load") (result externref)
+               (table.get $refs (i32.const 0))))"#,
+    );
+
+    let value = 42;
+    let anyref = unsafe { AnyRef::new(&value) };
+    instance.call("store", &[Value::AnyRef(anyref)]).unwrap();
+    assert_eq!(
+        instance.call("load", &[]).unwrap(),
+        vec![Value::AnyRef(anyref)]
+    );
+}
+
+#[test]
+fn host_anyref_isnt_accepted_as_a_funcref() {
+    let instance = instantiate_reference_types(TABLE_WAT);
+
+    // An `anyref` made by the host can't be passed as a `funcref`.
+    let value = 42;
+    let anyref = unsafe { AnyRef::new(&value) };
+    assert!(instance
+        .call("set", &[Value::I32(1), Value::AnyRef(anyref)])
+        .is_err());
+}
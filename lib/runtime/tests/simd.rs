@@ -0,0 +1,268 @@
+use std::sync::Arc;
+use wasmer_runtime::{
+    compile_with_config, imports,
+    wasm::{FuncSig, Type},
+    CompilerConfig, DynamicFunc, Features, ImportObject, Instance, Value,
+};
+
+fn simd_config() -> CompilerConfig {
+    CompilerConfig {
+        features: Features {
+            simd: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+fn instantiate_simd(wat: &str, import_object: &ImportObject) -> Instance {
+    let wasm = wat::parse_str(wat).unwrap();
+    let module = compile_with_config(&wasm, simd_config()).unwrap();
+    module.instantiate(import_object).unwrap()
+}
+
+fn call_v128(instance: &Instance, name: &str, args: &[Value]) -> u128 {
+    match instance.call(name, args).unwrap()[..] {
+        [Value::V128(x)] => x,
+        ref results => panic!("{} returned {:?}", name, results),
+    }
+}
+
+fn call_i32(instance: &Instance, name: &str, args: &[Value]) -> i32 {
+    match instance.call(name, args).unwrap()[..] {
+        [Value::I32(x)] => x,
+        ref results => panic!("{} returned {:?}", name, results),
+    }
+}
+
+static LANES_WAT: &str = r#"(module
+  (func (export "i32x4_add") (param v128 v128) (result v128)
+    (i32x4.add (local.get 0) (local.get 1)))
+  (func (export "i8x16_shuffle") (param v128 v128) (result v128)
+    (i8x16.shuffle 16 0 17 1 18 2 19 3 20 4 21 5 22 6 23 7
+      (local.get 0) (local.get 1)))
+  (func (export "bitselect") (param v128 v128 v128) (result v128)
+    (v128.bitselect (local.get 0) (local.get 1) (local.get 2)))
+  (func (export "sum_lanes") (param i32) (result i32)
+    (local $v v128)
+    (local.set $v (i32x4.splat (local.get 0)))
+    (local.set $v (i32x4.replace_lane 3 (local.get $v) (i32.const 100)))
+    (i32.add
+      (i32.add (i32x4.extract_lane 0 (local.get $v)) (i32x4.extract_lane 1 (local.get $v)))
+      (i32.add (i32x4.extract_lane 2 (local.get $v)) (i32x4.extract_lane 3 (local.get $v)))))
+  (func (export "f64x2_max_lane") (param f64 f64) (result f64)
+    (f64x2.extract_lane 1
+      (f64x2.max
+        (f64x2.replace_lane 1 (f64x2.splat (local.get 0)) (local.get 1))
+        (v128.const f64x2 0 0))))
+  (func (export "bitmask") (param v128) (result i32)
+    (i8x16.bitmask (local.get 0)))
+  (func (export "all_true") (param v128) (result i32)
+    (i16x8.all_true (local.get 0)))
+  (func (export "select") (param v128 v128 i32) (result v128)
+    (select (local.get 0) (local.get 1) (local.get 2)))
+  (func (export "block") (param i32) (result v128)
+    (block (result v128)
+      (br_if 0 (v128.const i64x2 1 2) (local.get 0))
+      (drop)
+      (v128.const i64x2 3 4))))"#;
+
+#[test]
+fn lane_instructions() {
+    let instance = instantiate_simd(LANES_WAT, &imports! {});
+
+    assert_eq!(
+        call_v128(
+            &instance,
+            "i32x4_add",
+            &[
+                Value::V128(0x0000_0004_0000_0003_0000_0002_ffff_ffff),
+                Value::V128(0x0000_0010_0000_0020_0000_0030_0000_0001),
+            ],
+        ),
+        0x0000_0014_0000_0023_0000_0032_0000_0000
+    );
+    assert_eq!(
+        call_v128(
+            &instance,
+            "i8x16_shuffle",
+            &[
+                Value::V128(0x0f0e_0d0c_0b0a_0908_0706_0504_0302_0100),
+                Value::V128(0x1f1e_1d1c_1b1a_1918_1716_1514_1312_1110),
+            ],
+        ),
+        0x0717_0616_0515_0414_0313_0212_0111_0010
+    );
+    assert_eq!(
+        call_v128(
+            &instance,
+            "bitselect",
+            &[
+                Value::V128(u128::max_value()),
+                Value::V128(0),
+                Value::V128(0xff00_ff00),
+            ],
+        ),
+        0xff00_ff00
+    );
+    assert_eq!(call_i32(&instance, "sum_lanes", &[Value::I32(7)]), 121);
+    assert_eq!(
+        instance
+            .call("f64x2_max_lane", &[Value::F64(1.0), Value::F64(-2.5)])
+            .unwrap(),
+        vec![Value::F64(0.0)]
+    );
+}
+
+#[test]
+fn boolean_reductions() {
+    let instance = instantiate_simd(LANES_WAT, &imports! {});
+
+    assert_eq!(
+        call_i32(&instance, "bitmask", &[Value::V128(0x8000_0000_0000_0080)]),
+        0b1000_0001
+    );
+    assert_eq!(
+        call_i32(
+            &instance,
+            "all_true",
+            &[Value::V128(0x0001_0001_0001_0001_0001_0001_0001_0001)]
+        ),
+        1
+    );
+    assert_eq!(
+        call_i32(
+            &instance,
+            "all_true",
+            &[Value::V128(0x0001_0001_0001_0001_0001_0000_0001_0001)]
+        ),
+        0
+    );
+}
+
+#[test]
+fn v128_control_flow() {
+    let instance = instantiate_simd(LANES_WAT, &imports! {});
+
+    let (a, b) = (Value::V128(1 << 100), Value::V128(5));
+    assert_eq!(
+        call_v128(&instance, "select", &[a.clone(), b.clone(), Value::I32(1)]),
+        1 << 100
+    );
+    assert_eq!(call_v128(&instance, "select", &[a, b, Value::I32(0)]), 5);
+    assert_eq!(call_v128(&instance, "block", &[Value::I32(1)]), 2 << 64 | 1);
+    assert_eq!(call_v128(&instance, "block", &[Value::I32(0)]), 4 << 64 | 3);
+}
+
+#[test]
+fn memory_instructions() {
+    let instance = instantiate_simd(
+        r#"(module
+             (memory 1)
+             (data (i32.const 16) "\00\01\02\03\04\05\06\07\08\09\0a\0b\0c\0d\0e\0f")
+             (data (i32.const 32) "\ff\fe\fd\fc")
+             (func (export "load") (param i32) (result v128)
+               (v128.load offset=1 (local.get 0)))
+             (func (export "store") (param i32 v128)
+               (v128.store (local.get 0) (local.get 1)))
+             (func (export "load8_splat") (param i32) (result v128)
+               (v128.load8_splat (local.get 0)))
+             (func (export "load8x8_s") (param i32) (result v128)
+               (v128.load8x8_s (local.get 0)))
+             (func (export "load32_zero") (param i32) (result v128)
+               (v128.load32_zero (local.get 0)))
+             (func (export "load16_lane") (param i32 v128) (result v128)
+               (v128.load16_lane 7 (local.get 0) (local.get 1)))
+             (func (export "store32_lane") (param i32 v128)
+               (v128.store32_lane 2 (local.get 0) (local.get 1)))
+             (func (export "load_i32") (param i32) (result i32)
+               (i32.load (local.get 0))))"#,
+        &imports! {},
+    );
+
+    assert_eq!(
+        call_v128(&instance, "load", &[Value::I32(15)]),
+        0x0f0e_0d0c_0b0a_0908_0706_0504_0302_0100
+    );
+    instance
+        .call("store", &[Value::I32(100), Value::V128(0xdead_beef << 64)])
+        .unwrap();
+    assert_eq!(
+        call_i32(&instance, "load_i32", &[Value::I32(108)]),
+        0xdead_beefu32 as i32
+    );
+    assert_eq!(
+        call_v128(&instance, "load8_splat", &[Value::I32(20)]),
+        0x0404_0404_0404_0404_0404_0404_0404_0404
+    );
+    assert_eq!(
+        call_v128(&instance, "load8x8_s", &[Value::I32(30)]),
+        0x0000_0000_fffc_fffd_fffe_ffff_000f_000e
+    );
+    assert_eq!(
+        call_v128(&instance, "load32_zero", &[Value::I32(16)]),
+        0x0302_0100
+    );
+    assert_eq!(
+        call_v128(&instance, "load16_lane", &[Value::I32(16), Value::V128(0)]),
+        0x0100 << 112
+    );
+    instance
+        .call(
+            "store32_lane",
+            &[Value::I32(200), Value::V128(0x1234_5678 << 64)],
+        )
+        .unwrap();
+    assert_eq!(
+        call_i32(&instance, "load_i32", &[Value::I32(200)]),
+        0x1234_5678
+    );
+
+    // A `v128` that's partly out of bounds traps.
+    assert!(instance.call("load", &[Value::I32(0xfff0)]).is_err());
+}
+
+#[test]
+fn wasm_passes_v128_to_a_dynamic_function() {
+    let swap_halves = DynamicFunc::new(
+        Arc::new(FuncSig::new(vec![Type::V128], vec![Type::V128])),
+        |_ctx, args| match args {
+            [Value::V128(v)] => Ok(vec![Value::V128(v.rotate_left(64))]),
+            _ => Err("unexpected arguments"),
+        },
+    );
+    let import_object = imports! {
+        "env" => {
+            "swap_halves" => swap_halves,
+        },
+    };
+    let instance = instantiate_simd(
+        r#"(module
+             (import "env" "swap_halves" (func $swap_halves (param v128) (result v128)))
+             (func (export "swap_and_add") (param v128) (result v128)
+               (i64x2.add (call $swap_halves (local.get 0)) (v128.const i64x2 1 1))))"#,
+        &import_object,
+    );
+
+    assert_eq!(
+        call_v128(&instance, "swap_and_add", &[Value::V128(2 << 64 | 7)]),
+        8 << 64 | 3
+    );
+}
+
+#[test]
+fn simd_isnt_compiled_with_reference_types() {
+    let wasm = wat::parse_str(r#"(module (func (result v128) (v128.const i64x2 0 0)))"#).unwrap();
+    let config = CompilerConfig {
+        features: Features {
+            simd: true,
+            reference_types: true,
+            bulk_memory: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    assert!(compile_with_config(&wasm, config).is_err());
+    assert!(compile_with_config(&wasm, simd_config()).is_ok());
+}
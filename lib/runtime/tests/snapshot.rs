@@ -0,0 +1,100 @@
+use wasmer_runtime::{
+    compile_with_config,
+    error::{Error, SnapshotError},
+    imports, CompilerConfig, Features, Instance, Module, Snapshot, Value,
+};
+
+fn compile(wat: &str) -> Module {
+    let wasm = wat::parse_str(wat).unwrap();
+    let config = CompilerConfig {
+        features: Features {
+            // For `table.set`.
+            bulk_memory: true,
+            reference_types: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    compile_with_config(&wasm, config).unwrap()
+}
+
+fn call_i32(instance: &Instance, name: &str, args: &[Value]) -> i32 {
+    match instance.call(name, args).unwrap()[..] {
+        [Value::I32(x)] => x,
+        ref results => panic!("{} returned {:?}", name, results),
+    }
+}
+
+// The start function counts how many times it ran in `$starts`.
+static COUNTER_WAT: &str = r#"(module
+  (type $ret_i32 (func (result i32)))
+  (memory 1)
+  (data (i32.const 0) "\01")
+  (table $funcs 2 funcref)
+  (elem (i32.const 0) $one)
+  (global $counter (mut i32) (i32.const 0))
+  (global $starts (mut i32) (i32.const 0))
+  (func $one (result i32) (i32.const 1))
+  (func $two (result i32) (i32.const 2))
+  (func $start
+    (global.set $starts (i32.add (global.get $starts) (i32.const 1))))
+  (start $start)
+
+  (func (export "mutate")
+    (i32.store (i32.const 0) (i32.const 0x1234))
+    (i32.store (i32.const 0x8000) (i32.const 0x5678))
+    (global.set $counter (i32.const 42))
+    (table.set $funcs (i32.const 1) (ref.func $two)))
+  (func (export "load") (param i32) (result i32)
+    (i32.load (local.get 0)))
+  (func (export "counter") (result i32)
+    (global.get $counter))
+  (func (export "starts") (result i32)
+    (global.get $starts))
+  (func (export "call") (param i32) (result i32)
+    (call_indirect $funcs (type $ret_i32) (local.get 0))))"#;
+
+#[test]
+fn snapshot_round_trip() {
+    let module = compile(COUNTER_WAT);
+    let instance = module.instantiate(&imports! {}).unwrap();
+    assert_eq!(call_i32(&instance, "starts", &[]), 1);
+    instance.call("mutate", &[]).unwrap();
+
+    let bytes = instance.snapshot().unwrap().serialize().unwrap();
+    drop(instance);
+    let snapshot = Snapshot::deserialize(&bytes).unwrap();
+    let restored = module
+        .instantiate_from_snapshot(&imports! {}, &snapshot)
+        .unwrap();
+
+    assert_eq!(call_i32(&restored, "load", &[Value::I32(0)]), 0x1234);
+    assert_eq!(call_i32(&restored, "load", &[Value::I32(0x8000)]), 0x5678);
+    assert_eq!(call_i32(&restored, "counter", &[]), 42);
+    assert_eq!(call_i32(&restored, "call", &[Value::I32(0)]), 1);
+    assert_eq!(call_i32(&restored, "call", &[Value::I32(1)]), 2);
+    // The start function ran for the first instance only.
+    assert_eq!(call_i32(&restored, "starts", &[]), 1);
+
+    // The snapshot can be restored more than once, and
+    // each instance starts off from the same state.
+    restored.call("mutate", &[]).unwrap();
+    let again = module
+        .instantiate_from_snapshot(&imports! {}, &snapshot)
+        .unwrap();
+    assert_eq!(call_i32(&again, "counter", &[]), 42);
+}
+
+#[test]
+fn snapshot_of_another_module() {
+    let module = compile(COUNTER_WAT);
+    let instance = module.instantiate(&imports! {}).unwrap();
+    let snapshot = instance.snapshot().unwrap();
+
+    let other = compile(r#"(module (memory 1) (table 2 funcref))"#);
+    match other.instantiate_from_snapshot(&imports! {}, &snapshot) {
+        Err(Error::SnapshotError(SnapshotError::WrongModule)) => {}
+        Err(error) => panic!("unexpected error {:?}", error),
+        Ok(_) => panic!("instantiated another module from the snapshot"),
+    }
+}
@@ -6,7 +6,7 @@ use std::fs::File;
 use std::path::PathBuf;
 use std::{env, fs, io::Write};
 use wabt::script::{Action, Command, CommandKind, ModuleBinary, ScriptParser, Value};
-use wabt::wasm2wat;
+use wabt::{wasm2wat_with_features, Features};
 
 static BANNER: &str = "// Rust test file autogenerated with cargo build (build/spectests.rs).
 // Please do NOT modify it by hand, as it will be reset on next build.\n";
@@ -20,6 +20,7 @@ const TESTS: &[&str] = &[
     "spectests/br_if.wast",
     "spectests/br_table.wast",
     "spectests/break_drop.wast",
+    "spectests/bulk_memory.wast",
     "spectests/call.wast",
     "spectests/call_indirect.wast",
     "spectests/comments.wast",
@@ -49,19 +50,27 @@ const TESTS: &[&str] = &[
     "spectests/i32_.wast",
     "spectests/i64_.wast",
     "spectests/if_.wast",
+    "spectests/imports.wast",
     "spectests/int_exprs.wast",
     "spectests/int_literals.wast",
     "spectests/labels.wast",
     "spectests/left_to_right.wast",
+    "spectests/linking.wast",
     "spectests/loop_.wast",
     "spectests/memory.wast",
     "spectests/memory_grow.wast",
     "spectests/memory_redundancy.wast",
     "spectests/memory_trap.wast",
+    "spectests/multi_value.wast",
+    "spectests/names.wast",
+    "spectests/nontrapping_float_to_int_conversions.wast",
     "spectests/nop.wast",
     "spectests/return_.wast",
     "spectests/select.wast",
     "spectests/set_local.wast",
+    "spectests/skip_stack_guard_page.wast",
+    "spectests/sign_extension_ops.wast",
+    "spectests/simd.wast",
     "spectests/stack.wast",
     "spectests/start.wast",
     "spectests/store_retval.wast",
@@ -74,27 +83,141 @@ const TESTS: &[&str] = &[
     "spectests/unwind.wast",
 ];
 
+/// Tests that are slow to run, which only run without `fast-tests`.
+const SLOW_TESTS: &[&str] = &["spectests/skip_stack_guard_page.wast"];
+
+/// Tests of proposals that aren't part of the MVP, with the proposals
+/// their modules use. Proposals are named after `Features` fields.
+const PROPOSAL_TESTS: &[(&str, &[&str])] = &[
+    ("spectests/bulk_memory.wast", &["bulk_memory"]),
+    ("spectests/multi_value.wast", &["multi_value"]),
+    (
+        "spectests/nontrapping_float_to_int_conversions.wast",
+        &["sat_float_to_int"],
+    ),
+    ("spectests/sign_extension_ops.wast", &["sign_extension"]),
+    ("spectests/simd.wast", &["simd"]),
+];
+
 static COMMON: &'static str = r##"
 use std::{{f32, f64}};
-use wabt::wat2wasm;
+use wabt::{wat2wasm, wat2wasm_with_features};
 use wasmer_clif_backend::CraneliftCompiler;
-use wasmer_runtime_core::import::ImportObject;
+use wasmer_runtime_core::backend::CompilerConfig;
+use wasmer_runtime_core::export::Export;
+use wasmer_runtime_core::import::{ImportObject, LikeNamespace};
+use wasmer_runtime_core::linker::Linker;
 use wasmer_runtime_core::types::Value;
 use wasmer_runtime_core::{{Instance, module::Module}};
-use wasmer_runtime_core::error::Result;
+use wasmer_runtime_core::error::{CallResult, CompileResult, Result};
 use wasmer_runtime_core::vm::Ctx;
 
 static IMPORT_MODULE: &str = r#"
 (module
-  (type $t0 (func (param i32)))
-  (type $t1 (func))
-  (func $print_i32 (export "print_i32") (type $t0) (param $lhs i32))
-  (func $print (export "print") (type $t1))
+  (func $print (export "print"))
+  (func $print_i32 (export "print_i32") (param i32))
+  (func $print_i64 (export "print_i64") (param i64))
+  (func $print_f32 (export "print_f32") (param f32))
+  (func $print_f64 (export "print_f64") (param f64))
+  (func $print_i32_f32 (export "print_i32_f32") (param i32 f32))
+  (func $print_f64_f64 (export "print_f64_f64") (param f64 f64))
   (table $table (export "table") 10 20 anyfunc)
   (memory $memory (export "memory") 1 2)
-  (global $global_i32 (export "global_i32") i32 (i32.const 666)))
+  (global $global_i32 (export "global_i32") i32 (i32.const 666))
+  (global $global_i64 (export "global_i64") i64 (i64.const 666))
+  (global $global_f32 (export "global_f32") f32 (f32.const 666.6))
+  (global $global_f64 (export "global_f64") f64 (f64.const 666.6)))
 "#;
 
+/// The `wabt` features for the proposals a script uses.
+fn wabt_features(proposals: &[&str]) -> wabt::Features {
+    let mut features = wabt::Features::new();
+    for proposal in proposals {
+        match *proposal {
+            "bulk_memory" => features.enable_bulk_memory(),
+            "multi_value" => features.enable_multi_value(),
+            "sat_float_to_int" => features.enable_sat_float_to_int(),
+            "sign_extension" => features.enable_sign_extension(),
+            "simd" => features.enable_simd(),
+            _ => panic!("unknown proposal {:?}", proposal),
+        }
+    }
+    features
+}
+
+/// Compiles `wasm` with the proposals a script uses enabled.
+fn compile(wasm: &[u8], proposals: &[&str]) -> CompileResult<Module> {
+    let mut config = CompilerConfig::default();
+    for proposal in proposals {
+        match *proposal {
+            "bulk_memory" => config.features.bulk_memory = true,
+            "multi_value" => config.features.multi_value = true,
+            "sat_float_to_int" => config.features.sat_float_to_int = true,
+            "sign_extension" => config.features.sign_extension = true,
+            "simd" => config.features.simd = true,
+            _ => panic!("unknown proposal {:?}", proposal),
+        }
+    }
+    wasmer_runtime_core::compile_with_config(wasm, &CraneliftCompiler::new(), config)
+}
+
+/// Checks that `error` is the trap that the spec tests describe as `message`.
+fn assert_trap_message(error: wasmer_runtime_core::error::Error, message: &str) {
+    use wasmer_runtime_core::error::{CallError, Error, RuntimeError, TrapKind};
+
+    // Instantiation traps when a segment doesn't fit with bulk memory.
+    let kind = match &error {
+        Error::CallError(CallError::Runtime(RuntimeError::Trap { kind, .. }))
+        | Error::RuntimeError(RuntimeError::Trap { kind, .. }) => kind,
+        _ => panic!("expected a trap with message {:?}, got {:?}", message, error),
+    };
+    let expected = match kind {
+        TrapKind::OutOfBoundsAccess { .. } => "out of bounds memory access",
+        // Accesses out of a table's bounds that aren't calls are described
+        // the way accesses out of a memory's bounds are.
+        TrapKind::TableOutOfBounds { .. } if message.starts_with("out of bounds") => {
+            "out of bounds table access"
+        }
+        TrapKind::TableOutOfBounds { .. } => "undefined element",
+        TrapKind::IndirectCallToNull { .. } => "uninitialized element",
+        TrapKind::IndirectCallSignature { .. } => "indirect call type mismatch",
+        TrapKind::StackOverflow => "call stack exhausted",
+        TrapKind::Unreachable => "unreachable",
+        TrapKind::IntegerOverflow => "integer overflow",
+        TrapKind::IntegerDivisionByZero => "integer divide by zero",
+        TrapKind::BadConversionToInteger => "invalid conversion to integer",
+        TrapKind::UnalignedAtomic => "unaligned atomic",
+        _ => panic!("expected a trap with message {:?}, got {}", message, kind),
+    };
+    // Scripts shorten the message ("uninitialized") or add details to it
+    // ("uninitialized element 7"), so either one may be a prefix of the other.
+    assert!(
+        message.starts_with(expected) || expected.starts_with(message),
+        "expected a trap with message {:?}, got {}",
+        message,
+        kind
+    );
+}
+
+/// Checks that instantiating a module failed because it couldn't be linked.
+fn assert_unlinkable(result: Result<Instance>) {
+    use wasmer_runtime_core::error::Error;
+
+    match result {
+        Err(Error::LinkError(_)) => {}
+        Err(error) => panic!("expected a link error, got {:?}", error),
+        Ok(_) => panic!("expected a link error, but the module was instantiated"),
+    }
+}
+
+/// Reads an exported global, the way invoking a function reads its results.
+fn get_global(instance: &Instance, name: &str) -> CallResult<Vec<Value>> {
+    match instance.get_export(name) {
+        Some(Export::Global(global)) => Ok(vec![global.get()]),
+        _ => panic!("no global is exported as {:?}", name),
+    }
+}
+
 pub fn generate_imports() -> ImportObject {
     let wasm_binary = wat2wasm(IMPORT_MODULE.as_bytes()).expect("WAST not valid or malformed");
     let module = wasmer_runtime_core::compile_with(&wasm_binary[..], &CraneliftCompiler::new())
@@ -164,6 +287,7 @@ fn wabt2rust_type(v: &Value) -> String {
         Value::I64(_v) => format!("i64"),
         Value::F32(_v) => format!("f32"),
         Value::F64(_v) => format!("f64"),
+        Value::V128(_v) => format!("u128"),
     }
 }
 
@@ -173,6 +297,7 @@ fn wabt2rust_type_destructure(v: &Value, placeholder: &str) -> String {
         Value::I64(_v) => format!("Value::I64({})", placeholder),
         Value::F32(_v) => format!("Value::F32({})", placeholder),
         Value::F64(_v) => format!("Value::F64({})", placeholder),
+        Value::V128(_v) => format!("Value::V128({})", placeholder),
     }
 }
 
@@ -216,6 +341,7 @@ fn wabt2rust_value_bare(v: &Value) -> String {
                 format!("{:?}", v)
             }
         }
+        Value::V128(v) => format!("{:#x} as u128", v),
     }
 }
 
@@ -250,7 +376,54 @@ fn wabt2rust_value(v: &Value) -> String {
                 format!("Value::F64(({:?}f64))", v)
             }
         }
+        Value::V128(v) => format!("Value::V128({:#x})", v),
+    }
+}
+
+/// The `wabt` features for `proposals`, so that
+/// their instructions can be parsed and printed.
+fn wabt_features(proposals: &[&str]) -> Features {
+    let mut features = Features::new();
+    for proposal in proposals {
+        match *proposal {
+            "bulk_memory" => features.enable_bulk_memory(),
+            "multi_value" => features.enable_multi_value(),
+            "sat_float_to_int" => features.enable_sat_float_to_int(),
+            "sign_extension" => features.enable_sign_extension(),
+            "simd" => features.enable_simd(),
+            _ => panic!("unknown proposal {:?}", proposal),
+        }
     }
+    features
+}
+
+/// Whether a script links modules to each other, by registering
+/// them or by acting on modules other than the last one.
+fn is_linked_script(source: &[u8], filename: &str, features: Features) -> bool {
+    let mut script =
+        ScriptParser::from_source_and_name_with_features(source, filename, features).unwrap();
+    while let Some(Command { kind, .. }) = script.next().unwrap() {
+        let action = match kind {
+            CommandKind::Register { .. } => return true,
+            CommandKind::AssertReturn { action, .. }
+            | CommandKind::AssertReturnCanonicalNan { action }
+            | CommandKind::AssertReturnArithmeticNan { action }
+            | CommandKind::AssertTrap { action, .. }
+            | CommandKind::AssertExhaustion { action }
+            | CommandKind::PerformAction(action) => action,
+            _ => continue,
+        };
+        match action {
+            Action::Invoke {
+                module: Some(_), ..
+            }
+            | Action::Get {
+                module: Some(_), ..
+            } => return true,
+            _ => {}
+        }
+    }
+    false
 }
 
 struct WastTestGenerator {
@@ -260,13 +433,26 @@ struct WastTestGenerator {
     script_parser: ScriptParser,
     module_calls: HashMap<i32, Vec<String>>,
     buffer: String,
+    /// Linked scripts run their commands in order, in a single test,
+    /// instead of grouping them by module.
+    linked: bool,
+    module_names: HashMap<String, i32>,
+    registered_modules: HashMap<i32, String>,
+    script_calls: Vec<String>,
+    /// The proposals the script's modules use.
+    proposals: &'static [&'static str],
 }
 
 impl WastTestGenerator {
-    fn new(path: &PathBuf) -> Self {
+    fn new(path: &PathBuf, proposals: &'static [&'static str]) -> Self {
         let filename = path.file_name().unwrap().to_str().unwrap();
         let source = fs::read(&path).unwrap();
-        let script: ScriptParser = ScriptParser::from_source_and_name(&source, filename).unwrap();
+        let script: ScriptParser = ScriptParser::from_source_and_name_with_features(
+            &source,
+            filename,
+            wabt_features(proposals),
+        )
+        .unwrap();
         let buffer = String::new();
         WastTestGenerator {
             last_module: 0,
@@ -275,6 +461,11 @@ impl WastTestGenerator {
             script_parser: script,
             buffer: buffer,
             module_calls: HashMap::new(),
+            linked: is_linked_script(&source, filename, wabt_features(proposals)),
+            module_names: HashMap::new(),
+            registered_modules: HashMap::new(),
+            script_calls: Vec::new(),
+            proposals,
         }
     }
 
@@ -309,8 +500,12 @@ impl WastTestGenerator {
             self.visit_command(&kind);
             self.command_no = self.command_no + 1;
         }
-        for n in 1..self.last_module + 1 {
-            self.flush_module_calls(n);
+        if self.linked {
+            self.flush_script_calls();
+        } else {
+            for n in 1..self.last_module + 1 {
+                self.flush_module_calls(n);
+            }
         }
     }
 
@@ -318,20 +513,55 @@ impl WastTestGenerator {
         format!("c{}_l{}", self.command_no, self.last_line)
     }
 
+    /// The module an action acts on: the named one, or else the last one.
+    fn action_module(&self, action: &Action) -> i32 {
+        let name = match action {
+            Action::Invoke { module, .. } | Action::Get { module, .. } => module,
+        };
+        match name {
+            Some(name) => self.module_names[name],
+            None => self.last_module,
+        }
+    }
+
+    /// The instance of a module in the test of a linked script.
+    fn script_instance(&self, module: i32) -> String {
+        match self.registered_modules.get(&module) {
+            Some(as_name) => format!("linker.instance({:?}).unwrap()", as_name),
+            None => format!("&instance_{}", module),
+        }
+    }
+
+    fn flush_script_calls(&mut self) {
+        self.buffer.push_str(
+            format!(
+                "\n#[test]
+fn test_script() {{
+    let mut linker = Linker::with_imports(generate_imports());
+    // The commands run in order, as the modules share their state
+    {}
+}}\n",
+                self.script_calls.join("\n    ")
+            )
+            .as_str(),
+        );
+    }
+
     fn flush_module_calls(&mut self, module: i32) {
         let calls: Vec<String> = self
             .module_calls
             .entry(module)
             .or_insert(Vec::new())
             .iter()
-            .map(|call_str| format!("{}(&mut instance);", call_str))
+            .map(|call_str| format!("{}(&instance);", call_str))
             .collect();
         if calls.len() > 0 {
             self.buffer.push_str(
                 format!(
                     "\n#[test]
 fn test_module_{}() {{
-    let mut instance = create_module_{}();
+    let linker = Linker::with_imports(generate_imports());
+    let instance = create_module_{}(&linker);
     // We group the calls together
     {}
 }}\n",
@@ -345,21 +575,33 @@ fn test_module_{}() {{
         self.module_calls.remove(&module);
     }
 
-    fn visit_module(&mut self, module: &ModuleBinary, _name: &Option<String>) {
+    fn visit_module(&mut self, module: &ModuleBinary, name: &Option<String>) {
         let wasm_binary: Vec<u8> = module.clone().into_vec();
-        let wast_string = wasm2wat(wasm_binary).expect("Can't convert back to wasm");
+        let wast_string = wasm2wat_with_features(wasm_binary, wabt_features(self.proposals))
+            .expect("Can't convert back to wasm");
         let last_module = self.last_module;
-        self.flush_module_calls(last_module);
+        if !self.linked {
+            self.flush_module_calls(last_module);
+        }
         self.last_module = self.last_module + 1;
+        if let Some(name) = name {
+            self.module_names.insert(name.clone(), self.last_module);
+        }
+        if self.linked {
+            self.script_calls.push(format!(
+                "let instance_{0} = create_module_{0}(&linker);",
+                self.last_module
+            ));
+        }
         // self.module_calls.insert(self.last_module, vec![]);
         self.buffer.push_str(
             format!(
-                "fn create_module_{}() -> Instance {{
+                "fn create_module_{}(linker: &Linker) -> Instance {{
     let module_str = \"{}\";
     println!(\"{{}}\", module_str);
-    let wasm_binary = wat2wasm(module_str.as_bytes()).expect(\"WAST not valid or malformed\");
-    let module = wasmer_runtime_core::compile_with(&wasm_binary[..], &CraneliftCompiler::new()).expect(\"WASM can't be compiled\");
-    module.instantiate(&generate_imports()).expect(\"WASM can't be instantiated\")
+    let wasm_binary = wat2wasm_with_features(module_str.as_bytes(), wabt_features(PROPOSALS)).expect(\"WAST not valid or malformed\");
+    let module = compile(&wasm_binary[..], PROPOSALS).expect(\"WASM can't be compiled\");
+    linker.instantiate(&module).expect(\"WASM can't be instantiated\")
 }}\n",
                 self.last_module,
                 // We do this to ident four spaces, so it looks aligned to the function body
@@ -381,7 +623,7 @@ fn test_module_{}() {{
                 "#[test]
 fn {}_assert_invalid() {{
     let wasm_binary = {:?};
-    let module = wasmer_runtime_core::compile_with(&wasm_binary, &CraneliftCompiler::new());
+    let module = compile(&wasm_binary, PROPOSALS);
     assert!(module.is_err(), \"WASM should not compile as is invalid\");
 }}\n",
                 command_name,
@@ -421,7 +663,7 @@ fn {}_assert_invalid() {{
                 let func_name = format!("{}_assert_return_arithmetic_nan", self.command_name());
                 self.buffer.push_str(
                     format!(
-                        "fn {func_name}(instance: &mut Instance) {{
+                        "fn {func_name}(instance: &Instance) {{
     println!(\"Executing function {{}}\", \"{func_name}\");
     let result = instance.call(\"{field}\", &[{args_values}]).unwrap().first().expect(\"Missing result in {func_name}\").clone();
     {assertion}
@@ -436,10 +678,7 @@ fn {}_assert_invalid() {{
                 //                field=field,
                 //                args_types=args_types.join(", "),
                 //                func_return=func_return,
-                self.module_calls
-                    .entry(self.last_module)
-                    .or_insert(Vec::new())
-                    .push(func_name);
+                self.push_call(action, func_name);
                 // let mut module_calls = self.module_calls.get(&self.last_module).unwrap();
                 // module_calls.push(func_name);
             }
@@ -480,7 +719,7 @@ fn {}_assert_invalid() {{
                 let func_name = format!("{}_assert_return_canonical_nan", self.command_name());
                 self.buffer.push_str(
                     format!(
-                        "fn {func_name}(instance: &mut Instance) {{
+                        "fn {func_name}(instance: &Instance) {{
     println!(\"Executing function {{}}\", \"{func_name}\");
     let result = instance.call(\"{field}\", &[{args_values}]).unwrap().first().expect(\"Missing result in {func_name}\").clone();
     {assertion}
@@ -492,10 +731,7 @@ fn {}_assert_invalid() {{
                     )
                     .as_str(),
                 );
-                self.module_calls
-                    .entry(self.last_module)
-                    .or_insert(Vec::new())
-                    .push(func_name);
+                self.push_call(action, func_name);
                 // let mut module_calls = self.module_calls.get(&self.last_module).unwrap();
                 // module_calls.push(func_name);
             }
@@ -512,7 +748,7 @@ fn {}_assert_invalid() {{
                 "#[test]
 fn {}_assert_malformed() {{
     let wasm_binary = {:?};
-    let compilation = wasmer_runtime_core::compile_with(&wasm_binary, &CraneliftCompiler::new());
+    let compilation = compile(&wasm_binary, PROPOSALS);
     assert!(compilation.is_err(), \"WASM should not compile as is malformed\");
 }}\n",
                 command_name,
@@ -526,140 +762,145 @@ fn {}_assert_malformed() {{
     }
 
     // TODO: Refactor repetitive code
-    fn visit_action(&mut self, action: &Action, expected: Option<&Vec<Value>>) -> Option<String> {
-        match action {
-            Action::Invoke {
-                module: _,
-                field,
-                args,
-            } => {
-                let (_func_return, assertion) = match expected {
-                    Some(expected) => {
-                        let func_return = if expected.len() > 0 {
-                            format!(" -> {}", wabt2rust_type(&expected[0]))
-                        } else {
-                            "".to_string()
-                        };
-                        let expected_result = if expected.len() > 0 {
-                            wabt2rust_value_bare(&expected[0])
-                        } else {
-                            "should not use this expect result".to_string()
-                        };
-                        let expected_vec_result = if expected.len() > 0 {
-                            format!("Ok(vec![{}])", wabt2rust_value(&expected[0]))
-                        } else {
-                            "Ok(vec![])".to_string()
-                        };
-                        let return_type = if expected.len() > 0 {
-                            wabt2rust_type(&expected[0])
-                        } else {
-                            "should not use this return type".to_string()
-                        };
-                        let return_type_destructure = if expected.len() > 0 {
-                            wabt2rust_type_destructure(&expected[0], "result")
-                        } else {
-                            "should not use this result return type destructure".to_string()
-                        };
-                        let _expected_type_destructure = if expected.len() > 0 {
-                            wabt2rust_type_destructure(&expected[0], "expected")
-                        } else {
-                            "should not use this expected return type destructure".to_string()
-                        };
-                        let assertion = if expected.len() > 0 && is_nan(&expected[0]) {
-                            format!(
-                                "let expected = {expected_result};
+    fn visit_action(&mut self, action: &Action, expected: Option<&Vec<Value>>) -> String {
+        let (_func_return, assertion) = match expected {
+            Some(expected) => {
+                let func_return = if expected.len() > 0 {
+                    format!(" -> {}", wabt2rust_type(&expected[0]))
+                } else {
+                    "".to_string()
+                };
+                let expected_result = if expected.len() > 0 {
+                    wabt2rust_value_bare(&expected[0])
+                } else {
+                    "should not use this expect result".to_string()
+                };
+                let expected_values: Vec<String> = expected.iter().map(wabt2rust_value).collect();
+                let expected_vec_result = format!("Ok(vec![{}])", expected_values.join(", "));
+                let return_type = if expected.len() > 0 {
+                    wabt2rust_type(&expected[0])
+                } else {
+                    "should not use this return type".to_string()
+                };
+                let return_type_destructure = if expected.len() > 0 {
+                    wabt2rust_type_destructure(&expected[0], "result")
+                } else {
+                    "should not use this result return type destructure".to_string()
+                };
+                let _expected_type_destructure = if expected.len() > 0 {
+                    wabt2rust_type_destructure(&expected[0], "expected")
+                } else {
+                    "should not use this expected return type destructure".to_string()
+                };
+                let assertion = if expected.len() > 0 && is_nan(&expected[0]) {
+                    format!(
+                        "let expected = {expected_result};
                                 if let {return_type_destructure} = result.clone().unwrap().first().unwrap() {{
                                 assert!((*result as {return_type}).is_nan());
             assert_eq!((*result as {return_type}).is_sign_positive(), (expected as {return_type}).is_sign_positive());
             }} else {{
               panic!(\"Unexpected result type {{:?}}\", result);
             }}",
-                                expected_result=expected_result,
-                                return_type=return_type,
-                                return_type_destructure=return_type_destructure
-                            )
-                        } else {
-                            format!("assert_eq!(result, {});", expected_vec_result)
-                        };
-                        (func_return, assertion)
-                    }
-                    None => ("".to_string(), "".to_string()),
+                        expected_result=expected_result,
+                        return_type=return_type,
+                        return_type_destructure=return_type_destructure
+                    )
+                } else {
+                    format!("assert_eq!(result, {});", expected_vec_result)
                 };
+                (func_return, assertion)
+            }
+            None => ("".to_string(), "".to_string()),
+        };
 
+        let (kind, call) = match action {
+            Action::Invoke {
+                module: _,
+                field,
+                args,
+            } => {
                 // We map the arguments provided into the raw Arguments provided
                 // to libffi
                 // let mut args_types: Vec<String> = args.iter().map(wabt2rust_type).collect();
                 //                args_types.push("&Instance".to_string());
                 let args_values: Vec<String> = args.iter().map(wabt2rust_value).collect();
                 //                args_values.push("&result_object.instance".to_string());
-                let func_name = format!("{}_action_invoke", self.command_name());
-                self.buffer.push_str(
-                    format!(
-                        "fn {func_name}(instance: &mut Instance) -> Result<()> {{
+                (
+                    "invoke",
+                    format!("instance.call({:?}, &[{}])", field, args_values.join(", ")),
+                )
+            }
+            Action::Get { module: _, field } => {
+                ("get", format!("get_global(instance, {:?})", field))
+            }
+        };
+        let func_name = format!("{}_action_{}", self.command_name(), kind);
+        self.buffer.push_str(
+            format!(
+                "fn {func_name}(instance: &Instance) -> Result<()> {{
     println!(\"Executing function {{}}\", \"{func_name}\");
-    let result = instance.call(\"{field}\", &[{args_values}]);
+    let result = {call};
     {assertion}
     result?;
     Ok(())
 }}\n",
-                        func_name = func_name,
-                        field = field,
-                        args_values = args_values.join(", "),
-                        assertion = assertion,
-                    )
-                    .as_str(),
-                );
-                Some(func_name)
-                // let mut module_calls = self.module_calls.get(&self.last_module).unwrap();
-                // module_calls.push(func_name);
-            }
-            _ => None,
+                func_name = func_name,
+                call = call,
+                assertion = assertion,
+            )
+            .as_str(),
+        );
+        func_name
+    }
+
+    /// Runs a call on the instance of the module the action acts on.
+    fn push_call(&mut self, action: &Action, func_name: String) {
+        if self.linked {
+            let instance = self.script_instance(self.action_module(action));
+            self.script_calls
+                .push(format!("{}({});", func_name, instance));
+        } else {
+            self.module_calls
+                .entry(self.last_module)
+                .or_insert(Vec::new())
+                .push(func_name);
         }
     }
 
     fn visit_assert_return(&mut self, action: &Action, expected: &Vec<Value>) {
         let action_fn_name = self.visit_action(action, Some(expected));
-
-        if action_fn_name.is_none() {
-            return;
-        }
-        self.module_calls
-            .entry(self.last_module)
-            .or_insert(Vec::new())
-            .push(action_fn_name.unwrap());
+        self.push_call(action, action_fn_name);
     }
 
     fn visit_perform_action(&mut self, action: &Action) {
         let action_fn_name = self.visit_action(action, None);
-
-        if action_fn_name.is_none() {
-            return;
-        }
-        self.module_calls
-            .entry(self.last_module)
-            .or_insert(Vec::new())
-            .push(action_fn_name.unwrap());
+        self.push_call(action, action_fn_name);
     }
 
-    fn visit_assert_trap(&mut self, action: &Action) {
+    fn visit_assert_trap(&mut self, action: &Action, message: &str, assertion: &str) {
         let action_fn_name = self.visit_action(action, None);
 
-        if action_fn_name.is_none() {
+        if self.linked {
+            let instance = self.script_instance(self.action_module(action));
+            self.script_calls.push(format!(
+                "assert_trap_message({}({}).unwrap_err(), {:?});",
+                action_fn_name, instance, message
+            ));
             return;
         }
-        let trap_func_name = format!("{}_assert_trap", self.command_name());
+        let trap_func_name = format!("{}_{}", self.command_name(), assertion);
         self.buffer.push_str(
             format!(
                 "
 #[test]
 fn {}() {{
-    let mut instance = create_module_{}();
-    let result = {}(&mut instance);
+    let linker = Linker::with_imports(generate_imports());
+    let instance = create_module_{}(&linker);
+    let result = {}(&instance);
     assert!(result.is_err());
+    assert_trap_message(result.unwrap_err(), {:?});
 }}\n",
-                trap_func_name,
-                self.last_module,
-                action_fn_name.unwrap(),
+                trap_func_name, self.last_module, action_fn_name, message,
             )
             .as_str(),
         );
@@ -672,6 +913,85 @@ fn {}() {{
         //     .push(trap_func_name);
     }
 
+    /// Checks that instantiating `module` fails, with `check` being the
+    /// statement that checks the result of the command's `_instantiate` function.
+    fn visit_failed_instantiation(
+        &mut self,
+        module: &ModuleBinary,
+        assertion: &str,
+        check: String,
+    ) {
+        let wasm_binary: Vec<u8> = module.clone().into_vec();
+        let command_name = self.command_name();
+        self.buffer.push_str(
+            format!(
+                "fn {}_instantiate(linker: &Linker) -> Result<Instance> {{
+    let wasm_binary = {:?};
+    let module = compile(&wasm_binary, PROPOSALS).expect(\"WASM can't be compiled\");
+    linker.instantiate(&module)
+}}\n",
+                command_name, wasm_binary,
+            )
+            .as_str(),
+        );
+
+        if self.linked {
+            self.script_calls.push(check);
+            return;
+        }
+        self.buffer.push_str(
+            format!(
+                "
+#[test]
+fn {}_{}() {{
+    let linker = Linker::with_imports(generate_imports());
+    {}
+}}\n",
+                command_name, assertion, check,
+            )
+            .as_str(),
+        );
+    }
+
+    fn visit_assert_uninstantiable(&mut self, module: &ModuleBinary, message: &str) {
+        // The start function of an uninstantiable module traps.
+        let check = format!(
+            "assert_trap_message({}_instantiate(&linker).err().expect(\"WASM should not be instantiable\"), {:?});",
+            self.command_name(),
+            message
+        );
+        self.visit_failed_instantiation(module, "assert_uninstantiable", check);
+    }
+
+    fn visit_assert_unlinkable(&mut self, module: &ModuleBinary) {
+        let check = format!(
+            "assert_unlinkable({}_instantiate(&linker));",
+            self.command_name()
+        );
+        self.visit_failed_instantiation(module, "assert_unlinkable", check);
+    }
+
+    fn visit_register(&mut self, name: &Option<String>, as_name: &str) {
+        let module = match name {
+            Some(name) => self.module_names[name],
+            None => self.last_module,
+        };
+        if let Some(registered_as) = self.registered_modules.get(&module) {
+            panic!(
+                "module {} can't be registered as {:?}, it is already registered as {:?}",
+                module, as_name, registered_as
+            );
+        }
+
+        // The linker takes the instance, so the module's
+        // actions get it back from the linker from now on.
+        self.script_calls.push(format!(
+            "linker.register({:?}, instance_{});",
+            as_name, module
+        ));
+        self.registered_modules.insert(module, as_name.to_string());
+    }
+
     fn visit_command(&mut self, cmd: &CommandKind) {
         match cmd {
             CommandKind::Module { module, name } => {
@@ -686,8 +1006,8 @@ fn {}() {{
             CommandKind::AssertReturnArithmeticNan { action } => {
                 self.visit_assert_return_arithmetic_nan(action);
             }
-            CommandKind::AssertTrap { action, message: _ } => {
-                self.visit_assert_trap(action);
+            CommandKind::AssertTrap { action, message } => {
+                self.visit_assert_trap(action, message, "assert_trap");
             }
             CommandKind::AssertInvalid { module, message: _ } => {
                 self.visit_assert_invalid(module);
@@ -695,26 +1015,17 @@ fn {}() {{
             CommandKind::AssertMalformed { module, message: _ } => {
                 self.visit_assert_malformed(module);
             }
-            CommandKind::AssertUninstantiable {
-                module: _,
-                message: _,
-            } => {
-                // Do nothing for now
+            CommandKind::AssertUninstantiable { module, message } => {
+                self.visit_assert_uninstantiable(module, message);
             }
-            CommandKind::AssertExhaustion { action: _ } => {
-                // Do nothing for now
+            CommandKind::AssertExhaustion { action } => {
+                self.visit_assert_trap(action, "call stack exhausted", "assert_exhaustion");
             }
-            CommandKind::AssertUnlinkable {
-                module: _,
-                message: _,
-            } => {
-                // Do nothing for now
+            CommandKind::AssertUnlinkable { module, message: _ } => {
+                self.visit_assert_unlinkable(module);
             }
-            CommandKind::Register {
-                name: _,
-                as_name: _,
-            } => {
-                // Do nothing for now
+            CommandKind::Register { name, as_name } => {
+                self.visit_register(name, as_name);
             }
             CommandKind::PerformAction(action) => {
                 self.visit_perform_action(action);
@@ -726,13 +1037,28 @@ fn {}() {{
     }
 }
 
-fn generate_spectest(out: &mut File, test_name: &str, wast: &PathBuf) -> std::io::Result<()> {
-    let mut generator = WastTestGenerator::new(wast);
+fn fast_tests() -> bool {
+    env::var("CARGO_FEATURE_FAST_TESTS").is_ok()
+}
+
+fn generate_spectest(
+    out: &mut File,
+    test_name: &str,
+    wast: &PathBuf,
+    proposals: &'static [&'static str],
+) -> std::io::Result<()> {
+    let mut generator = WastTestGenerator::new(wast, proposals);
     generator.consume();
     let generated_script = generator.finalize();
 
     if !generator.is_fat_test() {
-        out.write(format!("mod test_{} {{\nuse super::*;\n", test_name).as_bytes())?;
+        out.write(
+            format!(
+                "mod test_{} {{\nuse super::*;\nconst PROPOSALS: &[&str] = &{:?};\n",
+                test_name, proposals
+            )
+            .as_bytes(),
+        )?;
         out.write(generated_script.as_bytes())?;
         out.write("\n}\n".as_bytes())?;
     }
@@ -746,12 +1072,20 @@ pub fn build() -> std::io::Result<()> {
     out_file.write(COMMON.as_bytes())?;
 
     for test in TESTS.iter() {
+        if SLOW_TESTS.contains(test) && fast_tests() {
+            continue;
+        }
+        let proposals = PROPOSAL_TESTS
+            .iter()
+            .find(|(proposal_test, _)| proposal_test == test)
+            .map_or(&[][..], |(_, proposals)| proposals);
         let mut wast_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         wast_path.push(test);
         generate_spectest(
             &mut out_file,
             test.split("/").last().unwrap().split(".").next().unwrap(),
             &wast_path,
+            proposals,
         )?
     }
 
@@ -0,0 +1,119 @@
+;; Functions with more than one result, from the multi-value proposal's
+;; func.wast, call.wast and call_indirect.wast. Blocks with function types
+;; aren't supported, so their tests are left out.
+
+(module
+  (type $sig-2 (func (result i32 i64)))
+  (type $sig-3 (func (param i32 i64 f32) (result f32 i64 i32)))
+
+  (func $type-multiple-result (result i32 i32) (unreachable))
+
+  (func (export "value-i32-i64") (result i32 i64)
+    (i32.const 1) (i64.const 2)
+  )
+  (func (export "value-f32-f64-i32") (result f32 f64 i32)
+    (f32.const 1.5) (f64.const -2.25) (i32.const 3)
+  )
+  (func (export "value-i32-i32-i32-i32") (result i32 i32 i32 i32)
+    (i32.const 1) (i32.const 2) (i32.const 3) (i32.const 4)
+  )
+
+  (func $swap (param i32 i64 f32) (result f32 i64 i32)
+    (local.get 2) (local.get 1) (local.get 0)
+  )
+  (func (export "swap") (param i32 i64 f32) (result f32 i64 i32)
+    (call $swap (local.get 0) (local.get 1) (local.get 2))
+  )
+  (func (export "swap-and-add") (param i32 i64 f32) (result i32 i64 f32)
+    (local $a f32) (local $b i64) (local $c i32)
+    (call $swap (local.get 0) (local.get 1) (local.get 2))
+    (local.set $c) (local.set $b) (local.set $a)
+    (local.get $c)
+    (i64.add (local.get $b) (i64.const 1))
+    (f32.add (local.get $a) (f32.const 0.5))
+  )
+  (func (export "return-early") (param i32) (result i32 i64)
+    (if (local.get 0) (then (return (i32.const 1) (i64.const 1))))
+    (i32.const 0) (i64.const 0)
+  )
+  (func (export "sum-of-results") (result i64)
+    (local $sum i64)
+    (call $swap (i32.const 7) (i64.const 20) (f32.const 300))
+    (i64.extend_i32_u)
+    (i64.add)
+    (local.set $sum)
+    (i64.trunc_f32_s)
+    (i64.add (local.get $sum))
+  )
+
+  (func $const-2 (type $sig-2) (i32.const 10) (i64.const 20))
+  (func $const-2-other (type $sig-2) (i32.const 30) (i64.const 40))
+  (table funcref (elem $const-2 $const-2-other $swap))
+  (func (export "call-indirect") (param i32) (result i32 i64)
+    (call_indirect (type $sig-2) (local.get 0))
+  )
+  (func (export "call-indirect-swap") (param i32) (result f32 i64 i32)
+    (call_indirect (type $sig-3)
+      (i32.const -1) (i64.const -2) (f32.const -3)
+      (local.get 0)
+    )
+  )
+)
+
+(assert_return (invoke "value-i32-i64") (i32.const 1) (i64.const 2))
+(assert_return (invoke "value-f32-f64-i32") (f32.const 1.5) (f64.const -2.25) (i32.const 3))
+(assert_return
+  (invoke "value-i32-i32-i32-i32")
+  (i32.const 1) (i32.const 2) (i32.const 3) (i32.const 4)
+)
+(assert_return
+  (invoke "swap" (i32.const 1) (i64.const 2) (f32.const 3))
+  (f32.const 3) (i64.const 2) (i32.const 1)
+)
+(assert_return
+  (invoke "swap-and-add" (i32.const 1) (i64.const 2) (f32.const 3))
+  (i32.const 1) (i64.const 3) (f32.const 3.5)
+)
+(assert_return (invoke "return-early" (i32.const 1)) (i32.const 1) (i64.const 1))
+(assert_return (invoke "return-early" (i32.const 0)) (i32.const 0) (i64.const 0))
+(assert_return (invoke "sum-of-results") (i64.const 327))
+(assert_return (invoke "call-indirect" (i32.const 0)) (i32.const 10) (i64.const 20))
+(assert_return (invoke "call-indirect" (i32.const 1)) (i32.const 30) (i64.const 40))
+(assert_trap (invoke "call-indirect" (i32.const 2)) "indirect call type mismatch")
+(assert_trap (invoke "call-indirect" (i32.const 3)) "undefined element")
+(assert_return
+  (invoke "call-indirect-swap" (i32.const 2))
+  (f32.const -3) (i64.const -2) (i32.const -1)
+)
+(assert_trap (invoke "call-indirect-swap" (i32.const 0)) "indirect call type mismatch")
+
+
+;; Invalid typing of results
+
+(assert_invalid
+  (module (func $type-value-num-vs-nums (result i32 i32) (i32.const 0)))
+  "type mismatch"
+)
+(assert_invalid
+  (module (func $type-value-nums-vs-num (result i32) (i32.const 0) (i32.const 0)))
+  "type mismatch"
+)
+(assert_invalid
+  (module (func $type-value-nums-vs-nums (result i32 i64) (i64.const 0) (i32.const 0)))
+  "type mismatch"
+)
+(assert_invalid
+  (module
+    (func $type-return-partial (result i32 i64)
+      (return (i64.const 1))
+    )
+  )
+  "type mismatch"
+)
+(assert_invalid
+  (module
+    (func $f (result i32 i32) (i32.const 0) (i32.const 0))
+    (func $type-call-results-vs-num (result i32) (call $f))
+  )
+  "type mismatch"
+)
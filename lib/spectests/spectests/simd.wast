@@ -0,0 +1,341 @@
+;; `v128` values of the SIMD proposal, passed to and returned from
+;; functions, and through locals and blocks, and the instructions
+;; that operate on them.
+
+(module
+  (func (export "id") (param v128) (result v128)
+    (local.get 0))
+  (func (export "select") (param v128 v128 i32) (result v128)
+    (if (result v128) (local.get 2)
+      (then (local.get 0))
+      (else (local.get 1))))
+  (func (export "swap") (param v128 i64 v128) (result v128)
+    (local $tmp v128)
+    (local.set $tmp (local.get 2))
+    (block (result v128) (local.get $tmp)))
+)
+
+(assert_return (invoke "id" (v128.const i32x4 1 2 3 4)) (v128.const i32x4 1 2 3 4))
+(assert_return
+  (invoke "id" (v128.const i64x2 0x0102030405060708 -1))
+  (v128.const i8x16 8 7 6 5 4 3 2 1 -1 -1 -1 -1 -1 -1 -1 -1)
+)
+(assert_return
+  (invoke "select" (v128.const i32x4 1 2 3 4) (v128.const i32x4 5 6 7 8) (i32.const 1))
+  (v128.const i32x4 1 2 3 4)
+)
+(assert_return
+  (invoke "select" (v128.const i32x4 1 2 3 4) (v128.const i32x4 5 6 7 8) (i32.const 0))
+  (v128.const i32x4 5 6 7 8)
+)
+(assert_return
+  (invoke "swap" (v128.const i32x4 1 2 3 4) (i64.const 9) (v128.const f32x4 0.5 -1 2 1e10))
+  (v128.const f32x4 0.5 -1 2 1e10)
+)
+;; Integer lanes.
+
+(module
+  (func (export "i8x16.add_sat_s") (param v128 v128) (result v128)
+    (i8x16.add_sat_s (local.get 0) (local.get 1)))
+  (func (export "i16x8.sub") (param v128 v128) (result v128)
+    (i16x8.sub (local.get 0) (local.get 1)))
+  (func (export "i32x4.mul") (param v128 v128) (result v128)
+    (i32x4.mul (local.get 0) (local.get 1)))
+  (func (export "i64x2.add") (param v128 v128) (result v128)
+    (i64x2.add (local.get 0) (local.get 1)))
+  (func (export "i32x4.min_u") (param v128 v128) (result v128)
+    (i32x4.min_u (local.get 0) (local.get 1)))
+  (func (export "i8x16.avgr_u") (param v128 v128) (result v128)
+    (i8x16.avgr_u (local.get 0) (local.get 1)))
+  (func (export "i16x8.shr_s") (param v128 i32) (result v128)
+    (i16x8.shr_s (local.get 0) (local.get 1)))
+  (func (export "i64x2.shl") (param v128 i32) (result v128)
+    (i64x2.shl (local.get 0) (local.get 1)))
+  (func (export "i32x4.lt_s") (param v128 v128) (result v128)
+    (i32x4.lt_s (local.get 0) (local.get 1)))
+  (func (export "i64x2.ge_s") (param v128 v128) (result v128)
+    (i64x2.ge_s (local.get 0) (local.get 1)))
+  (func (export "i8x16.abs") (param v128) (result v128)
+    (i8x16.abs (local.get 0)))
+  (func (export "i8x16.popcnt") (param v128) (result v128)
+    (i8x16.popcnt (local.get 0)))
+  (func (export "i8x16.narrow_i16x8_u") (param v128 v128) (result v128)
+    (i8x16.narrow_i16x8_u (local.get 0) (local.get 1)))
+  (func (export "i32x4.extend_high_i16x8_s") (param v128) (result v128)
+    (i32x4.extend_high_i16x8_s (local.get 0)))
+  (func (export "i64x2.extmul_low_i32x4_u") (param v128 v128) (result v128)
+    (i64x2.extmul_low_i32x4_u (local.get 0) (local.get 1)))
+  (func (export "i32x4.dot_i16x8_s") (param v128 v128) (result v128)
+    (i32x4.dot_i16x8_s (local.get 0) (local.get 1)))
+  (func (export "i16x8.extadd_pairwise_i8x16_u") (param v128) (result v128)
+    (i16x8.extadd_pairwise_i8x16_u (local.get 0)))
+)
+
+(assert_return
+  (invoke "i8x16.add_sat_s"
+    (v128.const i8x16 127 -128 1 2 3 4 5 6 7 8 9 10 11 12 13 14)
+    (v128.const i8x16 1 -1 1 1 1 1 1 1 1 1 1 1 1 1 1 1))
+  (v128.const i8x16 127 -128 2 3 4 5 6 7 8 9 10 11 12 13 14 15)
+)
+(assert_return
+  (invoke "i16x8.sub" (v128.const i16x8 0 1 2 3 4 5 6 7) (v128.const i16x8 1 1 1 1 1 1 1 1))
+  (v128.const i16x8 -1 0 1 2 3 4 5 6)
+)
+(assert_return
+  (invoke "i32x4.mul" (v128.const i32x4 0x10000 3 -4 5) (v128.const i32x4 0x10000 3 4 -5))
+  (v128.const i32x4 0 9 -16 -25)
+)
+(assert_return
+  (invoke "i64x2.add" (v128.const i64x2 -1 1) (v128.const i64x2 1 0x7fffffffffffffff))
+  (v128.const i64x2 0 0x8000000000000000)
+)
+(assert_return
+  (invoke "i32x4.min_u" (v128.const i32x4 -1 1 2 3) (v128.const i32x4 0 -1 1 4))
+  (v128.const i32x4 0 1 1 3)
+)
+(assert_return
+  (invoke "i8x16.avgr_u"
+    (v128.const i8x16 0 1 2 255 0 0 0 0 0 0 0 0 0 0 0 0)
+    (v128.const i8x16 0 2 2 255 0 0 0 0 0 0 0 0 0 0 0 1))
+  (v128.const i8x16 0 2 2 255 0 0 0 0 0 0 0 0 0 0 0 1)
+)
+(assert_return
+  (invoke "i16x8.shr_s" (v128.const i16x8 -16 16 -1 1 0 0 0 0x4000) (i32.const 18))
+  (v128.const i16x8 -4 4 -1 0 0 0 0 0x1000)
+)
+(assert_return
+  (invoke "i64x2.shl" (v128.const i64x2 1 3) (i32.const 65))
+  (v128.const i64x2 2 6)
+)
+(assert_return
+  (invoke "i32x4.lt_s" (v128.const i32x4 -1 1 2 3) (v128.const i32x4 0 0 2 4))
+  (v128.const i32x4 -1 0 0 -1)
+)
+(assert_return
+  (invoke "i64x2.ge_s" (v128.const i64x2 -1 5) (v128.const i64x2 0 5))
+  (v128.const i64x2 0 -1)
+)
+(assert_return
+  (invoke "i8x16.abs" (v128.const i8x16 -128 -1 1 0 0 0 0 0 0 0 0 0 0 0 0 -5))
+  (v128.const i8x16 -128 1 1 0 0 0 0 0 0 0 0 0 0 0 0 5)
+)
+(assert_return
+  (invoke "i8x16.popcnt" (v128.const i8x16 -1 0 1 3 7 15 0x55 0x80 0 0 0 0 0 0 0 0))
+  (v128.const i8x16 8 0 1 2 3 4 4 1 0 0 0 0 0 0 0 0)
+)
+(assert_return
+  (invoke "i8x16.narrow_i16x8_u"
+    (v128.const i16x8 -1 0 255 256 1 2 3 4)
+    (v128.const i16x8 5 6 7 8 9 10 11 300))
+  (v128.const i8x16 0 0 255 255 1 2 3 4 5 6 7 8 9 10 11 255)
+)
+(assert_return
+  (invoke "i32x4.extend_high_i16x8_s" (v128.const i16x8 0 0 0 0 -1 2 -32768 32767))
+  (v128.const i32x4 -1 2 -32768 32767)
+)
+(assert_return
+  (invoke "i64x2.extmul_low_i32x4_u" (v128.const i32x4 -1 2 9 9) (v128.const i32x4 -1 3 9 9))
+  (v128.const i64x2 0xfffffffe00000001 6)
+)
+(assert_return
+  (invoke "i32x4.dot_i16x8_s"
+    (v128.const i16x8 1 2 3 4 -32768 -32768 0 0)
+    (v128.const i16x8 5 6 7 8 -32768 -32768 0 0))
+  (v128.const i32x4 17 53 -2147483648 0)
+)
+(assert_return
+  (invoke "i16x8.extadd_pairwise_i8x16_u"
+    (v128.const i8x16 255 255 1 2 0 0 0 0 0 0 0 0 0 0 0 0))
+  (v128.const i16x8 510 3 0 0 0 0 0 0)
+)
+
+;; Float lanes, without NaN results, which the spectests can't compare.
+
+(module
+  (func (export "f32x4.add") (param v128 v128) (result v128)
+    (f32x4.add (local.get 0) (local.get 1)))
+  (func (export "f64x2.div") (param v128 v128) (result v128)
+    (f64x2.div (local.get 0) (local.get 1)))
+  (func (export "f32x4.min") (param v128 v128) (result v128)
+    (f32x4.min (local.get 0) (local.get 1)))
+  (func (export "f64x2.pmax") (param v128 v128) (result v128)
+    (f64x2.pmax (local.get 0) (local.get 1)))
+  (func (export "f32x4.nearest") (param v128) (result v128)
+    (f32x4.nearest (local.get 0)))
+  (func (export "f64x2.floor") (param v128) (result v128)
+    (f64x2.floor (local.get 0)))
+  (func (export "f32x4.neg") (param v128) (result v128)
+    (f32x4.neg (local.get 0)))
+  (func (export "f32x4.le") (param v128 v128) (result v128)
+    (f32x4.le (local.get 0) (local.get 1)))
+  (func (export "i32x4.trunc_sat_f32x4_s") (param v128) (result v128)
+    (i32x4.trunc_sat_f32x4_s (local.get 0)))
+  (func (export "f64x2.convert_low_i32x4_u") (param v128) (result v128)
+    (f64x2.convert_low_i32x4_u (local.get 0)))
+  (func (export "f32x4.demote_f64x2_zero") (param v128) (result v128)
+    (f32x4.demote_f64x2_zero (local.get 0)))
+)
+
+(assert_return
+  (invoke "f32x4.add" (v128.const f32x4 1 2.5 -3 3e38) (v128.const f32x4 0.5 -2.5 3 3e38))
+  (v128.const f32x4 1.5 0 0 inf)
+)
+(assert_return
+  (invoke "f64x2.div" (v128.const f64x2 1 -1) (v128.const f64x2 4 0))
+  (v128.const f64x2 0.25 -inf)
+)
+(assert_return
+  (invoke "f32x4.min" (v128.const f32x4 0 -0 1 -inf) (v128.const f32x4 -0 0 -1 inf))
+  (v128.const f32x4 -0 -0 -1 -inf)
+)
+(assert_return
+  (invoke "f64x2.pmax" (v128.const f64x2 -0 1) (v128.const f64x2 0 2))
+  (v128.const f64x2 -0 2)
+)
+(assert_return
+  (invoke "f32x4.nearest" (v128.const f32x4 0.5 1.5 -2.5 -0.4))
+  (v128.const f32x4 0 2 -2 -0)
+)
+(assert_return
+  (invoke "f64x2.floor" (v128.const f64x2 -1.5 1.5))
+  (v128.const f64x2 -2 1)
+)
+(assert_return
+  (invoke "f32x4.neg" (v128.const f32x4 0 -1 inf 2))
+  (v128.const f32x4 -0 1 -inf -2)
+)
+(assert_return
+  (invoke "f32x4.le" (v128.const f32x4 1 2 nan -0) (v128.const f32x4 1 1 nan 0))
+  (v128.const i32x4 -1 0 0 -1)
+)
+(assert_return
+  (invoke "i32x4.trunc_sat_f32x4_s" (v128.const f32x4 -1.9 1e10 -inf nan))
+  (v128.const i32x4 -1 2147483647 -2147483648 0)
+)
+(assert_return
+  (invoke "f64x2.convert_low_i32x4_u" (v128.const i32x4 -1 3 7 7))
+  (v128.const f64x2 4294967295 3)
+)
+(assert_return
+  (invoke "f32x4.demote_f64x2_zero" (v128.const f64x2 0.5 1e300))
+  (v128.const f32x4 0.5 inf 0 0)
+)
+
+;; Lanes and bitwise operations.
+
+(module
+  (func (export "splat_extract") (param i32) (result i32)
+    (i8x16.extract_lane_s 15 (i8x16.splat (local.get 0))))
+  (func (export "replace_lane") (param v128 i64) (result v128)
+    (i64x2.replace_lane 1 (local.get 0) (local.get 1)))
+  (func (export "f32x4.extract_lane") (param v128) (result f32)
+    (f32x4.extract_lane 2 (local.get 0)))
+  (func (export "shuffle") (param v128 v128) (result v128)
+    (i8x16.shuffle 31 30 29 28 27 26 25 24 7 6 5 4 3 2 1 0 (local.get 0) (local.get 1)))
+  (func (export "swizzle") (param v128 v128) (result v128)
+    (i8x16.swizzle (local.get 0) (local.get 1)))
+  (func (export "bitselect") (param v128 v128 v128) (result v128)
+    (v128.bitselect (local.get 0) (local.get 1) (local.get 2)))
+  (func (export "andnot") (param v128 v128) (result v128)
+    (v128.andnot (local.get 0) (local.get 1)))
+  (func (export "any_true") (param v128) (result i32)
+    (v128.any_true (local.get 0)))
+  (func (export "i32x4.all_true") (param v128) (result i32)
+    (i32x4.all_true (local.get 0)))
+  (func (export "i16x8.bitmask") (param v128) (result i32)
+    (i16x8.bitmask (local.get 0)))
+  (func (export "select") (param v128 v128 i32) (result v128)
+    (select (local.get 0) (local.get 1) (local.get 2)))
+)
+
+(assert_return (invoke "splat_extract" (i32.const 0x180)) (i32.const -128))
+(assert_return
+  (invoke "replace_lane" (v128.const i64x2 1 2) (i64.const -3))
+  (v128.const i64x2 1 -3)
+)
+(assert_return (invoke "f32x4.extract_lane" (v128.const f32x4 1 2 -3.5 4)) (f32.const -3.5))
+(assert_return
+  (invoke "shuffle"
+    (v128.const i8x16 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15)
+    (v128.const i8x16 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31))
+  (v128.const i8x16 31 30 29 28 27 26 25 24 7 6 5 4 3 2 1 0)
+)
+(assert_return
+  (invoke "swizzle"
+    (v128.const i8x16 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25)
+    (v128.const i8x16 15 0 16 255 1 1 1 1 1 1 1 1 1 1 1 1))
+  (v128.const i8x16 25 10 0 0 11 11 11 11 11 11 11 11 11 11 11 11)
+)
+(assert_return
+  (invoke "bitselect"
+    (v128.const i64x2 -1 0)
+    (v128.const i64x2 0 -1)
+    (v128.const i64x2 0xffff0000ffff0000 0xff))
+  (v128.const i64x2 0xffff0000ffff0000 0xffffffffffffff00)
+)
+(assert_return
+  (invoke "andnot" (v128.const i32x4 -1 -1 0 5) (v128.const i32x4 0 -1 -1 4))
+  (v128.const i32x4 -1 0 0 1)
+)
+(assert_return (invoke "any_true" (v128.const i64x2 0 0)) (i32.const 0))
+(assert_return (invoke "any_true" (v128.const i64x2 0 0x100)) (i32.const 1))
+(assert_return (invoke "i32x4.all_true" (v128.const i32x4 1 -1 2 3)) (i32.const 1))
+(assert_return (invoke "i32x4.all_true" (v128.const i32x4 1 -1 0 3)) (i32.const 0))
+(assert_return (invoke "i16x8.bitmask" (v128.const i16x8 -1 0 0 -32768 1 0 0 -2)) (i32.const 0x89))
+(assert_return
+  (invoke "select" (v128.const i32x4 1 2 3 4) (v128.const i32x4 5 6 7 8) (i32.const 1))
+  (v128.const i32x4 1 2 3 4)
+)
+(assert_return
+  (invoke "select" (v128.const i32x4 1 2 3 4) (v128.const i32x4 5 6 7 8) (i32.const 0))
+  (v128.const i32x4 5 6 7 8)
+)
+
+;; Memory.
+
+(module
+  (memory 1)
+  (data (i32.const 0) "\00\01\02\03\04\05\06\07\08\09\0a\0b\0c\0d\0e\0f\80\81")
+  (func (export "load") (param i32) (result v128)
+    (v128.load (local.get 0)))
+  (func (export "store") (param i32 v128)
+    (v128.store offset=2 (local.get 0) (local.get 1)))
+  (func (export "load16_splat") (param i32) (result v128)
+    (v128.load16_splat (local.get 0)))
+  (func (export "load8x8_u") (param i32) (result v128)
+    (v128.load8x8_u (local.get 0)))
+  (func (export "load32x2_s") (param i32) (result v128)
+    (v128.load32x2_s (local.get 0)))
+  (func (export "load64_zero") (param i32) (result v128)
+    (v128.load64_zero (local.get 0)))
+  (func (export "load8_lane") (param i32 v128) (result v128)
+    (v128.load8_lane 15 (local.get 0) (local.get 1)))
+  (func (export "store64_lane") (param i32 v128)
+    (v128.store64_lane 1 (local.get 0) (local.get 1)))
+)
+
+(assert_return (invoke "load" (i32.const 1)) (v128.const i8x16 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 -128))
+(assert_return (invoke "load16_splat" (i32.const 16)) (v128.const i16x8 -32384 -32384 -32384 -32384 -32384 -32384 -32384 -32384))
+(assert_return (invoke "load8x8_u" (i32.const 10)) (v128.const i16x8 10 11 12 13 14 15 128 129))
+(assert_return (invoke "load32x2_s" (i32.const 14)) (v128.const i64x2 0xffffffff81800f0e 0))
+(assert_return (invoke "load64_zero" (i32.const 8)) (v128.const i64x2 0x0f0e0d0c0b0a0908 0))
+(assert_return
+  (invoke "load8_lane" (i32.const 17) (v128.const i64x2 -1 -1))
+  (v128.const i8x16 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -127)
+)
+(assert_return (invoke "store" (i32.const 30) (v128.const i64x2 5 6)))
+(assert_return (invoke "load" (i32.const 32)) (v128.const i64x2 5 6))
+(assert_return (invoke "store64_lane" (i32.const 64) (v128.const i64x2 5 6)))
+(assert_return (invoke "load" (i32.const 64)) (v128.const i64x2 6 0))
+(assert_trap (invoke "load" (i32.const 65521)) "out of bounds memory access")
+(assert_trap (invoke "store" (i32.const 65520) (v128.const i64x2 0 0)) "out of bounds memory access")
+(assert_trap (invoke "load8_lane" (i32.const 65536) (v128.const i64x2 0 0)) "out of bounds memory access")
+
+(assert_invalid
+  (module (func (result v128) (i32x4.add (v128.const i64x2 0 0) (i32.const 0))))
+  "type mismatch"
+)
+(assert_invalid
+  (module (func (result i32) (i8x16.extract_lane_u 16 (v128.const i64x2 0 0))))
+  "invalid lane index"
+)
@@ -0,0 +1,10 @@
+/// Unwrap a `Result<_, __wasi_errno_t>`, returning the errno
+/// from the enclosing syscall on failure.
+macro_rules! wasi_try {
+    ($expr:expr) => {{
+        match $expr {
+            Ok(val) => val,
+            Err(err) => return u32::from(err),
+        }
+    }};
+}
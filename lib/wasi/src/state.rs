@@ -0,0 +1,227 @@
+use crate::syscalls::types::*;
+use hashbrown::HashMap;
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+/// What a WASI file descriptor refers to on the host.
+pub enum Kind {
+    Stdin,
+    Stdout,
+    Stderr,
+    File { handle: fs::File },
+    Dir { path: PathBuf },
+}
+
+pub struct Fd {
+    pub rights: __wasi_rights_t,
+    pub rights_inheriting: __wasi_rights_t,
+    pub flags: __wasi_fdflags_t,
+    pub kind: Kind,
+    /// The name the guest sees for a preopened directory.
+    pub preopen_name: Option<String>,
+}
+
+impl Fd {
+    pub fn filetype(&self) -> __wasi_filetype_t {
+        match self.kind {
+            Kind::Stdin | Kind::Stdout | Kind::Stderr => __WASI_FILETYPE_CHARACTER_DEVICE,
+            Kind::File { .. } => __WASI_FILETYPE_REGULAR_FILE,
+            Kind::Dir { .. } => __WASI_FILETYPE_DIRECTORY,
+        }
+    }
+
+    pub fn has_rights(&self, rights: __wasi_rights_t) -> bool {
+        self.rights & rights == rights
+    }
+}
+
+/// The file descriptor table of a WASI instance.
+pub struct WasiFs {
+    fds: HashMap<__wasi_fd_t, Fd>,
+    next_fd: __wasi_fd_t,
+}
+
+impl WasiFs {
+    /// Check that each of `dirs` is a directory, and pair
+    /// the name the guest sees with the path on the host.
+    pub fn preopen(dirs: &[String]) -> io::Result<Vec<(String, PathBuf)>> {
+        dirs.iter()
+            .map(|dir| {
+                let path = fs::canonicalize(dir).map_err(|e| {
+                    io::Error::new(e.kind(), format!("can't preopen {}: {}", dir, e))
+                })?;
+                if !path.is_dir() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("preopened path {} is not a directory", dir),
+                    ));
+                }
+                Ok((dir.clone(), path))
+            })
+            .collect()
+    }
+
+    /// Create the file descriptor table, with the directories
+    /// checked by [`preopen`] after stdin, stdout and stderr.
+    ///
+    /// [`preopen`]: #method.preopen
+    pub fn new(preopened_dirs: &[(String, PathBuf)]) -> Self {
+        let mut wasi_fs = WasiFs {
+            fds: HashMap::new(),
+            next_fd: 0,
+        };
+
+        let stdio_rights = __WASI_RIGHT_FD_FDSTAT_SET_FLAGS
+            | __WASI_RIGHT_FD_FILESTAT_GET
+            | __WASI_RIGHT_POLL_FD_READWRITE;
+        wasi_fs.insert(Fd {
+            rights: stdio_rights | __WASI_RIGHT_FD_READ,
+            rights_inheriting: 0,
+            flags: 0,
+            kind: Kind::Stdin,
+            preopen_name: None,
+        });
+        wasi_fs.insert(Fd {
+            rights: stdio_rights | __WASI_RIGHT_FD_WRITE,
+            rights_inheriting: 0,
+            flags: __WASI_FDFLAG_APPEND,
+            kind: Kind::Stdout,
+            preopen_name: None,
+        });
+        wasi_fs.insert(Fd {
+            rights: stdio_rights | __WASI_RIGHT_FD_WRITE,
+            rights_inheriting: 0,
+            flags: __WASI_FDFLAG_APPEND,
+            kind: Kind::Stderr,
+            preopen_name: None,
+        });
+
+        for (name, path) in preopened_dirs {
+            wasi_fs.insert(Fd {
+                rights: ALL_RIGHTS,
+                rights_inheriting: ALL_RIGHTS,
+                flags: 0,
+                kind: Kind::Dir { path: path.clone() },
+                preopen_name: Some(name.clone()),
+            });
+        }
+
+        wasi_fs
+    }
+
+    pub fn insert(&mut self, fd: Fd) -> __wasi_fd_t {
+        let index = self.next_fd;
+        self.next_fd += 1;
+        self.fds.insert(index, fd);
+        index
+    }
+
+    pub fn get(&self, fd: __wasi_fd_t) -> Result<&Fd, __wasi_errno_t> {
+        self.fds.get(&fd).ok_or(__WASI_EBADF)
+    }
+
+    pub fn get_mut(&mut self, fd: __wasi_fd_t) -> Result<&mut Fd, __wasi_errno_t> {
+        self.fds.get_mut(&fd).ok_or(__WASI_EBADF)
+    }
+
+    pub fn remove(&mut self, fd: __wasi_fd_t) -> Result<Fd, __wasi_errno_t> {
+        self.fds.remove(&fd).ok_or(__WASI_EBADF)
+    }
+
+    /// Move `from` over `to`, closing whatever `to` referred to.
+    pub fn renumber(&mut self, from: __wasi_fd_t, to: __wasi_fd_t) -> Result<(), __wasi_errno_t> {
+        if !self.fds.contains_key(&to) {
+            return Err(__WASI_EBADF);
+        }
+        let fd = self.remove(from)?;
+        self.fds.insert(to, fd);
+        Ok(())
+    }
+
+    /// Resolve `path` relative to the directory `dir_fd`.
+    ///
+    /// Absolute paths and paths that climb out of the directory
+    /// with `..` are rejected, so the guest can only reach what
+    /// it has been given a capability for.
+    pub fn resolve(
+        &self,
+        dir_fd: __wasi_fd_t,
+        path: &str,
+        rights: __wasi_rights_t,
+    ) -> Result<PathBuf, __wasi_errno_t> {
+        let fd = self.get(dir_fd)?;
+        let base = match &fd.kind {
+            Kind::Dir { path } => path,
+            _ => return Err(__WASI_ENOTDIR),
+        };
+        if !fd.has_rights(rights) {
+            return Err(__WASI_ENOTCAPABLE);
+        }
+
+        let mut resolved = base.clone();
+        let mut depth = 0usize;
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(name) => {
+                    resolved.push(name);
+                    depth += 1;
+                }
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if depth == 0 {
+                        return Err(__WASI_ENOTCAPABLE);
+                    }
+                    resolved.pop();
+                    depth -= 1;
+                }
+                Component::RootDir | Component::Prefix(_) => return Err(__WASI_ENOTCAPABLE),
+            }
+        }
+
+        Ok(resolved)
+    }
+}
+
+/// The host state of a WASI instance, stored in `Ctx::data`.
+pub struct WasiState {
+    pub fs: WasiFs,
+    pub args: Vec<Vec<u8>>,
+    pub envs: Vec<Vec<u8>>,
+}
+
+#[cfg(test)]
+mod state_tests {
+    use super::WasiFs;
+    use crate::syscalls::types::*;
+
+    #[test]
+    fn test_resolve_stays_in_preopened_dir() {
+        let wasi_fs = WasiFs::new(&WasiFs::preopen(&[".".to_string()]).unwrap());
+        let base = std::fs::canonicalize(".").unwrap();
+
+        assert_eq!(
+            wasi_fs.resolve(3, "a/./b/../c", __WASI_RIGHT_PATH_OPEN),
+            Ok(base.join("a").join("c"))
+        );
+        assert_eq!(
+            wasi_fs.resolve(3, "a/../..", __WASI_RIGHT_PATH_OPEN),
+            Err(__WASI_ENOTCAPABLE)
+        );
+        assert_eq!(
+            wasi_fs.resolve(3, "/etc/passwd", __WASI_RIGHT_PATH_OPEN),
+            Err(__WASI_ENOTCAPABLE)
+        );
+        assert_eq!(
+            wasi_fs.resolve(1, "a", __WASI_RIGHT_PATH_OPEN),
+            Err(__WASI_ENOTDIR)
+        );
+    }
+
+    #[test]
+    fn test_preopen_rejects_missing_and_non_dirs() {
+        assert!(WasiFs::preopen(&["./does/not/exist".to_string()]).is_err());
+        assert!(WasiFs::preopen(&["Cargo.toml".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,958 @@
+pub mod types;
+
+#[cfg(unix)]
+mod unix;
+
+#[cfg(windows)]
+mod windows;
+
+#[cfg(unix)]
+use self::unix::*;
+
+#[cfg(windows)]
+use self::windows::*;
+
+use self::types::*;
+use crate::{
+    state::{Fd, Kind, WasiState},
+    utils::{check_bytes, read_bytes, read_iovecs, read_string, write_bytes, write_u32, write_u64},
+};
+use rand::RngCore;
+use std::{
+    cmp, fs,
+    io::{self, Read, Seek, SeekFrom, Write},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use wasmer_runtime_core::{error::ExitCode, vm::Ctx};
+
+/// The state lives in `Ctx::data` for as long as the instance does,
+/// so it is not tied to the borrow of `ctx`.
+fn get_wasi_state<'a>(ctx: &Ctx) -> &'a mut WasiState {
+    unsafe { &mut *(ctx.data as *mut WasiState) }
+}
+
+fn io_error_to_errno(err: io::Error) -> __wasi_errno_t {
+    if let Some(errno) = err.raw_os_error().and_then(platform_errno) {
+        return errno;
+    }
+    match err.kind() {
+        io::ErrorKind::NotFound => __WASI_ENOENT,
+        io::ErrorKind::PermissionDenied => __WASI_EACCES,
+        io::ErrorKind::AlreadyExists => __WASI_EEXIST,
+        io::ErrorKind::InvalidInput => __WASI_EINVAL,
+        io::ErrorKind::BrokenPipe => __WASI_EPIPE,
+        io::ErrorKind::Interrupted => __WASI_EINTR,
+        io::ErrorKind::WouldBlock => __WASI_EAGAIN,
+        io::ErrorKind::TimedOut => __WASI_ETIMEDOUT,
+        _ => __WASI_EIO,
+    }
+}
+
+fn filetype_from(file_type: fs::FileType) -> __wasi_filetype_t {
+    if file_type.is_dir() {
+        __WASI_FILETYPE_DIRECTORY
+    } else if file_type.is_file() {
+        __WASI_FILETYPE_REGULAR_FILE
+    } else if file_type.is_symlink() {
+        __WASI_FILETYPE_SYMBOLIC_LINK
+    } else {
+        __WASI_FILETYPE_UNKNOWN
+    }
+}
+
+fn timestamp(time: io::Result<SystemTime>) -> __wasi_timestamp_t {
+    time.ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs() * 1_000_000_000 + u64::from(duration.subsec_nanos()))
+        .unwrap_or(0)
+}
+
+fn filestat_from_metadata(metadata: &fs::Metadata) -> __wasi_filestat_t {
+    let (st_dev, st_ino, st_nlink, st_ctim) = platform_metadata(metadata);
+    __wasi_filestat_t {
+        st_dev,
+        st_ino,
+        st_filetype: filetype_from(metadata.file_type()),
+        st_nlink,
+        st_size: metadata.len(),
+        st_atim: timestamp(metadata.accessed()),
+        st_mtim: timestamp(metadata.modified()),
+        st_ctim,
+    }
+}
+
+fn check_rights(fd: &Fd, rights: __wasi_rights_t) -> Result<(), __wasi_errno_t> {
+    if fd.has_rights(rights) {
+        Ok(())
+    } else {
+        Err(__WASI_ENOTCAPABLE)
+    }
+}
+
+fn get_file(fd: &mut Fd) -> Result<&mut fs::File, __wasi_errno_t> {
+    match &mut fd.kind {
+        Kind::File { handle } => Ok(handle),
+        Kind::Dir { .. } => Err(__WASI_EISDIR),
+        _ => Err(__WASI_ESPIPE),
+    }
+}
+
+/// ### `args_get()`
+/// Read command-line argument data.
+pub fn args_get(ctx: &mut Ctx, argv: u32, argv_buf: u32) -> u32 {
+    debug!("wasi::args_get");
+    let state = get_wasi_state(ctx);
+    wasi_try!(write_string_list(ctx, &state.args, argv, argv_buf));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `args_sizes_get()`
+/// Return command-line argument data sizes.
+pub fn args_sizes_get(ctx: &mut Ctx, argc: u32, argv_buf_size: u32) -> u32 {
+    debug!("wasi::args_sizes_get");
+    let state = get_wasi_state(ctx);
+    wasi_try!(write_string_list_sizes(
+        ctx,
+        &state.args,
+        argc,
+        argv_buf_size
+    ));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `environ_get()`
+/// Read environment variable data.
+pub fn environ_get(ctx: &mut Ctx, environ: u32, environ_buf: u32) -> u32 {
+    debug!("wasi::environ_get");
+    let state = get_wasi_state(ctx);
+    wasi_try!(write_string_list(ctx, &state.envs, environ, environ_buf));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `environ_sizes_get()`
+/// Return environment variable data sizes.
+pub fn environ_sizes_get(ctx: &mut Ctx, environ_count: u32, environ_buf_size: u32) -> u32 {
+    debug!("wasi::environ_sizes_get");
+    let state = get_wasi_state(ctx);
+    wasi_try!(write_string_list_sizes(
+        ctx,
+        &state.envs,
+        environ_count,
+        environ_buf_size
+    ));
+    __WASI_ESUCCESS.into()
+}
+
+/// Write `list` as an array of pointers to nul-terminated strings,
+/// the layout shared by `args_get` and `environ_get`.
+fn write_string_list(
+    ctx: &Ctx,
+    list: &[Vec<u8>],
+    ptrs: u32,
+    buf: u32,
+) -> Result<(), __wasi_errno_t> {
+    let memory = ctx.memory(0);
+    let mut offset = buf;
+    for (i, string) in list.iter().enumerate() {
+        let ptr = (i as u32)
+            .checked_mul(4)
+            .and_then(|i| ptrs.checked_add(i))
+            .ok_or(__WASI_EFAULT)?;
+        write_u32(memory, ptr, offset)?;
+        write_bytes(memory, offset, string)?;
+        let end = offset
+            .checked_add(string.len() as u32)
+            .ok_or(__WASI_EFAULT)?;
+        write_bytes(memory, end, &[0])?;
+        offset = end.checked_add(1).ok_or(__WASI_EFAULT)?;
+    }
+    Ok(())
+}
+
+fn write_string_list_sizes(
+    ctx: &Ctx,
+    list: &[Vec<u8>],
+    count: u32,
+    buf_size: u32,
+) -> Result<(), __wasi_errno_t> {
+    let memory = ctx.memory(0);
+    let total_size: usize = list.iter().map(|string| string.len() + 1).sum();
+    write_u32(memory, count, list.len() as u32)?;
+    write_u32(memory, buf_size, total_size as u32)
+}
+
+/// ### `clock_res_get()`
+/// Get the resolution of the specified clock.
+pub fn clock_res_get(ctx: &mut Ctx, clock_id: u32, resolution: u32) -> u32 {
+    debug!("wasi::clock_res_get");
+    let res = wasi_try!(platform_clock_res_get(clock_id));
+    wasi_try!(write_u64(ctx.memory(0), resolution, res));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `clock_time_get()`
+/// Get the time of the specified clock.
+pub fn clock_time_get(ctx: &mut Ctx, clock_id: u32, _precision: u64, time: u32) -> u32 {
+    debug!("wasi::clock_time_get");
+    let now = wasi_try!(platform_clock_time_get(clock_id));
+    wasi_try!(write_u64(ctx.memory(0), time, now));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `fd_advise()`
+/// Advise the system about how a file will be used. This is only a hint, so it is ignored.
+pub fn fd_advise(ctx: &mut Ctx, fd: u32, _offset: u64, _len: u64, _advice: u32) -> u32 {
+    debug!("wasi::fd_advise");
+    let state = get_wasi_state(ctx);
+    let fd = wasi_try!(state.fs.get(fd));
+    wasi_try!(check_rights(fd, __WASI_RIGHT_FD_ADVISE));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `fd_allocate()`
+/// Make sure the file has space for `offset + len` bytes.
+pub fn fd_allocate(ctx: &mut Ctx, fd: u32, offset: u64, len: u64) -> u32 {
+    debug!("wasi::fd_allocate");
+    let state = get_wasi_state(ctx);
+    let fd = wasi_try!(state.fs.get_mut(fd));
+    wasi_try!(check_rights(fd, __WASI_RIGHT_FD_ALLOCATE));
+    let file = wasi_try!(get_file(fd));
+    let new_size = wasi_try!(offset.checked_add(len).ok_or(__WASI_EINVAL));
+    let current_size = wasi_try!(file.metadata().map_err(io_error_to_errno)).len();
+    if new_size > current_size {
+        wasi_try!(file.set_len(new_size).map_err(io_error_to_errno));
+    }
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `fd_close()`
+/// Close an open file descriptor.
+pub fn fd_close(ctx: &mut Ctx, fd: u32) -> u32 {
+    debug!("wasi::fd_close: fd={}", fd);
+    let state = get_wasi_state(ctx);
+    wasi_try!(state.fs.remove(fd));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `fd_datasync()`
+/// Synchronize the data of a file to disk.
+pub fn fd_datasync(ctx: &mut Ctx, fd: u32) -> u32 {
+    debug!("wasi::fd_datasync");
+    let state = get_wasi_state(ctx);
+    let fd = wasi_try!(state.fs.get_mut(fd));
+    wasi_try!(check_rights(fd, __WASI_RIGHT_FD_DATASYNC));
+    let file = wasi_try!(get_file(fd));
+    wasi_try!(file.sync_data().map_err(io_error_to_errno));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `fd_fdstat_get()`
+/// Get metadata of a file descriptor.
+pub fn fd_fdstat_get(ctx: &mut Ctx, fd: u32, buf: u32) -> u32 {
+    debug!("wasi::fd_fdstat_get: fd={}", fd);
+    let state = get_wasi_state(ctx);
+    let fd = wasi_try!(state.fs.get(fd));
+    let fdstat = __wasi_fdstat_t {
+        fs_filetype: fd.filetype(),
+        fs_flags: fd.flags,
+        fs_rights_base: fd.rights,
+        fs_rights_inheriting: fd.rights_inheriting,
+    };
+    wasi_try!(write_bytes(ctx.memory(0), buf, &fdstat.to_bytes()));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `fd_fdstat_set_flags()`
+/// Set file descriptor flags for a file descriptor.
+pub fn fd_fdstat_set_flags(ctx: &mut Ctx, fd: u32, flags: u32) -> u32 {
+    debug!("wasi::fd_fdstat_set_flags");
+    let state = get_wasi_state(ctx);
+    let fd = wasi_try!(state.fs.get_mut(fd));
+    wasi_try!(check_rights(fd, __WASI_RIGHT_FD_FDSTAT_SET_FLAGS));
+    fd.flags = flags as __wasi_fdflags_t;
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `fd_fdstat_set_rights()`
+/// Drop rights from a file descriptor. Rights can never be added back.
+pub fn fd_fdstat_set_rights(
+    ctx: &mut Ctx,
+    fd: u32,
+    fs_rights_base: u64,
+    fs_rights_inheriting: u64,
+) -> u32 {
+    debug!("wasi::fd_fdstat_set_rights");
+    let state = get_wasi_state(ctx);
+    let fd = wasi_try!(state.fs.get_mut(fd));
+    if fs_rights_base & !fd.rights != 0 || fs_rights_inheriting & !fd.rights_inheriting != 0 {
+        return __WASI_ENOTCAPABLE.into();
+    }
+    fd.rights = fs_rights_base;
+    fd.rights_inheriting = fs_rights_inheriting;
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `fd_filestat_get()`
+/// Get the metadata of an open file.
+pub fn fd_filestat_get(ctx: &mut Ctx, fd: u32, buf: u32) -> u32 {
+    debug!("wasi::fd_filestat_get");
+    let state = get_wasi_state(ctx);
+    let fd = wasi_try!(state.fs.get(fd));
+    wasi_try!(check_rights(fd, __WASI_RIGHT_FD_FILESTAT_GET));
+    let filestat = match &fd.kind {
+        Kind::File { handle } => {
+            filestat_from_metadata(&wasi_try!(handle.metadata().map_err(io_error_to_errno)))
+        }
+        Kind::Dir { path } => {
+            filestat_from_metadata(&wasi_try!(fs::metadata(path).map_err(io_error_to_errno)))
+        }
+        _ => __wasi_filestat_t {
+            st_dev: 0,
+            st_ino: 0,
+            st_filetype: fd.filetype(),
+            st_nlink: 1,
+            st_size: 0,
+            st_atim: 0,
+            st_mtim: 0,
+            st_ctim: 0,
+        },
+    };
+    wasi_try!(write_bytes(ctx.memory(0), buf, &filestat.to_bytes()));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `fd_filestat_set_size()`
+/// Truncate or extend an open file.
+pub fn fd_filestat_set_size(ctx: &mut Ctx, fd: u32, size: u64) -> u32 {
+    debug!("wasi::fd_filestat_set_size");
+    let state = get_wasi_state(ctx);
+    let fd = wasi_try!(state.fs.get_mut(fd));
+    wasi_try!(check_rights(fd, __WASI_RIGHT_FD_FILESTAT_SET_SIZE));
+    let file = wasi_try!(get_file(fd));
+    wasi_try!(file.set_len(size).map_err(io_error_to_errno));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `fd_filestat_set_times()`
+/// Setting timestamps is not supported yet.
+pub fn fd_filestat_set_times(ctx: &mut Ctx, fd: u32, _atim: u64, _mtim: u64, _flags: u32) -> u32 {
+    debug!("wasi::fd_filestat_set_times");
+    let state = get_wasi_state(ctx);
+    let fd = wasi_try!(state.fs.get(fd));
+    wasi_try!(check_rights(fd, __WASI_RIGHT_FD_FILESTAT_SET_TIMES));
+    __WASI_ENOTSUP.into()
+}
+
+/// ### `fd_pread()`
+/// Read from a file at the given offset, without moving the cursor.
+pub fn fd_pread(ctx: &mut Ctx, fd: u32, iovs: u32, iovs_len: u32, offset: u64, nread: u32) -> u32 {
+    debug!("wasi::fd_pread");
+    let state = get_wasi_state(ctx);
+    let memory = ctx.memory(0);
+    let iovecs = wasi_try!(read_iovecs(memory, iovs, iovs_len));
+    let fd = wasi_try!(state.fs.get_mut(fd));
+    wasi_try!(check_rights(
+        fd,
+        __WASI_RIGHT_FD_READ | __WASI_RIGHT_FD_SEEK
+    ));
+    let file = wasi_try!(get_file(fd));
+    for &(buf, buf_len) in &iovecs {
+        wasi_try!(check_bytes(memory, buf, buf_len));
+    }
+
+    let mut total = 0u32;
+    for (buf, buf_len) in iovecs {
+        let mut bytes = vec![0; buf_len as usize];
+        let offset = wasi_try!(offset.checked_add(u64::from(total)).ok_or(__WASI_EINVAL));
+        let read = wasi_try!(platform_read_at(file, &mut bytes, offset).map_err(io_error_to_errno));
+        wasi_try!(write_bytes(memory, buf, &bytes[..read]));
+        total += read as u32;
+        if read < buf_len as usize {
+            break;
+        }
+    }
+    wasi_try!(write_u32(memory, nread, total));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `fd_prestat_get()`
+/// Get metadata about a preopened directory.
+pub fn fd_prestat_get(ctx: &mut Ctx, fd: u32, buf: u32) -> u32 {
+    debug!("wasi::fd_prestat_get: fd={}", fd);
+    let state = get_wasi_state(ctx);
+    let fd = wasi_try!(state.fs.get(fd));
+    let name = match &fd.preopen_name {
+        Some(name) => name,
+        None => return __WASI_EBADF.into(),
+    };
+    let mut prestat = [0; PRESTAT_SIZE as usize];
+    prestat[0] = __WASI_PREOPENTYPE_DIR;
+    prestat[4..8].copy_from_slice(&(name.len() as u32).to_le_bytes());
+    wasi_try!(write_bytes(ctx.memory(0), buf, &prestat));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `fd_prestat_dir_name()`
+/// Get the name the guest should use for a preopened directory.
+pub fn fd_prestat_dir_name(ctx: &mut Ctx, fd: u32, path: u32, path_len: u32) -> u32 {
+    debug!("wasi::fd_prestat_dir_name: fd={}", fd);
+    let state = get_wasi_state(ctx);
+    let fd = wasi_try!(state.fs.get(fd));
+    let name = match &fd.preopen_name {
+        Some(name) => name,
+        None => return __WASI_EBADF.into(),
+    };
+    if (path_len as usize) < name.len() {
+        return __WASI_EINVAL.into();
+    }
+    wasi_try!(write_bytes(ctx.memory(0), path, name.as_bytes()));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `fd_pwrite()`
+/// Write to a file at the given offset, without moving the cursor.
+pub fn fd_pwrite(
+    ctx: &mut Ctx,
+    fd: u32,
+    iovs: u32,
+    iovs_len: u32,
+    offset: u64,
+    nwritten: u32,
+) -> u32 {
+    debug!("wasi::fd_pwrite");
+    let state = get_wasi_state(ctx);
+    let memory = ctx.memory(0);
+    let iovecs = wasi_try!(read_iovecs(memory, iovs, iovs_len));
+    let fd = wasi_try!(state.fs.get_mut(fd));
+    wasi_try!(check_rights(
+        fd,
+        __WASI_RIGHT_FD_WRITE | __WASI_RIGHT_FD_SEEK
+    ));
+    let file = wasi_try!(get_file(fd));
+
+    let mut total = 0u32;
+    for (buf, buf_len) in iovecs {
+        let bytes = wasi_try!(read_bytes(memory, buf, buf_len));
+        let offset = wasi_try!(offset.checked_add(u64::from(total)).ok_or(__WASI_EINVAL));
+        let written = wasi_try!(platform_write_at(file, &bytes, offset).map_err(io_error_to_errno));
+        total += written as u32;
+        if written < bytes.len() {
+            break;
+        }
+    }
+    wasi_try!(write_u32(memory, nwritten, total));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `fd_read()`
+/// Read data from a file descriptor.
+pub fn fd_read(ctx: &mut Ctx, fd: u32, iovs: u32, iovs_len: u32, nread: u32) -> u32 {
+    debug!("wasi::fd_read: fd={}", fd);
+    let state = get_wasi_state(ctx);
+    let memory = ctx.memory(0);
+    let iovecs = wasi_try!(read_iovecs(memory, iovs, iovs_len));
+    let fd = wasi_try!(state.fs.get_mut(fd));
+    wasi_try!(check_rights(fd, __WASI_RIGHT_FD_READ));
+    for &(buf, buf_len) in &iovecs {
+        wasi_try!(check_bytes(memory, buf, buf_len));
+    }
+
+    let mut total = 0u32;
+    for (buf, buf_len) in iovecs {
+        let mut bytes = vec![0; buf_len as usize];
+        let read = match &mut fd.kind {
+            Kind::Stdin => io::stdin().read(&mut bytes),
+            Kind::File { handle } => handle.read(&mut bytes),
+            Kind::Dir { .. } => return __WASI_EISDIR.into(),
+            Kind::Stdout | Kind::Stderr => return __WASI_EBADF.into(),
+        };
+        let read = wasi_try!(read.map_err(io_error_to_errno));
+        wasi_try!(write_bytes(memory, buf, &bytes[..read]));
+        total += read as u32;
+        if read < buf_len as usize {
+            break;
+        }
+    }
+    wasi_try!(write_u32(memory, nread, total));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `fd_readdir()`
+/// Read directory entries, starting at `cookie`. The last entry
+/// is truncated if it does not fit in the buffer.
+pub fn fd_readdir(
+    ctx: &mut Ctx,
+    fd: u32,
+    buf: u32,
+    buf_len: u32,
+    cookie: u64,
+    bufused: u32,
+) -> u32 {
+    debug!("wasi::fd_readdir");
+    let state = get_wasi_state(ctx);
+    let memory = ctx.memory(0);
+    let fd = wasi_try!(state.fs.get(fd));
+    wasi_try!(check_rights(fd, __WASI_RIGHT_FD_READDIR));
+    let path = match &fd.kind {
+        Kind::Dir { path } => path,
+        _ => return __WASI_ENOTDIR.into(),
+    };
+
+    let mut entries = wasi_try!(fs::read_dir(path)
+        .and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+        .map_err(io_error_to_errno));
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut buf_used = 0u32;
+    for (index, entry) in entries.iter().enumerate().skip(cookie as usize) {
+        if buf_used == buf_len {
+            break;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let dirent = __wasi_dirent_t {
+            d_next: index as u64 + 1,
+            d_ino: 0,
+            d_namlen: name.len() as u32,
+            d_type: entry
+                .file_type()
+                .map(filetype_from)
+                .unwrap_or(__WASI_FILETYPE_UNKNOWN),
+        };
+        let mut bytes = dirent.to_bytes().to_vec();
+        bytes.extend_from_slice(name.as_bytes());
+
+        let len = cmp::min(bytes.len(), (buf_len - buf_used) as usize);
+        let ptr = wasi_try!(buf.checked_add(buf_used).ok_or(__WASI_EFAULT));
+        wasi_try!(write_bytes(memory, ptr, &bytes[..len]));
+        buf_used += len as u32;
+    }
+    wasi_try!(write_u32(memory, bufused, buf_used));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `fd_renumber()`
+/// Atomically replace a file descriptor by renumbering another one.
+pub fn fd_renumber(ctx: &mut Ctx, from: u32, to: u32) -> u32 {
+    debug!("wasi::fd_renumber: from={}, to={}", from, to);
+    let state = get_wasi_state(ctx);
+    wasi_try!(state.fs.renumber(from, to));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `fd_seek()`
+/// Move the offset of a file descriptor.
+pub fn fd_seek(ctx: &mut Ctx, fd: u32, offset: i64, whence: u32, newoffset: u32) -> u32 {
+    debug!("wasi::fd_seek: fd={}", fd);
+    let state = get_wasi_state(ctx);
+    let fd = wasi_try!(state.fs.get_mut(fd));
+    wasi_try!(check_rights(fd, __WASI_RIGHT_FD_SEEK));
+    let file = wasi_try!(get_file(fd));
+    let seek_from = match whence as __wasi_whence_t {
+        __WASI_WHENCE_CUR => SeekFrom::Current(offset),
+        __WASI_WHENCE_END => SeekFrom::End(offset),
+        __WASI_WHENCE_SET if offset >= 0 => SeekFrom::Start(offset as u64),
+        _ => return __WASI_EINVAL.into(),
+    };
+    let new_offset = wasi_try!(file.seek(seek_from).map_err(io_error_to_errno));
+    wasi_try!(write_u64(ctx.memory(0), newoffset, new_offset));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `fd_sync()`
+/// Synchronize the data and metadata of a file to disk.
+pub fn fd_sync(ctx: &mut Ctx, fd: u32) -> u32 {
+    debug!("wasi::fd_sync");
+    let state = get_wasi_state(ctx);
+    let fd = wasi_try!(state.fs.get_mut(fd));
+    wasi_try!(check_rights(fd, __WASI_RIGHT_FD_SYNC));
+    let file = wasi_try!(get_file(fd));
+    wasi_try!(file.sync_all().map_err(io_error_to_errno));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `fd_tell()`
+/// Get the offset of a file descriptor.
+pub fn fd_tell(ctx: &mut Ctx, fd: u32, offset: u32) -> u32 {
+    debug!("wasi::fd_tell");
+    let state = get_wasi_state(ctx);
+    let fd = wasi_try!(state.fs.get_mut(fd));
+    wasi_try!(check_rights(fd, __WASI_RIGHT_FD_TELL));
+    let file = wasi_try!(get_file(fd));
+    let current = wasi_try!(file.stream_position().map_err(io_error_to_errno));
+    wasi_try!(write_u64(ctx.memory(0), offset, current));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `fd_write()`
+/// Write data to a file descriptor.
+pub fn fd_write(ctx: &mut Ctx, fd: u32, iovs: u32, iovs_len: u32, nwritten: u32) -> u32 {
+    debug!("wasi::fd_write: fd={}", fd);
+    let state = get_wasi_state(ctx);
+    let memory = ctx.memory(0);
+    let iovecs = wasi_try!(read_iovecs(memory, iovs, iovs_len));
+    let fd = wasi_try!(state.fs.get_mut(fd));
+    wasi_try!(check_rights(fd, __WASI_RIGHT_FD_WRITE));
+
+    let mut total = 0u32;
+    for (buf, buf_len) in iovecs {
+        let bytes = wasi_try!(read_bytes(memory, buf, buf_len));
+        let written = match &mut fd.kind {
+            Kind::Stdout => io::stdout().write_all(&bytes),
+            Kind::Stderr => io::stderr().write_all(&bytes),
+            Kind::File { handle } => handle.write_all(&bytes),
+            Kind::Dir { .. } => return __WASI_EISDIR.into(),
+            Kind::Stdin => return __WASI_EBADF.into(),
+        };
+        wasi_try!(written.map_err(io_error_to_errno));
+        total += buf_len;
+    }
+    wasi_try!(write_u32(memory, nwritten, total));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `path_create_directory()`
+/// Create a directory.
+pub fn path_create_directory(ctx: &mut Ctx, fd: u32, path: u32, path_len: u32) -> u32 {
+    debug!("wasi::path_create_directory");
+    let state = get_wasi_state(ctx);
+    let path = wasi_try!(read_string(ctx.memory(0), path, path_len));
+    let full_path = wasi_try!(state
+        .fs
+        .resolve(fd, &path, __WASI_RIGHT_PATH_CREATE_DIRECTORY));
+    wasi_try!(fs::create_dir(full_path).map_err(io_error_to_errno));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `path_filestat_get()`
+/// Get the metadata of a file or directory.
+pub fn path_filestat_get(
+    ctx: &mut Ctx,
+    fd: u32,
+    flags: u32,
+    path: u32,
+    path_len: u32,
+    buf: u32,
+) -> u32 {
+    debug!("wasi::path_filestat_get");
+    let state = get_wasi_state(ctx);
+    let memory = ctx.memory(0);
+    let path = wasi_try!(read_string(memory, path, path_len));
+    let full_path = wasi_try!(state.fs.resolve(fd, &path, __WASI_RIGHT_PATH_FILESTAT_GET));
+    let metadata = if flags & __WASI_LOOKUP_SYMLINK_FOLLOW != 0 {
+        fs::metadata(full_path)
+    } else {
+        fs::symlink_metadata(full_path)
+    };
+    let metadata = wasi_try!(metadata.map_err(io_error_to_errno));
+    wasi_try!(write_bytes(
+        memory,
+        buf,
+        &filestat_from_metadata(&metadata).to_bytes()
+    ));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `path_filestat_set_times()`
+/// Setting timestamps is not supported yet.
+#[allow(clippy::too_many_arguments)]
+pub fn path_filestat_set_times(
+    ctx: &mut Ctx,
+    fd: u32,
+    _flags: u32,
+    path: u32,
+    path_len: u32,
+    _atim: u64,
+    _mtim: u64,
+    _fst_flags: u32,
+) -> u32 {
+    debug!("wasi::path_filestat_set_times");
+    let state = get_wasi_state(ctx);
+    let path = wasi_try!(read_string(ctx.memory(0), path, path_len));
+    wasi_try!(state
+        .fs
+        .resolve(fd, &path, __WASI_RIGHT_PATH_FILESTAT_SET_TIMES));
+    __WASI_ENOTSUP.into()
+}
+
+/// ### `path_link()`
+/// Create a hard link.
+#[allow(clippy::too_many_arguments)]
+pub fn path_link(
+    ctx: &mut Ctx,
+    old_fd: u32,
+    _old_flags: u32,
+    old_path: u32,
+    old_path_len: u32,
+    new_fd: u32,
+    new_path: u32,
+    new_path_len: u32,
+) -> u32 {
+    debug!("wasi::path_link");
+    let state = get_wasi_state(ctx);
+    let memory = ctx.memory(0);
+    let old_path = wasi_try!(read_string(memory, old_path, old_path_len));
+    let new_path = wasi_try!(read_string(memory, new_path, new_path_len));
+    let old_full_path =
+        wasi_try!(state
+            .fs
+            .resolve(old_fd, &old_path, __WASI_RIGHT_PATH_LINK_SOURCE));
+    let new_full_path =
+        wasi_try!(state
+            .fs
+            .resolve(new_fd, &new_path, __WASI_RIGHT_PATH_LINK_TARGET));
+    wasi_try!(fs::hard_link(old_full_path, new_full_path).map_err(io_error_to_errno));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `path_open()`
+/// Open a file or directory relative to a directory file descriptor.
+#[allow(clippy::too_many_arguments)]
+pub fn path_open(
+    ctx: &mut Ctx,
+    dirfd: u32,
+    _dirflags: u32,
+    path: u32,
+    path_len: u32,
+    oflags: u32,
+    fs_rights_base: u64,
+    fs_rights_inheriting: u64,
+    fs_flags: u32,
+    fd: u32,
+) -> u32 {
+    debug!("wasi::path_open");
+    let state = get_wasi_state(ctx);
+    let memory = ctx.memory(0);
+    let oflags = oflags as __wasi_oflags_t;
+    let fs_flags = fs_flags as __wasi_fdflags_t;
+
+    let path = wasi_try!(read_string(memory, path, path_len));
+    let full_path = wasi_try!(state.fs.resolve(dirfd, &path, __WASI_RIGHT_PATH_OPEN));
+    let dir_fd = wasi_try!(state.fs.get(dirfd));
+    // A file descriptor can never have more rights than its parent hands down.
+    if fs_rights_base & !dir_fd.rights_inheriting != 0 {
+        return __WASI_ENOTCAPABLE.into();
+    }
+
+    let kind = if oflags & __WASI_O_DIRECTORY != 0
+        || (oflags & __WASI_O_CREAT == 0 && full_path.is_dir())
+    {
+        if !full_path.is_dir() {
+            return __WASI_ENOTDIR.into();
+        }
+        Kind::Dir { path: full_path }
+    } else {
+        let create = oflags & __WASI_O_CREAT != 0;
+        if create {
+            wasi_try!(check_rights(dir_fd, __WASI_RIGHT_PATH_CREATE_FILE));
+        }
+        let write = create
+            || oflags & __WASI_O_TRUNC != 0
+            || fs_rights_base
+                & (__WASI_RIGHT_FD_WRITE
+                    | __WASI_RIGHT_FD_ALLOCATE
+                    | __WASI_RIGHT_FD_FILESTAT_SET_SIZE)
+                != 0;
+        let handle = wasi_try!(fs::OpenOptions::new()
+            .read(fs_rights_base & __WASI_RIGHT_FD_READ != 0 || !write)
+            .write(write)
+            .append(fs_flags & __WASI_FDFLAG_APPEND != 0)
+            .truncate(oflags & __WASI_O_TRUNC != 0)
+            .create(create)
+            .create_new(create && oflags & __WASI_O_EXCL != 0)
+            .open(&full_path)
+            .map_err(io_error_to_errno));
+        Kind::File { handle }
+    };
+
+    let new_fd = state.fs.insert(Fd {
+        rights: fs_rights_base,
+        rights_inheriting: fs_rights_inheriting,
+        flags: fs_flags,
+        kind,
+        preopen_name: None,
+    });
+    wasi_try!(write_u32(memory, fd, new_fd));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `path_readlink()`
+/// Read the contents of a symbolic link.
+pub fn path_readlink(
+    ctx: &mut Ctx,
+    fd: u32,
+    path: u32,
+    path_len: u32,
+    buf: u32,
+    buf_len: u32,
+    bufused: u32,
+) -> u32 {
+    debug!("wasi::path_readlink");
+    let state = get_wasi_state(ctx);
+    let memory = ctx.memory(0);
+    let path = wasi_try!(read_string(memory, path, path_len));
+    let full_path = wasi_try!(state.fs.resolve(fd, &path, __WASI_RIGHT_PATH_READLINK));
+    let target = wasi_try!(fs::read_link(full_path).map_err(io_error_to_errno));
+    let target = target.to_string_lossy();
+    let len = cmp::min(target.len(), buf_len as usize);
+    wasi_try!(write_bytes(memory, buf, &target.as_bytes()[..len]));
+    wasi_try!(write_u32(memory, bufused, len as u32));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `path_remove_directory()`
+/// Remove an empty directory.
+pub fn path_remove_directory(ctx: &mut Ctx, fd: u32, path: u32, path_len: u32) -> u32 {
+    debug!("wasi::path_remove_directory");
+    let state = get_wasi_state(ctx);
+    let path = wasi_try!(read_string(ctx.memory(0), path, path_len));
+    let full_path = wasi_try!(state
+        .fs
+        .resolve(fd, &path, __WASI_RIGHT_PATH_REMOVE_DIRECTORY));
+    wasi_try!(fs::remove_dir(full_path).map_err(io_error_to_errno));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `path_rename()`
+/// Rename a file or directory.
+pub fn path_rename(
+    ctx: &mut Ctx,
+    old_fd: u32,
+    old_path: u32,
+    old_path_len: u32,
+    new_fd: u32,
+    new_path: u32,
+    new_path_len: u32,
+) -> u32 {
+    debug!("wasi::path_rename");
+    let state = get_wasi_state(ctx);
+    let memory = ctx.memory(0);
+    let old_path = wasi_try!(read_string(memory, old_path, old_path_len));
+    let new_path = wasi_try!(read_string(memory, new_path, new_path_len));
+    let old_full_path =
+        wasi_try!(state
+            .fs
+            .resolve(old_fd, &old_path, __WASI_RIGHT_PATH_RENAME_SOURCE));
+    let new_full_path =
+        wasi_try!(state
+            .fs
+            .resolve(new_fd, &new_path, __WASI_RIGHT_PATH_RENAME_TARGET));
+    wasi_try!(fs::rename(old_full_path, new_full_path).map_err(io_error_to_errno));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `path_symlink()`
+/// Create a symbolic link.
+pub fn path_symlink(
+    ctx: &mut Ctx,
+    old_path: u32,
+    old_path_len: u32,
+    fd: u32,
+    new_path: u32,
+    new_path_len: u32,
+) -> u32 {
+    debug!("wasi::path_symlink");
+    let state = get_wasi_state(ctx);
+    let memory = ctx.memory(0);
+    let old_path = wasi_try!(read_string(memory, old_path, old_path_len));
+    let new_path = wasi_try!(read_string(memory, new_path, new_path_len));
+    // The target must stay inside the directory as well.
+    wasi_try!(state.fs.resolve(fd, &old_path, __WASI_RIGHT_PATH_SYMLINK));
+    let new_full_path = wasi_try!(state.fs.resolve(fd, &new_path, __WASI_RIGHT_PATH_SYMLINK));
+    wasi_try!(platform_symlink(&old_path, &new_full_path).map_err(io_error_to_errno));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `path_unlink_file()`
+/// Remove a file.
+pub fn path_unlink_file(ctx: &mut Ctx, fd: u32, path: u32, path_len: u32) -> u32 {
+    debug!("wasi::path_unlink_file");
+    let state = get_wasi_state(ctx);
+    let path = wasi_try!(read_string(ctx.memory(0), path, path_len));
+    let full_path = wasi_try!(state.fs.resolve(fd, &path, __WASI_RIGHT_PATH_UNLINK_FILE));
+    wasi_try!(fs::remove_file(full_path).map_err(io_error_to_errno));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `poll_oneoff()`
+/// Polling is not supported yet.
+pub fn poll_oneoff(
+    _ctx: &mut Ctx,
+    _in: u32,
+    _out: u32,
+    _nsubscriptions: u32,
+    _nevents: u32,
+) -> u32 {
+    debug!("wasi::poll_oneoff");
+    __WASI_ENOTSUP.into()
+}
+
+/// ### `proc_exit()`
+/// Terminate the instance with the given exit code. The call into the
+/// instance fails with `RuntimeError::Exit`, rather than the host exiting.
+pub fn proc_exit(_ctx: &mut Ctx, code: u32) -> Result<(), ExitCode> {
+    debug!("wasi::proc_exit: code={}", code);
+    Err(ExitCode(code as i32))
+}
+
+/// ### `proc_raise()`
+/// Sending signals is not supported.
+pub fn proc_raise(_ctx: &mut Ctx, _sig: u32) -> u32 {
+    debug!("wasi::proc_raise");
+    __WASI_ENOTSUP.into()
+}
+
+/// ### `random_get()`
+/// Fill a buffer with random bytes.
+pub fn random_get(ctx: &mut Ctx, buf: u32, buf_len: u32) -> u32 {
+    debug!("wasi::random_get");
+    wasi_try!(check_bytes(ctx.memory(0), buf, buf_len));
+    let mut bytes = vec![0; buf_len as usize];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    wasi_try!(write_bytes(ctx.memory(0), buf, &bytes));
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `sched_yield()`
+/// Yield execution of the current thread.
+pub fn sched_yield(_ctx: &mut Ctx) -> u32 {
+    debug!("wasi::sched_yield");
+    thread::yield_now();
+    __WASI_ESUCCESS.into()
+}
+
+/// ### `sock_recv()`
+/// Sockets are not supported yet.
+pub fn sock_recv(
+    _ctx: &mut Ctx,
+    _sock: u32,
+    _ri_data: u32,
+    _ri_data_len: u32,
+    _ri_flags: u32,
+    _ro_datalen: u32,
+    _ro_flags: u32,
+) -> u32 {
+    debug!("wasi::sock_recv");
+    __WASI_ENOTSUP.into()
+}
+
+/// ### `sock_send()`
+/// Sockets are not supported yet.
+pub fn sock_send(
+    _ctx: &mut Ctx,
+    _sock: u32,
+    _si_data: u32,
+    _si_data_len: u32,
+    _si_flags: u32,
+    _so_datalen: u32,
+) -> u32 {
+    debug!("wasi::sock_send");
+    __WASI_ENOTSUP.into()
+}
+
+/// ### `sock_shutdown()`
+/// Sockets are not supported yet.
+pub fn sock_shutdown(_ctx: &mut Ctx, _sock: u32, _how: u32) -> u32 {
+    debug!("wasi::sock_shutdown");
+    __WASI_ENOTSUP.into()
+}
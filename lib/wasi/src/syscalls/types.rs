@@ -0,0 +1,195 @@
+//! Type definitions and constants of the `wasi_unstable` ABI.
+//!
+//! Integer types are the sizes used in guest memory. Values passed
+//! directly as arguments are widened to `u32`/`u64` by the wasm ABI.
+#![allow(non_camel_case_types, dead_code)]
+
+pub type __wasi_advice_t = u8;
+pub type __wasi_clockid_t = u32;
+pub type __wasi_device_t = u64;
+pub type __wasi_dircookie_t = u64;
+pub type __wasi_errno_t = u16;
+pub type __wasi_exitcode_t = u32;
+pub type __wasi_fd_t = u32;
+pub type __wasi_fdflags_t = u16;
+pub type __wasi_filedelta_t = i64;
+pub type __wasi_filesize_t = u64;
+pub type __wasi_filetype_t = u8;
+pub type __wasi_fstflags_t = u16;
+pub type __wasi_inode_t = u64;
+pub type __wasi_linkcount_t = u32;
+pub type __wasi_lookupflags_t = u32;
+pub type __wasi_oflags_t = u16;
+pub type __wasi_rights_t = u64;
+pub type __wasi_timestamp_t = u64;
+pub type __wasi_whence_t = u8;
+
+pub const __WASI_ESUCCESS: __wasi_errno_t = 0;
+pub const __WASI_E2BIG: __wasi_errno_t = 1;
+pub const __WASI_EACCES: __wasi_errno_t = 2;
+pub const __WASI_EAGAIN: __wasi_errno_t = 6;
+pub const __WASI_EBADF: __wasi_errno_t = 8;
+pub const __WASI_EEXIST: __wasi_errno_t = 20;
+pub const __WASI_EFAULT: __wasi_errno_t = 21;
+pub const __WASI_EILSEQ: __wasi_errno_t = 25;
+pub const __WASI_EINTR: __wasi_errno_t = 27;
+pub const __WASI_EINVAL: __wasi_errno_t = 28;
+pub const __WASI_EIO: __wasi_errno_t = 29;
+pub const __WASI_EISDIR: __wasi_errno_t = 31;
+pub const __WASI_ENOENT: __wasi_errno_t = 44;
+pub const __WASI_ENOSYS: __wasi_errno_t = 52;
+pub const __WASI_ENOTDIR: __wasi_errno_t = 54;
+pub const __WASI_ENOTEMPTY: __wasi_errno_t = 55;
+pub const __WASI_ENOTSUP: __wasi_errno_t = 58;
+pub const __WASI_EPERM: __wasi_errno_t = 63;
+pub const __WASI_EPIPE: __wasi_errno_t = 64;
+pub const __WASI_ESPIPE: __wasi_errno_t = 70;
+pub const __WASI_ETIMEDOUT: __wasi_errno_t = 73;
+pub const __WASI_ENOTCAPABLE: __wasi_errno_t = 76;
+
+pub const __WASI_CLOCK_REALTIME: __wasi_clockid_t = 0;
+pub const __WASI_CLOCK_MONOTONIC: __wasi_clockid_t = 1;
+pub const __WASI_CLOCK_PROCESS_CPUTIME_ID: __wasi_clockid_t = 2;
+pub const __WASI_CLOCK_THREAD_CPUTIME_ID: __wasi_clockid_t = 3;
+
+pub const __WASI_FDFLAG_APPEND: __wasi_fdflags_t = 1 << 0;
+pub const __WASI_FDFLAG_DSYNC: __wasi_fdflags_t = 1 << 1;
+pub const __WASI_FDFLAG_NONBLOCK: __wasi_fdflags_t = 1 << 2;
+pub const __WASI_FDFLAG_RSYNC: __wasi_fdflags_t = 1 << 3;
+pub const __WASI_FDFLAG_SYNC: __wasi_fdflags_t = 1 << 4;
+
+pub const __WASI_FILETYPE_UNKNOWN: __wasi_filetype_t = 0;
+pub const __WASI_FILETYPE_BLOCK_DEVICE: __wasi_filetype_t = 1;
+pub const __WASI_FILETYPE_CHARACTER_DEVICE: __wasi_filetype_t = 2;
+pub const __WASI_FILETYPE_DIRECTORY: __wasi_filetype_t = 3;
+pub const __WASI_FILETYPE_REGULAR_FILE: __wasi_filetype_t = 4;
+pub const __WASI_FILETYPE_SOCKET_DGRAM: __wasi_filetype_t = 5;
+pub const __WASI_FILETYPE_SOCKET_STREAM: __wasi_filetype_t = 6;
+pub const __WASI_FILETYPE_SYMBOLIC_LINK: __wasi_filetype_t = 7;
+
+pub const __WASI_FILESTAT_SET_ATIM: __wasi_fstflags_t = 1 << 0;
+pub const __WASI_FILESTAT_SET_ATIM_NOW: __wasi_fstflags_t = 1 << 1;
+pub const __WASI_FILESTAT_SET_MTIM: __wasi_fstflags_t = 1 << 2;
+pub const __WASI_FILESTAT_SET_MTIM_NOW: __wasi_fstflags_t = 1 << 3;
+
+pub const __WASI_LOOKUP_SYMLINK_FOLLOW: __wasi_lookupflags_t = 1 << 0;
+
+pub const __WASI_O_CREAT: __wasi_oflags_t = 1 << 0;
+pub const __WASI_O_DIRECTORY: __wasi_oflags_t = 1 << 1;
+pub const __WASI_O_EXCL: __wasi_oflags_t = 1 << 2;
+pub const __WASI_O_TRUNC: __wasi_oflags_t = 1 << 3;
+
+pub const __WASI_PREOPENTYPE_DIR: u8 = 0;
+
+pub const __WASI_RIGHT_FD_DATASYNC: __wasi_rights_t = 1 << 0;
+pub const __WASI_RIGHT_FD_READ: __wasi_rights_t = 1 << 1;
+pub const __WASI_RIGHT_FD_SEEK: __wasi_rights_t = 1 << 2;
+pub const __WASI_RIGHT_FD_FDSTAT_SET_FLAGS: __wasi_rights_t = 1 << 3;
+pub const __WASI_RIGHT_FD_SYNC: __wasi_rights_t = 1 << 4;
+pub const __WASI_RIGHT_FD_TELL: __wasi_rights_t = 1 << 5;
+pub const __WASI_RIGHT_FD_WRITE: __wasi_rights_t = 1 << 6;
+pub const __WASI_RIGHT_FD_ADVISE: __wasi_rights_t = 1 << 7;
+pub const __WASI_RIGHT_FD_ALLOCATE: __wasi_rights_t = 1 << 8;
+pub const __WASI_RIGHT_PATH_CREATE_DIRECTORY: __wasi_rights_t = 1 << 9;
+pub const __WASI_RIGHT_PATH_CREATE_FILE: __wasi_rights_t = 1 << 10;
+pub const __WASI_RIGHT_PATH_LINK_SOURCE: __wasi_rights_t = 1 << 11;
+pub const __WASI_RIGHT_PATH_LINK_TARGET: __wasi_rights_t = 1 << 12;
+pub const __WASI_RIGHT_PATH_OPEN: __wasi_rights_t = 1 << 13;
+pub const __WASI_RIGHT_FD_READDIR: __wasi_rights_t = 1 << 14;
+pub const __WASI_RIGHT_PATH_READLINK: __wasi_rights_t = 1 << 15;
+pub const __WASI_RIGHT_PATH_RENAME_SOURCE: __wasi_rights_t = 1 << 16;
+pub const __WASI_RIGHT_PATH_RENAME_TARGET: __wasi_rights_t = 1 << 17;
+pub const __WASI_RIGHT_PATH_FILESTAT_GET: __wasi_rights_t = 1 << 18;
+pub const __WASI_RIGHT_PATH_FILESTAT_SET_SIZE: __wasi_rights_t = 1 << 19;
+pub const __WASI_RIGHT_PATH_FILESTAT_SET_TIMES: __wasi_rights_t = 1 << 20;
+pub const __WASI_RIGHT_FD_FILESTAT_GET: __wasi_rights_t = 1 << 21;
+pub const __WASI_RIGHT_FD_FILESTAT_SET_SIZE: __wasi_rights_t = 1 << 22;
+pub const __WASI_RIGHT_FD_FILESTAT_SET_TIMES: __wasi_rights_t = 1 << 23;
+pub const __WASI_RIGHT_PATH_SYMLINK: __wasi_rights_t = 1 << 24;
+pub const __WASI_RIGHT_PATH_REMOVE_DIRECTORY: __wasi_rights_t = 1 << 25;
+pub const __WASI_RIGHT_PATH_UNLINK_FILE: __wasi_rights_t = 1 << 26;
+pub const __WASI_RIGHT_POLL_FD_READWRITE: __wasi_rights_t = 1 << 27;
+pub const __WASI_RIGHT_SOCK_SHUTDOWN: __wasi_rights_t = 1 << 28;
+
+/// Every right defined by `wasi_unstable`.
+pub const ALL_RIGHTS: __wasi_rights_t = (1 << 29) - 1;
+
+pub const __WASI_WHENCE_CUR: __wasi_whence_t = 0;
+pub const __WASI_WHENCE_END: __wasi_whence_t = 1;
+pub const __WASI_WHENCE_SET: __wasi_whence_t = 2;
+
+/// Size in guest memory of a `__wasi_ciovec_t`/`__wasi_iovec_t`.
+pub const IOVEC_SIZE: u32 = 8;
+/// Size in guest memory of a `__wasi_fdstat_t`.
+pub const FDSTAT_SIZE: u32 = 24;
+/// Size in guest memory of a `__wasi_filestat_t`.
+pub const FILESTAT_SIZE: u32 = 56;
+/// Size in guest memory of a `__wasi_prestat_t`.
+pub const PRESTAT_SIZE: u32 = 8;
+/// Size in guest memory of a `__wasi_dirent_t`, excluding the name.
+pub const DIRENT_SIZE: u32 = 24;
+
+pub struct __wasi_fdstat_t {
+    pub fs_filetype: __wasi_filetype_t,
+    pub fs_flags: __wasi_fdflags_t,
+    pub fs_rights_base: __wasi_rights_t,
+    pub fs_rights_inheriting: __wasi_rights_t,
+}
+
+impl __wasi_fdstat_t {
+    /// Serialize into the layout the guest expects.
+    pub fn to_bytes(&self) -> [u8; FDSTAT_SIZE as usize] {
+        let mut bytes = [0; FDSTAT_SIZE as usize];
+        bytes[0] = self.fs_filetype;
+        bytes[2..4].copy_from_slice(&self.fs_flags.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.fs_rights_base.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.fs_rights_inheriting.to_le_bytes());
+        bytes
+    }
+}
+
+pub struct __wasi_filestat_t {
+    pub st_dev: __wasi_device_t,
+    pub st_ino: __wasi_inode_t,
+    pub st_filetype: __wasi_filetype_t,
+    pub st_nlink: __wasi_linkcount_t,
+    pub st_size: __wasi_filesize_t,
+    pub st_atim: __wasi_timestamp_t,
+    pub st_mtim: __wasi_timestamp_t,
+    pub st_ctim: __wasi_timestamp_t,
+}
+
+impl __wasi_filestat_t {
+    /// Serialize into the layout the guest expects.
+    pub fn to_bytes(&self) -> [u8; FILESTAT_SIZE as usize] {
+        let mut bytes = [0; FILESTAT_SIZE as usize];
+        bytes[0..8].copy_from_slice(&self.st_dev.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.st_ino.to_le_bytes());
+        bytes[16] = self.st_filetype;
+        bytes[20..24].copy_from_slice(&self.st_nlink.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.st_size.to_le_bytes());
+        bytes[32..40].copy_from_slice(&self.st_atim.to_le_bytes());
+        bytes[40..48].copy_from_slice(&self.st_mtim.to_le_bytes());
+        bytes[48..56].copy_from_slice(&self.st_ctim.to_le_bytes());
+        bytes
+    }
+}
+
+pub struct __wasi_dirent_t {
+    pub d_next: __wasi_dircookie_t,
+    pub d_ino: __wasi_inode_t,
+    pub d_namlen: u32,
+    pub d_type: __wasi_filetype_t,
+}
+
+impl __wasi_dirent_t {
+    /// Serialize into the layout the guest expects.
+    pub fn to_bytes(&self) -> [u8; DIRENT_SIZE as usize] {
+        let mut bytes = [0; DIRENT_SIZE as usize];
+        bytes[0..8].copy_from_slice(&self.d_next.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.d_ino.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.d_namlen.to_le_bytes());
+        bytes[20] = self.d_type;
+        bytes
+    }
+}
@@ -0,0 +1,77 @@
+use super::types::*;
+use std::{
+    fs, io, mem,
+    os::unix::fs::{FileExt, MetadataExt},
+    path::Path,
+};
+
+fn clock_id_to_libc(clock_id: u32) -> Result<libc::clockid_t, __wasi_errno_t> {
+    match clock_id {
+        __WASI_CLOCK_REALTIME => Ok(libc::CLOCK_REALTIME),
+        __WASI_CLOCK_MONOTONIC => Ok(libc::CLOCK_MONOTONIC),
+        __WASI_CLOCK_PROCESS_CPUTIME_ID => Ok(libc::CLOCK_PROCESS_CPUTIME_ID),
+        __WASI_CLOCK_THREAD_CPUTIME_ID => Ok(libc::CLOCK_THREAD_CPUTIME_ID),
+        _ => Err(__WASI_EINVAL),
+    }
+}
+
+fn timespec_to_timestamp(timespec: libc::timespec) -> __wasi_timestamp_t {
+    timespec.tv_sec as u64 * 1_000_000_000 + timespec.tv_nsec as u64
+}
+
+pub fn platform_clock_res_get(clock_id: u32) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+    let clock_id = clock_id_to_libc(clock_id)?;
+    let mut timespec: libc::timespec = unsafe { mem::zeroed() };
+    if unsafe { libc::clock_getres(clock_id, &mut timespec) } != 0 {
+        return Err(__WASI_EINVAL);
+    }
+    Ok(timespec_to_timestamp(timespec))
+}
+
+pub fn platform_clock_time_get(clock_id: u32) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+    let clock_id = clock_id_to_libc(clock_id)?;
+    let mut timespec: libc::timespec = unsafe { mem::zeroed() };
+    if unsafe { libc::clock_gettime(clock_id, &mut timespec) } != 0 {
+        return Err(__WASI_EINVAL);
+    }
+    Ok(timespec_to_timestamp(timespec))
+}
+
+/// Returns `(st_dev, st_ino, st_nlink, st_ctim)`.
+pub fn platform_metadata(
+    metadata: &fs::Metadata,
+) -> (
+    __wasi_device_t,
+    __wasi_inode_t,
+    __wasi_linkcount_t,
+    __wasi_timestamp_t,
+) {
+    (
+        metadata.dev(),
+        metadata.ino(),
+        metadata.nlink() as __wasi_linkcount_t,
+        metadata.ctime() as u64 * 1_000_000_000 + metadata.ctime_nsec() as u64,
+    )
+}
+
+pub fn platform_errno(raw: i32) -> Option<__wasi_errno_t> {
+    match raw {
+        libc::ENOTDIR => Some(__WASI_ENOTDIR),
+        libc::EISDIR => Some(__WASI_EISDIR),
+        libc::ENOTEMPTY => Some(__WASI_ENOTEMPTY),
+        libc::EPERM => Some(__WASI_EPERM),
+        _ => None,
+    }
+}
+
+pub fn platform_read_at(file: &fs::File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    file.read_at(buf, offset)
+}
+
+pub fn platform_write_at(file: &fs::File, buf: &[u8], offset: u64) -> io::Result<usize> {
+    file.write_at(buf, offset)
+}
+
+pub fn platform_symlink(target: &str, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
@@ -0,0 +1,55 @@
+use super::types::*;
+use std::{
+    fs, io,
+    os::windows::fs::FileExt,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub fn platform_clock_res_get(clock_id: u32) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+    match clock_id {
+        // `SystemTime` has a resolution of 100ns on Windows.
+        __WASI_CLOCK_REALTIME => Ok(100),
+        _ => Err(__WASI_ENOTSUP),
+    }
+}
+
+pub fn platform_clock_time_get(clock_id: u32) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+    match clock_id {
+        __WASI_CLOCK_REALTIME => {
+            let duration = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|_| __WASI_EIO)?;
+            Ok(duration.as_secs() * 1_000_000_000 + u64::from(duration.subsec_nanos()))
+        }
+        _ => Err(__WASI_ENOTSUP),
+    }
+}
+
+/// Returns `(st_dev, st_ino, st_nlink, st_ctim)`.
+pub fn platform_metadata(
+    _metadata: &fs::Metadata,
+) -> (
+    __wasi_device_t,
+    __wasi_inode_t,
+    __wasi_linkcount_t,
+    __wasi_timestamp_t,
+) {
+    (0, 0, 1, 0)
+}
+
+pub fn platform_errno(_raw: i32) -> Option<__wasi_errno_t> {
+    None
+}
+
+pub fn platform_read_at(file: &fs::File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    file.seek_read(buf, offset)
+}
+
+pub fn platform_write_at(file: &fs::File, buf: &[u8], offset: u64) -> io::Result<usize> {
+    file.seek_write(buf, offset)
+}
+
+pub fn platform_symlink(target: &str, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
@@ -0,0 +1,116 @@
+use crate::syscalls::types::*;
+use std::cell::Cell;
+use wasmer_runtime_core::{memory::Memory, module::Module};
+
+/// We check if a provided module is a WASI one, by looking
+/// for any import from the `wasi_unstable` namespace.
+pub fn is_wasi_module(module: &Module) -> bool {
+    module
+        .info()
+        .imported_functions
+        .iter()
+        .any(|(_, import_name)| {
+            module
+                .info()
+                .namespace_table
+                .get(import_name.namespace_index)
+                == "wasi_unstable"
+        })
+}
+
+pub(crate) fn read_bytes(memory: &Memory, ptr: u32, len: u32) -> Result<Vec<u8>, __wasi_errno_t> {
+    let start = ptr as usize;
+    let end = start.checked_add(len as usize).ok_or(__WASI_EFAULT)?;
+    memory
+        .view::<u8>()
+        .get(start..end)
+        .map(|cells| cells.iter().map(Cell::get).collect())
+        .ok_or(__WASI_EFAULT)
+}
+
+/// Check that the `len` bytes at `ptr` are in `memory`, so that a
+/// buffer of that size can be allocated on the host to fill them.
+pub(crate) fn check_bytes(memory: &Memory, ptr: u32, len: u32) -> Result<(), __wasi_errno_t> {
+    let end = (ptr as usize)
+        .checked_add(len as usize)
+        .ok_or(__WASI_EFAULT)?;
+    if end <= memory.view::<u8>().len() {
+        Ok(())
+    } else {
+        Err(__WASI_EFAULT)
+    }
+}
+
+pub(crate) fn write_bytes(memory: &Memory, ptr: u32, bytes: &[u8]) -> Result<(), __wasi_errno_t> {
+    let start = ptr as usize;
+    let end = start.checked_add(bytes.len()).ok_or(__WASI_EFAULT)?;
+    let view = memory.view::<u8>();
+    let cells = view.get(start..end).ok_or(__WASI_EFAULT)?;
+    for (cell, &byte) in cells.iter().zip(bytes) {
+        cell.set(byte);
+    }
+    Ok(())
+}
+
+pub(crate) fn read_u32(memory: &Memory, ptr: u32) -> Result<u32, __wasi_errno_t> {
+    let bytes = read_bytes(memory, ptr, 4)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+pub(crate) fn write_u32(memory: &Memory, ptr: u32, value: u32) -> Result<(), __wasi_errno_t> {
+    write_bytes(memory, ptr, &value.to_le_bytes())
+}
+
+pub(crate) fn write_u64(memory: &Memory, ptr: u32, value: u64) -> Result<(), __wasi_errno_t> {
+    write_bytes(memory, ptr, &value.to_le_bytes())
+}
+
+pub(crate) fn read_string(memory: &Memory, ptr: u32, len: u32) -> Result<String, __wasi_errno_t> {
+    String::from_utf8(read_bytes(memory, ptr, len)?).map_err(|_| __WASI_EILSEQ)
+}
+
+/// Read an array of `__wasi_iovec_t` as `(buf, buf_len)` pairs.
+pub(crate) fn read_iovecs(
+    memory: &Memory,
+    iovs: u32,
+    iovs_len: u32,
+) -> Result<Vec<(u32, u32)>, __wasi_errno_t> {
+    (0..iovs_len)
+        .map(|i| {
+            let iov = iovs
+                .checked_add(i.checked_mul(IOVEC_SIZE).ok_or(__WASI_EFAULT)?)
+                .ok_or(__WASI_EFAULT)?;
+            let buf_len = iov.checked_add(4).ok_or(__WASI_EFAULT)?;
+            Ok((read_u32(memory, iov)?, read_u32(memory, buf_len)?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod utils_tests {
+    use super::check_bytes;
+    use crate::syscalls::types::*;
+    use wasmer_runtime_core::{memory::Memory, types::MemoryDescriptor, units::Pages};
+
+    #[test]
+    fn test_check_bytes() {
+        let memory = Memory::new(MemoryDescriptor {
+            minimum: Pages(1),
+            maximum: None,
+            shared: false,
+        })
+        .unwrap();
+
+        assert_eq!(check_bytes(&memory, 0, 0x1_0000), Ok(()));
+        assert_eq!(check_bytes(&memory, 0x1_0000, 0), Ok(()));
+        assert_eq!(check_bytes(&memory, 1, 0x1_0000), Err(__WASI_EFAULT));
+        assert_eq!(
+            check_bytes(&memory, 0, u32::max_value()),
+            Err(__WASI_EFAULT)
+        );
+        assert_eq!(
+            check_bytes(&memory, u32::max_value(), u32::max_value()),
+            Err(__WASI_EFAULT)
+        );
+    }
+}
@@ -6,6 +6,8 @@ use std::io;
 use std::io::Read;
 use std::path::PathBuf;
 use std::process::exit;
+use std::thread;
+use std::time::Duration;
 
 use structopt::StructOpt;
 
@@ -13,6 +15,9 @@ use wasmer::webassembly::InstanceABI;
 use wasmer::*;
 use wasmer_emscripten;
 use wasmer_runtime::cache::{Cache as BaseCache, FileSystemCache, WasmHash};
+use wasmer_runtime::error::{CallError, RuntimeError};
+use wasmer_runtime::CompilerConfig;
+use wasmer_wasi;
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "wasmer", about = "Wasm execution runtime.")]
@@ -40,6 +45,18 @@ struct Run {
     #[structopt(long = "disable-cache")]
     disable_cache: bool,
 
+    /// WASI pre-opened directory
+    #[structopt(long = "dir")]
+    pre_opened_directories: Vec<String>,
+
+    /// WASI environment variable, in the form KEY=VALUE
+    #[structopt(long = "env")]
+    env_vars: Vec<String>,
+
+    /// Stop the program after it has run for this many seconds
+    #[structopt(long = "timeout")]
+    timeout: Option<u64>,
+
     /// Input file
     #[structopt(parse(from_os_str))]
     path: PathBuf,
@@ -80,13 +97,16 @@ fn get_cache_dir() -> PathBuf {
     }
 }
 
-/// Execute a wasm/wat file
-fn execute_wasm(options: &Run) -> Result<(), String> {
+/// Execute a wasm/wat file, returning the code it exited with
+fn execute_wasm(options: &Run) -> Result<i32, String> {
     // force disable caching on windows
     #[cfg(target_os = "windows")]
     let disable_cache = true;
     #[cfg(not(target_os = "windows"))]
     let disable_cache = options.disable_cache;
+    // Only interruptible modules can be stopped after the timeout,
+    // and the cached ones aren't.
+    let disable_cache = disable_cache || options.timeout.is_some();
 
     let wasm_path = &options.path;
 
@@ -138,7 +158,11 @@ fn execute_wasm(options: &Run) -> Result<(), String> {
         };
         module
     } else {
-        webassembly::compile(&wasm_binary[..])
+        let compiler_config = CompilerConfig {
+            interruptible: options.timeout.is_some(),
+            ..Default::default()
+        };
+        webassembly::compile_with_config(&wasm_binary[..], compiler_config)
             .map_err(|e| format!("Can't compile module: {:?}", e))?
     };
 
@@ -149,6 +173,27 @@ fn execute_wasm(options: &Run) -> Result<(), String> {
             wasmer_emscripten::generate_emscripten_env(&mut emscripten_globals),
             Some(emscripten_globals), // TODO Em Globals is here to extend, lifetime, find better solution
         )
+    } else if wasmer_wasi::is_wasi_module(&module) {
+        (
+            InstanceABI::WASI,
+            wasmer_wasi::generate_import_object(
+                // The program name goes first, like in `argv`.
+                [options.path.to_str().unwrap().to_owned()]
+                    .iter()
+                    .chain(options.args.iter())
+                    .cloned()
+                    .map(|arg| arg.into_bytes())
+                    .collect(),
+                options
+                    .env_vars
+                    .iter()
+                    .map(|env_var| env_var.clone().into_bytes())
+                    .collect(),
+                options.pre_opened_directories.clone(),
+            )
+            .map_err(|e| format!("Can't preopen directories: {}", e))?,
+            None,
+        )
     } else {
         (
             InstanceABI::None,
@@ -161,22 +206,46 @@ fn execute_wasm(options: &Run) -> Result<(), String> {
         .instantiate(&import_object)
         .map_err(|e| format!("Can't instantiate module: {:?}", e))?;
 
-    webassembly::run_instance(
+    if let Some(timeout) = options.timeout {
+        let interrupt_handle = instance.interrupt_handle();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_secs(timeout));
+            interrupt_handle.interrupt();
+        });
+    }
+
+    let result = webassembly::run_instance(
         &module,
         &mut instance,
         options.path.to_str().unwrap(),
         options.args.iter().map(|arg| arg.as_str()).collect(),
-    )
-    .map_err(|e| format!("{:?}", e))?;
+    );
+    // WASI programs exit with `proc_exit`, which only ends the call.
+    if let Err(CallError::Runtime(RuntimeError::Exit { code })) = result {
+        return Ok(code);
+    }
+    result.map_err(|e| {
+        let mut message = format!("{:?}", e);
+        if let CallError::Runtime(runtime_error) = &e {
+            if !runtime_error.backtrace().is_empty() {
+                message.push_str("\nwasm backtrace:");
+                for (i, frame) in runtime_error.backtrace().iter().enumerate() {
+                    message.push_str(&format!("\n  {}: {}", i, frame));
+                }
+            }
+        }
+        message
+    })?;
 
-    Ok(())
+    Ok(0)
 }
 
 fn run(options: Run) {
     match execute_wasm(&options) {
-        Ok(()) => {}
+        Ok(0) => {}
+        Ok(code) => exit(code),
         Err(message) => {
-            eprintln!("{:?}", message);
+            eprintln!("{}", message);
             exit(1);
         }
     }
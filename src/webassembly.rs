@@ -2,10 +2,11 @@ use std::panic;
 use wasmer_runtime::{
     self as runtime,
     error::{CallResult, Result},
-    ImportObject, Instance, Module,
+    CompilerConfig, ImportObject, Instance, Module,
 };
 
 use wasmer_emscripten::{is_emscripten_module, run_emscripten_instance};
+use wasmer_wasi::is_wasi_module;
 
 pub struct ResultObject {
     /// A webassembly::Module object representing the compiled WebAssembly module.
@@ -19,6 +20,7 @@ pub struct ResultObject {
 #[derive(PartialEq)]
 pub enum InstanceABI {
     Emscripten,
+    WASI,
     None,
 }
 
@@ -75,6 +77,16 @@ pub fn compile(buffer_source: &[u8]) -> Result<Module> {
     Ok(module)
 }
 
+/// The same as webassembly::compile(), but compiles the module
+/// with `compiler_config`, for instance to make it interruptible.
+pub fn compile_with_config(
+    buffer_source: &[u8],
+    compiler_config: CompilerConfig,
+) -> Result<Module> {
+    let module = runtime::compile_with_config(buffer_source, compiler_config)?;
+    Ok(module)
+}
+
 /// Performs common instance operations needed when an instance is first run
 /// including data setup, handling arguments and calling a main function
 pub fn run_instance(
@@ -85,6 +97,8 @@ pub fn run_instance(
 ) -> CallResult<()> {
     if is_emscripten_module(module) {
         run_emscripten_instance(module, instance, path, args)?;
+    } else if is_wasi_module(module) {
+        instance.call("_start", &[])?;
     } else {
         instance.call("main", &[])?;
     };